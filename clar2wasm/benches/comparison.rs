@@ -2,9 +2,9 @@
 
 use std::hint::black_box;
 
-use clar2wasm::compile;
 use clar2wasm::datastore::{BurnDatastore, Datastore, StacksConstants};
 use clar2wasm::initialize::initialize_contract;
+use clar2wasm::{compile_with_options, GeneratorOptions};
 use clarity::consts::CHAIN_ID_TESTNET;
 use clarity::types::{PrivateKey, StacksEpochId};
 use clarity::util::hash::Keccak256Hash;
@@ -120,8 +120,13 @@ where
     global_context.commit().unwrap();
 }
 
-fn webassembly<M, F>(b: &mut Bencher<M>, fn_name: &str, clarity: &str, init: F)
-where
+fn webassembly<M, F>(
+    b: &mut Bencher<M>,
+    fn_name: &str,
+    clarity: &str,
+    options: GeneratorOptions,
+    init: F,
+) where
     M: 'static + Measurement,
     F: FnOnce(&mut Environment) -> Vec<Value>,
 {
@@ -145,14 +150,14 @@ where
     // Create a new analysis database
     let mut analysis_db = AnalysisDatabase::new(&mut clarity_store);
 
-    let mut compilation = compile(
+    let mut compilation = compile_with_options(
         clarity,
         &contract_id,
         cost_tracker,
         ClarityVersion::latest(),
         StacksEpochId::latest(),
         &mut analysis_db,
-        false,
+        options,
     )
     .expect("Failed compiling clarity to WASM");
 
@@ -236,6 +241,7 @@ macro_rules! decl_benches {
                             b,
                             black_box($fn_name),
                             black_box($clarity),
+                            GeneratorOptions::default(),
                             |_| vec![$(black_box($arg)),*]
                         );
                     });
@@ -274,6 +280,7 @@ macro_rules! decl_benches {
                                 b,
                                 black_box($fn_name),
                                 black_box(&clarity),
+                                GeneratorOptions::default(),
                                 |env| { $init(i, env) }
                             )
                         });
@@ -317,6 +324,16 @@ decl_benches! {
         "#),
         |i, _: &mut Environment| vec![Value::cons_list_unsanitized((1..=i).map(Value::Int).collect()).unwrap(), Value::Int(0)]
     ),
+    (
+        "fold_add",
+        (1..=1001).step_by(50),
+        |i| format!(r#"
+        (define-public (fold_add (l (list {i} uint)) (init uint))
+            (ok (fold + l init))
+        )
+        "#),
+        |i, _: &mut Environment| vec![Value::cons_list_unsanitized((1..=i as u128).map(Value::UInt).collect()).unwrap(), Value::UInt(0)]
+    ),
     (
         "map_set_entries",
         (1..=1001).step_by(50),
@@ -428,6 +445,50 @@ decl_benches! {
     ),
 }
 
+/// Measures the cost of generating modules without bulk memory: the copies of
+/// sequences use the `memory.copy` instruction by default, and the byte loops
+/// replacing it when bulk memory is disabled.
+fn copy_buffers(c: &mut Criterion) {
+    let mut group = c.benchmark_group("copy_buffers");
+
+    let clarity = r#"
+        (define-read-only (copy_buffers (a (buff 16384)) (b (buff 16384)))
+            (let ((joined (concat a b)))
+                (len (unwrap-panic (replace-at? (unwrap-panic (slice? joined u1 (len joined))) u0 0x00)))
+            )
+        )
+    "#;
+
+    for size in (1024..=16384).step_by(3072) {
+        let init = move |_: &mut Environment| {
+            vec![
+                Value::buff_from(vec![1; size]).unwrap(),
+                Value::buff_from(vec![2; size]).unwrap(),
+            ]
+        };
+        for (name, bulk_memory) in [("bulk-memory", true), ("byte-loops", false)] {
+            group.bench_with_input(BenchmarkId::new(name, size), &size, |b, _| {
+                webassembly(
+                    b,
+                    black_box("copy_buffers"),
+                    black_box(clarity),
+                    GeneratorOptions {
+                        bulk_memory,
+                        ..GeneratorOptions::default()
+                    },
+                    init,
+                )
+            });
+        }
+    }
+}
+
+criterion_group! {
+    name = bulk_memory;
+    config = criterion_config();
+    targets = copy_buffers
+}
+
 fn add_prices_init(n: usize, env: &mut Environment) -> Value {
     let mut prices = Vec::with_capacity(n);
 
@@ -492,4 +553,4 @@ fn add_prices_init(n: usize, env: &mut Environment) -> Value {
     Value::cons_list_unsanitized(prices).unwrap()
 }
 
-criterion_main!(single, range);
+criterion_main!(single, range, bulk_memory);
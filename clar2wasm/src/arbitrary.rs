@@ -0,0 +1,925 @@
+//! Generation of arbitrary Clarity values, for property testing.
+//!
+//! [prop_signature] generates type signatures, and [PropValue::from_type]
+//! valid values of a given [TypeSignature]. A [PropValue] is displayed as a
+//! Clarity literal, and [TypePrinter] renders the type of a value, so that
+//! both can be inserted in contract snippets.
+//!
+//! [ValueConfig] bounds the size of the generated values, and biases them
+//! towards edge cases: minimum and maximum integers, empty and full sequences.
+//!
+//! Values are not generated for trait and callable types, see
+//! [has_prop_values].
+
+use clarity::vm::types::{
+    ASCIIData, BuffData, CharType, ListData, ListTypeData, OptionalData, PrincipalData,
+    QualifiedContractIdentifier, ResponseData, SequenceData, SequenceSubtype,
+    StandardPrincipalData, StringSubtype, TupleData, TupleTypeSignature, TypeSignature, UTF8Data,
+    Value,
+};
+use clarity::vm::ContractName;
+use proptest::prelude::*;
+
+/// Weight of random values against [ValueConfig::edge_case_weight].
+const RANDOM_WEIGHT: u32 = 10;
+
+/// Bounds and biases of the generated values.
+///
+/// The default configuration generates full buffers and strings, lists of any
+/// length allowed by their type, and no particular edge case.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ValueConfig {
+    /// Maximum number of elements of a list, whatever its type allows.
+    pub max_list_len: u32,
+    /// Maximum length of a buffer or a string, whatever its type allows.
+    pub max_sequence_len: u32,
+    /// Generate buffers and strings of any length up to their maximum,
+    /// instead of always filling them.
+    pub variable_lengths: bool,
+    /// Weight of the edge cases against a weight of 10 for random values.
+    /// Edge cases are disabled with a weight of 0.
+    pub edge_case_weight: u32,
+}
+
+impl Default for ValueConfig {
+    fn default() -> Self {
+        Self {
+            max_list_len: u32::MAX,
+            max_sequence_len: u32::MAX,
+            variable_lengths: false,
+            edge_case_weight: 0,
+        }
+    }
+}
+
+impl ValueConfig {
+    /// Sequences of any length, and an edge case for one value in six.
+    pub fn edge_cases() -> Self {
+        Self {
+            variable_lengths: true,
+            edge_case_weight: 2,
+            ..Default::default()
+        }
+    }
+}
+
+/// Picks from `edge_cases` instead of `random` with the weight configured in
+/// `config`.
+fn biased(
+    config: ValueConfig,
+    random: BoxedStrategy<Value>,
+    edge_cases: BoxedStrategy<Value>,
+) -> BoxedStrategy<Value> {
+    if config.edge_case_weight == 0 {
+        return random;
+    }
+    prop_oneof![
+        config.edge_case_weight => edge_cases,
+        RANDOM_WEIGHT => random,
+    ]
+    .boxed()
+}
+
+/// Type signatures of Clarity values. Only types with values are generated,
+/// see [has_prop_values].
+pub fn prop_signature() -> impl Strategy<Value = TypeSignature> {
+    let leaf = prop_oneof![
+        Just(TypeSignature::IntType),
+        Just(TypeSignature::UIntType),
+        Just(TypeSignature::BoolType),
+        (0u32..128).prop_filter_map("invalid length", |s| Some(TypeSignature::SequenceType(
+            SequenceSubtype::BufferType(s.try_into().ok()?)
+        ))),
+        (0u32..128).prop_filter_map("invalid length", |s| Some(TypeSignature::SequenceType(
+            SequenceSubtype::StringType(StringSubtype::ASCII(s.try_into().ok()?))
+        ))),
+        Just(TypeSignature::PrincipalType),
+        (0u32..32).prop_filter_map("invalid length", |s| Some(TypeSignature::SequenceType(
+            SequenceSubtype::StringType(StringSubtype::UTF8(s.try_into().ok()?))
+        )))
+    ];
+    leaf.prop_recursive(5, 64, 10, |inner| {
+        prop_oneof![
+            // optional type: 10% NoType + 90% any other type
+            prop_oneof![
+                1 => Just(TypeSignature::NoType),
+                9 => inner.clone(),
+            ]
+            .prop_map(|t| TypeSignature::new_option(t.clone()).unwrap_or(t)),
+            // response type: 20% (NoType, any) + 20% (any, NoType) + 60% (any, any)
+            prop_oneof![
+                1 => inner.clone().prop_map(|ok_ty| TypeSignature::new_response(ok_ty.clone(), TypeSignature::NoType).unwrap_or(ok_ty)),
+                1 => inner.clone().prop_map(|err_ty| TypeSignature::new_response(TypeSignature::NoType, err_ty.clone()).unwrap_or(err_ty)),
+                3 => (inner.clone(), inner.clone()).prop_map(|(ok_ty, err_ty)| TypeSignature::new_response(ok_ty.clone(), err_ty).unwrap_or(ok_ty)),
+            ],
+            // tuple type
+            prop::collection::btree_map(
+                r#"[a-zA-Z]{1,16}"#.prop_filter_map("invalid name", |name| name.try_into().ok()),
+                inner.clone(),
+                1..8
+            )
+            .prop_filter_map("invalid tuple", |btree| {
+                let fallback = btree.values().next().cloned()?;
+                Some(TupleTypeSignature::try_from(btree).map(TypeSignature::TupleType).unwrap_or(fallback))
+            }),
+            // list type
+            (8u32..32, inner.clone()).prop_map(|(s, ty)| TypeSignature::list_of(ty.clone(), s).unwrap_or(ty)),
+        ]
+    })
+    .prop_filter("type without values", has_prop_values)
+}
+
+#[derive(Clone, PartialEq, Eq)]
+pub struct PropValue(pub Value);
+
+impl From<Value> for PropValue {
+    fn from(value: Value) -> Self {
+        PropValue(value)
+    }
+}
+
+impl From<PropValue> for Value {
+    fn from(value: PropValue) -> Self {
+        value.0
+    }
+}
+
+impl std::fmt::Debug for PropValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PropValue")
+            .field("value", &self.to_string())
+            .field("type", &self.type_string())
+            .finish()
+    }
+}
+
+impl std::fmt::Display for PropValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.0 {
+            Value::Sequence(SequenceData::String(clarity::vm::types::CharType::ASCII(
+                ASCIIData { data },
+            ))) => {
+                write!(f, "\"")?;
+                for b in data {
+                    if [b'\\', b'"'].contains(b) {
+                        write!(f, "\\")?;
+                    }
+                    write!(f, "{}", *b as char)?;
+                }
+                write!(f, "\"")
+            }
+            Value::Sequence(SequenceData::String(CharType::UTF8(UTF8Data { data }))) => {
+                write!(f, "u\"")?;
+                let chars = data
+                    .iter()
+                    .filter_map(|bytes| std::str::from_utf8(bytes).ok())
+                    .flat_map(str::chars);
+                for c in chars {
+                    match c {
+                        '\\' | '\"' => write!(f, "\\{c}")?,
+                        _ if c.is_ascii_graphic() => write!(f, "{c}")?,
+                        _ => write!(f, r#"\u{{{:X}}}"#, c as u32)?,
+                    }
+                }
+                write!(f, "\"")
+            }
+            Value::Principal(p) => write!(f, "'{p}"),
+            Value::Optional(OptionalData { data }) => match data {
+                Some(inner) => write!(f, "(some {})", PropValue(*inner.clone())),
+                None => write!(f, "none"),
+            },
+            Value::Response(ResponseData { committed, data }) => {
+                if *committed {
+                    write!(f, "(ok {})", PropValue(*data.clone()))
+                } else {
+                    write!(f, "(err {})", PropValue(*data.clone()))
+                }
+            }
+            Value::Sequence(SequenceData::List(ListData { data, .. })) => {
+                write!(f, "(list")?;
+                for d in data {
+                    write!(f, " ")?;
+                    write!(f, "{}", PropValue(d.clone()))?;
+                }
+                write!(f, ")")
+            }
+            Value::Tuple(data) => {
+                write!(f, "(tuple")?;
+                for (key, value) in &data.data_map {
+                    write!(f, " ")?;
+                    write!(f, "({} {})", &**key, PropValue(value.clone()))?;
+                }
+                write!(f, ")")
+            }
+            otherwise => write!(f, "{otherwise}"),
+        }
+    }
+}
+
+impl PropValue {
+    pub fn any() -> impl Strategy<Value = Self> {
+        prop_signature().prop_flat_map(prop_value).prop_map_into()
+    }
+
+    pub fn from_type(ty: TypeSignature) -> impl Strategy<Value = Self> {
+        prop_value(ty).prop_map_into()
+    }
+
+    /// Values of type `ty`, bounded and biased according to `config`.
+    pub fn from_type_with(ty: TypeSignature, config: ValueConfig) -> impl Strategy<Value = Self> {
+        prop_value_with(ty, config).prop_map_into()
+    }
+
+    pub fn many_from_type(ty: TypeSignature, count: usize) -> impl Strategy<Value = Vec<Self>> {
+        prop::collection::vec(Self::from_type(ty.clone()), count)
+    }
+
+    pub fn any_sequence(size: usize) -> impl Strategy<Value = Self> {
+        let any_list = prop_signature()
+            .prop_filter_map("skip too large", move |ty| {
+                ListTypeData::new_list(ty, size as u32).ok()
+            })
+            .prop_ind_flat_map2(move |list_ty| {
+                prop::collection::vec(prop_value(list_ty.get_list_item_type().clone()), size)
+            })
+            .prop_map(|(type_signature, data)| {
+                Value::Sequence(SequenceData::List(ListData {
+                    data,
+                    type_signature,
+                }))
+            });
+        // TODO: add string-utf8
+        prop_oneof![
+            // 10% chance for a buffer
+            1 => buffer(size as u32),
+            // 10% chance for a string-ascii
+            1 => string_ascii(size as u32),
+            // 80% chance for a list
+            8 => any_list
+        ]
+        .prop_map_into()
+    }
+
+    pub fn inner(&self) -> &Value {
+        &self.0
+    }
+}
+
+impl TryFrom<Vec<PropValue>> for PropValue {
+    type Error = clarity::vm::errors::Error;
+
+    fn try_from(values: Vec<PropValue>) -> Result<Self, Self::Error> {
+        let values = values.into_iter().map(Value::from).collect();
+        Value::cons_list_unsanitized(values).map(PropValue::from)
+    }
+}
+
+pub fn prop_value(ty: TypeSignature) -> impl Strategy<Value = Value> {
+    prop_value_with(ty, ValueConfig::default())
+}
+
+/// Returns `true` if [prop_value_with] generates values of type `ty`. There are
+/// no values of `NoType`, other than in an empty sequence, an optional or a
+/// response, and no values are generated for trait and callable types.
+pub fn has_prop_values(ty: &TypeSignature) -> bool {
+    match ty {
+        TypeSignature::NoType
+        | TypeSignature::ListUnionType(_)
+        | TypeSignature::CallableType(_)
+        | TypeSignature::TraitReferenceType(_) => false,
+        TypeSignature::IntType
+        | TypeSignature::UIntType
+        | TypeSignature::BoolType
+        | TypeSignature::PrincipalType
+        | TypeSignature::SequenceType(
+            SequenceSubtype::BufferType(_) | SequenceSubtype::StringType(_),
+        ) => true,
+        TypeSignature::OptionalType(inner) => {
+            matches!(**inner, TypeSignature::NoType) || has_prop_values(inner)
+        }
+        TypeSignature::ResponseType(ok_err) => match &**ok_err {
+            (TypeSignature::NoType, ty) | (ty, TypeSignature::NoType) => has_prop_values(ty),
+            (ok_ty, err_ty) => has_prop_values(ok_ty) && has_prop_values(err_ty),
+        },
+        TypeSignature::SequenceType(SequenceSubtype::ListType(list_ty)) => {
+            list_ty.get_max_len() == 0 || has_prop_values(list_ty.get_list_item_type())
+        }
+        TypeSignature::TupleType(tuple_ty) => tuple_ty.get_type_map().values().all(has_prop_values),
+    }
+}
+
+/// Rejects every value, for the types without values. Types that don't come
+/// from [prop_signature] must be checked with [has_prop_values] before
+/// generating their values, so that this strategy is never reached.
+fn no_values(ty: &TypeSignature) -> BoxedStrategy<Value> {
+    Just(Value::none())
+        .prop_filter(format!("no values of type {ty}"), |_| false)
+        .boxed()
+}
+
+pub fn prop_value_with(ty: TypeSignature, config: ValueConfig) -> BoxedStrategy<Value> {
+    match ty {
+        TypeSignature::IntType => int_with(config),
+        TypeSignature::UIntType => uint_with(config),
+        TypeSignature::BoolType => bool().boxed(),
+        TypeSignature::OptionalType(ty) => optional_with(*ty, config),
+        TypeSignature::ResponseType(ok_err) => response_with(ok_err.0, ok_err.1, config),
+        TypeSignature::SequenceType(SequenceSubtype::BufferType(size)) => {
+            buffer_with(size.into(), config)
+        }
+        TypeSignature::SequenceType(SequenceSubtype::StringType(StringSubtype::ASCII(size))) => {
+            string_ascii_with(size.into(), config)
+        }
+        TypeSignature::SequenceType(SequenceSubtype::StringType(StringSubtype::UTF8(size))) => {
+            string_utf8_with(size.into(), config)
+        }
+        TypeSignature::SequenceType(SequenceSubtype::ListType(list_type_data)) => {
+            list_with(list_type_data, config)
+        }
+        TypeSignature::TupleType(tuple_ty) => tuple_with(tuple_ty, config),
+        TypeSignature::PrincipalType => {
+            prop_oneof![standard_principal(), qualified_principal()].boxed()
+        }
+        TypeSignature::NoType
+        | TypeSignature::ListUnionType(_)
+        | TypeSignature::CallableType(_)
+        | TypeSignature::TraitReferenceType(_) => no_values(&ty),
+    }
+}
+
+pub fn int() -> impl Strategy<Value = Value> {
+    any::<i128>().prop_map(Value::Int)
+}
+
+fn int_with(config: ValueConfig) -> BoxedStrategy<Value> {
+    biased(
+        config,
+        int().boxed(),
+        prop::sample::select(vec![i128::MIN, i128::MAX, 0, 1, -1])
+            .prop_map(Value::Int)
+            .boxed(),
+    )
+}
+
+pub fn uint() -> impl Strategy<Value = Value> {
+    any::<u128>().prop_map(Value::UInt)
+}
+
+fn uint_with(config: ValueConfig) -> BoxedStrategy<Value> {
+    biased(
+        config,
+        uint().boxed(),
+        prop::sample::select(vec![0, 1, u128::MAX])
+            .prop_map(Value::UInt)
+            .boxed(),
+    )
+}
+
+pub fn bool() -> impl Strategy<Value = Value> {
+    any::<bool>().prop_map(Value::Bool)
+}
+
+/// Generates sequences of up to `size` elements from `element`, bounded by
+/// `max_len` and biased towards empty and full sequences.
+fn sequence_with<T: std::fmt::Debug + 'static>(
+    element: impl Strategy<Value = T> + Clone + 'static,
+    size: u32,
+    max_len: u32,
+    variable_length: bool,
+    config: ValueConfig,
+    into_value: impl Fn(Vec<T>) -> Value + Clone + 'static,
+) -> BoxedStrategy<Value> {
+    let max_len = size.min(max_len) as usize;
+    let min_len = if variable_length { 0 } else { max_len };
+    let random = prop::collection::vec(element.clone(), min_len..=max_len)
+        .prop_map(into_value.clone())
+        .boxed();
+    let edge_cases = prop_oneof![
+        Just(Vec::new()).prop_map(into_value.clone()),
+        prop::collection::vec(element, max_len..=max_len).prop_map(into_value),
+    ]
+    .boxed();
+    biased(config, random, edge_cases)
+}
+
+pub fn string_ascii(size: u32) -> impl Strategy<Value = Value> {
+    string_ascii_with(size, ValueConfig::default())
+}
+
+fn string_ascii_with(size: u32, config: ValueConfig) -> BoxedStrategy<Value> {
+    sequence_with(
+        0x20u8..0x7e,
+        size,
+        config.max_sequence_len,
+        config.variable_lengths,
+        config,
+        |bytes| {
+            Value::Sequence(SequenceData::String(clarity::vm::types::CharType::ASCII(
+                clarity::vm::types::ASCIIData { data: bytes },
+            )))
+        },
+    )
+}
+
+pub fn string_utf8(size: u32) -> impl Strategy<Value = Value> {
+    string_utf8_with(size, ValueConfig::default())
+}
+
+fn string_utf8_with(size: u32, config: ValueConfig) -> BoxedStrategy<Value> {
+    sequence_with(
+        any::<char>(),
+        size,
+        config.max_sequence_len,
+        config.variable_lengths,
+        config,
+        |chars| {
+            let mut data = Vec::with_capacity(chars.len());
+            for c in chars {
+                let mut encoded_char = vec![0; c.len_utf8()];
+                c.encode_utf8(encoded_char.as_mut());
+                data.push(encoded_char);
+            }
+            Value::Sequence(SequenceData::String(CharType::UTF8(UTF8Data { data })))
+        },
+    )
+}
+
+pub fn buffer(size: u32) -> impl Strategy<Value = Value> {
+    buffer_with(size, ValueConfig::default())
+}
+
+fn buffer_with(size: u32, config: ValueConfig) -> BoxedStrategy<Value> {
+    sequence_with(
+        any::<u8>(),
+        size,
+        config.max_sequence_len,
+        config.variable_lengths,
+        config,
+        |bytes| Value::Sequence(SequenceData::Buffer(BuffData { data: bytes })),
+    )
+}
+
+pub fn optional(inner_ty: TypeSignature) -> impl Strategy<Value = Value> {
+    optional_with(inner_ty, ValueConfig::default())
+}
+
+fn optional_with(inner_ty: TypeSignature, config: ValueConfig) -> BoxedStrategy<Value> {
+    match inner_ty {
+        TypeSignature::NoType => Just(Value::none()).boxed(),
+        _ => prop::option::of(prop_value_with(inner_ty, config))
+            .prop_map(|v| {
+                Value::Optional(OptionalData {
+                    data: v.map(Box::new),
+                })
+            })
+            .boxed(),
+    }
+}
+
+pub fn response(ok_ty: TypeSignature, err_ty: TypeSignature) -> impl Strategy<Value = Value> {
+    response_with(ok_ty, err_ty, ValueConfig::default())
+}
+
+fn response_with(
+    ok_ty: TypeSignature,
+    err_ty: TypeSignature,
+    config: ValueConfig,
+) -> BoxedStrategy<Value> {
+    match (ok_ty, err_ty) {
+        (TypeSignature::NoType, err_ty) => prop_value_with(err_ty, config)
+            .prop_map(|err| {
+                Value::Response(ResponseData {
+                    committed: false,
+                    data: Box::new(err),
+                })
+            })
+            .boxed(),
+        (ok_ty, TypeSignature::NoType) => prop_value_with(ok_ty, config)
+            .prop_map(|ok| {
+                Value::Response(ResponseData {
+                    committed: true,
+                    data: Box::new(ok),
+                })
+            })
+            .boxed(),
+        (ok_ty, err_ty) => prop::result::maybe_err(
+            prop_value_with(ok_ty, config),
+            prop_value_with(err_ty, config),
+        )
+        .prop_map(|res| {
+            Value::Response(ResponseData {
+                committed: res.is_ok(),
+                data: res.map_or_else(Box::new, Box::new),
+            })
+        })
+        .boxed(),
+    }
+}
+
+pub fn list(list_type_data: ListTypeData) -> impl Strategy<Value = Value> {
+    list_with(list_type_data, ValueConfig::default())
+}
+
+fn list_with(list_type_data: ListTypeData, config: ValueConfig) -> BoxedStrategy<Value> {
+    sequence_with(
+        prop_value_with(list_type_data.get_list_item_type().clone(), config),
+        list_type_data.get_max_len(),
+        config.max_list_len,
+        true,
+        config,
+        move |v| {
+            Value::Sequence(SequenceData::List(ListData {
+                data: v,
+                type_signature: list_type_data.clone(),
+            }))
+        },
+    )
+}
+
+pub fn tuple(tuple_ty: TupleTypeSignature) -> impl Strategy<Value = Value> {
+    tuple_with(tuple_ty, ValueConfig::default())
+}
+
+fn tuple_with(tuple_ty: TupleTypeSignature, config: ValueConfig) -> BoxedStrategy<Value> {
+    let fields: Vec<_> = tuple_ty.get_type_map().keys().cloned().collect();
+    let strategies: Vec<_> = tuple_ty
+        .get_type_map()
+        .values()
+        .cloned()
+        .map(|ty| prop_value_with(ty, config))
+        .collect();
+    strategies
+        .prop_map(move |vec_values| {
+            TupleData {
+                type_signature: tuple_ty.clone(),
+                data_map: fields.clone().into_iter().zip(vec_values).collect(),
+            }
+            .into()
+        })
+        .boxed()
+}
+
+fn standard_principal_data() -> impl Strategy<Value = StandardPrincipalData> {
+    // Generate a version byte in the range [0, 31] and a 20-byte hash.
+    (0u8..32, prop::array::uniform20(any::<u8>()))
+        .prop_filter_map("invalid version", |(v, hash)| {
+            StandardPrincipalData::new(v, hash).ok()
+        })
+        .no_shrink()
+}
+
+pub fn standard_principal() -> impl Strategy<Value = Value> {
+    standard_principal_data().prop_map(|principal| Value::Principal(principal.into()))
+}
+
+pub fn qualified_principal() -> impl Strategy<Value = Value> {
+    (standard_principal_data(), "[a-zA-Z]{1,40}").prop_map(|(issuer, name)| {
+        let name = ContractName::from(&*name);
+        Value::Principal(PrincipalData::Contract(QualifiedContractIdentifier {
+            issuer,
+            name,
+        }))
+    })
+}
+
+/// Renders the type of a value in Clarity syntax.
+pub trait TypePrinter {
+    fn type_string(&self) -> String;
+}
+
+impl TypePrinter for PropValue {
+    fn type_string(&self) -> String {
+        self.0.type_string()
+    }
+}
+
+impl TypePrinter for Value {
+    fn type_string(&self) -> String {
+        match &self {
+            Value::Int(_) => type_string(&TypeSignature::IntType),
+            Value::UInt(_) => type_string(&TypeSignature::UIntType),
+            Value::Bool(_) => type_string(&TypeSignature::BoolType),
+            Value::Sequence(SequenceData::Buffer(BuffData { data })) => {
+                format!("(buff {})", data.len())
+            }
+            Value::Sequence(SequenceData::String(CharType::ASCII(ASCIIData { data }))) => {
+                format!("(string-ascii {})", data.len())
+            }
+            Value::Sequence(SequenceData::String(CharType::UTF8(UTF8Data { data }))) => {
+                format!("(string-utf8 {})", data.len())
+            }
+            Value::Optional(inner) => inner.type_string(),
+            Value::Response(inner) => inner.type_string(),
+            Value::Sequence(SequenceData::List(list_data)) => list_data.type_string(),
+            Value::Tuple(data) => data.type_string(),
+            Value::Principal(_) => type_string(&TypeSignature::PrincipalType),
+            Value::CallableContract(_) => type_string(&TypeSignature::PrincipalType),
+        }
+    }
+}
+
+impl TypePrinter for OptionalData {
+    fn type_string(&self) -> String {
+        let inner = match self.data {
+            Some(ref inner) => inner.type_string(),
+            None => "int".to_owned(), // We need to default to something here
+        };
+        format!("(optional {inner})")
+    }
+}
+
+impl TypePrinter for ResponseData {
+    fn type_string(&self) -> String {
+        let (ok_string, err_string) = if self.committed {
+            (self.data.type_string(), "int".to_owned())
+        } else {
+            ("int".to_owned(), self.data.type_string())
+        };
+        format!("(response {ok_string} {err_string})")
+    }
+}
+
+impl TypePrinter for ListData {
+    fn type_string(&self) -> String {
+        format!(
+            "(list {} {})",
+            self.data.len(),
+            type_string(self.type_signature.get_list_item_type())
+        )
+    }
+}
+
+impl TypePrinter for TupleData {
+    fn type_string(&self) -> String {
+        type_string(&TypeSignature::TupleType(self.type_signature.clone()))
+    }
+}
+
+pub fn type_string(ty: &TypeSignature) -> String {
+    match ty {
+        TypeSignature::IntType => "int".to_owned(),
+        TypeSignature::UIntType => "uint".to_owned(),
+        TypeSignature::BoolType => "bool".to_owned(),
+        TypeSignature::OptionalType(inner) => format!("(optional {})", type_string(inner)),
+        TypeSignature::ResponseType(inner) => format!(
+            "(response {} {})",
+            type_string(&inner.0),
+            type_string(&inner.1)
+        ),
+        TypeSignature::SequenceType(SequenceSubtype::BufferType(len)) => format!("(buff {len})"),
+        TypeSignature::SequenceType(SequenceSubtype::StringType(StringSubtype::ASCII(len))) => {
+            format!("(string-ascii {len})")
+        }
+        TypeSignature::SequenceType(SequenceSubtype::StringType(StringSubtype::UTF8(len))) => {
+            format!("(string-utf8 {len})")
+        }
+        TypeSignature::SequenceType(SequenceSubtype::ListType(list_type_data)) => {
+            format!(
+                "(list {} {})",
+                list_type_data.get_max_len(),
+                type_string(list_type_data.get_list_item_type())
+            )
+        }
+        TypeSignature::TupleType(tuple_ty) => {
+            let tuple_ty = {
+                let mut v: Vec<_> = tuple_ty.get_type_map().iter().collect();
+                v.sort_unstable_by_key(|&(k, _)| k);
+                v
+            };
+            let mut s = String::new();
+            s.push('{');
+            for (key, value) in tuple_ty {
+                s.push_str(key);
+                s.push_str(": ");
+                s.push_str(&type_string(value));
+                s.push(',');
+            }
+            s.push('}');
+            s
+        }
+        TypeSignature::PrincipalType => "principal".to_owned(),
+        TypeSignature::CallableType(_) => "principal".to_owned(),
+        TypeSignature::TraitReferenceType(_) => "principal".to_owned(),
+        TypeSignature::ListUnionType(_) => "principal".to_owned(),
+        TypeSignature::NoType => "int".to_owned(), // Use "int" as a default type
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clarity::vm::types::{PrincipalData, UTF8Data};
+
+    use super::*;
+
+    #[test]
+    fn check_type_string() {
+        assert_eq!(Value::Int(0).type_string(), "int");
+        assert_eq!(Value::UInt(0).type_string(), "uint");
+        assert_eq!(Value::Bool(false).type_string(), "bool");
+        assert_eq!(
+            Value::Sequence(SequenceData::Buffer(BuffData { data: vec![] })).type_string(),
+            "(buff 0)"
+        );
+        assert_eq!(
+            Value::Sequence(SequenceData::Buffer(BuffData {
+                data: vec![1, 2, 3, 4, 5]
+            }))
+            .type_string(),
+            "(buff 5)"
+        );
+        assert_eq!(
+            Value::Sequence(SequenceData::String(CharType::ASCII(ASCIIData {
+                data: vec![]
+            })))
+            .type_string(),
+            "(string-ascii 0)"
+        );
+        assert_eq!(
+            Value::Sequence(SequenceData::String(CharType::ASCII(ASCIIData {
+                data: vec![0x68, 0x65, 0x6c, 0x6c, 0x6f]
+            })))
+            .type_string(),
+            "(string-ascii 5)"
+        );
+        assert_eq!(
+            Value::Sequence(SequenceData::String(CharType::UTF8(UTF8Data {
+                data: vec![]
+            })))
+            .type_string(),
+            "(string-utf8 0)"
+        );
+        assert_eq!(
+            Value::Sequence(SequenceData::String(CharType::UTF8(UTF8Data {
+                data: vec![vec![0x68], vec![0x65], vec![0x6c], vec![0x6c], vec![0x6f]]
+            })))
+            .type_string(),
+            "(string-utf8 5)"
+        );
+        assert_eq!(
+            Value::Optional(OptionalData { data: None }).type_string(),
+            "(optional int)"
+        );
+        assert_eq!(
+            Value::Optional(OptionalData {
+                data: Some(Box::new(Value::UInt(0)))
+            })
+            .type_string(),
+            "(optional uint)"
+        );
+        assert_eq!(
+            Value::Response(ResponseData {
+                committed: true,
+                data: Box::new(Value::UInt(0))
+            })
+            .type_string(),
+            "(response uint int)"
+        );
+        assert_eq!(
+            Value::Response(ResponseData {
+                committed: false,
+                data: Box::new(Value::UInt(0))
+            })
+            .type_string(),
+            "(response int uint)"
+        );
+        assert_eq!(
+            Value::Sequence(SequenceData::List(ListData {
+                data: vec![],
+                type_signature: ListTypeData::new_list(TypeSignature::IntType, 0).unwrap()
+            }))
+            .type_string(),
+            "(list 0 int)"
+        );
+        assert_eq!(
+            Value::Sequence(SequenceData::List(ListData {
+                data: vec![Value::Int(0), Value::Int(1), Value::Int(2)],
+                type_signature: ListTypeData::new_list(TypeSignature::IntType, 3).unwrap()
+            }))
+            .type_string(),
+            "(list 3 int)"
+        );
+        assert_eq!(
+            Value::Tuple(
+                TupleData::from_data(vec![
+                    ("a".into(), Value::Int(42)),
+                    ("b".into(), Value::UInt(42)),
+                    ("c".into(), Value::Bool(true)),
+                ])
+                .unwrap()
+            )
+            .type_string(),
+            "{a: int,b: uint,c: bool,}"
+        );
+        assert_eq!(
+            Value::from(
+                PrincipalData::parse_standard_principal(
+                    "SM2J6ZY48GV1EZ5V2V5RB9MP66SW86PYKKQVX8X0G"
+                )
+                .unwrap()
+            )
+            .type_string(),
+            "principal"
+        );
+        assert_eq!(
+            // (list (ok 0))
+            Value::cons_list_unsanitized(vec![Value::okay(Value::Int(0)).unwrap()])
+                .unwrap()
+                .type_string(),
+            "(list 1 (response int int))"
+        );
+    }
+
+    fn sample(strategy: impl Strategy<Value = PropValue>, count: usize) -> Vec<Value> {
+        use proptest::strategy::ValueTree;
+
+        let mut runner = proptest::test_runner::TestRunner::deterministic();
+        (0..count)
+            .map(|_| strategy.new_tree(&mut runner).unwrap().current().0)
+            .collect()
+    }
+
+    #[test]
+    fn default_config_fills_sequences() {
+        let ty = TypeSignature::SequenceType(SequenceSubtype::BufferType(8u32.try_into().unwrap()));
+        for value in sample(PropValue::from_type(ty), 50) {
+            assert_eq!(value.type_string(), "(buff 8)");
+        }
+    }
+
+    #[test]
+    fn config_bounds_sequences() {
+        let ty = TypeSignature::list_of(
+            TypeSignature::SequenceType(SequenceSubtype::StringType(StringSubtype::ASCII(
+                100u32.try_into().unwrap(),
+            ))),
+            100,
+        )
+        .unwrap();
+        let config = ValueConfig {
+            max_list_len: 3,
+            max_sequence_len: 5,
+            ..ValueConfig::edge_cases()
+        };
+
+        for value in sample(PropValue::from_type_with(ty, config), 100) {
+            let list = value.expect_list().unwrap();
+            assert!(list.len() <= 3);
+            for string in list {
+                assert!(string.expect_ascii().unwrap().len() <= 5);
+            }
+        }
+    }
+
+    #[test]
+    fn edge_cases_are_generated() {
+        let ints = sample(
+            PropValue::from_type_with(TypeSignature::IntType, ValueConfig::edge_cases()),
+            200,
+        );
+        assert!(ints.contains(&Value::Int(i128::MIN)));
+        assert!(ints.contains(&Value::Int(i128::MAX)));
+
+        let ty =
+            TypeSignature::SequenceType(SequenceSubtype::BufferType(32u32.try_into().unwrap()));
+        let buffers: Vec<_> = sample(
+            PropValue::from_type_with(ty, ValueConfig::edge_cases()),
+            200,
+        )
+        .into_iter()
+        .map(|value| value.expect_buff(32).unwrap().len())
+        .collect();
+        assert!(buffers.contains(&0));
+        assert!(buffers.contains(&32));
+    }
+
+    #[test]
+    fn types_without_values() {
+        let trait_ty = TypeSignature::TraitReferenceType(clarity::vm::types::TraitIdentifier {
+            name: "trait".into(),
+            contract_identifier: QualifiedContractIdentifier::transient(),
+        });
+        assert!(!has_prop_values(&trait_ty));
+        assert!(!has_prop_values(&TypeSignature::NoType));
+        assert!(has_prop_values(
+            &TypeSignature::new_option(TypeSignature::NoType).unwrap()
+        ));
+        assert!(has_prop_values(
+            &TypeSignature::new_response(TypeSignature::IntType, TypeSignature::NoType).unwrap()
+        ));
+
+        let mut runner = proptest::test_runner::TestRunner::deterministic();
+        assert!(PropValue::from_type(trait_ty)
+            .new_tree(&mut runner)
+            .is_err());
+    }
+
+    #[test]
+    fn generated_types_have_values() {
+        let mut runner = proptest::test_runner::TestRunner::deterministic();
+        for _ in 0..500 {
+            let ty = prop_signature().new_tree(&mut runner).unwrap().current();
+            assert!(has_prop_values(&ty), "{ty}");
+            assert!(PropValue::from_type(ty).new_tree(&mut runner).is_ok());
+        }
+    }
+}
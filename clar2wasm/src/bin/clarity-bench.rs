@@ -0,0 +1,432 @@
+mod utils;
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use clap::Parser;
+use clar2wasm::compile;
+use clar2wasm::tools::TestEnvironment;
+use clarity::types::StacksEpochId;
+use clarity::vm::analysis::run_analysis;
+use clarity::vm::ast::build_ast;
+use clarity::vm::costs::{ExecutionCost, LimitedCostTracker};
+use clarity::vm::database::MemoryBackingStore;
+use clarity::vm::errors::Error;
+use clarity::vm::types::{PrincipalData, QualifiedContractIdentifier, StandardPrincipalData};
+use clarity::vm::{ClarityVersion, Value};
+use utils::*;
+
+/// clarity-bench compares the interpreted and compiled execution of a contract.
+///
+/// The contract is compiled and deployed repeatedly with both engines, then a
+/// function is called repeatedly in a deployed contract. The duration of each
+/// step is summarized per engine, followed by the mean cost of a call.
+///
+/// The costs are charged to the cost tracker of the environment, metered with
+/// the cost contracts of the chain. The compiled contract only charges the
+/// costs of its host calls, such as contract calls, so its costs are lower.
+/// No costs are reported if the cost contracts can't be deployed.
+#[derive(Parser)]
+#[command(name = "clarity-bench", version = env!("CARGO_PKG_VERSION"))]
+struct Args {
+    /// Clarity source file of the contract, named after its file
+    contract: String,
+    /// Function of the contract to call
+    #[arg(short, long)]
+    function: String,
+    /// Arguments of the function, as Clarity expressions or hex-encoded
+    /// serialized values prefixed with `0x`
+    #[arg(long = "arg")]
+    args: Vec<String>,
+    /// Sender of the calls (the deployer by default)
+    #[arg(long, value_parser = parse_principal)]
+    sender: Option<PrincipalData>,
+    /// Number of measured calls
+    #[arg(short, long, default_value_t = 100)]
+    iterations: usize,
+    /// Number of calls made before measuring
+    #[arg(long, default_value_t = 10)]
+    warmup: usize,
+    /// Number of measured compilations and deployments
+    #[arg(long, default_value_t = 10)]
+    deployments: usize,
+    /// Epoch of the stacks chain
+    #[arg(long)]
+    stacks_epoch: Option<WrappedEpochId>,
+    /// The clarity version to use
+    #[arg(long)]
+    clarity_version: Option<WrappedClarityVersion>,
+    /// Profile the measured calls and write a flamegraph of each engine to
+    /// `<prefix>-compiled.svg` and `<prefix>-interpreted.svg`
+    #[arg(long, value_name = "PREFIX")]
+    flamegraph: Option<String>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Engine {
+    Compiled,
+    Interpreted,
+}
+
+impl Engine {
+    const ALL: [Engine; 2] = [Engine::Compiled, Engine::Interpreted];
+
+    fn name(self) -> &'static str {
+        match self {
+            Engine::Compiled => "compiled",
+            Engine::Interpreted => "interpreted",
+        }
+    }
+}
+
+/// Summary statistics of a set of measurements.
+struct Summary {
+    min: Duration,
+    mean: Duration,
+    median: Duration,
+    p95: Duration,
+    max: Duration,
+    stddev: Duration,
+}
+
+impl Summary {
+    fn new(mut samples: Vec<Duration>) -> Option<Self> {
+        if samples.is_empty() {
+            return None;
+        }
+        samples.sort();
+        let n = samples.len();
+        let mean = samples.iter().map(Duration::as_secs_f64).sum::<f64>() / n as f64;
+        let variance = samples
+            .iter()
+            .map(|sample| (sample.as_secs_f64() - mean).powi(2))
+            .sum::<f64>()
+            / n as f64;
+        Some(Self {
+            min: samples[0],
+            mean: Duration::from_secs_f64(mean),
+            median: samples[n / 2],
+            p95: samples[((n * 95).div_ceil(100)).saturating_sub(1)],
+            max: samples[n - 1],
+            stddev: Duration::from_secs_f64(variance.sqrt()),
+        })
+    }
+}
+
+fn print_row(step: &str, engine: Engine, summary: &Summary) {
+    println!(
+        "{step:<8} {:<12} {:>12.2?} {:>12.2?} {:>12.2?} {:>12.2?} {:>12.2?} {:>12.2?}",
+        engine.name(),
+        summary.min,
+        summary.mean,
+        summary.median,
+        summary.p95,
+        summary.max,
+        summary.stddev
+    );
+}
+
+/// The contract under benchmark.
+struct Bench {
+    epoch: StacksEpochId,
+    version: ClarityVersion,
+    name: String,
+    source: String,
+}
+
+impl Bench {
+    fn contract_id(&self) -> QualifiedContractIdentifier {
+        QualifiedContractIdentifier::new(
+            StandardPrincipalData::transient(),
+            self.name.as_str().into(),
+        )
+    }
+
+    /// Parses and analyzes the contract, and generates its Wasm module when
+    /// compiled.
+    fn compile(&self, engine: Engine) -> Result<Duration, String> {
+        let contract_id = self.contract_id();
+        let mut datastore = MemoryBackingStore::new();
+
+        let start = Instant::now();
+        match engine {
+            Engine::Compiled => {
+                let mut result = compile(
+                    &self.source,
+                    &contract_id,
+                    LimitedCostTracker::new_free(),
+                    self.version,
+                    self.epoch,
+                    &mut datastore.as_analysis_db(),
+                    false,
+                )
+                .map_err(|e| format!("failed to compile {}: {e:?}", self.name))?;
+                result.module.emit_wasm();
+            }
+            Engine::Interpreted => {
+                // Errors of the closure must convert from analysis errors.
+                let analysis: Result<_, Error> =
+                    datastore.as_analysis_db().execute(|analysis_db| {
+                        let mut cost_tracker = LimitedCostTracker::new_free();
+                        let ast = build_ast(
+                            &contract_id,
+                            &self.source,
+                            &mut cost_tracker,
+                            self.version,
+                            self.epoch,
+                        )
+                        .map_err(Error::from)?;
+                        run_analysis(
+                            &contract_id,
+                            &ast.expressions,
+                            analysis_db,
+                            false,
+                            cost_tracker,
+                            self.epoch,
+                            self.version,
+                            true,
+                        )
+                        .map_err(|(e, _)| Error::from(e))
+                    });
+                analysis.map_err(|e| format!("failed to analyze {}: {e}", self.name))?;
+            }
+        }
+        Ok(start.elapsed())
+    }
+
+    /// Deploys the contract in a fresh environment.
+    fn deploy(&self, engine: Engine) -> Result<(TestEnvironment, Duration), String> {
+        self.deploy_with(TestEnvironment::new(self.epoch, self.version), engine)
+    }
+
+    /// Deploys the contract in a fresh environment tracking its costs, and
+    /// returns whether they are tracked.
+    fn deploy_tracking_costs(&self, engine: Engine) -> Result<(TestEnvironment, bool), String> {
+        let mut env = TestEnvironment::new(self.epoch, self.version);
+        let tracked = match env.enable_cost_tracking() {
+            Ok(()) => true,
+            Err(e) => {
+                eprintln!(
+                    "the costs of the {} calls are not reported: {e}",
+                    engine.name()
+                );
+                false
+            }
+        };
+        let (env, _) = self.deploy_with(env, engine)?;
+        Ok((env, tracked))
+    }
+
+    /// Deploys the contract in `env`.
+    fn deploy_with(
+        &self,
+        mut env: TestEnvironment,
+        engine: Engine,
+    ) -> Result<(TestEnvironment, Duration), String> {
+        let start = Instant::now();
+        match engine {
+            Engine::Compiled => env.init_contract_with_snippet(&self.name, &self.source),
+            Engine::Interpreted => env.interpret_contract_with_snippet(&self.name, &self.source),
+        }
+        .map_err(|e| format!("failed to deploy {}: {e}", self.name))?;
+        Ok((env, start.elapsed()))
+    }
+}
+
+/// Calls the function `iterations` times, and returns the last result with
+/// the duration of each call.
+fn call(
+    env: &mut TestEnvironment,
+    name: &str,
+    function: &str,
+    args: &[Value],
+    sender: &Option<PrincipalData>,
+    iterations: usize,
+) -> Result<(Option<Value>, Vec<Duration>), String> {
+    let mut result = None;
+    let mut samples = Vec::with_capacity(iterations);
+    for _ in 0..iterations {
+        let start = Instant::now();
+        let value = env
+            .call_contract_function(name, function, args, sender.clone())
+            .map_err(|e| format!("failed to call {function}: {e}"))?;
+        samples.push(start.elapsed());
+        result = Some(value);
+    }
+    Ok((result, samples))
+}
+
+/// The mean cost of `iterations` calls, from the total costs `before` and
+/// `after` them.
+fn cost_per_call(
+    before: &ExecutionCost,
+    mut after: ExecutionCost,
+    iterations: usize,
+) -> Option<ExecutionCost> {
+    after.sub(before).ok()?;
+    let n = u64::try_from(iterations.max(1)).ok()?;
+    Some(ExecutionCost {
+        runtime: after.runtime / n,
+        read_count: after.read_count / n,
+        read_length: after.read_length / n,
+        write_count: after.write_count / n,
+        write_length: after.write_length / n,
+    })
+}
+
+#[cfg(feature = "flamegraph")]
+fn profile<T>(path: &str, run: impl FnOnce() -> Result<T, String>) -> Result<T, String> {
+    let guard = pprof::ProfilerGuardBuilder::default()
+        .frequency(1000)
+        .blocklist(&["libc", "libgcc", "pthread", "vdso"])
+        .build()
+        .map_err(|e| format!("failed to start the profiler: {e}"))?;
+    let result = run()?;
+    let report = guard
+        .report()
+        .build()
+        .map_err(|e| format!("failed to build the profile: {e}"))?;
+    let file = fs::File::create(path).map_err(|e| format!("Error writing {path}: {e}"))?;
+    report
+        .flamegraph(file)
+        .map_err(|e| format!("Error writing {path}: {e}"))?;
+    Ok(result)
+}
+
+#[cfg(not(feature = "flamegraph"))]
+fn profile<T>(_path: &str, _run: impl FnOnce() -> Result<T, String>) -> Result<T, String> {
+    Err("flamegraphs require clarity-bench to be built with `--features flamegraph`".into())
+}
+
+fn main() {
+    let args = Args::parse();
+    let epoch = args.stacks_epoch.unwrap_or_default().into();
+    let version = args.clarity_version.unwrap_or_default().into();
+
+    // Require a .clar extension
+    let Some(name) = args
+        .contract
+        .strip_suffix(".clar")
+        .and_then(|path| Path::new(path).file_name())
+        .and_then(|name| name.to_str())
+    else {
+        exit_with_error(format!(
+            "Input file must have a .clar extension: {}",
+            args.contract
+        ));
+    };
+    let source = fs::read_to_string(&args.contract)
+        .unwrap_or_else(|error| exit_with_error(format!("Error reading file: {error}")));
+    let function_args: Vec<Value> = args
+        .args
+        .iter()
+        .map(|arg| parse_value(arg, epoch, version))
+        .collect::<Result<_, _>>()
+        .unwrap_or_else(|e| exit_with_error(e));
+
+    let bench = Bench {
+        epoch,
+        version,
+        name: name.to_string(),
+        source,
+    };
+
+    let mut compiles = Vec::new();
+    let mut deploys = Vec::new();
+    let mut calls = Vec::new();
+    let mut results = Vec::new();
+    let mut costs = Vec::new();
+    for engine in Engine::ALL {
+        let run = || -> Result<_, String> {
+            let compile: Vec<_> = (0..args.deployments)
+                .map(|_| bench.compile(engine))
+                .collect::<Result<_, _>>()?;
+            let deploy: Vec<_> = (0..args.deployments)
+                .map(|_| bench.deploy(engine).map(|(_, duration)| duration))
+                .collect::<Result<_, _>>()?;
+
+            let (mut env, tracked) = bench.deploy_tracking_costs(engine)?;
+            let calls = |env: &mut TestEnvironment, iterations| {
+                call(
+                    env,
+                    &bench.name,
+                    &args.function,
+                    &function_args,
+                    &args.sender,
+                    iterations,
+                )
+            };
+            calls(&mut env, args.warmup)?;
+            let before = env.total_cost();
+            let (result, call) = match &args.flamegraph {
+                Some(prefix) => profile(&format!("{prefix}-{}.svg", engine.name()), || {
+                    calls(&mut env, args.iterations)
+                })?,
+                None => calls(&mut env, args.iterations)?,
+            };
+            let cost = tracked
+                .then(|| cost_per_call(&before, env.total_cost(), args.iterations))
+                .flatten();
+            Ok((compile, deploy, call, result, cost))
+        };
+        let (compile, deploy, call, result, cost) = run().unwrap_or_else(|e| exit_with_error(e));
+        compiles.push((engine, compile));
+        deploys.push((engine, deploy));
+        calls.push((engine, call));
+        results.push((engine, result));
+        costs.push((engine, cost));
+    }
+
+    println!(
+        "{name}.{} with {} deployments and {} calls\n",
+        args.function, args.deployments, args.iterations
+    );
+    println!(
+        "{:<8} {:<12} {:>12} {:>12} {:>12} {:>12} {:>12} {:>12}",
+        "step", "engine", "min", "mean", "median", "p95", "max", "stddev"
+    );
+    // Deployments include the compilation of the contract.
+    let mut means = Vec::new();
+    for (step, samples) in [("compile", compiles), ("deploy", deploys), ("call", calls)] {
+        for (engine, samples) in samples {
+            if let Some(summary) = Summary::new(samples) {
+                print_row(step, engine, &summary);
+                if step == "call" {
+                    means.push(summary.mean);
+                }
+            }
+        }
+    }
+
+    if let [compiled, interpreted] = means[..] {
+        println!(
+            "\ncompiled calls are {:.2}x faster than interpreted calls",
+            interpreted.as_secs_f64() / compiled.as_secs_f64()
+        );
+    }
+
+    println!(
+        "\n{:<21} {:>12} {:>12} {:>12} {:>12} {:>12}",
+        "cost per call", "runtime", "read_count", "read_length", "write_count", "write_length"
+    );
+    for (engine, cost) in costs {
+        match cost {
+            Some(cost) => println!(
+                "{:<21} {:>12} {:>12} {:>12} {:>12} {:>12}",
+                engine.name(),
+                cost.runtime,
+                cost.read_count,
+                cost.read_length,
+                cost.write_count,
+                cost.write_length
+            ),
+            None => println!("{:<21} {:>12}", engine.name(), "n/a"),
+        }
+    }
+    println!();
+    for (engine, result) in results {
+        if let Some(result) = result {
+            println!("{} result: {result}", engine.name());
+        }
+    }
+}
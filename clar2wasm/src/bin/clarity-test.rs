@@ -0,0 +1,283 @@
+mod utils;
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use clap::Parser;
+use clar2wasm::coverage::SharedCoverage;
+use clar2wasm::tools::TestEnvironment;
+use clarity::types::StacksEpochId;
+use clarity::vm::costs::ExecutionCost;
+use clarity::vm::events::{SmartContractEventData, StacksTransactionEvent};
+use clarity::vm::types::PrincipalData;
+use clarity::vm::{ClarityVersion, Value};
+use utils::*;
+
+const NO_COST: ExecutionCost = ExecutionCost {
+    runtime: 0,
+    read_count: 0,
+    read_length: 0,
+    write_count: 0,
+    write_length: 0,
+};
+
+/// clarity-test runs unit tests written in Clarity against compiled contracts.
+///
+/// The contracts are deployed in order, followed by the test contract. Every
+/// public function of the test contract whose name starts with `test-` is then
+/// called in a copy of this environment, and passes if it returns an `ok`
+/// response.
+/// The duration and the cost of each test are reported, and the total cost of
+/// the tests.
+#[derive(Parser)]
+#[command(name = "clarity-test", version = env!("CARGO_PKG_VERSION"))]
+struct Args {
+    /// Clarity source files of the contracts under test, deployed in order.
+    /// Each contract is named after its file.
+    contracts: Vec<String>,
+    /// Clarity source file of the test contract
+    #[arg(short, long)]
+    test: String,
+    /// Epoch of the stacks chain
+    #[arg(long)]
+    stacks_epoch: Option<WrappedEpochId>,
+    /// The clarity version to use
+    #[arg(long)]
+    clarity_version: Option<WrappedClarityVersion>,
+    /// Sender of the test transactions (the deployer by default)
+    #[arg(long, value_parser = parse_principal)]
+    sender: Option<PrincipalData>,
+    /// Block height at which the tests run
+    #[arg(long, default_value_t = 0)]
+    block_height: u32,
+    /// Starting STX balance of an account, as `<principal>=<amount>`
+    #[arg(long = "balance", value_parser = parse_balance)]
+    balances: Vec<(PrincipalData, u128)>,
+    /// Write an LCOV report of the coverage of the contracts to this file
+    #[arg(long)]
+    coverage: Option<String>,
+}
+
+fn parse_balance(balance: &str) -> Result<(PrincipalData, u128), String> {
+    let (principal, amount) = balance
+        .split_once('=')
+        .ok_or_else(|| format!("expected `<principal>=<amount>`, got `{balance}`"))?;
+    Ok((
+        parse_principal(principal)?,
+        amount.parse().map_err(|e| format!("invalid amount: {e}"))?,
+    ))
+}
+
+/// Reads a contract, named after its file.
+fn read_contract(path: &str) -> (String, String, String) {
+    // Require a .clar extension
+    let Some(name) = path
+        .strip_suffix(".clar")
+        .and_then(|path| Path::new(path).file_name())
+        .and_then(|name| name.to_str())
+    else {
+        exit_with_error(format!("Input file must have a .clar extension: {path}"));
+    };
+
+    // Read the file.
+    match fs::read_to_string(path) {
+        Ok(source) => (name.to_string(), source, path.to_string()),
+        Err(error) => exit_with_error(format!("Error reading file: {error}")),
+    }
+}
+
+/// Everything needed to set up the environment of a test.
+struct Setup {
+    epoch: StacksEpochId,
+    version: ClarityVersion,
+    block_height: u32,
+    balances: Vec<(PrincipalData, u128)>,
+    /// Contracts as `(name, source, path)`.
+    contracts: Vec<(String, String, String)>,
+    test_contract: (String, String, String),
+    /// Counters shared by the copies of the environment run by the tests.
+    coverage: Option<SharedCoverage>,
+}
+
+impl Setup {
+    /// Creates an environment with the balances and the block height of the
+    /// setup, in which the contracts and the test contract are deployed.
+    fn environment(&self) -> Result<TestEnvironment, String> {
+        let mut env = TestEnvironment::new(self.epoch, self.version);
+        env.enable_cost_tracking()
+            .map_err(|e| format!("failed to enable cost tracking: {e}"))?;
+        if let Some(coverage) = &self.coverage {
+            env.share_coverage(coverage.clone());
+        }
+        for (principal, amount) in &self.balances {
+            env.credit_stx(principal, *amount)
+                .map_err(|e| format!("failed to credit {principal}: {e}"))?;
+        }
+        if self.block_height > 0 {
+            env.advance_chain_tip(self.block_height);
+        }
+        for (name, source, _) in self.contracts.iter().chain([&self.test_contract]) {
+            env.init_contract_with_snippet(name, source)
+                .map_err(|e| format!("failed to deploy {name}: {e}"))?;
+        }
+        Ok(env)
+    }
+
+    /// Names of the `test-` prefixed public functions of the test contract.
+    fn discover_tests(&self, env: &TestEnvironment) -> Result<Vec<String>, String> {
+        let context = env
+            .get_contract_context(&self.test_contract.0)
+            .ok_or_else(|| format!("test contract {} not found", self.test_contract.0))?;
+
+        let mut tests: Vec<_> = context
+            .functions
+            .iter()
+            .filter(|(name, function)| {
+                name.starts_with("test-") && function.is_public() && !function.is_read_only()
+            })
+            .map(|(name, _)| name.to_string())
+            .collect();
+        tests.sort();
+        Ok(tests)
+    }
+}
+
+struct TestOutcome {
+    name: String,
+    result: Result<Value, String>,
+    events: Vec<StacksTransactionEvent>,
+    duration: Duration,
+    cost: ExecutionCost,
+}
+
+impl TestOutcome {
+    fn passed(&self) -> bool {
+        matches!(&self.result, Ok(Value::Response(response)) if response.committed)
+    }
+}
+
+/// Runs a test in a copy of `env`, so that the tests don't see each other's
+/// changes and the contracts are only deployed once.
+fn run_test(
+    setup: &Setup,
+    env: &TestEnvironment,
+    name: &str,
+    sender: Option<PrincipalData>,
+) -> TestOutcome {
+    let mut env = env.clone();
+    let batches = env.get_events().len();
+    let before = env.total_cost();
+
+    let start = Instant::now();
+    let result = env
+        .call_contract_function(&setup.test_contract.0, name, &[], sender)
+        .map_err(|e| e.to_string());
+    let duration = start.elapsed();
+
+    let mut cost = env.total_cost();
+    if cost.sub(&before).is_err() {
+        cost = NO_COST;
+    }
+
+    TestOutcome {
+        name: name.to_string(),
+        result,
+        events: env.get_events()[batches..]
+            .iter()
+            .flat_map(|batch| batch.events.iter().cloned())
+            .collect(),
+        duration,
+        cost,
+    }
+}
+
+fn describe_cost(cost: &ExecutionCost) -> String {
+    format!(
+        "runtime {}, reads {} ({} bytes), writes {} ({} bytes)",
+        cost.runtime, cost.read_count, cost.read_length, cost.write_count, cost.write_length
+    )
+}
+
+fn describe_event(event: &StacksTransactionEvent) -> String {
+    match event {
+        StacksTransactionEvent::SmartContractEvent(SmartContractEventData { value, .. }) => {
+            format!("print {value}")
+        }
+        event => format!("{event:?}"),
+    }
+}
+
+fn main() {
+    let args = Args::parse();
+
+    let setup = Setup {
+        epoch: args.stacks_epoch.unwrap_or_default().into(),
+        version: args.clarity_version.unwrap_or_default().into(),
+        block_height: args.block_height,
+        balances: args.balances,
+        contracts: args
+            .contracts
+            .iter()
+            .map(|path| read_contract(path))
+            .collect(),
+        test_contract: read_contract(&args.test),
+        coverage: args.coverage.is_some().then(SharedCoverage::default),
+    };
+
+    let env = setup.environment().unwrap_or_else(|e| exit_with_error(e));
+    let tests = setup
+        .discover_tests(&env)
+        .unwrap_or_else(|e| exit_with_error(e));
+
+    let mut failures = 0;
+    let mut total_cost = NO_COST;
+    for name in &tests {
+        let outcome = run_test(&setup, &env, name, args.sender.clone());
+        let status = if outcome.passed() {
+            "PASS"
+        } else {
+            failures += 1;
+            "FAIL"
+        };
+        let result = match &outcome.result {
+            Ok(value) => value.to_string(),
+            Err(error) => format!("error: {error}"),
+        };
+        println!(
+            "{status} {} -> {result} ({:?}, {})",
+            outcome.name,
+            outcome.duration,
+            describe_cost(&outcome.cost)
+        );
+        total_cost
+            .add(&outcome.cost)
+            .unwrap_or_else(|e| exit_with_error(e.to_string()));
+        for event in &outcome.events {
+            println!("    {}", describe_event(event));
+        }
+    }
+
+    println!(
+        "\n{} tests, {} passed, {failures} failed",
+        tests.len(),
+        tests.len() - failures
+    );
+    println!("total cost: {}", describe_cost(&total_cost));
+
+    if let Some(path) = &args.coverage {
+        // Counters of all the environments are shared, any of them can report them.
+        let lcov: String = setup
+            .contracts
+            .iter()
+            .chain([&setup.test_contract])
+            .filter_map(|(name, _, source_file)| env.coverage_report(name, source_file))
+            .collect();
+        if let Err(error) = fs::write(path, lcov) {
+            exit_with_error(format!("Error writing coverage: {error}"));
+        }
+    }
+
+    if failures > 0 {
+        std::process::exit(1);
+    }
+}
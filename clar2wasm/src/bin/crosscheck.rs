@@ -1,45 +1,328 @@
 mod utils;
 use std::fs;
+use std::path::Path;
 
-use clap::Parser;
-use clar2wasm::tools::crosscheck_compare_only_with_epoch_and_version;
+use clap::{Parser, ValueEnum};
+use clar2wasm::tools::{events_divergence, TestEnvironment};
+use clarity::types::StacksEpochId;
+use clarity::vm::errors::Error;
+use clarity::vm::types::PrincipalData;
+use clarity::vm::{ClarityVersion, Value};
+use serde::{Deserialize, Serialize};
 use utils::*;
 
 /// crosscheck is a tool to compare the results of the compiled and interpreted
-/// versions of a Clarity snippet.
+/// versions of Clarity contracts.
 #[derive(Parser)]
 #[command(name = "crosscheck", version = env!("CARGO_PKG_VERSION"))]
 struct Args {
-    /// Clarity source file to compile
-    input: String,
+    /// Clarity source files to deploy, in order. Each contract is named after
+    /// its file.
+    #[arg(required = true)]
+    inputs: Vec<String>,
     /// Epoch of the stacks chain
     #[arg(long)]
     stacks_epoch: Option<WrappedEpochId>,
     /// The clarity version to use
     #[arg(long)]
     clarity_version: Option<WrappedClarityVersion>,
+    /// Check every valid pair of epoch and clarity version
+    #[arg(long, conflicts_with_all = ["stacks_epoch", "clarity_version"])]
+    all_epochs: bool,
+    /// JSON file listing the functions to call once the contracts are deployed
+    #[arg(long)]
+    calls: Option<String>,
+    /// Write a JSON report of the agreements and divergences to this file
+    /// (`-` for stdout)
+    #[arg(long)]
+    report: Option<String>,
 }
 
-fn main() {
-    let args = Args::parse();
+/// A function call, as listed in the calls file:
+///
+/// ```json
+/// [{ "contract": "counter", "function": "add", "args": ["u1"], "sender": "ST1..." }]
+/// ```
+///
+/// Arguments are hex-encoded serialized values or Clarity expressions,
+/// evaluated before the call.
+#[derive(Deserialize)]
+struct Call {
+    contract: String,
+    function: String,
+    #[serde(default)]
+    args: Vec<String>,
+    sender: Option<String>,
+}
 
-    // Require a .clar extension
-    if !args.input.ends_with(".clar") {
-        eprintln!("Input file must have a .clar extension");
-        std::process::exit(1);
+#[derive(Serialize)]
+struct Report {
+    agreements: usize,
+    divergences: usize,
+    /// Calls which could not be run, because of an invalid argument or sender.
+    input_errors: usize,
+    runs: Vec<Run>,
+}
+
+/// The steps checked for one pair of epoch and clarity version.
+#[derive(Serialize)]
+struct Run {
+    epoch: String,
+    clarity_version: String,
+    steps: Vec<Step>,
+}
+
+/// A deployment or a call, run both compiled and interpreted.
+#[derive(Serialize)]
+struct Step {
+    step: String,
+    compiled: String,
+    interpreted: String,
+    /// Description of the divergence, if any.
+    divergence: Option<String>,
+    /// Why the step could not be run, if it was not.
+    input_error: Option<String>,
+}
+
+/// The variant of an error and of the error it wraps, e.g.
+/// `Unchecked::TypeError`, without the details the compiler and the
+/// interpreter report differently.
+fn error_kind(error: &Error) -> String {
+    format!("{error:?}")
+        .split(['(', ')', ',', ' ', '{'])
+        .filter(|part| !part.is_empty())
+        .take(2)
+        .collect::<Vec<_>>()
+        .join("::")
+}
+
+fn describe<T: ToString>(result: &Result<Option<T>, Error>) -> String {
+    match result {
+        Ok(Some(value)) => value.to_string(),
+        Ok(None) => "<no value>".to_string(),
+        Err(error) => format!("error: {error}"),
+    }
+}
+
+/// Runs a step in both environments, and checks that the results, the events
+/// emitted by the step and the resulting states are the same.
+fn check_step<F, G>(
+    step: String,
+    envs: &mut (TestEnvironment, TestEnvironment),
+    run_compiled: F,
+    run_interpreted: G,
+    failures_agree: bool,
+) -> Step
+where
+    F: FnOnce(&mut TestEnvironment) -> Result<Option<Value>, Error>,
+    G: FnOnce(&mut TestEnvironment) -> Result<Option<Value>, Error>,
+{
+    let (compiled_env, interpreted_env) = envs;
+    let compiled_batches = compiled_env.get_events().len();
+    let interpreted_batches = interpreted_env.get_events().len();
+
+    let compiled = run_compiled(compiled_env);
+    let interpreted = run_interpreted(interpreted_env);
+
+    let divergence = match (&compiled, &interpreted) {
+        (Err(compiled), Err(interpreted)) if failures_agree => {
+            let (compiled, interpreted) = (error_kind(compiled), error_kind(interpreted));
+            (compiled != interpreted)
+                .then(|| format!("errors mismatch: {compiled} compiled, {interpreted} interpreted"))
+        }
+        _ if compiled != interpreted => Some("results mismatch".to_string()),
+        _ => events_divergence(
+            &interpreted_env.get_events()[interpreted_batches..],
+            &compiled_env.get_events()[compiled_batches..],
+        )
+        .or_else(|| {
+            interpreted_env
+                .dump_state()
+                .diff(&compiled_env.dump_state())
+                .map(|diff| format!("states mismatch (- interpreted, + compiled)\n{diff}"))
+        }),
+    };
+
+    Step {
+        step,
+        compiled: describe(&compiled),
+        interpreted: describe(&interpreted),
+        divergence,
+        input_error: None,
+    }
+}
+
+/// Evaluates the arguments and the sender of a call.
+fn parse_call(
+    call: &Call,
+    epoch: StacksEpochId,
+    version: ClarityVersion,
+) -> Result<(Vec<Value>, Option<PrincipalData>), String> {
+    let args = call
+        .args
+        .iter()
+        .map(|arg| parse_value(arg, epoch, version))
+        .collect::<Result<_, _>>()?;
+    let sender = call
+        .sender
+        .as_deref()
+        .map(parse_principal)
+        .transpose()
+        .map_err(|error| format!("invalid sender: {error}"))?;
+    Ok((args, sender))
+}
+
+fn check_run(
+    epoch: StacksEpochId,
+    version: ClarityVersion,
+    contracts: &[(String, String)],
+    calls: &[Call],
+) -> Run {
+    let env = TestEnvironment::new(epoch, version);
+    let mut envs = (env.clone(), env);
+    let mut steps = Vec::new();
+
+    for (name, source) in contracts {
+        // A contract which cannot be deployed in this epoch is not a divergence
+        // if both fail with the same kind of error, even if the messages of the
+        // compiler and the interpreter differ.
+        steps.push(check_step(
+            format!("deploy {name}"),
+            &mut envs,
+            |env| env.init_contract_with_snippet(name, source),
+            |env| env.interpret_contract_with_snippet(name, source),
+            true,
+        ));
     }
 
-    // Read the file.
-    let source = match fs::read_to_string(args.input.as_str()) {
-        Ok(source) => source,
-        Err(error) => {
-            eprintln!("Error reading file: {error}");
-            std::process::exit(1);
+    for call in calls {
+        let step = format!("call {}.{}", call.contract, call.function);
+        match parse_call(call, epoch, version) {
+            Ok((args, sender)) => {
+                let run = |env: &mut TestEnvironment| {
+                    env.call_contract_function(
+                        &call.contract,
+                        &call.function,
+                        &args,
+                        sender.clone(),
+                    )
+                    .map(Some)
+                };
+                steps.push(check_step(step, &mut envs, run, run, false));
+            }
+            Err(error) => steps.push(Step {
+                step,
+                compiled: String::new(),
+                interpreted: String::new(),
+                divergence: None,
+                input_error: Some(error),
+            }),
         }
+    }
+
+    Run {
+        epoch: epoch.to_string(),
+        clarity_version: version.to_string(),
+        steps,
+    }
+}
+
+fn main() {
+    let args = Args::parse();
+
+    let contracts: Vec<_> = args
+        .inputs
+        .iter()
+        .map(|input| {
+            // Require a .clar extension
+            let Some(name) = input
+                .strip_suffix(".clar")
+                .and_then(|path| Path::new(path).file_name())
+                .and_then(|name| name.to_str())
+            else {
+                exit_with_error(format!("Input file must have a .clar extension: {input}"));
+            };
+
+            // Read the file.
+            match fs::read_to_string(input) {
+                Ok(source) => (name.to_string(), source),
+                Err(error) => exit_with_error(format!("Error reading file: {error}")),
+            }
+        })
+        .collect();
+
+    let calls: Vec<Call> = match &args.calls {
+        Some(path) => fs::read_to_string(path)
+            .map_err(|error| error.to_string())
+            .and_then(|calls| serde_json::from_str(&calls).map_err(|error| error.to_string()))
+            .unwrap_or_else(|error| exit_with_error(format!("Error reading calls: {error}"))),
+        None => Vec::new(),
     };
 
-    let epoch = args.stacks_epoch.unwrap_or_default().into();
-    let version = args.clarity_version.unwrap_or_default().into();
+    let matrix: Vec<(StacksEpochId, ClarityVersion)> = if args.all_epochs {
+        WrappedEpochId::value_variants()
+            .iter()
+            .flat_map(|epoch| {
+                WrappedClarityVersion::value_variants()
+                    .iter()
+                    .map(|version| (epoch.clone().into(), version.clone().into()))
+            })
+            .filter(|(epoch, version)| TestEnvironment::epoch_and_clarity_match(*epoch, *version))
+            .collect()
+    } else {
+        vec![(
+            args.stacks_epoch.unwrap_or_default().into(),
+            args.clarity_version.unwrap_or_default().into(),
+        )]
+    };
+
+    let runs: Vec<_> = matrix
+        .into_iter()
+        .map(|(epoch, version)| check_run(epoch, version, &contracts, &calls))
+        .collect();
+
+    let steps = || runs.iter().flat_map(|run| &run.steps);
+    let divergences = steps().filter(|step| step.divergence.is_some()).count();
+    let input_errors = steps().filter(|step| step.input_error.is_some()).count();
+    let report = Report {
+        agreements: steps().count() - divergences - input_errors,
+        divergences,
+        input_errors,
+        runs,
+    };
+
+    for run in &report.runs {
+        for step in &run.steps {
+            if let Some(divergence) = &step.divergence {
+                eprintln!(
+                    "[{} / {}] {}: {divergence}\ncompiled: {}\ninterpreted: {}",
+                    run.epoch, run.clarity_version, step.step, step.compiled, step.interpreted
+                );
+            }
+            if let Some(input_error) = &step.input_error {
+                eprintln!(
+                    "[{} / {}] {}: not run, {input_error}",
+                    run.epoch, run.clarity_version, step.step
+                );
+            }
+        }
+    }
+    eprintln!(
+        "{} agreements, {} divergences, {} input errors",
+        report.agreements, report.divergences, report.input_errors
+    );
+
+    if let Some(path) = &args.report {
+        let json = serde_json::to_string_pretty(&report)
+            .unwrap_or_else(|error| exit_with_error(format!("Error writing report: {error}")));
+        if path == "-" {
+            println!("{json}");
+        } else if let Err(error) = fs::write(path, json) {
+            exit_with_error(format!("Error writing report: {error}"));
+        }
+    }
 
-    crosscheck_compare_only_with_epoch_and_version(&source, epoch, version);
+    if report.divergences > 0 || report.input_errors > 0 {
+        std::process::exit(1);
+    }
 }
@@ -2,7 +2,7 @@ mod utils;
 use std::fs;
 
 use clap::Parser;
-use clar2wasm::CompileError;
+use clar2wasm::{CompileError, GeneratorOptions};
 use clarity::vm::costs::LimitedCostTracker;
 use clarity::vm::database::MemoryBackingStore;
 use clarity::vm::types::QualifiedContractIdentifier;
@@ -26,6 +26,13 @@ struct Args {
     /// Whether to emit cost-tracking code.
     #[arg(long, default_value_t = false)]
     cost_tracking: bool,
+    /// Don't use the bulk-memory instructions, for engines without support
+    /// for the bulk-memory proposal.
+    #[arg(long, default_value_t = false)]
+    no_bulk_memory: bool,
+    /// Grow the memory on demand instead of sizing it at compile time.
+    #[arg(long, default_value_t = false)]
+    dynamic_memory: bool,
 }
 
 fn main() {
@@ -56,26 +63,23 @@ fn main() {
     let cost_track = LimitedCostTracker::new_free();
 
     // Pass the source code to the compiler.
-    let result = clar2wasm::compile(
+    let result = clar2wasm::compile_with_options(
         &source,
         &contract_id,
         cost_track,
         clarity_version,
         epoch,
         &mut datastore.as_analysis_db(),
-        args.cost_tracking,
+        GeneratorOptions {
+            emit_cost_code: args.cost_tracking,
+            bulk_memory: !args.no_bulk_memory,
+            dynamic_memory: args.dynamic_memory,
+            ..GeneratorOptions::default()
+        },
     )
-    .unwrap_or_else(|err| match err {
-        CompileError::Generic {
-            diagnostics,
-            ast: _,
-            cost_tracker: _,
-        } => {
-            for diagnostic in diagnostics.iter() {
-                eprintln!("{diagnostic}");
-            }
-            std::process::exit(1);
-        }
+    .unwrap_or_else(|err: CompileError| {
+        eprint!("{}", err.render(&source));
+        std::process::exit(1);
     });
 
     let mut module = result.module;
@@ -0,0 +1,430 @@
+mod utils;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use clap::Parser;
+use clar2wasm::tools::{events_divergence, Network, TestEnvironment};
+use clarity::vm::errors::Error;
+use clarity::vm::events::{
+    FTEventType, NFTEventType, STXEventType, SmartContractEventData, StacksTransactionEvent,
+};
+use clarity::vm::types::{AssetIdentifier, PrincipalData, QualifiedContractIdentifier};
+use clarity::vm::Value;
+use serde::Deserialize;
+use serde_json::json;
+use stacks_common::util::hash::to_hex;
+use utils::*;
+
+/// replay runs the transactions of a fixture through both the compiled and the
+/// interpreted versions of the contracts, and reports the first divergence
+/// between them, or with the results and events expected by the fixture.
+#[derive(Parser)]
+#[command(name = "replay", version = env!("CARGO_PKG_VERSION"))]
+struct Args {
+    /// JSON fixture listing the transactions to replay
+    fixture: String,
+    /// Epoch of the stacks chain
+    #[arg(long)]
+    stacks_epoch: Option<WrappedEpochId>,
+    /// The clarity version to use
+    #[arg(long)]
+    clarity_version: Option<WrappedClarityVersion>,
+    /// Replay on testnet instead of mainnet
+    #[arg(long)]
+    testnet: bool,
+}
+
+/// A fixture, as exported from a node:
+///
+/// ```json
+/// {
+///   "balances": { "SP...": "1000000" },
+///   "transactions": [
+///     { "type": "deploy", "contract_id": "SP....token", "source": "token.clar", "block_height": 10 },
+///     {
+///       "type": "call", "contract_id": "SP....token", "function": "transfer",
+///       "args": ["0x0100000000000000000000000000000064", "'SP..."], "sender": "SP...",
+///       "block_height": 12,
+///       "expected": { "result": "(ok true)", "events": [{ "type": "ft_transfer_event", ... }] }
+///     }
+///   ]
+/// }
+/// ```
+///
+/// Sources are relative to the fixture. Arguments and expected results are
+/// either hex-encoded serialized values, or Clarity expressions. Expected
+/// events use the format of the node event observer, and only the fields known
+/// to both sides are compared.
+#[derive(Deserialize)]
+struct Fixture {
+    /// Starting balances of the accounts, in microSTX.
+    #[serde(default)]
+    balances: BTreeMap<String, String>,
+    transactions: Vec<Transaction>,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+enum Transaction {
+    Deploy {
+        contract_id: String,
+        source: String,
+        block_height: Option<u32>,
+        expected: Option<Expected>,
+    },
+    Call {
+        contract_id: String,
+        function: String,
+        #[serde(default)]
+        args: Vec<String>,
+        sender: String,
+        block_height: Option<u32>,
+        expected: Option<Expected>,
+    },
+}
+
+#[derive(Deserialize)]
+struct Expected {
+    result: Option<String>,
+    events: Option<Vec<serde_json::Value>>,
+}
+
+fn serialize(value: &Value) -> String {
+    value.serialize_to_vec().map_or_else(
+        |_| "<unserializable>".to_string(),
+        |bytes| format!("0x{}", to_hex(&bytes)),
+    )
+}
+
+fn describe(result: &Result<Option<Value>, Error>) -> String {
+    match result {
+        Ok(Some(value)) => value.to_string(),
+        Ok(None) => "<no value>".to_string(),
+        Err(error) => format!("error: {error}"),
+    }
+}
+
+fn asset(asset: &AssetIdentifier) -> String {
+    format!("{}::{}", asset.contract_identifier, asset.asset_name)
+}
+
+/// Converts an event to the format of the node event observer.
+fn event_json(event: &StacksTransactionEvent) -> serde_json::Value {
+    match event {
+        StacksTransactionEvent::SmartContractEvent(SmartContractEventData { key, value }) => {
+            json!({
+                "type": "contract_event",
+                "contract_event": {
+                    "contract_identifier": key.0.to_string(),
+                    "topic": key.1,
+                    "raw_value": serialize(value),
+                }
+            })
+        }
+        StacksTransactionEvent::STXEvent(STXEventType::STXTransferEvent(data)) => json!({
+            "type": "stx_transfer_event",
+            "stx_transfer_event": {
+                "sender": data.sender.to_string(),
+                "recipient": data.recipient.to_string(),
+                "amount": data.amount.to_string(),
+                "memo": to_hex(&data.memo.data),
+            }
+        }),
+        StacksTransactionEvent::STXEvent(STXEventType::STXMintEvent(data)) => json!({
+            "type": "stx_mint_event",
+            "stx_mint_event": {
+                "recipient": data.recipient.to_string(),
+                "amount": data.amount.to_string(),
+            }
+        }),
+        StacksTransactionEvent::STXEvent(STXEventType::STXBurnEvent(data)) => json!({
+            "type": "stx_burn_event",
+            "stx_burn_event": {
+                "sender": data.sender.to_string(),
+                "amount": data.amount.to_string(),
+            }
+        }),
+        StacksTransactionEvent::STXEvent(STXEventType::STXLockEvent(data)) => json!({
+            "type": "stx_lock_event",
+            "stx_lock_event": {
+                "locked_amount": data.locked_amount.to_string(),
+                "unlock_height": data.unlock_height.to_string(),
+                "locked_address": data.locked_address.to_string(),
+                "contract_identifier": data.contract_identifier.to_string(),
+            }
+        }),
+        StacksTransactionEvent::FTEvent(FTEventType::FTTransferEvent(data)) => json!({
+            "type": "ft_transfer_event",
+            "ft_transfer_event": {
+                "asset_identifier": asset(&data.asset_identifier),
+                "sender": data.sender.to_string(),
+                "recipient": data.recipient.to_string(),
+                "amount": data.amount.to_string(),
+            }
+        }),
+        StacksTransactionEvent::FTEvent(FTEventType::FTMintEvent(data)) => json!({
+            "type": "ft_mint_event",
+            "ft_mint_event": {
+                "asset_identifier": asset(&data.asset_identifier),
+                "recipient": data.recipient.to_string(),
+                "amount": data.amount.to_string(),
+            }
+        }),
+        StacksTransactionEvent::FTEvent(FTEventType::FTBurnEvent(data)) => json!({
+            "type": "ft_burn_event",
+            "ft_burn_event": {
+                "asset_identifier": asset(&data.asset_identifier),
+                "sender": data.sender.to_string(),
+                "amount": data.amount.to_string(),
+            }
+        }),
+        StacksTransactionEvent::NFTEvent(NFTEventType::NFTTransferEvent(data)) => json!({
+            "type": "nft_transfer_event",
+            "nft_transfer_event": {
+                "asset_identifier": asset(&data.asset_identifier),
+                "sender": data.sender.to_string(),
+                "recipient": data.recipient.to_string(),
+                "raw_value": serialize(&data.value),
+            }
+        }),
+        StacksTransactionEvent::NFTEvent(NFTEventType::NFTMintEvent(data)) => json!({
+            "type": "nft_mint_event",
+            "nft_mint_event": {
+                "asset_identifier": asset(&data.asset_identifier),
+                "recipient": data.recipient.to_string(),
+                "raw_value": serialize(&data.value),
+            }
+        }),
+        StacksTransactionEvent::NFTEvent(NFTEventType::NFTBurnEvent(data)) => json!({
+            "type": "nft_burn_event",
+            "nft_burn_event": {
+                "asset_identifier": asset(&data.asset_identifier),
+                "sender": data.sender.to_string(),
+                "raw_value": serialize(&data.value),
+            }
+        }),
+    }
+}
+
+/// Compares the fields found in both `expected` and `actual`, and returns the
+/// path of the first one which differs.
+fn json_divergence(expected: &serde_json::Value, actual: &serde_json::Value) -> Option<String> {
+    match (expected, actual) {
+        (serde_json::Value::Object(expected), serde_json::Value::Object(actual)) => {
+            expected.iter().find_map(|(key, expected)| {
+                let actual = actual.get(key)?;
+                json_divergence(expected, actual).map(|path| format!(".{key}{path}"))
+            })
+        }
+        _ if expected == actual => None,
+        _ => Some(format!(": expected {expected}, got {actual}")),
+    }
+}
+
+/// Checks a result and its events against the expectations of the fixture.
+fn expectation_divergence(
+    expected: &Expected,
+    result: &Result<Option<Value>, Error>,
+    events: &[StacksTransactionEvent],
+) -> Option<String> {
+    if let Some(expected_result) = &expected.result {
+        let matches = match result {
+            Ok(Some(value)) if expected_result.starts_with("0x") => {
+                serialize(value) == *expected_result
+            }
+            Ok(Some(value)) => value.to_string() == *expected_result,
+            _ => false,
+        };
+        if !matches {
+            return Some(format!(
+                "expected result {expected_result}, got {}",
+                describe(result)
+            ));
+        }
+    }
+
+    let expected_events = expected.events.as_ref()?;
+    if expected_events.len() != events.len() {
+        return Some(format!(
+            "expected {} events, got {}",
+            expected_events.len(),
+            events.len()
+        ));
+    }
+    expected_events
+        .iter()
+        .zip(events)
+        .enumerate()
+        .find_map(|(i, (expected, event))| {
+            json_divergence(expected, &event_json(event))
+                .map(|path| format!("event #{i} mismatch at {path}"))
+        })
+}
+
+/// The events emitted since the first `batches` batches.
+fn new_events(env: &TestEnvironment, batches: usize) -> Vec<StacksTransactionEvent> {
+    env.get_events()[batches..]
+        .iter()
+        .flat_map(|batch| batch.events.iter().cloned())
+        .collect()
+}
+
+/// Replays a transaction in both environments, and describes the first
+/// divergence, if any.
+fn replay(
+    transaction: &Transaction,
+    fixture_dir: &Path,
+    envs: &mut (TestEnvironment, TestEnvironment),
+) -> Result<String, String> {
+    let (compiled_env, interpreted_env) = envs;
+    let (epoch, version) = (compiled_env.epoch, compiled_env.version);
+
+    let (block_height, expected) = match transaction {
+        Transaction::Deploy {
+            block_height,
+            expected,
+            ..
+        }
+        | Transaction::Call {
+            block_height,
+            expected,
+            ..
+        } => (*block_height, expected),
+    };
+    if let Some(block_height) = block_height {
+        let current = compiled_env.block_height();
+        if block_height < current {
+            return Err(format!(
+                "block height {block_height} is lower than the current height {current}"
+            ));
+        }
+        compiled_env.advance_chain_tip(block_height - current);
+        interpreted_env.advance_chain_tip(block_height - current);
+    }
+
+    let compiled_batches = compiled_env.get_events().len();
+    let interpreted_batches = interpreted_env.get_events().len();
+
+    let (step, compiled, interpreted) = match transaction {
+        Transaction::Deploy {
+            contract_id,
+            source,
+            ..
+        } => {
+            let id = QualifiedContractIdentifier::parse(contract_id)
+                .map_err(|error| format!("invalid contract `{contract_id}`: {error}"))?;
+            let source = fs::read_to_string(fixture_dir.join(source))
+                .map_err(|error| format!("Error reading {source}: {error}"))?;
+            (
+                format!("deploy {contract_id}"),
+                compiled_env.init_contract_with_id(&id, &source),
+                interpreted_env.interpret_contract_with_id(&id, &source),
+            )
+        }
+        Transaction::Call {
+            contract_id,
+            function,
+            args,
+            sender,
+            ..
+        } => {
+            let args = args
+                .iter()
+                .map(|arg| parse_value(arg, epoch, version))
+                .collect::<Result<Vec<_>, _>>()?;
+            let sender = PrincipalData::parse(sender)
+                .map_err(|error| format!("invalid sender `{sender}`: {error}"))?;
+            let call = |env: &mut TestEnvironment| {
+                env.call_contract_function(contract_id, function, &args, Some(sender.clone()))
+                    .map(Some)
+            };
+            (
+                format!("call {contract_id}.{function} from {sender}"),
+                call(compiled_env),
+                call(interpreted_env),
+            )
+        }
+    };
+
+    let compiled_events = new_events(compiled_env, compiled_batches);
+    let interpreted_events = new_events(interpreted_env, interpreted_batches);
+
+    let divergence = if compiled != interpreted {
+        Some("results mismatch".to_string())
+    } else if let Some(divergence) = expected
+        .as_ref()
+        .and_then(|expected| expectation_divergence(expected, &compiled, &compiled_events))
+    {
+        Some(divergence)
+    } else {
+        events_divergence(
+            &interpreted_env.get_events()[interpreted_batches..],
+            &compiled_env.get_events()[compiled_batches..],
+        )
+        .or_else(|| {
+            interpreted_env
+                .dump_state()
+                .diff(&compiled_env.dump_state())
+                .map(|diff| format!("states mismatch (- interpreted, + compiled)\n{diff}"))
+        })
+    };
+
+    match divergence {
+        None => Ok(step),
+        Some(divergence) => Err(format!(
+            "{step}: {divergence}\ncompiled: {}\ninterpreted: {}",
+            describe(&compiled),
+            describe(&interpreted)
+        )),
+    }
+}
+
+fn main() {
+    let args = Args::parse();
+
+    let fixture: Fixture = fs::read_to_string(&args.fixture)
+        .map_err(|error| error.to_string())
+        .and_then(|fixture| serde_json::from_str(&fixture).map_err(|error| error.to_string()))
+        .unwrap_or_else(|error| exit_with_error(format!("Error reading fixture: {error}")));
+    let fixture_dir = Path::new(&args.fixture)
+        .parent()
+        .unwrap_or_else(|| Path::new("."));
+
+    let network = if args.testnet {
+        Network::Testnet
+    } else {
+        Network::Mainnet
+    };
+    let mut env = TestEnvironment::new_with_network(
+        args.stacks_epoch.unwrap_or_default().into(),
+        args.clarity_version.unwrap_or_default().into(),
+        network,
+    );
+    for (principal, amount) in &fixture.balances {
+        let credited = PrincipalData::parse(principal)
+            .map_err(|error| error.to_string())
+            .and_then(|principal| {
+                let amount = amount
+                    .parse()
+                    .map_err(|error| format!("invalid amount: {error}"))?;
+                env.credit_stx(&principal, amount)
+                    .map_err(|error| error.to_string())
+            });
+        if let Err(error) = credited {
+            exit_with_error(format!("Error crediting {principal}: {error}"));
+        }
+    }
+
+    let mut envs = (env.clone(), env);
+    for (i, transaction) in fixture.transactions.iter().enumerate() {
+        match replay(transaction, fixture_dir, &mut envs) {
+            Ok(step) => println!("#{i} {step}"),
+            Err(divergence) => exit_with_error(format!("#{i} {divergence}")),
+        }
+    }
+    println!(
+        "{} transactions replayed without divergence",
+        fixture.transactions.len()
+    );
+}
@@ -1,7 +1,9 @@
 use clap::builder::PossibleValue;
 use clap::ValueEnum;
+use clar2wasm::tools::TestEnvironment;
 use clarity::types::StacksEpochId;
-use clarity::vm::ClarityVersion;
+use clarity::vm::types::PrincipalData;
+use clarity::vm::{ClarityVersion, Value};
 
 #[derive(Clone)]
 pub struct WrappedEpochId(StacksEpochId);
@@ -84,3 +86,34 @@ impl ValueEnum for WrappedClarityVersion {
         }
     }
 }
+
+// Not every binary uses every helper.
+
+#[allow(dead_code)]
+pub fn exit_with_error(message: String) -> ! {
+    eprintln!("{message}");
+    std::process::exit(1);
+}
+
+#[allow(dead_code)]
+pub fn parse_principal(principal: &str) -> Result<PrincipalData, String> {
+    PrincipalData::parse(principal).map_err(|e| e.to_string())
+}
+
+/// Parses a hex-encoded serialized value, or evaluates a Clarity expression.
+#[allow(dead_code)]
+pub fn parse_value(
+    value: &str,
+    epoch: StacksEpochId,
+    version: ClarityVersion,
+) -> Result<Value, String> {
+    match value.strip_prefix("0x") {
+        Some(hex) => Value::try_deserialize_hex_untyped(hex)
+            .map_err(|error| format!("invalid value `{value}`: {error}")),
+        None => match TestEnvironment::new(epoch, version).interpret(value) {
+            Ok(Some(value)) => Ok(value),
+            Ok(None) => Err(format!("`{value}` has no value")),
+            Err(error) => Err(format!("invalid value `{value}`: {error}")),
+        },
+    }
+}
@@ -0,0 +1,262 @@
+//! Lowering of the bulk-memory instructions, for engines without support for
+//! the bulk-memory proposal.
+//!
+//! The generator and the standard library always copy and fill memory with
+//! `memory.copy` and `memory.fill`. When bulk memory is disabled, those
+//! instructions are replaced, once the module is generated, by calls to
+//! functions copying and filling memory byte by byte. The replacements have
+//! the same parameters as the instructions, and handle overlapping copies the
+//! same way; an out-of-bounds access still traps, but may do so after writing
+//! part of the destination.
+
+use walrus::ir::{
+    dfs_pre_order_mut, BinaryOp, Call, ExtendedLoad, Instr, InstrLocId, LoadKind, MemArg,
+    StoreKind, UnaryOp, VisitorMut,
+};
+use walrus::{FunctionBuilder, FunctionId, InstrSeqBuilder, LocalId, MemoryId, Module, ValType};
+
+use crate::wasm_generator::{GeneratorError, WasmGenerator};
+
+const BYTE: MemArg = MemArg {
+    align: 1,
+    offset: 0,
+};
+
+impl WasmGenerator {
+    /// Generates a module without the bulk-memory instructions.
+    pub fn without_bulk_memory(mut self) -> Self {
+        self.bulk_memory = false;
+        self
+    }
+
+    /// Replaces the bulk-memory instructions of all the functions of the
+    /// module.
+    pub(crate) fn lower_bulk_memory(&mut self) -> Result<(), GeneratorError> {
+        let memory = self.get_memory()?;
+        let mut lowering = Lowering {
+            copy: add_memory_copy(&mut self.module, memory),
+            fill: add_memory_fill(&mut self.module, memory),
+        };
+        for (_, function) in self.module.funcs.iter_local_mut() {
+            let entry = function.entry_block();
+            dfs_pre_order_mut(&mut lowering, function, entry);
+        }
+        Ok(())
+    }
+}
+
+struct Lowering {
+    copy: FunctionId,
+    fill: FunctionId,
+}
+
+impl VisitorMut for Lowering {
+    fn visit_instr_mut(&mut self, instr: &mut Instr, _: &mut InstrLocId) {
+        let func = match instr {
+            Instr::MemoryCopy(_) => self.copy,
+            Instr::MemoryFill(_) => self.fill,
+            _ => return,
+        };
+        *instr = Instr::Call(Call { func });
+    }
+}
+
+/// Pushes `base + i` to the stack.
+fn add_offset(builder: &mut InstrSeqBuilder, base: LocalId, i: LocalId) {
+    builder.local_get(base).local_get(i).binop(BinaryOp::I32Add);
+}
+
+/// Copies the byte at `$src + $i` to `$dst + $i`.
+fn copy_byte(
+    builder: &mut InstrSeqBuilder,
+    memory: MemoryId,
+    dst: LocalId,
+    src: LocalId,
+    i: LocalId,
+) {
+    add_offset(builder, dst, i);
+    add_offset(builder, src, i);
+    builder
+        .load(
+            memory,
+            LoadKind::I32_8 {
+                kind: ExtendedLoad::ZeroExtend,
+            },
+            BYTE,
+        )
+        .store(memory, StoreKind::I32_8 { atomic: false }, BYTE);
+}
+
+/// Adds the function `(func (param $dst i32) (param $src i32) (param $len i32))`
+/// copying `$len` bytes from `$src` to `$dst`. Overlapping regions are copied
+/// backwards when `$dst` is after `$src`.
+fn add_memory_copy(module: &mut Module, memory: MemoryId) -> FunctionId {
+    let dst = module.locals.add(ValType::I32);
+    let src = module.locals.add(ValType::I32);
+    let len = module.locals.add(ValType::I32);
+    let i = module.locals.add(ValType::I32);
+
+    let mut function = FunctionBuilder::new(&mut module.types, &[ValType::I32; 3], &[]);
+    function.name("stdlib.memory-copy".to_owned());
+
+    function
+        .func_body()
+        .local_get(dst)
+        .local_get(src)
+        .binop(BinaryOp::I32LeU)
+        .if_else(
+            None,
+            |forward| {
+                forward.block(None, |done| {
+                    let done_id = done.id();
+                    done.loop_(None, |loop_| {
+                        let loop_id = loop_.id();
+                        loop_
+                            .local_get(i)
+                            .local_get(len)
+                            .binop(BinaryOp::I32GeU)
+                            .br_if(done_id);
+                        copy_byte(loop_, memory, dst, src, i);
+                        loop_
+                            .local_get(i)
+                            .i32_const(1)
+                            .binop(BinaryOp::I32Add)
+                            .local_set(i)
+                            .br(loop_id);
+                    });
+                });
+            },
+            |backward| {
+                backward.local_get(len).local_set(i);
+                backward.block(None, |done| {
+                    let done_id = done.id();
+                    done.loop_(None, |loop_| {
+                        let loop_id = loop_.id();
+                        loop_
+                            .local_get(i)
+                            .unop(UnaryOp::I32Eqz)
+                            .br_if(done_id)
+                            .local_get(i)
+                            .i32_const(1)
+                            .binop(BinaryOp::I32Sub)
+                            .local_set(i);
+                        copy_byte(loop_, memory, dst, src, i);
+                        loop_.br(loop_id);
+                    });
+                });
+            },
+        );
+
+    function.finish(vec![dst, src, len], &mut module.funcs)
+}
+
+/// Adds the function `(func (param $dst i32) (param $val i32) (param $len i32))`
+/// setting `$len` bytes at `$dst` to `$val`.
+fn add_memory_fill(module: &mut Module, memory: MemoryId) -> FunctionId {
+    let dst = module.locals.add(ValType::I32);
+    let val = module.locals.add(ValType::I32);
+    let len = module.locals.add(ValType::I32);
+    let i = module.locals.add(ValType::I32);
+
+    let mut function = FunctionBuilder::new(&mut module.types, &[ValType::I32; 3], &[]);
+    function.name("stdlib.memory-fill".to_owned());
+
+    function.func_body().block(None, |done| {
+        let done_id = done.id();
+        done.loop_(None, |loop_| {
+            let loop_id = loop_.id();
+            loop_
+                .local_get(i)
+                .local_get(len)
+                .binop(BinaryOp::I32GeU)
+                .br_if(done_id);
+            add_offset(loop_, dst, i);
+            loop_
+                .local_get(val)
+                .store(memory, StoreKind::I32_8 { atomic: false }, BYTE)
+                .local_get(i)
+                .i32_const(1)
+                .binop(BinaryOp::I32Add)
+                .local_set(i)
+                .br(loop_id);
+        });
+    });
+
+    function.finish(vec![dst, val, len], &mut module.funcs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A module exporting its memory and a `run` function, which fills and
+    /// copies overlapping regions.
+    fn module() -> Module {
+        let mut module = Module::default();
+        let memory = module.memories.add_local(false, 1, None);
+        module.exports.add("memory", memory);
+
+        let mut run = FunctionBuilder::new(&mut module.types, &[], &[]);
+        run.func_body()
+            .i32_const(0)
+            .i32_const(1)
+            .i32_const(8)
+            .memory_fill(memory)
+            .i32_const(8)
+            .i32_const(2)
+            .i32_const(8)
+            .memory_fill(memory)
+            .i32_const(4)
+            .i32_const(0)
+            .i32_const(8)
+            .memory_copy(memory, memory)
+            .i32_const(0)
+            .i32_const(6)
+            .i32_const(8)
+            .memory_copy(memory, memory);
+        let run = run.finish(vec![], &mut module.funcs);
+        module.exports.add("run", run);
+
+        module
+    }
+
+    fn run(module: &mut Module, config: &wasmtime::Config) -> Vec<u8> {
+        let engine = wasmtime::Engine::new(config).unwrap();
+        let module = wasmtime::Module::new(&engine, module.emit_wasm()).unwrap();
+        let mut store = wasmtime::Store::new(&engine, ());
+        let instance = wasmtime::Instance::new(&mut store, &module, &[]).unwrap();
+        instance
+            .get_typed_func::<(), ()>(&mut store, "run")
+            .unwrap()
+            .call(&mut store, ())
+            .unwrap();
+        instance
+            .get_memory(&mut store, "memory")
+            .unwrap()
+            .data(&store)[..20]
+            .to_vec()
+    }
+
+    fn expected() -> Vec<u8> {
+        let mut memory = vec![0u8; 20];
+        memory[0..8].fill(1);
+        memory[8..16].fill(2);
+        memory.copy_within(0..8, 4);
+        memory.copy_within(6..14, 0);
+        memory
+    }
+
+    #[test]
+    fn lowering_preserves_copies_and_fills() {
+        let mut module = module();
+        assert_eq!(run(&mut module, &wasmtime::Config::new()), expected());
+
+        let mut generator = WasmGenerator::empty();
+        generator.module = module;
+        generator.lower_bulk_memory().unwrap();
+
+        let mut config = wasmtime::Config::new();
+        config.wasm_reference_types(false).wasm_bulk_memory(false);
+        assert_eq!(run(&mut generator.module, &config), expected());
+    }
+}
@@ -0,0 +1,93 @@
+//! A bounded cache of the data derived from a key, like the layout of a type
+//! or a decoded type table.
+//!
+//! Entries are identified by a hash of the content of their key, so that a
+//! lookup doesn't compare or copy the key. When the cache is full, inserting
+//! an entry evicts the oldest one.
+
+use std::collections::{HashMap, VecDeque};
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+#[derive(Debug)]
+pub(crate) struct ContentCache<V> {
+    capacity: usize,
+    entries: HashMap<u64, V>,
+    /// The hashes of the entries, oldest first.
+    order: VecDeque<u64>,
+}
+
+impl<V: Clone> ContentCache<V> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: HashMap::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    /// Returns the data cached for `key`, or caches the data returned by
+    /// `derive`. `derive` must not use the cache.
+    pub fn get_or_try_insert<K: Hash + ?Sized, E>(
+        &mut self,
+        key: &K,
+        derive: impl FnOnce() -> Result<V, E>,
+    ) -> Result<V, E> {
+        let hash = content_hash(key);
+        if let Some(value) = self.entries.get(&hash) {
+            return Ok(value.clone());
+        }
+        let value = derive()?;
+        if self.order.len() >= self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.entries.remove(&oldest);
+            }
+        }
+        self.order.push_back(hash);
+        self.entries.insert(hash, value.clone());
+        Ok(value)
+    }
+}
+
+fn content_hash<K: Hash + ?Sized>(key: &K) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+
+    use super::*;
+
+    #[test]
+    fn oldest_entry_is_evicted() {
+        let mut cache = ContentCache::new(2);
+        let mut derived = Vec::new();
+        let mut get = |cache: &mut ContentCache<usize>, key: &str| {
+            cache
+                .get_or_try_insert(key, || {
+                    derived.push(key.to_owned());
+                    Ok::<_, Infallible>(key.len())
+                })
+                .unwrap()
+        };
+
+        assert_eq!(get(&mut cache, "a"), 1);
+        assert_eq!(get(&mut cache, "bb"), 2);
+        assert_eq!(get(&mut cache, "a"), 1);
+        assert_eq!(get(&mut cache, "ccc"), 3);
+        // "a" was evicted, "bb" is still cached.
+        assert_eq!(get(&mut cache, "bb"), 2);
+        assert_eq!(get(&mut cache, "a"), 1);
+        assert_eq!(derived, ["a", "bb", "ccc", "a"]);
+    }
+
+    #[test]
+    fn failures_are_not_cached() {
+        let mut cache = ContentCache::new(2);
+        assert_eq!(cache.get_or_try_insert("a", || Err(())), Err(()));
+        assert_eq!(cache.get_or_try_insert("a", || Ok::<_, ()>(1)), Ok(1));
+        assert_eq!(cache.get_or_try_insert("a", || Ok::<_, ()>(2)), Ok(1));
+    }
+}
@@ -12,9 +12,9 @@ use std::fmt;
 use clarity::vm::{ClarityName, ClarityVersion};
 use walrus::ir::{BinaryOp, Instr, UnaryOp, Unop};
 use walrus::{FunctionId, GlobalId, InstrSeqBuilder, LocalId, Module};
-use wasmtime::{AsContextMut, Extern, Global, Mutability, Val, ValType};
 
 use crate::error_mapping::ErrorMap;
+use crate::runtime::{AsContextMut, Extern, Global, Linker, Mutability, Val, ValType};
 use crate::wasm_generator::{GeneratorError, WasmGenerator};
 use crate::words::Word;
 
@@ -75,7 +75,7 @@ impl fmt::Display for GetCostGlobalsError {
 
 impl std::error::Error for GetCostGlobalsError {}
 
-impl<T> CostLinker<T> for wasmtime::Linker<T> {
+impl<T> CostLinker<T> for Linker<T> {
     fn get_cost_globals(
         &self,
         mut store: impl AsContextMut<Data = T>,
@@ -126,12 +126,12 @@ impl<T> CostLinker<T> for wasmtime::Linker<T> {
 }
 
 fn define_cost_global_import<T>(
-    linker: &mut wasmtime::Linker<T>,
+    linker: &mut Linker<T>,
     mut store: impl AsContextMut<Data = T>,
     name: &str,
     value: u64,
 ) -> wasmtime::Result<()> {
-    use wasmtime::{Global, GlobalType};
+    use crate::runtime::GlobalType;
 
     let mut store = store.as_context_mut();
 
@@ -797,7 +797,7 @@ mod test_caf {
         initial: u64,
         caf: impl FnOnce(LocalId) -> (Caf, S),
     ) -> Result<u64, i64> {
-        use wasmtime::{Engine, Linker, Module, Store};
+        use crate::runtime::{Engine, Linker, Module, Store, Val};
 
         let engine = Engine::default();
         let binary = module_with_caf(caf);
@@ -822,14 +822,13 @@ mod test_caf {
 
         let instance = linker.instantiate(&mut store, &module).unwrap();
 
-        let func = instance
-            .get_typed_func::<i32, i32>(&mut store, "identity")
-            .unwrap();
+        let func = instance.get_func(&mut store, "identity").unwrap();
         let err_code = instance.get_global(&mut store, "err-code").unwrap();
 
-        match func.call(&mut store, arg) {
+        let mut results = [Val::I32(0)];
+        match func.call(&mut store, &[Val::I32(arg)], &mut results) {
             Ok(_) => Ok(linker.get_cost_meter(&mut store).unwrap().runtime),
-            Err(_) => Err(err_code.get(&mut store).unwrap_i64()),
+            Err(_) => Err(err_code.get(&mut store).i64().unwrap()),
         }
     }
 
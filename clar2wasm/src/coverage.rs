@@ -0,0 +1,516 @@
+//! Clarity-level code coverage of compiled contracts.
+//!
+//! When coverage is enabled, the generator assigns a counter to each function
+//! application, and to each branch of `if`, `match`, `asserts!`, `unwrap!`,
+//! `unwrap-err!` and `try!`. The counters live in a region at the end of the
+//! literal memory, whose offset and length are exported as the
+//! `coverage-counters` and `coverage-probes` globals, and are incremented each
+//! time their expression or branch is executed. The source location of each
+//! counter is saved in the `clarity-coverage` custom section of the module.
+//!
+//! After each call from the host, the counters are added to the
+//! [CoverageCounters] attached through the
+//! [ExecutionOptions](crate::initialize::ExecutionOptions), from which an LCOV
+//! report can be produced. Contracts called with `contract-call?` run with the
+//! same options, so their counters are collected too.
+
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use std::rc::Rc;
+
+use clarity::vm::errors::{Error, WasmError};
+use clarity::vm::types::QualifiedContractIdentifier;
+use clarity::vm::SymbolicExpression;
+use walrus::ir::{BinaryOp, LoadKind, MemArg, StoreKind};
+use walrus::{GlobalId, InitExpr, InstrSeqBuilder, RawCustomSection, ValType};
+
+use crate::initialize::ClarityWasmContext;
+use crate::runtime::{AsContextMut, Instance, Store, Val};
+use crate::wasm_generator::{GeneratorError, WasmGenerator};
+
+/// Name of the custom section containing the [CoverageMap] of a module.
+pub const COVERAGE_SECTION: &str = "clarity-coverage";
+
+/// Name of the exported global containing the offset of the counters.
+const COUNTERS_GLOBAL: &str = "coverage-counters";
+
+/// Name of the exported global containing the number of counters.
+const PROBES_GLOBAL: &str = "coverage-probes";
+
+/// What a counter counts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProbeKind {
+    /// Executions of an expression.
+    Expression,
+    /// Executions of the branch `branch` of the conditional `block`.
+    Branch { block: u32, branch: u32 },
+}
+
+/// A counter, and the location of the expression it counts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Probe {
+    pub line: u32,
+    pub column: u32,
+    pub kind: ProbeKind,
+}
+
+/// The probes of a module, indexed by counter.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CoverageMap {
+    pub probes: Vec<Probe>,
+}
+
+impl CoverageMap {
+    /// Size of a serialized probe: line, column, kind, block and branch.
+    const PROBE_SIZE: usize = 17;
+
+    fn serialize(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(self.probes.len() * Self::PROBE_SIZE);
+        for probe in &self.probes {
+            let (kind, block, branch) = match probe.kind {
+                ProbeKind::Expression => (0, 0, 0),
+                ProbeKind::Branch { block, branch } => (1, block, branch),
+            };
+            data.extend_from_slice(&probe.line.to_le_bytes());
+            data.extend_from_slice(&probe.column.to_le_bytes());
+            data.push(kind);
+            data.extend_from_slice(&block.to_le_bytes());
+            data.extend_from_slice(&branch.to_le_bytes());
+        }
+        data
+    }
+
+    fn deserialize(data: &[u8]) -> Option<Self> {
+        let read_u32 =
+            |bytes: &[u8]| -> Option<u32> { Some(u32::from_le_bytes(bytes.try_into().ok()?)) };
+
+        if data.len() % Self::PROBE_SIZE != 0 {
+            return None;
+        }
+        let probes = data
+            .chunks(Self::PROBE_SIZE)
+            .map(|probe| {
+                let kind = match probe[8] {
+                    0 => ProbeKind::Expression,
+                    1 => ProbeKind::Branch {
+                        block: read_u32(&probe[9..13])?,
+                        branch: read_u32(&probe[13..17])?,
+                    },
+                    _ => return None,
+                };
+                Some(Probe {
+                    line: read_u32(&probe[0..4])?,
+                    column: read_u32(&probe[4..8])?,
+                    kind,
+                })
+            })
+            .collect::<Option<_>>()?;
+        Some(Self { probes })
+    }
+
+    /// Reads the coverage map from the custom section of a Wasm binary.
+    ///
+    /// Returns `None` if the module was not instrumented.
+    pub fn from_wasm(wasm: &[u8]) -> Option<Self> {
+        fn read_leb128(bytes: &[u8], pos: &mut usize) -> Option<usize> {
+            let mut result = 0usize;
+            for shift in (0..35).step_by(7) {
+                let byte = *bytes.get(*pos)?;
+                *pos += 1;
+                result |= ((byte & 0x7f) as usize) << shift;
+                if byte & 0x80 == 0 {
+                    return Some(result);
+                }
+            }
+            None
+        }
+
+        // Skip the magic number and the version.
+        let mut pos = 8;
+        while pos < wasm.len() {
+            let id = wasm[pos];
+            pos += 1;
+            let size = read_leb128(wasm, &mut pos)?;
+            let end = pos.checked_add(size)?;
+            let section = wasm.get(pos..end)?;
+            if id == 0 {
+                let mut name_pos = 0;
+                let name_len = read_leb128(section, &mut name_pos)?;
+                let name = section.get(name_pos..name_pos + name_len)?;
+                if name == COVERAGE_SECTION.as_bytes() {
+                    return Self::deserialize(&section[name_pos + name_len..]);
+                }
+            }
+            pos = end;
+        }
+        None
+    }
+
+    /// Writes an LCOV record for the contract whose source is `source_file`,
+    /// with the counters collected for the contract.
+    ///
+    /// A line is hit if any expression starting on it was executed.
+    pub fn lcov(&self, source_file: &str, counters: &[u64]) -> String {
+        let count = |i: usize| counters.get(i).copied().unwrap_or(0);
+
+        let mut lines = BTreeMap::new();
+        let mut branches = BTreeMap::new();
+        for (i, probe) in self.probes.iter().enumerate() {
+            match probe.kind {
+                ProbeKind::Expression => {
+                    let hits = lines.entry(probe.line).or_insert(0);
+                    *hits = count(i).max(*hits);
+                }
+                ProbeKind::Branch { block, branch } => {
+                    branches.insert((probe.line, block, branch), count(i));
+                }
+            }
+        }
+
+        let mut lcov = format!("TN:\nSF:{source_file}\n");
+        for ((line, block, branch), taken) in &branches {
+            let _ = writeln!(lcov, "BRDA:{line},{block},{branch},{taken}");
+        }
+        let branches_hit = branches.values().filter(|&&taken| taken > 0).count();
+        let _ = writeln!(lcov, "BRF:{}\nBRH:{branches_hit}", branches.len());
+        for (line, hits) in &lines {
+            let _ = writeln!(lcov, "DA:{line},{hits}");
+        }
+        let lines_hit = lines.values().filter(|&&hits| hits > 0).count();
+        let _ = writeln!(lcov, "LF:{}\nLH:{lines_hit}", lines.len());
+        lcov.push_str("end_of_record\n");
+        lcov
+    }
+}
+
+/// Counters collected for each contract, indexed like its [CoverageMap].
+#[derive(Debug, Clone, Default)]
+pub struct CoverageCounters(HashMap<QualifiedContractIdentifier, Vec<u64>>);
+
+/// Counters shared between the embedder and the contexts running contracts.
+pub type SharedCoverage = Rc<RefCell<CoverageCounters>>;
+
+impl CoverageCounters {
+    /// Returns the counters collected for `contract`.
+    pub fn get(&self, contract: &QualifiedContractIdentifier) -> &[u64] {
+        self.0.get(contract).map_or(&[], Vec::as_slice)
+    }
+
+    fn add(&mut self, contract: &QualifiedContractIdentifier, counters: &[u32]) {
+        let total = self.0.entry(contract.clone()).or_default();
+        if total.len() < counters.len() {
+            total.resize(counters.len(), 0);
+        }
+        for (total, &count) in total.iter_mut().zip(counters) {
+            *total += u64::from(count);
+        }
+    }
+}
+
+/// Adds the counters of `instance` to the coverage attached to its context.
+///
+/// Does nothing if no coverage is attached, or if the module was not
+/// instrumented.
+pub(crate) fn collect_coverage(
+    instance: &Instance,
+    store: &mut Store<ClarityWasmContext>,
+) -> Result<(), Error> {
+    let Some(coverage) = store.data().coverage.clone() else {
+        return Ok(());
+    };
+    let contract = store.data().contract_context().contract_identifier.clone();
+    collect(&coverage, &contract, instance, store)
+}
+
+fn collect(
+    coverage: &SharedCoverage,
+    contract: &QualifiedContractIdentifier,
+    instance: &Instance,
+    mut store: impl AsContextMut,
+) -> Result<(), Error> {
+    let mut get_global = |name: &str| {
+        instance
+            .get_global(&mut store, name)
+            .map(|global| global.get(&mut store))
+    };
+    let (Some(Val::I32(offset)), Some(Val::I32(probe_count))) =
+        (get_global(COUNTERS_GLOBAL), get_global(PROBES_GLOBAL))
+    else {
+        return Ok(());
+    };
+    let memory = instance
+        .get_memory(&mut store, "memory")
+        .ok_or(Error::Wasm(WasmError::MemoryNotFound))?;
+
+    let mut buffer = vec![0u8; probe_count as usize * 4];
+    memory
+        .read(&mut store, offset as usize, &mut buffer)
+        .map_err(|e| Error::Wasm(WasmError::UnableToReadMemory(e.into())))?;
+    let counters: Vec<u32> = buffer
+        .chunks_exact(4)
+        .map(|count| u32::from_le_bytes([count[0], count[1], count[2], count[3]]))
+        .collect();
+
+    coverage.borrow_mut().add(contract, &counters);
+    Ok(())
+}
+
+/// State of the instrumentation while generating a module.
+#[derive(Debug)]
+pub(crate) struct CoverageInstrumentation {
+    /// Global containing the offset of the counters.
+    counters: GlobalId,
+    map: CoverageMap,
+    /// Number of conditionals instrumented so far.
+    blocks: u32,
+}
+
+/// The conditional owning a set of branches.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct BranchBlock {
+    line: u32,
+    column: u32,
+    block: u32,
+}
+
+impl BranchBlock {
+    pub(crate) fn branch(self, branch: u32) -> Probe {
+        Probe {
+            line: self.line,
+            column: self.column,
+            kind: ProbeKind::Branch {
+                block: self.block,
+                branch,
+            },
+        }
+    }
+}
+
+/// Expressions built by the generator itself have no location in the source.
+fn has_location(expr: &SymbolicExpression) -> bool {
+    expr.span.start_line > 0
+}
+
+impl WasmGenerator {
+    /// Instrument the generated module to count the executions of its
+    /// expressions and branches.
+    pub fn with_coverage(mut self) -> Self {
+        let counters = self.module.globals.add_local(
+            ValType::I32,
+            false,
+            InitExpr::Value(walrus::ir::Value::I32(0)),
+        );
+        self.module.exports.add(COUNTERS_GLOBAL, counters);
+        self.coverage = Some(CoverageInstrumentation {
+            counters,
+            map: CoverageMap::default(),
+            blocks: 0,
+        });
+        self
+    }
+
+    /// Starts a new set of branches for the conditional `expr`, if coverage is
+    /// enabled.
+    pub(crate) fn coverage_branches(&mut self, expr: &SymbolicExpression) -> Option<BranchBlock> {
+        let coverage = self.coverage.as_mut().filter(|_| has_location(expr))?;
+        coverage.blocks += 1;
+        Some(BranchBlock {
+            line: expr.span.start_line,
+            column: expr.span.start_column,
+            block: coverage.blocks - 1,
+        })
+    }
+
+    /// Counts the executions of the expression `expr`, if coverage is enabled.
+    pub(crate) fn count_expression(
+        &mut self,
+        builder: &mut InstrSeqBuilder,
+        expr: &SymbolicExpression,
+    ) -> Result<(), GeneratorError> {
+        if !has_location(expr) {
+            return Ok(());
+        }
+        self.count(
+            builder,
+            Probe {
+                line: expr.span.start_line,
+                column: expr.span.start_column,
+                kind: ProbeKind::Expression,
+            },
+        )
+    }
+
+    /// Counts the executions of a branch, if coverage is enabled.
+    pub(crate) fn count_branch(
+        &mut self,
+        builder: &mut InstrSeqBuilder,
+        branch: Option<Probe>,
+    ) -> Result<(), GeneratorError> {
+        match branch {
+            Some(probe) => self.count(builder, probe),
+            None => Ok(()),
+        }
+    }
+
+    fn count(&mut self, builder: &mut InstrSeqBuilder, probe: Probe) -> Result<(), GeneratorError> {
+        let memory = self.get_memory()?;
+        let Some(coverage) = self.coverage.as_mut() else {
+            return Ok(());
+        };
+
+        let offset = coverage.map.probes.len() as u32 * 4;
+        coverage.map.probes.push(probe);
+
+        let memarg = MemArg { align: 4, offset };
+        builder
+            .global_get(coverage.counters)
+            .global_get(coverage.counters)
+            .load(memory, LoadKind::I32 { atomic: false }, memarg)
+            .i32_const(1)
+            .binop(BinaryOp::I32Add)
+            .store(memory, StoreKind::I32 { atomic: false }, memarg);
+        Ok(())
+    }
+
+    /// Reserves the memory of the counters after the literal memory, and saves
+    /// the coverage map in the module.
+    pub(crate) fn finish_coverage(&mut self) {
+        let Some(coverage) = self.coverage.take() else {
+            return;
+        };
+
+        let offset = self.literal_memory_end;
+        self.literal_memory_end += coverage.map.probes.len() as u32 * 4;
+        self.module.globals.get_mut(coverage.counters).kind =
+            walrus::GlobalKind::Local(InitExpr::Value(walrus::ir::Value::I32(offset as i32)));
+        let probes = self.module.globals.add_local(
+            ValType::I32,
+            false,
+            InitExpr::Value(walrus::ir::Value::I32(coverage.map.probes.len() as i32)),
+        );
+        self.module.exports.add(PROBES_GLOBAL, probes);
+
+        self.module.customs.add(RawCustomSection {
+            name: COVERAGE_SECTION.to_owned(),
+            data: coverage.map.serialize(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clarity::vm::errors::RuntimeErrorType;
+    use clarity::vm::types::StandardPrincipalData;
+
+    use super::*;
+    use crate::tools::TestEnvironment;
+
+    const CONTRACT: &str = r#"(define-public (check (n uint))
+  (begin
+    (asserts! (> n u1) (err u1))
+    (ok (if (> n u10)
+      u10
+      n))))
+(check u5)
+"#;
+
+    #[test]
+    fn map_roundtrips_through_wasm() {
+        let mut env = TestEnvironment::default();
+        let coverage = env.enable_coverage();
+        env.init_contract_with_snippet("contract", CONTRACT)
+            .expect("Failed to init contract.");
+
+        let map = env
+            .coverage_map("contract")
+            .expect("module should be instrumented");
+        assert!(map.probes.iter().any(|p| p.kind == ProbeKind::Expression));
+
+        let contract_id =
+            QualifiedContractIdentifier::new(StandardPrincipalData::transient(), "contract".into());
+        assert_eq!(coverage.borrow().get(&contract_id).len(), map.probes.len());
+    }
+
+    #[test]
+    fn counts_branches() {
+        let mut env = TestEnvironment::default();
+        env.enable_coverage();
+        env.init_contract_with_snippet("contract", CONTRACT)
+            .expect("Failed to init contract.");
+        env.call_contract_function("contract", "check", &[clarity::vm::Value::UInt(0)], None)
+            .expect("Failed to call function.");
+
+        let lcov = env
+            .coverage_report("contract", "contract.clar")
+            .expect("module should be instrumented");
+
+        // `asserts!` passed once at deployment, and failed once.
+        assert!(lcov.contains("BRDA:3,0,0,1\n"), "{lcov}");
+        assert!(lcov.contains("BRDA:3,0,1,1\n"), "{lcov}");
+        // the `if` only took the `else` branch.
+        assert!(lcov.contains("BRDA:4,1,0,0\n"), "{lcov}");
+        assert!(lcov.contains("BRDA:4,1,1,1\n"), "{lcov}");
+        assert!(lcov.contains("BRF:4\nBRH:3\n"), "{lcov}");
+        // the top-level call and the function body were executed.
+        assert!(lcov.contains("DA:7,1\n"), "{lcov}");
+        assert!(lcov.contains("DA:2,2\n"), "{lcov}");
+        // `u10` is a literal, and `(if ...)` was executed once.
+        assert!(lcov.contains("DA:4,1\n"), "{lcov}");
+    }
+
+    #[test]
+    fn counts_callees() {
+        let mut env = TestEnvironment::default();
+        env.enable_coverage();
+        env.init_contract_with_snippet("contract", CONTRACT)
+            .expect("Failed to init contract.");
+        let snippet = format!(
+            "(contract-call? '{}.contract check u0)",
+            StandardPrincipalData::transient()
+        );
+        env.init_contract_with_snippet("caller", &snippet)
+            .expect("Failed to init contract.");
+
+        let lcov = env
+            .coverage_report("contract", "contract.clar")
+            .expect("module should be instrumented");
+
+        // the body ran at deployment, and once more when called by `caller`.
+        assert!(lcov.contains("DA:2,2\n"), "{lcov}");
+        assert!(lcov.contains("BRDA:3,0,1,1\n"), "{lcov}");
+    }
+
+    #[test]
+    fn uninstrumented_module_has_no_map() {
+        let mut env = TestEnvironment::default();
+        env.init_contract_with_snippet("contract", CONTRACT)
+            .expect("Failed to init contract.");
+        assert_eq!(env.coverage_map("contract"), None);
+    }
+
+    #[test]
+    fn failed_call_keeps_its_error_and_counters() {
+        let mut env = TestEnvironment::default();
+        env.enable_coverage();
+        env.init_contract_with_snippet(
+            "contract",
+            "(define-public (divide (n uint))\n  (ok (/ u1 n)))",
+        )
+        .expect("Failed to init contract.");
+
+        let result =
+            env.call_contract_function("contract", "divide", &[clarity::vm::Value::UInt(0)], None);
+        assert!(
+            matches!(
+                result,
+                Err(Error::Runtime(RuntimeErrorType::DivisionByZero, _))
+            ),
+            "{result:?}"
+        );
+        let lcov = env
+            .coverage_report("contract", "contract.clar")
+            .expect("module should be instrumented");
+        assert!(lcov.contains("DA:2,1\n"), "{lcov}");
+    }
+}
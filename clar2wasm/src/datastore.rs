@@ -18,7 +18,7 @@ use clarity::types::StacksEpochId;
 use clarity::util::hash::Sha512Trunc256Sum;
 use clarity::vm::analysis::AnalysisDatabase;
 use clarity::vm::costs::ExecutionCost;
-use clarity::vm::database::{BurnStateDB, ClarityBackingStore, HeadersDB};
+use clarity::vm::database::{BurnStateDB, ClarityBackingStore, HeadersDB, SpecialCaseHandler};
 use clarity::vm::errors::{InterpreterError, InterpreterResult as Result};
 use clarity::vm::types::{QualifiedContractIdentifier, TupleData};
 use clarity::vm::{StacksEpoch, Value};
@@ -34,6 +34,18 @@ pub struct Datastore {
     current_chain_tip: StacksBlockId,
     chain_height: u32,
     height_at_chain_tip: HashMap<StacksBlockId, u32>,
+    special_cases_handler: Option<SpecialCases>,
+}
+
+/// The handler run after each successful `contract-call?`, which the PoX
+/// contracts rely on in a node.
+#[derive(Clone, Copy)]
+struct SpecialCases(SpecialCaseHandler);
+
+impl std::fmt::Debug for SpecialCases {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("SpecialCases")
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -165,6 +177,7 @@ impl Datastore {
             current_chain_tip: id,
             chain_height: 0,
             height_at_chain_tip: id_height_map,
+            special_cases_handler: None,
         }
     }
 
@@ -188,6 +201,27 @@ impl Datastore {
         self.current_chain_tip = self.open_chain_tip;
         self.chain_height
     }
+
+    /// Sets the handler run after each successful `contract-call?`, as a node
+    /// does for the PoX contracts.
+    pub fn set_cc_special_cases_handler(&mut self, handler: SpecialCaseHandler) {
+        self.special_cases_handler = Some(SpecialCases(handler));
+    }
+
+    /// Returns the height of the open chain tip, advanced by
+    /// [Datastore::advance_chain_tip].
+    pub fn chain_height(&self) -> u32 {
+        self.chain_height
+    }
+
+    /// Returns the key-value pairs visible at the open chain tip.
+    pub fn entries(&self) -> impl Iterator<Item = (&String, &String)> {
+        self.block_id_lookup
+            .get(&self.open_chain_tip)
+            .and_then(|id| self.store.get(id))
+            .into_iter()
+            .flatten()
+    }
 }
 
 impl Default for Datastore {
@@ -268,6 +302,11 @@ impl ClarityBackingStore for Datastore {
         self.open_chain_tip
     }
 
+    fn get_cc_special_cases_handler(&self) -> Option<SpecialCaseHandler> {
+        self.special_cases_handler
+            .map(|SpecialCases(handler)| handler)
+    }
+
     /// The contract commitment is the hash of the contract, plus the block height in
     ///   which the contract was initialized.
     fn make_contract_commitment(&mut self, _contract_hash: Sha512Trunc256Sum) -> String {
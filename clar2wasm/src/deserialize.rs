@@ -9,6 +9,7 @@ use walrus::ir::{
 };
 use walrus::{InstrSeqBuilder, LocalId, MemoryId, ValType};
 
+use crate::error_mapping::ErrorMap;
 use crate::wasm_generator::{
     add_placeholder_for_clarity_type, clar2wasm_ty, GeneratorError, WasmGenerator,
 };
@@ -1840,4 +1841,51 @@ impl WasmGenerator {
             ListUnionType(_) => unreachable!("ListUnionType should not be deserialized"),
         }
     }
+
+    /// Deserialize a value of type `ty` loaded by the host from a data-var or
+    /// a map entry. The offset and length of the loaded bytes are in
+    /// `offset_local` and `length_local`. Leaves the value on the top of the
+    /// stack. Since the host only stores values serialized by a contract,
+    /// bytes that do not hold exactly one value of type `ty` are a runtime
+    /// error.
+    pub(crate) fn deserialize_stored_value(
+        &mut self,
+        builder: &mut InstrSeqBuilder,
+        offset_local: LocalId,
+        length_local: LocalId,
+        ty: &TypeSignature,
+    ) -> Result<(), GeneratorError> {
+        let (offset_result, _) = self.create_call_stack_local(builder, ty, true, true);
+        let end = self.module.locals.add(ValType::I32);
+        builder
+            .local_get(offset_local)
+            .local_get(length_local)
+            .binop(BinaryOp::I32Add)
+            .local_set(end);
+
+        // Data stack: TOP | value | is_some |
+        self.deserialize_from_memory(builder, offset_local, end, offset_result, ty)?;
+        let value = self.save_to_locals(builder, ty, true);
+
+        let runtime_error = self.func_by_name("stdlib.runtime-error");
+        builder
+            .unop(UnaryOp::I32Eqz)
+            .local_get(offset_local)
+            .local_get(end)
+            .binop(BinaryOp::I32Ne)
+            .binop(BinaryOp::I32Or)
+            .if_else(
+                None,
+                |then| {
+                    then.i32_const(ErrorMap::InvalidStoredValue as i32)
+                        .call(runtime_error);
+                },
+                |_| {},
+            );
+
+        for local in value {
+            builder.local_get(local);
+        }
+        Ok(())
+    }
 }
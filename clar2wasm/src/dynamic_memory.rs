@@ -0,0 +1,220 @@
+//! Growth of the memory on demand.
+//!
+//! By default, the initial memory of a module is sized when it is generated,
+//! from the literal memory, the stack frames of all the functions and the work
+//! space needed by the standard library. With dynamic memory, the module
+//! starts with the literal memory and the work space only, and every update of
+//! the stack pointer goes through `stdlib.set-stack-pointer`, which grows the
+//! memory when the work space no longer fits after the new stack pointer.
+//!
+//! The memory grows up to the maximum enforced by the host (see
+//! [crate::limits::ResourceLimits::max_memory_bytes]). Past it, or past the
+//! 4 GiB of a 32-bit memory, the contract fails with
+//! [crate::error_mapping::ErrorMap::MemoryExhausted].
+//!
+//! `stdlib.set-stack-pointer` is exported, so that the host also grows the
+//! memory when it writes the arguments of a function on the stack.
+
+use walrus::ir::{
+    dfs_pre_order_mut, BinaryOp, Call, GlobalSet, Instr, InstrLocId, UnaryOp, VisitorMut,
+};
+use walrus::{FunctionBuilder, FunctionId, GlobalId, MemoryId, Module, ValType};
+
+use crate::error_mapping::ErrorMap;
+use crate::wasm_generator::{GeneratorError, WasmGenerator};
+
+/// Name of the function setting the stack pointer, growing the memory if
+/// needed.
+pub(crate) const SET_STACK_POINTER: &str = "stdlib.set-stack-pointer";
+
+/// log2 of the size of a Wasm memory page.
+const PAGE_SIZE_BITS: i64 = 16;
+
+impl WasmGenerator {
+    /// Generates a module growing its memory on demand.
+    pub fn with_dynamic_memory(mut self) -> Self {
+        self.dynamic_memory = true;
+        self
+    }
+
+    /// Replaces the updates of the stack pointer of all the functions of the
+    /// module by calls to `stdlib.set-stack-pointer`.
+    pub(crate) fn grow_memory_on_demand(&mut self) -> Result<(), GeneratorError> {
+        let memory = self.get_memory()?;
+        let runtime_error = self.func_by_name("stdlib.runtime-error");
+        let set_stack_pointer = add_set_stack_pointer(
+            &mut self.module,
+            memory,
+            self.stack_pointer,
+            runtime_error,
+            self.max_work_space,
+        );
+        self.module
+            .exports
+            .add(SET_STACK_POINTER, set_stack_pointer);
+
+        let mut rewriting = Rewriting {
+            stack_pointer: self.stack_pointer,
+            set_stack_pointer,
+        };
+        for (id, function) in self.module.funcs.iter_local_mut() {
+            if id != set_stack_pointer {
+                let entry = function.entry_block();
+                dfs_pre_order_mut(&mut rewriting, function, entry);
+            }
+        }
+        Ok(())
+    }
+}
+
+struct Rewriting {
+    stack_pointer: GlobalId,
+    set_stack_pointer: FunctionId,
+}
+
+impl VisitorMut for Rewriting {
+    fn visit_instr_mut(&mut self, instr: &mut Instr, _: &mut InstrLocId) {
+        if matches!(instr, Instr::GlobalSet(GlobalSet { global }) if *global == self.stack_pointer)
+        {
+            *instr = Instr::Call(Call {
+                func: self.set_stack_pointer,
+            });
+        }
+    }
+}
+
+/// Adds the function `(func (param $sp i32))` setting the stack pointer to
+/// `$sp`, and growing the memory until `work_space` bytes fit after it.
+fn add_set_stack_pointer(
+    module: &mut Module,
+    memory: MemoryId,
+    stack_pointer: GlobalId,
+    runtime_error: FunctionId,
+    work_space: u32,
+) -> FunctionId {
+    let sp = module.locals.add(ValType::I32);
+    let pages = module.locals.add(ValType::I32);
+
+    let mut function = FunctionBuilder::new(&mut module.types, &[ValType::I32], &[]);
+    function.name(SET_STACK_POINTER.to_owned());
+
+    function
+        .func_body()
+        .local_get(sp)
+        .global_set(stack_pointer)
+        // Pages needed up to the end of the work space, computed in 64 bits
+        // since it can end past 4 GiB.
+        .local_get(sp)
+        .unop(UnaryOp::I64ExtendUI32)
+        .i64_const(work_space as i64 + (1 << PAGE_SIZE_BITS) - 1)
+        .binop(BinaryOp::I64Add)
+        .i64_const(PAGE_SIZE_BITS)
+        .binop(BinaryOp::I64ShrU)
+        .unop(UnaryOp::I32WrapI64)
+        .local_tee(pages)
+        .memory_size(memory)
+        .binop(BinaryOp::I32GtU)
+        .if_else(
+            None,
+            |grow| {
+                grow.local_get(pages)
+                    .memory_size(memory)
+                    .binop(BinaryOp::I32Sub)
+                    .memory_grow(memory)
+                    .i32_const(-1)
+                    .binop(BinaryOp::I32Eq)
+                    .if_else(
+                        None,
+                        |exhausted| {
+                            exhausted
+                                .i32_const(ErrorMap::MemoryExhausted as i32)
+                                .call(runtime_error);
+                        },
+                        |_| {},
+                    );
+            },
+            |_| {},
+        );
+
+    function.finish(vec![sp], &mut module.funcs)
+}
+
+#[cfg(test)]
+mod tests {
+    use clarity::vm::errors::{CheckErrors, Error};
+    use clarity::vm::Value;
+
+    use crate::limits::{ResourceLimits, WASM_PAGE_SIZE};
+    use crate::tools::{TestConfig, TestEnvironment};
+
+    fn env() -> TestEnvironment {
+        let mut env =
+            TestEnvironment::new(TestConfig::latest_epoch(), TestConfig::clarity_version());
+        env.enable_dynamic_memory();
+        env
+    }
+
+    #[test]
+    fn memory_grows_for_large_lists() {
+        let mut env = env();
+        let snippet = format!(
+            "
+(define-private (fill (x int) (acc (list 4096 (buff 64))))
+  (unwrap-panic (as-max-len? (append acc 0x{}) u4096)))
+(len (fold fill (list 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15 16) (list)))
+",
+            "00".repeat(64)
+        );
+        assert_eq!(
+            env.init_contract_with_snippet("lists", &snippet),
+            Ok(Some(Value::UInt(16)))
+        );
+    }
+
+    #[test]
+    fn memory_grows_for_arguments() {
+        let mut env = env();
+        env.init_contract_with_snippet(
+            "args",
+            "(define-read-only (size (b (buff 1048576))) (len b))",
+        )
+        .expect("Failed to init contract.");
+
+        let arg = Value::buff_from(vec![1; 1_048_576]).unwrap();
+        assert_eq!(
+            env.call_contract_function("args", "size", &[arg], None),
+            Ok(Value::UInt(1_048_576))
+        );
+    }
+
+    #[test]
+    fn growth_past_maximum_is_refused() {
+        let mut env = env();
+        env.set_resource_limits(ResourceLimits {
+            max_memory_bytes: 4 * WASM_PAGE_SIZE,
+            ..Default::default()
+        });
+
+        // The module starts within the limit, but the frame of `concat`
+        // doesn't fit in it.
+        let snippet = format!(
+            "
+(define-private (grow (x int) (acc (buff 1000000)))
+  (unwrap-panic (as-max-len? (concat acc 0x{}) u1000000)))
+(len (fold grow (list 1 2 3) 0x))
+",
+            "00".repeat(32)
+        );
+        let err = env
+            .init_contract_with_snippet("exhausted", &snippet)
+            .expect_err("memory limit should be hit");
+        assert!(
+            matches!(
+                err,
+                Error::Unchecked(CheckErrors::MemoryBalanceExceeded(_, limit))
+                    if limit == 4 * WASM_PAGE_SIZE as u64
+            ),
+            "unexpected error: {err:?}"
+        );
+    }
+}
@@ -1,13 +1,18 @@
-use clarity::types::StacksEpochId;
+use clarity::vm::callables::DefinedFunction;
 use clarity::vm::costs::CostErrors;
-use clarity::vm::errors::{CheckErrors, Error, RuntimeErrorType, ShortReturnType, WasmError};
+use clarity::vm::errors::{
+    CheckErrors, Error, InterpreterError, RuntimeErrorType, ShortReturnType, StackTrace, WasmError,
+};
 use clarity::vm::types::ResponseData;
-use clarity::vm::{ClarityVersion, Value};
-use wasmtime::{AsContextMut, Instance, Trap};
+use clarity::vm::{ContractContext, Value};
+use wasmtime::Trap;
 
-use crate::wasm_utils::{
-    read_bytes_from_wasm, read_from_wasm_indirect, read_identifier_from_wasm, signature_from_string,
-};
+use crate::initialize::ClarityWasmContext;
+use crate::inlining::INLINED_FRAMES_GLOBAL;
+use crate::limits::Limiter;
+use crate::runtime::{self, AsContext, AsContextMut, Instance};
+use crate::type_table;
+use crate::wasm_utils::{read_bytes_from_wasm, read_from_wasm_indirect, read_identifier_from_wasm};
 
 const LOG2_ERROR_MESSAGE: &str = "log2 must be passed a positive integer";
 const SQRTI_ERROR_MESSAGE: &str = "sqrti must be passed a positive integer";
@@ -79,6 +84,14 @@ pub enum ErrorMap {
     /// Indicates an attempt to use a function with too many arguments
     ArgumentCountAtMost = 15,
 
+    /// Indicates that the bytes loaded from a data-var or a map entry are not
+    /// the consensus serialization of a value of the stored type.
+    InvalidStoredValue = 16,
+
+    /// Indicates that the memory could not grow to make room for the stack,
+    /// in a module growing its memory on demand.
+    MemoryExhausted = 17,
+
     /// Indicates a runtime cost overrun
     CostOverrunRuntime = 100,
 
@@ -119,6 +132,8 @@ impl From<i32> for ErrorMap {
             13 => ErrorMap::ArgumentCountMismatch,
             14 => ErrorMap::ArgumentCountAtLeast,
             15 => ErrorMap::ArgumentCountAtMost,
+            16 => ErrorMap::InvalidStoredValue,
+            17 => ErrorMap::MemoryExhausted,
             100 => ErrorMap::CostOverrunRuntime,
             101 => ErrorMap::CostOverrunReadCount,
             102 => ErrorMap::CostOverrunReadLength,
@@ -129,12 +144,112 @@ impl From<i32> for ErrorMap {
     }
 }
 
-pub(crate) fn resolve_error(
+/// Converts an error raised while running a contract into a Clarity `Error`.
+///
+/// Runtime errors get the stack trace of the Clarity functions of the contract
+/// which were active when the error was raised, see [stack_trace].
+pub(crate) fn resolve_error<'a, 'b>(
+    e: wasmtime::Error,
+    instance: Instance,
+    mut store: impl AsContextMut<Data = ClarityWasmContext<'a, 'b>>,
+) -> Error {
+    let mut trace = stack_trace(&e, store.as_context().data().contract_context());
+    insert_inlined_frames(&mut trace, &instance, &mut store);
+    // A refused memory growth is taken in any case, so that it isn't reported
+    // for a later error.
+    let limit_error = store.as_context_mut().data_mut().limiter.take_error();
+    let error = match (convert_error(e, instance, &mut store), limit_error) {
+        // The module ran out of memory because the limiter refused to grow
+        // it, and its error holds the desired and maximum sizes.
+        (Error::Unchecked(CheckErrors::MemoryBalanceExceeded(..)), Some(limit_error)) => {
+            limit_error
+        }
+        (error, _) => error,
+    };
+    with_stack_trace(error, trace)
+}
+
+/// Returns the identifiers of the functions of `contract_context` found in the
+/// Wasm backtrace of `e`, from the outermost.
+///
+/// Native functions and the top-level expressions of the contract are not part
+/// of the trace. It is empty if the runtime backend doesn't capture backtraces.
+fn stack_trace(e: &wasmtime::Error, contract_context: &ContractContext) -> StackTrace {
+    let mut trace: StackTrace = runtime::backtrace(e)
+        .iter()
+        .filter_map(|name| contract_context.functions.get(name.as_str()))
+        .map(DefinedFunction::get_identifier)
+        .collect();
+    trace.reverse();
+    trace
+}
+
+/// Inserts in `trace` the frames of the functions which were inlined in the
+/// function raising the error, see [crate::inlining].
+///
+/// They come after the frame of their enclosing function, or first if they
+/// were inlined at the top level.
+fn insert_inlined_frames<'a, 'b>(
+    trace: &mut StackTrace,
+    instance: &Instance,
+    store: &mut impl AsContextMut<Data = ClarityWasmContext<'a, 'b>>,
+) {
+    let Some(frames) = instance
+        .get_global(&mut *store, INLINED_FRAMES_GLOBAL)
+        .and_then(|global| global.get(&mut *store).i64())
+        .filter(|frames| *frames != 0)
+    else {
+        return;
+    };
+    let Some(names) = instance
+        .get_memory(&mut *store, "memory")
+        .and_then(|memory| {
+            read_bytes_from_wasm(memory, &mut *store, (frames >> 32) as i32, frames as i32).ok()
+        })
+        .and_then(|bytes| String::from_utf8(bytes).ok())
+    else {
+        return;
+    };
+
+    let context = store.as_context();
+    let contract_context = context.data().contract_context();
+    let mut names = names.split(' ');
+    let position = match names
+        .next()
+        .and_then(|name| contract_context.functions.get(name))
+    {
+        Some(enclosing) => {
+            let enclosing = enclosing.get_identifier();
+            match trace.iter().position(|frame| *frame == enclosing) {
+                Some(position) => position + 1,
+                None => return,
+            }
+        }
+        None => 0,
+    };
+    let inlined: StackTrace = names
+        .filter_map(|name| contract_context.functions.get(name))
+        .map(DefinedFunction::get_identifier)
+        .collect();
+    trace.splice(position..position, inlined);
+}
+
+/// Sets the stack trace of a runtime error.
+///
+/// An error raised in a contract called by this one already holds the frames
+/// of the callee, which are kept after the frames of the caller.
+fn with_stack_trace(mut error: Error, mut trace: StackTrace) -> Error {
+    if let Error::Runtime(_, stack_trace) = &mut error {
+        trace.extend(stack_trace.take().unwrap_or_default());
+        *stack_trace = Some(trace);
+    }
+    error
+}
+
+fn convert_error<'a, 'b>(
     e: wasmtime::Error,
     instance: Instance,
-    mut store: impl AsContextMut,
-    epoch_id: &StacksEpochId,
-    clarity_version: &ClarityVersion,
+    mut store: impl AsContextMut<Data = ClarityWasmContext<'a, 'b>>,
 ) -> Error {
     if let Some(vm_error) = e.root_cause().downcast_ref::<Error>() {
         // SAFETY:
@@ -192,13 +307,34 @@ pub(crate) fn resolve_error(
     // In this case, runtime errors are handled
     // by being mapped to the corresponding ClarityWasm Errors.
     if let Some(Trap::UnreachableCodeReached) = e.root_cause().downcast_ref::<Trap>() {
-        return from_runtime_error_code(instance, &mut store, e, epoch_id, clarity_version);
+        return from_runtime_error_code(instance, &mut store, e);
+    }
+
+    // A Wasm stack overflow is the compiled counterpart of the interpreter
+    // reaching its maximum stack depth.
+    if let Some(Trap::StackOverflow) = e.root_cause().downcast_ref::<Trap>() {
+        return Error::Runtime(RuntimeErrorType::MaxStackDepthReached, Some(Vec::new()));
     }
 
     // All other errors are treated as general runtime errors.
     Error::Wasm(WasmError::Runtime(e))
 }
 
+/// Converts an error raised while instantiating a module into a Clarity `Error`.
+///
+/// Errors coming from the host, like the resource limiter refusing the initial
+/// memory allocation, are returned as is. Any other error means the module
+/// could not be loaded.
+pub(crate) fn resolve_instantiation_error(e: wasmtime::Error, limiter: &mut Limiter) -> Error {
+    if let Some(limit_error) = limiter.take_error() {
+        return limit_error;
+    }
+    match e.downcast::<Error>() {
+        Ok(vm_error) => vm_error,
+        Err(e) => Error::Wasm(WasmError::UnableToLoadModule(e)),
+    }
+}
+
 /// Converts a WebAssembly runtime error code into a Clarity `Error`.
 ///
 /// This function interprets an error code from a WebAssembly runtime execution and
@@ -210,12 +346,10 @@ pub(crate) fn resolve_error(
 /// Returns a Clarity `Error` that corresponds to the runtime error encountered during
 /// WebAssembly execution.
 ///
-fn from_runtime_error_code(
+fn from_runtime_error_code<'a, 'b>(
     instance: Instance,
-    mut store: impl AsContextMut,
+    mut store: impl AsContextMut<Data = ClarityWasmContext<'a, 'b>>,
     e: wasmtime::Error,
-    epoch_id: &StacksEpochId,
-    clarity_version: &ClarityVersion,
 ) -> Error {
     let runtime_error_code = get_global_i32(&instance, &mut store, "runtime-error-code");
 
@@ -243,13 +377,14 @@ fn from_runtime_error_code(
         }
         ErrorMap::Panic => {
             // TODO: see issue: #531
-            // This RuntimeErrorType::UnwrapFailure need to have a proper context.
+            // This RuntimeErrorType::UnwrapFailure need to have a proper context,
+            // only the stack trace locates it for now.
             Error::Runtime(RuntimeErrorType::UnwrapFailure, Some(Vec::new()))
         }
-        ErrorMap::ShortReturnAssertionFailure => {
-            let clarity_val = short_return_value(&instance, &mut store, epoch_id, clarity_version);
-            Error::ShortReturn(ShortReturnType::AssertionFailed(clarity_val))
-        }
+        ErrorMap::ShortReturnAssertionFailure => match short_return_value(&instance, &mut store) {
+            Ok(clarity_val) => Error::ShortReturn(ShortReturnType::AssertionFailed(clarity_val)),
+            Err(e) => e,
+        },
         ErrorMap::ArithmeticPowError => Error::Runtime(
             RuntimeErrorType::Arithmetic(POW_ERROR_MESSAGE.into()),
             Some(Vec::new()),
@@ -274,23 +409,25 @@ fn from_runtime_error_code(
             Error::Unchecked(CheckErrors::NameAlreadyUsed(arg_name))
         }
         ErrorMap::ShortReturnExpectedValueResponse => {
-            let clarity_val = short_return_value(&instance, &mut store, epoch_id, clarity_version);
-            Error::ShortReturn(ShortReturnType::ExpectedValue(Value::Response(
-                ResponseData {
-                    committed: false,
-                    data: Box::new(clarity_val),
-                },
-            )))
+            match short_return_value(&instance, &mut store) {
+                Ok(clarity_val) => Error::ShortReturn(ShortReturnType::ExpectedValue(
+                    Value::Response(ResponseData {
+                        committed: false,
+                        data: Box::new(clarity_val),
+                    }),
+                )),
+                Err(e) => e,
+            }
         }
         ErrorMap::ShortReturnExpectedValueOptional => {
             Error::ShortReturn(ShortReturnType::ExpectedValue(Value::Optional(
                 clarity::vm::types::OptionalData { data: None },
             )))
         }
-        ErrorMap::ShortReturnExpectedValue => {
-            let clarity_val = short_return_value(&instance, &mut store, epoch_id, clarity_version);
-            Error::ShortReturn(ShortReturnType::ExpectedValue(clarity_val))
-        }
+        ErrorMap::ShortReturnExpectedValue => match short_return_value(&instance, &mut store) {
+            Ok(clarity_val) => Error::ShortReturn(ShortReturnType::ExpectedValue(clarity_val)),
+            Err(e) => e,
+        },
         ErrorMap::ArgumentCountMismatch => {
             let (expected, got) = get_runtime_error_arg_lengths(&instance, &mut store);
             Error::Unchecked(CheckErrors::IncorrectArgumentCount(expected, got))
@@ -303,6 +440,18 @@ fn from_runtime_error_code(
             let (expected, got) = get_runtime_error_arg_lengths(&instance, &mut store);
             Error::Unchecked(CheckErrors::RequiresAtMostArguments(expected, got))
         }
+        ErrorMap::InvalidStoredValue => Error::Interpreter(InterpreterError::Expect(
+            "stored value does not match its type".to_string(),
+        )),
+        ErrorMap::MemoryExhausted => {
+            // Replaced by the error of the limiter in `resolve_error`. Without
+            // a limiter, the desired size is unknown and reported as the
+            // current one.
+            let size = instance
+                .get_memory(&mut store, "memory")
+                .map_or(0, |memory| memory.data_size(&store) as u64);
+            Error::from(CostErrors::MemoryBalanceExceeded(size, size))
+        }
         ErrorMap::CostOverrunRuntime => Error::from(CostErrors::CostOverflow),
         ErrorMap::CostOverrunReadCount => Error::from(CostErrors::CostOverflow),
         ErrorMap::CostOverrunReadLength => Error::from(CostErrors::CostOverflow),
@@ -355,34 +504,42 @@ fn extract_expected_and_got(bytes: &[u8]) -> (usize, usize) {
 ///
 /// This function is used to extract a Clarity value that has been stored in WebAssembly memory
 /// as part of a short return operation. It reads necessary metadata from global variables,
-/// deserializes the type information, and then reads and deserializes the actual value.
+/// looks up the type of the value in the type table of the module, and then reads and
+/// deserializes the actual value.
 ///
 /// # Returns
 ///
-/// Returns a deserialized Clarity `Value` representing the short return value.
+/// Returns a deserialized Clarity `Value` representing the short return value, or an error if
+/// the type table or the value can't be read.
 ///
-fn short_return_value(
+fn short_return_value<'a, 'b>(
     instance: &Instance,
-    store: &mut impl AsContextMut,
-    epoch_id: &StacksEpochId,
-    clarity_version: &ClarityVersion,
-) -> Value {
+    store: &mut impl AsContextMut<Data = ClarityWasmContext<'a, 'b>>,
+) -> Result<Value, Error> {
     let val_offset = get_global_i32(instance, store, "runtime-error-value-offset");
-    let type_ser_offset = get_global_i32(instance, store, "runtime-error-type-ser-offset");
-    let type_ser_len = get_global_i32(instance, store, "runtime-error-type-ser-len");
+    let type_id = get_global_i32(instance, store, "runtime-error-type-id");
 
     let memory = instance
         .get_memory(&mut *store, "memory")
-        .unwrap_or_else(|| panic!("Could not find wasm instance memory"));
-
-    let type_ser_str = read_identifier_from_wasm(memory, store, type_ser_offset, type_ser_len)
-        .unwrap_or_else(|e| panic!("Could not recover stringified type: {e}"));
-
-    let value_ty = signature_from_string(&type_ser_str, *clarity_version, *epoch_id)
-        .unwrap_or_else(|e| panic!("Could not recover thrown value: {e}"));
-
-    read_from_wasm_indirect(memory, store, &value_ty, val_offset, *epoch_id)
-        .unwrap_or_else(|e| panic!("Could not read thrown value from memory: {e}"))
+        .ok_or(Error::Wasm(WasmError::MemoryNotFound))?;
+
+    // The table is already decoded if a host function looked it up.
+    let type_table = match store.as_context().data().type_table.clone() {
+        Some(type_table) => type_table,
+        None => {
+            let type_table_offset = get_global_i32(instance, store, "type-table-offset");
+            let type_table_length = get_global_i32(instance, store, "type-table-length");
+            let bytes = read_bytes_from_wasm(memory, store, type_table_offset, type_table_length)?;
+            type_table::decoded_table(&bytes)?
+        }
+    };
+    let value_ty = type_table.get(type_id as usize).ok_or_else(|| {
+        Error::Wasm(WasmError::WasmGeneratorError(format!(
+            "type {type_id} not found in the type table"
+        )))
+    })?;
+
+    read_from_wasm_indirect(memory, store, value_ty, val_offset)
 }
 
 /// Retrieves the argument lengths from the runtime error global variables.
@@ -413,3 +570,100 @@ fn get_runtime_error_arg_lengths(
 
     extract_expected_and_got(&arg_lengths)
 }
+
+#[cfg(test)]
+mod tests {
+    use clarity::vm::callables::FunctionIdentifier;
+    use clarity::vm::errors::{Error, RuntimeErrorType};
+    use clarity::vm::Value;
+
+    use crate::tools::TestEnvironment;
+
+    /// Identifiers of the functions of contract `name`.
+    fn identifiers(
+        env: &TestEnvironment,
+        name: &str,
+        functions: &[&str],
+    ) -> Vec<FunctionIdentifier> {
+        let context = env.get_contract_context(name).unwrap();
+        functions
+            .iter()
+            .map(|function| context.functions[*function].get_identifier())
+            .collect()
+    }
+
+    fn stack_trace(result: Result<Value, Error>) -> Vec<FunctionIdentifier> {
+        match result {
+            Err(Error::Runtime(RuntimeErrorType::UnwrapFailure, Some(trace))) => trace,
+            result => panic!("expected an unwrap failure, got {result:?}"),
+        }
+    }
+
+    #[test]
+    #[cfg_attr(feature = "wasmi", ignore = "wasmi doesn't capture backtraces")]
+    fn runtime_errors_have_a_stack_trace() {
+        let mut env = TestEnvironment::default();
+        env.init_contract_with_snippet(
+            "callee",
+            r#"
+            (define-private (inner (n (optional uint)))
+                (unwrap-panic n))
+            (define-public (fail)
+                (ok (inner none)))
+            "#,
+        )
+        .unwrap();
+        env.init_contract_with_snippet(
+            "caller",
+            r#"
+            (define-private (forward)
+                (contract-call? .callee fail))
+            (define-public (call-callee)
+                (forward))
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            stack_trace(env.call_contract_function("callee", "fail", &[], None)),
+            identifiers(&env, "callee", &["fail", "inner"])
+        );
+
+        // The frames of the caller come first, followed by those of the callee.
+        let mut expected = identifiers(&env, "caller", &["call-callee", "forward"]);
+        expected.extend(identifiers(&env, "callee", &["fail", "inner"]));
+        assert_eq!(
+            stack_trace(env.call_contract_function("caller", "call-callee", &[], None)),
+            expected
+        );
+    }
+
+    #[test]
+    #[cfg_attr(feature = "wasmi", ignore = "wasmi doesn't capture backtraces")]
+    fn inlined_functions_have_a_frame() {
+        let mut env = TestEnvironment::default();
+        env.init_contract_with_snippet(
+            "contract",
+            r#"
+            (define-private (add-some (x (optional int)) (acc int))
+                (+ acc (unwrap-panic x)))
+            (define-private (sum (xs (list 3 (optional int))))
+                (fold add-some xs 0))
+            (define-public (sum-nested)
+                (ok (sum (list (some 1) none))))
+            (define-public (sum-inlined)
+                (ok (fold add-some (list (some 1) none) 0)))
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            stack_trace(env.call_contract_function("contract", "sum-inlined", &[], None)),
+            identifiers(&env, "contract", &["sum-inlined", "add-some"])
+        );
+        assert_eq!(
+            stack_trace(env.call_contract_function("contract", "sum-nested", &[], None)),
+            identifiers(&env, "contract", &["sum-nested", "sum", "add-some"])
+        );
+    }
+}
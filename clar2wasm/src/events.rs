@@ -0,0 +1,191 @@
+//! Streaming of the events emitted by compiled contracts.
+//!
+//! Events are collected in the event batches of the global context, and are
+//! only visible to the embedder once the transaction ends. Subscribers
+//! attached to a [ClarityWasmContext](crate::initialize::ClarityWasmContext)
+//! receive each event as soon as it is emitted instead, along with the contract
+//! emitting it and the depth of the call stack at that point. The callees of
+//! `contract-call?` run with the subscribers of their caller, so their events
+//! are delivered the same way.
+//!
+//! Since they are notified immediately, subscribers may see events which are
+//! later discarded, because the call emitting them is rolled back.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use clarity::vm::events::StacksTransactionEvent;
+use clarity::vm::types::QualifiedContractIdentifier;
+
+/// An event, as seen by an [EventSubscriber].
+#[derive(Debug, Clone, Copy)]
+pub struct ContractEvent<'a> {
+    /// The event pushed to the current event batch.
+    pub event: &'a StacksTransactionEvent,
+    /// The contract emitting the event.
+    pub contract: &'a QualifiedContractIdentifier,
+    /// Depth of the call stack when the event was emitted.
+    pub depth: usize,
+}
+
+/// Receives the events emitted while running a contract.
+pub trait EventSubscriber {
+    fn on_event(&mut self, event: &ContractEvent);
+}
+
+impl<F> EventSubscriber for F
+where
+    F: FnMut(&ContractEvent),
+{
+    fn on_event(&mut self, event: &ContractEvent) {
+        self(event)
+    }
+}
+
+/// A subscriber shared between the embedder and the contexts it is attached to.
+pub type SharedEventSubscriber = Rc<RefCell<dyn EventSubscriber>>;
+
+/// Wraps `subscriber` so that it can be attached to a context.
+pub fn shared_subscriber<S: EventSubscriber + 'static>(subscriber: S) -> SharedEventSubscriber {
+    Rc::new(RefCell::new(subscriber))
+}
+
+/// Notifies every subscriber of `subscribers` of the event.
+pub(crate) fn notify(subscribers: &[SharedEventSubscriber], event: &ContractEvent) {
+    for subscriber in subscribers {
+        subscriber.borrow_mut().on_event(event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clarity::vm::events::SmartContractEventData;
+    use clarity::vm::types::StandardPrincipalData;
+    use clarity::vm::Value;
+
+    use super::*;
+    use crate::tools::{TestConfig, TestEnvironment};
+
+    /// Records the printed values, with the emitting contract and call depth.
+    type Prints = Rc<RefCell<Vec<(Value, String, usize)>>>;
+
+    fn env_recording_prints() -> (TestEnvironment, Prints) {
+        let mut env =
+            TestEnvironment::new(TestConfig::latest_epoch(), TestConfig::clarity_version());
+        let prints = Prints::default();
+        let recorded = prints.clone();
+        env.subscribe(shared_subscriber(move |event: &ContractEvent| {
+            if let StacksTransactionEvent::SmartContractEvent(SmartContractEventData {
+                value,
+                ..
+            }) = event.event
+            {
+                recorded.borrow_mut().push((
+                    value.clone(),
+                    event.contract.name.to_string(),
+                    event.depth,
+                ));
+            }
+        }));
+        (env, prints)
+    }
+
+    #[test]
+    fn subscriber_receives_prints_in_order() {
+        let (mut env, prints) = env_recording_prints();
+        env.evaluate("(print 1) (print u2)")
+            .expect("Failed to init contract.");
+
+        assert_eq!(
+            *prints.borrow(),
+            vec![
+                (Value::Int(1), "snippet".to_string(), 0),
+                (Value::UInt(2), "snippet".to_string(), 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn subscriber_receives_nested_events() {
+        let (mut env, prints) = env_recording_prints();
+        env.init_contract_with_snippet("callee", "(define-public (foo) (ok (print u1)))")
+            .expect("Failed to init contract.");
+
+        let snippet = format!(
+            "(print 0) (contract-call? '{}.callee foo)",
+            StandardPrincipalData::transient()
+        );
+        env.init_contract_with_snippet("caller", &snippet)
+            .expect("Failed to init contract.");
+
+        assert_eq!(
+            *prints.borrow(),
+            vec![
+                (Value::Int(0), "caller".to_string(), 0),
+                (Value::UInt(1), "callee".to_string(), 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn nested_events_have_their_depth() {
+        let (mut env, prints) = env_recording_prints();
+        let issuer = StandardPrincipalData::transient();
+        env.init_contract_with_snippet("inner", "(define-public (foo) (ok (print u2)))")
+            .expect("Failed to init contract.");
+        env.init_contract_with_snippet(
+            "middle",
+            &format!(
+                "(define-public (foo) (begin (print u1) (contract-call? '{issuer}.inner foo)))"
+            ),
+        )
+        .expect("Failed to init contract.");
+
+        let snippet = format!("(contract-call? '{issuer}.middle foo) (print u3)");
+        env.init_contract_with_snippet("outer", &snippet)
+            .expect("Failed to init contract.");
+
+        assert_eq!(
+            *prints.borrow(),
+            vec![
+                (Value::UInt(1), "middle".to_string(), 1),
+                (Value::UInt(2), "inner".to_string(), 2),
+                (Value::UInt(3), "outer".to_string(), 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn subscriber_receives_events_of_failing_callee() {
+        let (mut env, prints) = env_recording_prints();
+        env.init_contract_with_snippet(
+            "callee",
+            "(define-public (fail) (begin (print u1) (ok (unwrap-panic (element-at? (list u2) u1)))))",
+        )
+        .expect("Failed to init contract.");
+
+        let snippet = format!(
+            "(print 0) (contract-call? '{}.callee fail)",
+            StandardPrincipalData::transient()
+        );
+        env.init_contract_with_snippet("caller", &snippet)
+            .expect_err("callee should fail");
+
+        assert_eq!(
+            *prints.borrow(),
+            vec![
+                (Value::Int(0), "caller".to_string(), 0),
+                (Value::UInt(1), "callee".to_string(), 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn events_are_still_batched() {
+        let (mut env, prints) = env_recording_prints();
+        env.evaluate("(print 1)").expect("Failed to init contract.");
+
+        let batched: usize = env.get_events().iter().map(|b| b.events.len()).sum();
+        assert_eq!(batched, prints.borrow().len());
+    }
+}
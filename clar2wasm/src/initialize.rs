@@ -1,13 +1,17 @@
 use clarity::vm::analysis::ContractAnalysis;
 use clarity::vm::contexts::GlobalContext;
-use clarity::vm::errors::{Error, RuntimeErrorType, WasmError};
+use clarity::vm::errors::{CheckErrors, Error, RuntimeErrorType, WasmError};
 use clarity::vm::events::*;
 use clarity::vm::types::{AssetIdentifier, BuffData, PrincipalData, QualifiedContractIdentifier};
 use clarity::vm::{CallStack, ContractContext, Value};
 use stacks_common::types::chainstate::StacksBlockId;
-use wasmtime::{Linker, Module, Store};
 
+use crate::coverage::{collect_coverage, SharedCoverage};
+use crate::events::{self, ContractEvent, SharedEventSubscriber};
+use crate::limits::{Limiter, ResourceLimits};
 use crate::linker::link_host_functions;
+use crate::runtime::{self, Engine, Linker, Module, Store};
+use crate::type_table::DecodedTable;
 use crate::wasm_utils::*;
 use crate::{error_mapping, CostLinker};
 
@@ -31,6 +35,50 @@ pub struct ClarityWasmContext<'a, 'b> {
     /// when initializing a contract. Should always be `Some` when initializing
     /// a contract, and `None` otherwise.
     pub contract_analysis: Option<&'a ContractAnalysis>,
+
+    /// Resource limiter installed in the store running the contract.
+    pub(crate) limiter: Limiter,
+
+    /// Engine of the execution options, passed on to the called contracts.
+    engine: Option<Engine>,
+
+    /// Subscribers notified of every event emitted by the contract.
+    subscribers: Vec<SharedEventSubscriber>,
+
+    /// Counters of the executed expressions, for instrumented contracts.
+    pub(crate) coverage: Option<SharedCoverage>,
+
+    /// The decoded type table of the module, looked up on first use (see
+    /// [crate::type_table]).
+    pub(crate) type_table: Option<DecodedTable>,
+}
+
+/// Options applied when running a compiled contract.
+#[derive(Clone, Default)]
+pub struct ExecutionOptions {
+    /// Resource limits enforced while running the contract.
+    pub limits: ResourceLimits,
+    /// Engine running the contract, which must enforce the limits applied by
+    /// the engine itself (see [runtime::limited_engine]). It is shared by the
+    /// contracts called with these options, and `None` runs them with the
+    /// engine of the global context.
+    pub engine: Option<Engine>,
+    /// Subscribers notified of each event as soon as it is emitted.
+    pub subscribers: Vec<SharedEventSubscriber>,
+    /// Collects the coverage counters of instrumented contracts.
+    pub coverage: Option<SharedCoverage>,
+}
+
+impl ExecutionOptions {
+    /// Options enforcing `limits`, with an engine built for them if they
+    /// need one.
+    pub fn with_limits(limits: ResourceLimits) -> Result<Self, Error> {
+        Ok(Self {
+            limits,
+            engine: runtime::limited_engine(&limits)?,
+            ..Default::default()
+        })
+    }
 }
 
 impl<'a, 'b> ClarityWasmContext<'a, 'b> {
@@ -55,6 +103,11 @@ impl<'a, 'b> ClarityWasmContext<'a, 'b> {
             caller_stack: vec![],
             bhh_stack: vec![],
             contract_analysis,
+            limiter: Limiter::default(),
+            engine: None,
+            subscribers: vec![],
+            coverage: None,
+            type_table: None,
         }
     }
 
@@ -79,6 +132,62 @@ impl<'a, 'b> ClarityWasmContext<'a, 'b> {
             caller_stack: vec![],
             bhh_stack: vec![],
             contract_analysis,
+            limiter: Limiter::default(),
+            engine: None,
+            subscribers: vec![],
+            coverage: None,
+            type_table: None,
+        }
+    }
+
+    /// Set the resource limits enforced while running the contract.
+    pub fn set_resource_limits(&mut self, limits: ResourceLimits) {
+        self.limiter = Limiter::new(limits);
+    }
+
+    pub fn resource_limits(&self) -> &ResourceLimits {
+        self.limiter.limits()
+    }
+
+    /// Attach a subscriber, notified of each event emitted from now on.
+    pub fn subscribe(&mut self, subscriber: SharedEventSubscriber) {
+        self.subscribers.push(subscriber);
+    }
+
+    /// Apply the resource limits, subscribers and coverage counters of `options`.
+    pub fn apply_options(&mut self, options: ExecutionOptions) {
+        self.set_resource_limits(options.limits);
+        self.engine = options.engine;
+        self.subscribers.extend(options.subscribers);
+        self.coverage = options.coverage;
+    }
+
+    /// The options this context runs with, applied to the contracts it calls.
+    pub(crate) fn execution_options(&self) -> ExecutionOptions {
+        ExecutionOptions {
+            limits: *self.resource_limits(),
+            engine: self.engine.clone(),
+            subscribers: self.subscribers.clone(),
+            coverage: self.coverage.clone(),
+        }
+    }
+
+    /// Return `true` if the current frame must not modify the state, either
+    /// because it is running a read-only function or an `at-block` expression,
+    /// here or in one of its callers.
+    pub fn is_read_only(&self) -> bool {
+        self.global_context.is_read_only()
+    }
+
+    /// Return an error if the current frame is read-only. Host functions
+    /// modifying the state call this first, so that a module writing from a
+    /// read-only context fails like the interpreter would, even if it was not
+    /// produced by our compiler.
+    pub fn check_writable(&self) -> Result<(), Error> {
+        if self.is_read_only() {
+            Err(CheckErrors::WriteAttemptedInReadOnly.into())
+        } else {
+            Ok(())
         }
     }
 
@@ -147,6 +256,16 @@ impl<'a, 'b> ClarityWasmContext<'a, 'b> {
     }
 
     pub fn push_to_event_batch(&mut self, event: StacksTransactionEvent) {
+        if !self.subscribers.is_empty() {
+            events::notify(
+                &self.subscribers,
+                &ContractEvent {
+                    event: &event,
+                    contract: &self.contract_context().contract_identifier,
+                    depth: self.call_stack.depth(),
+                },
+            );
+        }
         if let Some(batch) = self.global_context.event_batches.last_mut() {
             batch.events.push(event);
         }
@@ -320,14 +439,46 @@ pub fn initialize_contract(
     contract_context: &mut ContractContext,
     sponsor: Option<PrincipalData>,
     contract_analysis: &ContractAnalysis,
+) -> Result<Option<Value>, Error> {
+    initialize_contract_with_limits(
+        global_context,
+        contract_context,
+        sponsor,
+        contract_analysis,
+        ResourceLimits::default(),
+    )
+}
+
+/// Same as [initialize_contract], but enforcing the given resource limits.
+pub fn initialize_contract_with_limits(
+    global_context: &mut GlobalContext,
+    contract_context: &mut ContractContext,
+    sponsor: Option<PrincipalData>,
+    contract_analysis: &ContractAnalysis,
+    limits: ResourceLimits,
+) -> Result<Option<Value>, Error> {
+    initialize_contract_with_options(
+        global_context,
+        contract_context,
+        sponsor,
+        contract_analysis,
+        ExecutionOptions::with_limits(limits)?,
+    )
+}
+
+/// Same as [initialize_contract], but applying the given execution options.
+pub fn initialize_contract_with_options(
+    global_context: &mut GlobalContext,
+    contract_context: &mut ContractContext,
+    sponsor: Option<PrincipalData>,
+    contract_analysis: &ContractAnalysis,
+    options: ExecutionOptions,
 ) -> Result<Option<Value>, Error> {
     let publisher: PrincipalData = contract_context.contract_identifier.issuer.clone().into();
 
     let mut call_stack = CallStack::new();
-    let epoch = global_context.epoch_id;
-    let clarity_version = *contract_context.get_clarity_version();
-    let engine = global_context.engine.clone();
-    let init_context = ClarityWasmContext::new_init(
+    let engine = runtime::engine(global_context, &options);
+    let mut init_context = ClarityWasmContext::new_init(
         global_context,
         contract_context,
         &mut call_stack,
@@ -336,6 +487,7 @@ pub fn initialize_contract(
         sponsor.clone(),
         Some(contract_analysis),
     );
+    init_context.apply_options(options);
     let module = init_context
         .contract_context()
         .with_wasm_module(|wasm_module| {
@@ -343,6 +495,7 @@ pub fn initialize_contract(
                 .map_err(|e| Error::Wasm(WasmError::UnableToLoadModule(e)))
         })?;
     let mut store = Store::new(&engine, init_context);
+    store.limiter(|context| &mut context.limiter);
     let mut linker = Linker::new(&engine);
     // Link in the host interface functions.
     link_host_functions(&mut linker)?;
@@ -350,9 +503,9 @@ pub fn initialize_contract(
         .define_cost_globals(&mut store)
         .map_err(|e| Error::Wasm(WasmError::UnableToLoadModule(e)))?;
 
-    let instance = linker
-        .instantiate(&mut store, &module)
-        .map_err(|e| Error::Wasm(WasmError::UnableToLoadModule(e)))?;
+    let instance = linker.instantiate(&mut store, &module).map_err(|e| {
+        error_mapping::resolve_instantiation_error(e, &mut store.data_mut().limiter)
+    })?;
 
     // Call the `.top-level` function, which contains all top-level expressions
     // from the contract.
@@ -361,18 +514,16 @@ pub fn initialize_contract(
         .ok_or(Error::Wasm(WasmError::DefinesNotFound))?;
 
     // Get the return type of the top-level expressions function
-    let ty = top_level.ty(&mut store);
-    let results_iter = ty.results();
-    let mut results = vec![];
-    for result_ty in results_iter {
-        results.push(placeholder_for_type(result_ty));
-    }
+    let mut results = runtime::result_placeholders(&top_level, &mut store);
 
-    top_level
+    let call_result = top_level
         .call(&mut store, &[], results.as_mut_slice())
-        .map_err(|e| {
-            error_mapping::resolve_error(e, instance, &mut store, &epoch, &clarity_version)
-        })?;
+        .map_err(|e| error_mapping::resolve_error(e, instance, &mut store));
+    // The counters are collected even if the call failed, but the error of the
+    // call takes precedence over a failure to collect them.
+    let coverage = collect_coverage(&instance, &mut store);
+    call_result?;
+    coverage?;
 
     // Save the compiled Wasm module into the contract context
     store.data_mut().contract_context_mut()?.set_wasm_module(
@@ -394,7 +545,7 @@ pub fn initialize_contract(
         let memory = instance
             .get_memory(&mut store, "memory")
             .ok_or(Error::Wasm(WasmError::MemoryNotFound))?;
-        wasm_to_clarity_value(return_type, 0, &results, memory, &mut &mut store, epoch)
+        wasm_to_clarity_value(return_type, 0, &results, memory, &mut &mut store)
             .map(|(val, _offset)| val)
     } else {
         Ok(None)
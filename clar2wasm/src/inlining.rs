@@ -0,0 +1,360 @@
+//! Inlining of small private functions in the loops of `fold`, `map` and
+//! `filter`.
+//!
+//! Calling a private function for each element of a sequence goes through a
+//! call, which saves and restores the stack pointer. When the function is
+//! small, its body is instead traversed in the loop, with its parameters
+//! bound to the elements. Functions which can return early (with `asserts!`,
+//! `try!`, `unwrap!` or `unwrap-err!`) are not inlined, since their early
+//! returns would leave the enclosing function, and neither are functions
+//! returning in-memory values, which would not outlive the iteration.
+//!
+//! An inlined function has no Wasm frame, so while its body runs, the
+//! [INLINED_FRAMES_GLOBAL] global holds the offset (high 32 bits) and length
+//! (low 32 bits) of a literal listing, separated by spaces, the enclosing
+//! function (empty at the top level) followed by the functions being inlined.
+//! The host adds their frames to the stack trace of runtime errors.
+//!
+//! Functions are not inlined in modules instrumented for coverage, whose
+//! probes would be counted again in each inlined copy of the body.
+
+use clarity::vm::types::FunctionType;
+use clarity::vm::{ClarityName, SymbolicExpression, SymbolicExpressionType};
+use walrus::ir::Value;
+use walrus::{GlobalId, InitExpr, InstrSeqBuilder, ValType};
+
+use crate::wasm_generator::{has_in_memory_type, Bindings, GeneratorError, WasmGenerator};
+
+/// Name of the exported global holding the frames of the inlined functions.
+pub(crate) const INLINED_FRAMES_GLOBAL: &str = "inlined-frames";
+
+/// Maximum number of expressions in the body of an inlined function.
+const MAX_INLINED_SIZE: usize = 32;
+
+/// Words returning early from the function they are used in.
+const EARLY_RETURNS: [&str; 4] = ["asserts!", "try!", "unwrap!", "unwrap-err!"];
+
+/// Counts the expressions in `expr`, or returns `None` if it can return early.
+fn inlined_size(expr: &SymbolicExpression) -> Option<usize> {
+    match &expr.expr {
+        SymbolicExpressionType::List(exprs) => {
+            if let Some(name) = exprs.first().and_then(|e| e.match_atom()) {
+                if EARLY_RETURNS.contains(&name.as_str()) {
+                    return None;
+                }
+            }
+            exprs
+                .iter()
+                .try_fold(1, |size, expr| Some(size + inlined_size(expr)?))
+        }
+        _ => Some(1),
+    }
+}
+
+impl WasmGenerator {
+    /// Keeps the body of the private function `name` to be inlined in the
+    /// sequence loops calling it, if it can be.
+    pub(crate) fn register_inlinable_function(
+        &mut self,
+        name: &ClarityName,
+        body: &SymbolicExpression,
+    ) {
+        if self.coverage.is_some() {
+            return;
+        }
+
+        let Some(FunctionType::Fixed(function_type)) = self.get_function_type(name) else {
+            return;
+        };
+
+        // A reused parameter name makes the function raise an error instead
+        // of evaluating its body.
+        let unique_params = function_type
+            .args
+            .iter()
+            .enumerate()
+            .all(|(i, param)| function_type.args[..i].iter().all(|p| p.name != param.name));
+
+        if unique_params
+            && !has_in_memory_type(&function_type.returns)
+            && matches!(inlined_size(body), Some(size) if size <= MAX_INLINED_SIZE)
+        {
+            self.inlinable_functions.insert(name.clone(), body.clone());
+        }
+    }
+
+    /// Returns true if calls to the private function `name` in sequence
+    /// loops are inlined.
+    pub(crate) fn is_inlinable(&self, name: &ClarityName) -> bool {
+        self.inlinable_functions.contains_key(name)
+    }
+
+    /// Returns the global holding the frames of the inlined functions, adding
+    /// it on first use.
+    fn inlined_frames_global(&mut self) -> GlobalId {
+        *self.inlined_frames.get_or_insert_with(|| {
+            let global =
+                self.module
+                    .globals
+                    .add_local(ValType::I64, true, InitExpr::Value(Value::I64(0)));
+            self.module.exports.add(INLINED_FRAMES_GLOBAL, global);
+            global
+        })
+    }
+
+    /// Adds the literal listing the enclosing function and the functions
+    /// being inlined, and returns its offset and length packed in an `i64`.
+    fn inlined_frames_literal(&mut self) -> Result<i64, GeneratorError> {
+        let enclosing = self
+            .current_function_name
+            .as_ref()
+            .map_or("", |name| name.as_str());
+        let frames = std::iter::once(enclosing)
+            .chain(self.inlined_functions.iter().map(|name| name.as_str()))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let (offset, len) = self.add_string_literal(&frames)?;
+        Ok(((offset as i64) << 32) | len as i64)
+    }
+
+    /// Inlines a call to the private function `name`, whose arguments have
+    /// already been pushed to the stack.
+    pub(crate) fn inline_call(
+        &mut self,
+        builder: &mut InstrSeqBuilder,
+        name: &ClarityName,
+    ) -> Result<(), GeneratorError> {
+        let (Some(body), Some(FunctionType::Fixed(function_type))) = (
+            self.inlinable_functions.get(name).cloned(),
+            self.get_function_type(name).cloned(),
+        ) else {
+            return Err(GeneratorError::InternalError(format!(
+                "function {name} cannot be inlined"
+            )));
+        };
+
+        // Bind the arguments to the parameters, popping them in reverse order.
+        let mut bindings = Bindings::new();
+        for param in function_type.args.iter().rev() {
+            let locals = self.save_to_locals(builder, &param.signature, true);
+            bindings.insert(param.name.clone(), param.signature.clone(), locals);
+        }
+
+        // Like a call, the body cannot see the bindings of the caller, and
+        // the stack space it uses is released after it.
+        let frame_pointer = self.module.locals.add(ValType::I32);
+        builder
+            .global_get(self.stack_pointer)
+            .local_set(frame_pointer);
+
+        // While the body runs, the global holds the frames of the inlined
+        // functions, including this one, and those of the caller after it.
+        self.inlined_functions.push(name.clone());
+        let frames = self.inlined_frames_literal()?;
+        let frames_global = self.inlined_frames_global();
+        let caller_frames = self.module.locals.add(ValType::I64);
+        builder
+            .global_get(frames_global)
+            .local_set(caller_frames)
+            .i64_const(frames)
+            .global_set(frames_global);
+
+        let caller_bindings = std::mem::replace(&mut self.bindings, bindings);
+        let caller_type = self.current_function_type.replace(function_type.clone());
+        let caller_return_block = self.early_return_block_id.take();
+
+        self.set_expr_type(&body, function_type.returns)?;
+        let result = self.traverse_expr(builder, &body);
+
+        self.bindings = caller_bindings;
+        self.current_function_type = caller_type;
+        self.early_return_block_id = caller_return_block;
+        self.inlined_functions.pop();
+        result?;
+
+        builder
+            .local_get(caller_frames)
+            .global_set(frames_global)
+            .local_get(frame_pointer)
+            .global_set(self.stack_pointer);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clarity::types::StacksEpochId;
+    use clarity::vm::analysis::AnalysisDatabase;
+    use clarity::vm::costs::LimitedCostTracker;
+    use clarity::vm::database::MemoryBackingStore;
+    use clarity::vm::types::{QualifiedContractIdentifier, StandardPrincipalData, TupleData};
+    use clarity::vm::{ClarityVersion, Value};
+    use walrus::ir::{dfs_in_order, Call, Instr, InstrLocId, Visitor};
+    use walrus::ExportItem;
+
+    use crate::compile_with_coverage;
+    use crate::tools::crosscheck;
+
+    #[derive(Default)]
+    struct CalledFunctions(Vec<walrus::FunctionId>);
+
+    impl<'instr> Visitor<'instr> for CalledFunctions {
+        fn visit_instr(&mut self, instr: &'instr Instr, _: &'instr InstrLocId) {
+            if let Instr::Call(Call { func }) = instr {
+                self.0.push(*func);
+            }
+        }
+    }
+
+    /// Returns the names of the functions called by the top level of
+    /// `snippet`.
+    fn top_level_calls(snippet: &str, coverage: bool) -> Vec<String> {
+        let module = compile_with_coverage(
+            snippet,
+            &QualifiedContractIdentifier::new(StandardPrincipalData::transient(), ("tmp").into()),
+            LimitedCostTracker::new_free(),
+            ClarityVersion::Clarity2,
+            StacksEpochId::Epoch25,
+            &mut AnalysisDatabase::new(&mut MemoryBackingStore::new()),
+            false,
+            coverage,
+        )
+        .unwrap()
+        .module;
+        let top_level = module
+            .exports
+            .iter()
+            .find_map(|export| match export.item {
+                ExportItem::Function(id) if export.name == ".top-level" => Some(id),
+                _ => None,
+            })
+            .unwrap();
+        let function = module.funcs.get(top_level).kind.unwrap_local();
+
+        let mut calls = CalledFunctions::default();
+        dfs_in_order(&mut calls, function, function.entry_block());
+        calls
+            .0
+            .into_iter()
+            .filter_map(|id| module.funcs.get(id).name.clone())
+            .collect()
+    }
+
+    #[test]
+    fn inlined_function_is_not_called() {
+        let snippet = "
+(define-private (add-square (x int) (acc int)) (+ acc (* x x)))
+(fold add-square (list 1 2 3 4) 0)
+";
+        assert!(!top_level_calls(snippet, false).contains(&"add-square".to_owned()));
+    }
+
+    #[test]
+    fn early_returning_function_is_called() {
+        let snippet = "
+(define-private (check (x int) (acc (response int int)))
+  (begin (asserts! (> x 0) (err x)) (ok (+ x (try! acc)))))
+(fold check (list 1 2 -3 4) (ok 0))
+";
+        assert!(top_level_calls(snippet, false).contains(&"check".to_owned()));
+    }
+
+    #[test]
+    fn functions_are_not_inlined_with_coverage() {
+        let snippet = "
+(define-private (add-square (x int) (acc int)) (+ acc (* x x)))
+(fold add-square (list 1 2 3 4) 0)
+";
+        assert!(top_level_calls(snippet, true).contains(&"add-square".to_owned()));
+    }
+
+    #[test]
+    fn fold_inlined_function() {
+        crosscheck(
+            "
+(define-private (add-square (x int) (acc int)) (+ acc (* x x)))
+(fold add-square (list 1 2 3 4) 0)
+",
+            Ok(Some(Value::Int(30))),
+        );
+    }
+
+    #[test]
+    fn map_inlined_function() {
+        crosscheck(
+            "
+(define-private (pair (a int) (b uint)) {a: a, b: b})
+(map pair (list 1 2) (list u3 u4 u5))
+",
+            Ok(Some(
+                Value::cons_list_unsanitized(vec![
+                    Value::Tuple(
+                        TupleData::from_data(vec![
+                            ("a".into(), Value::Int(1)),
+                            ("b".into(), Value::UInt(3)),
+                        ])
+                        .unwrap(),
+                    ),
+                    Value::Tuple(
+                        TupleData::from_data(vec![
+                            ("a".into(), Value::Int(2)),
+                            ("b".into(), Value::UInt(4)),
+                        ])
+                        .unwrap(),
+                    ),
+                ])
+                .unwrap(),
+            )),
+        );
+    }
+
+    #[test]
+    fn filter_inlined_function() {
+        crosscheck(
+            "
+(define-private (is-even (x int)) (is-eq (mod x 2) 0))
+(filter is-even (list 1 2 3 4 5 6))
+",
+            Ok(Some(
+                Value::cons_list_unsanitized(vec![Value::Int(2), Value::Int(4), Value::Int(6)])
+                    .unwrap(),
+            )),
+        );
+    }
+
+    #[test]
+    fn inlined_parameters_shadow_caller_bindings() {
+        crosscheck(
+            "
+(define-constant x 10)
+(define-private (add-x (y int) (acc int)) (+ acc y x))
+(let ((y 100)) (fold add-x (list 1 2) y))
+",
+            Ok(Some(Value::Int(123))),
+        );
+    }
+
+    #[test]
+    fn early_return_is_not_inlined() {
+        crosscheck(
+            "
+(define-private (check (x int) (acc (response int int)))
+  (begin (asserts! (> x 0) (err x)) (ok (+ x (try! acc)))))
+(fold check (list 1 2 -3 4) (ok 0))
+",
+            Ok(Some(Value::error(Value::Int(-3)).unwrap())),
+        );
+    }
+
+    #[test]
+    fn inlined_function_releases_stack_space() {
+        crosscheck(
+            "
+(define-private (count-bytes (x int) (acc uint)) (+ acc (len (unwrap-panic (to-consensus-buff? x)))))
+(fold count-bytes (list 1 2 3 4 5 6 7 8 9 10) u0)
+",
+            Ok(Some(Value::UInt(170))),
+        );
+    }
+}
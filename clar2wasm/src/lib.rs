@@ -1,20 +1,34 @@
+use std::fmt::Write as _;
+
 use clarity::types::StacksEpochId;
 use clarity::vm::analysis::{run_analysis, AnalysisDatabase, ContractAnalysis};
 use clarity::vm::ast::{build_ast_with_diagnostics, ContractAST};
 use clarity::vm::costs::{ExecutionCost, LimitedCostTracker};
 use clarity::vm::diagnostic::Diagnostic;
+use clarity::vm::representations::Span;
 use clarity::vm::types::QualifiedContractIdentifier;
 use clarity::vm::ClarityVersion;
 pub use walrus::Module;
 use wasm_generator::{GeneratorError, WasmGenerator};
 
+mod bulk_memory;
+mod cache;
 mod cost;
 pub use cost::{AccessCostMeter, CostGlobals, CostLinker, CostMeter};
 
+pub mod coverage;
 mod deserialize;
+mod dynamic_memory;
+pub mod events;
 pub mod initialize;
+mod inlining;
+pub mod limits;
 pub mod linker;
+pub mod marshal;
+pub mod post_conditions;
+pub mod runtime;
 mod serialize;
+mod type_table;
 pub mod wasm_generator;
 pub mod wasm_utils;
 mod words;
@@ -29,6 +43,9 @@ mod error_mapping;
 #[cfg(feature = "developer-mode")]
 pub mod test_utils;
 
+#[cfg(feature = "arbitrary")]
+pub mod arbitrary;
+
 // FIXME: This is copied from stacks-blockchain
 // Block limit in Stacks 2.1
 pub const BLOCK_LIMIT_MAINNET_21: ExecutionCost = ExecutionCost {
@@ -47,23 +64,216 @@ pub struct CompileResult {
     pub contract_analysis: ContractAnalysis,
 }
 
+/// A failed compilation, by phase of the compiler.
+///
+/// Each variant holds the AST, the diagnostics collected up to the failure,
+/// whose last entry describes it, and the cost tracker.
 #[derive(Debug)]
 pub enum CompileError {
-    Generic {
+    /// The contract could not be parsed.
+    Parse {
+        ast: Box<ContractAST>,
+        diagnostics: Vec<Diagnostic>,
+        cost_tracker: Box<LimitedCostTracker>,
+    },
+    /// The contract was rejected by the analysis passes.
+    Analysis {
+        ast: Box<ContractAST>,
+        diagnostics: Vec<Diagnostic>,
+        cost_tracker: Box<LimitedCostTracker>,
+    },
+    /// The types inferred by the analysis could not be made concrete.
+    Concretization {
         ast: Box<ContractAST>,
         diagnostics: Vec<Diagnostic>,
         cost_tracker: Box<LimitedCostTracker>,
     },
+    /// The Wasm module could not be generated.
+    Generation {
+        ast: Box<ContractAST>,
+        diagnostics: Vec<Diagnostic>,
+        cost_tracker: Box<LimitedCostTracker>,
+        error: GeneratorError,
+    },
+}
+
+impl CompileError {
+    /// The name of the phase which failed.
+    pub fn phase(&self) -> &'static str {
+        match self {
+            CompileError::Parse { .. } => "parse",
+            CompileError::Analysis { .. } => "analysis",
+            CompileError::Concretization { .. } => "type concretization",
+            CompileError::Generation { .. } => "code generation",
+        }
+    }
+
+    pub fn ast(&self) -> &ContractAST {
+        match self {
+            CompileError::Parse { ast, .. }
+            | CompileError::Analysis { ast, .. }
+            | CompileError::Concretization { ast, .. }
+            | CompileError::Generation { ast, .. } => ast,
+        }
+    }
+
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        match self {
+            CompileError::Parse { diagnostics, .. }
+            | CompileError::Analysis { diagnostics, .. }
+            | CompileError::Concretization { diagnostics, .. }
+            | CompileError::Generation { diagnostics, .. } => diagnostics,
+        }
+    }
+
+    pub fn into_cost_tracker(self) -> LimitedCostTracker {
+        match self {
+            CompileError::Parse { cost_tracker, .. }
+            | CompileError::Analysis { cost_tracker, .. }
+            | CompileError::Concretization { cost_tracker, .. }
+            | CompileError::Generation { cost_tracker, .. } => *cost_tracker,
+        }
+    }
+
+    /// Renders the error diagnostics, with an excerpt of `source` under
+    /// each located one.
+    pub fn render(&self, source: &str) -> String {
+        let mut rendered = format!("{} failed\n", self.phase());
+        for diagnostic in self.diagnostics() {
+            let _ = writeln!(rendered, "{}", diagnostic_message(diagnostic));
+            if let Some(span) = diagnostic.spans.first() {
+                rendered.push_str(&source_excerpt(source, span));
+            }
+        }
+        rendered
+    }
+}
+
+/// The message of a diagnostic, without the location printed by its
+/// `Display` implementation.
+fn diagnostic_message(diagnostic: &Diagnostic) -> String {
+    let level = format!("{:?}", diagnostic.level).to_lowercase();
+    let mut message = format!("{level}: {}", diagnostic.message);
+    if let Some(suggestion) = &diagnostic.suggestion {
+        let _ = write!(message, "\n  {suggestion}");
+    }
+    message
+}
+
+/// Formats the first line of `span` in `source`, underlining the spanned
+/// columns.
+fn source_excerpt(source: &str, span: &Span) -> String {
+    // Lines are numbered from 1, a span on line 0 has no location.
+    let Some(line) = (span.start_line as usize)
+        .checked_sub(1)
+        .and_then(|index| source.lines().nth(index))
+    else {
+        return String::new();
+    };
+    let number = span.start_line.to_string();
+    let padding = " ".repeat(number.len());
+    let start = (span.start_column as usize).saturating_sub(1);
+    let end = if span.end_line == span.start_line {
+        span.end_column as usize
+    } else {
+        line.chars().count()
+    };
+    format!(
+        "{padding}--> line {}, column {}\n{padding} |\n{number} | {line}\n{padding} | {}{}\n",
+        span.start_line,
+        span.start_column,
+        " ".repeat(start),
+        "^".repeat(end.saturating_sub(start).max(1)),
+    )
 }
 
 pub fn compile(
     source: &str,
     contract_id: &QualifiedContractIdentifier,
-    mut cost_tracker: LimitedCostTracker,
+    cost_tracker: LimitedCostTracker,
     clarity_version: ClarityVersion,
     epoch: StacksEpochId,
     analysis_db: &mut AnalysisDatabase,
     emit_cost_code: bool,
+) -> Result<CompileResult, CompileError> {
+    compile_with_coverage(
+        source,
+        contract_id,
+        cost_tracker,
+        clarity_version,
+        epoch,
+        analysis_db,
+        emit_cost_code,
+        false,
+    )
+}
+
+/// Same as [compile], instrumenting the module with coverage counters if
+/// `coverage` is set (see [coverage]).
+#[allow(clippy::too_many_arguments)]
+pub fn compile_with_coverage(
+    source: &str,
+    contract_id: &QualifiedContractIdentifier,
+    cost_tracker: LimitedCostTracker,
+    clarity_version: ClarityVersion,
+    epoch: StacksEpochId,
+    analysis_db: &mut AnalysisDatabase,
+    emit_cost_code: bool,
+    coverage: bool,
+) -> Result<CompileResult, CompileError> {
+    compile_with_options(
+        source,
+        contract_id,
+        cost_tracker,
+        clarity_version,
+        epoch,
+        analysis_db,
+        GeneratorOptions {
+            emit_cost_code,
+            coverage,
+            ..GeneratorOptions::default()
+        },
+    )
+}
+
+/// Options of the code generation, see [compile_with_options].
+#[derive(Debug, Clone, Copy)]
+pub struct GeneratorOptions {
+    /// Emits cost-tracking code.
+    pub emit_cost_code: bool,
+    /// Instruments the module with coverage counters (see [coverage]).
+    pub coverage: bool,
+    /// Copies and fills memory with the `memory.copy` and `memory.fill`
+    /// instructions. Without them, the module can target engines which don't
+    /// support the bulk-memory proposal, at the cost of slower copies.
+    pub bulk_memory: bool,
+    /// Starts the module with the memory needed by its literals, and grows it
+    /// when the stack needs more, instead of sizing it for all the stack
+    /// frames. The host bounds the growth, see
+    /// [limits::ResourceLimits::max_memory_bytes].
+    pub dynamic_memory: bool,
+}
+
+impl Default for GeneratorOptions {
+    fn default() -> Self {
+        Self {
+            emit_cost_code: false,
+            coverage: false,
+            bulk_memory: true,
+            dynamic_memory: false,
+        }
+    }
+}
+
+/// Same as [compile], with all the options of the code generation.
+pub fn compile_with_options(
+    source: &str,
+    contract_id: &QualifiedContractIdentifier,
+    mut cost_tracker: LimitedCostTracker,
+    clarity_version: ClarityVersion,
+    epoch: StacksEpochId,
+    analysis_db: &mut AnalysisDatabase,
+    options: GeneratorOptions,
 ) -> Result<CompileResult, CompileError> {
     // Parse the contract
     let (ast, mut diagnostics, success) = build_ast_with_diagnostics(
@@ -75,7 +285,7 @@ pub fn compile(
     );
 
     if !success {
-        return Err(CompileError::Generic {
+        return Err(CompileError::Parse {
             ast: Box::new(ast),
             diagnostics,
             cost_tracker: Box::new(cost_tracker),
@@ -95,8 +305,8 @@ pub fn compile(
     ) {
         Ok(contract_analysis) => contract_analysis,
         Err((e, cost_track)) => {
-            diagnostics.push(Diagnostic::err(&e.err));
-            return Err(CompileError::Generic {
+            diagnostics.push(e.diagnostic);
+            return Err(CompileError::Analysis {
                 ast: Box::new(ast),
                 diagnostics,
                 cost_tracker: Box::new(cost_track),
@@ -109,9 +319,9 @@ pub fn compile(
     #[allow(clippy::expect_used)]
     if let Err(e) = utils::concretize(&mut contract_analysis) {
         diagnostics.push(e.diagnostic);
-        return Err(CompileError::Generic {
+        return Err(CompileError::Concretization {
             ast: Box::new(ast),
-            diagnostics: diagnostics.clone(),
+            diagnostics,
             cost_tracker: Box::new(
                 contract_analysis
                     .cost_track
@@ -122,10 +332,22 @@ pub fn compile(
     }
 
     #[allow(clippy::expect_used)]
-    let generator = match emit_cost_code {
+    let generator = match options.emit_cost_code {
         false => WasmGenerator::new(contract_analysis.clone()),
         true => WasmGenerator::with_cost_code(contract_analysis.clone()),
-    };
+    }
+    .map(|generator| match options.coverage {
+        false => generator,
+        true => generator.with_coverage(),
+    })
+    .map(|generator| match options.bulk_memory {
+        true => generator,
+        false => generator.without_bulk_memory(),
+    })
+    .map(|generator| match options.dynamic_memory {
+        false => generator,
+        true => generator.with_dynamic_memory(),
+    });
 
     match generator.and_then(WasmGenerator::generate) {
         Ok(module) => Ok(CompileResult {
@@ -134,9 +356,11 @@ pub fn compile(
             module,
             contract_analysis,
         }),
-        Err(e) => {
-            diagnostics.push(Diagnostic::err(&e));
-            Err(CompileError::Generic {
+        Err(error) => {
+            let mut diagnostic = Diagnostic::err(&error);
+            diagnostic.spans = error.span().cloned().into_iter().collect();
+            diagnostics.push(diagnostic);
+            Err(CompileError::Generation {
                 ast: Box::new(ast),
                 diagnostics,
                 #[allow(clippy::expect_used)]
@@ -146,6 +370,7 @@ pub fn compile(
                         .take()
                         .expect("Failed to take cost tracker from contract analysis"),
                 ),
+                error,
             })
         }
     }
@@ -0,0 +1,304 @@
+//! Resource limits applied to compiled contracts at runtime.
+//!
+//! These limits protect the host from adversarial contracts: they bound the
+//! linear memory an instance may reserve, the native stack used by Wasm code
+//! and the depth of nested contract-calls. Each violation is reported with
+//! the same error the interpreter would return in a similar situation.
+//!
+//! The limits apply to every contract of a call, including the callees of
+//! `contract-call?`, which run with the same options as their caller.
+
+use clarity::vm::costs::CostErrors;
+use clarity::vm::errors::{Error, RuntimeErrorType};
+use clarity::vm::MAX_CALL_STACK_DEPTH;
+
+/// Size of a Wasm memory page, in bytes.
+pub const WASM_PAGE_SIZE: usize = 64 * 1024;
+
+/// Maximum size of a 32-bit Wasm memory, in bytes.
+const WASM32_MAX_MEMORY_BYTES: usize = 65536 * WASM_PAGE_SIZE;
+
+/// Default maximum amount of linear memory available to a contract instance.
+pub const DEFAULT_MAX_MEMORY_BYTES: usize = 256 * 1024 * 1024;
+
+/// Configurable limits for the execution of a compiled contract.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResourceLimits {
+    /// Maximum size, in bytes, of the linear memory of an instance.
+    pub max_memory_bytes: usize,
+    /// Maximum size, in bytes, of the native stack usable by Wasm code.
+    /// `None` keeps the setting of the engine in the global context.
+    pub max_wasm_stack: Option<usize>,
+    /// Maximum depth of the call stack when entering a contract-call.
+    pub max_call_depth: usize,
+}
+
+impl Default for ResourceLimits {
+    fn default() -> Self {
+        Self {
+            max_memory_bytes: DEFAULT_MAX_MEMORY_BYTES,
+            max_wasm_stack: None,
+            max_call_depth: MAX_CALL_STACK_DEPTH,
+        }
+    }
+}
+
+impl ResourceLimits {
+    /// Applies the limits that are enforced by the engine itself (currently
+    /// the Wasm stack size) to an engine configuration.
+    pub fn configure_engine(&self, config: &mut wasmtime::Config) {
+        if let Some(max_wasm_stack) = self.max_wasm_stack {
+            config.max_wasm_stack(max_wasm_stack);
+        }
+    }
+
+    /// Returns an error if entering a new contract-call at `depth` would
+    /// exceed the maximum call depth.
+    pub fn check_call_depth(&self, depth: usize) -> Result<(), Error> {
+        if depth >= self.max_call_depth {
+            Err(Error::Runtime(
+                RuntimeErrorType::MaxStackDepthReached,
+                Some(Vec::new()),
+            ))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Resource limiter installed in every store running a compiled contract.
+///
+/// Growing the memory past the configured maximum is refused like growing it
+/// past the maximum of the memory: `memory.grow` returns -1, and the module
+/// fails with [crate::error_mapping::ErrorMap::MemoryExhausted]. The limiter
+/// records the refused growth, so that
+/// [crate::error_mapping::resolve_error] reports it with the error returned by
+/// the interpreter when its memory balance is exceeded. The engine-specific
+/// trait implementations live in [crate::runtime].
+#[derive(Debug, Clone, Default)]
+pub struct Limiter {
+    limits: ResourceLimits,
+    /// Memory growth refused by the limiter, as `(desired, maximum)` bytes.
+    exceeded: Option<(u64, u64)>,
+}
+
+impl Limiter {
+    pub fn new(limits: ResourceLimits) -> Self {
+        Self {
+            limits,
+            exceeded: None,
+        }
+    }
+
+    pub fn limits(&self) -> &ResourceLimits {
+        &self.limits
+    }
+
+    /// Returns `true` if the memory of an instance may grow to `desired`
+    /// bytes, within both the configured limit and the `maximum` of the
+    /// memory.
+    ///
+    /// A refused growth is remembered, to be reported by
+    /// [Limiter::take_error].
+    pub(crate) fn check_memory_growth(&mut self, desired: usize, maximum: Option<usize>) -> bool {
+        let max = maximum
+            .unwrap_or(WASM32_MAX_MEMORY_BYTES)
+            .min(self.limits.max_memory_bytes);
+        if desired > max {
+            self.exceeded = Some((desired as u64, max as u64));
+            return false;
+        }
+        true
+    }
+
+    /// Takes the error of the last refused memory growth, if any.
+    pub(crate) fn take_error(&mut self) -> Option<Error> {
+        self.exceeded
+            .take()
+            .map(|(desired, max)| CostErrors::MemoryBalanceExceeded(desired, max).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clarity::vm::errors::CheckErrors;
+    use clarity::vm::types::StandardPrincipalData;
+    use clarity::vm::Value;
+
+    use super::*;
+    use crate::runtime;
+    use crate::tools::{TestConfig, TestEnvironment};
+
+    fn env_with_limits(limits: ResourceLimits) -> TestEnvironment {
+        let mut env =
+            TestEnvironment::new(TestConfig::latest_epoch(), TestConfig::clarity_version());
+        env.set_resource_limits(limits);
+        env
+    }
+
+    #[test]
+    fn default_limits_allow_regular_contracts() {
+        let mut env = env_with_limits(ResourceLimits::default());
+        assert_eq!(env.evaluate("(+ 1 2)"), Ok(Some(Value::Int(3))));
+    }
+
+    #[test]
+    fn limiter_refuses_growth_past_maximum() {
+        let mut limiter = Limiter::new(ResourceLimits {
+            max_memory_bytes: WASM_PAGE_SIZE,
+            ..Default::default()
+        });
+
+        assert!(limiter.check_memory_growth(WASM_PAGE_SIZE, None));
+        assert!(limiter.take_error().is_none());
+
+        assert!(!limiter.check_memory_growth(2 * WASM_PAGE_SIZE, None));
+        assert_eq!(
+            limiter.take_error(),
+            Some(Error::from(CostErrors::MemoryBalanceExceeded(
+                2 * WASM_PAGE_SIZE as u64,
+                WASM_PAGE_SIZE as u64,
+            )))
+        );
+        assert!(limiter.take_error().is_none());
+
+        // The maximum of the memory is enforced too.
+        assert!(!limiter.check_memory_growth(WASM_PAGE_SIZE, Some(0)));
+    }
+
+    #[test]
+    fn engine_is_built_for_stack_limit_only() {
+        assert!(runtime::limited_engine(&ResourceLimits::default())
+            .unwrap()
+            .is_none());
+        assert!(runtime::limited_engine(&ResourceLimits {
+            max_wasm_stack: Some(1024 * 1024),
+            ..Default::default()
+        })
+        .unwrap()
+        .is_some());
+    }
+
+    #[test]
+    fn memory_limit_is_enforced() {
+        let mut env = env_with_limits(ResourceLimits {
+            max_memory_bytes: WASM_PAGE_SIZE,
+            ..Default::default()
+        });
+
+        // The literal alone needs more than one page of memory.
+        let snippet = format!("(len 0x{})", "00".repeat(2 * WASM_PAGE_SIZE));
+        let err = env
+            .evaluate(&snippet)
+            .expect_err("memory limit should be hit");
+        assert!(
+            matches!(
+                err,
+                Error::Unchecked(CheckErrors::MemoryBalanceExceeded(_, limit))
+                    if limit == WASM_PAGE_SIZE as u64
+            ),
+            "unexpected error: {err:?}"
+        );
+    }
+
+    #[test]
+    fn wasm_stack_limit_is_enforced() {
+        let mut env = env_with_limits(ResourceLimits {
+            max_wasm_stack: Some(1),
+            ..Default::default()
+        });
+
+        assert_eq!(
+            env.evaluate("(define-private (foo) (+ 1 2)) (foo)"),
+            Err(Error::Runtime(
+                RuntimeErrorType::MaxStackDepthReached,
+                Some(Vec::new())
+            ))
+        );
+    }
+
+    #[test]
+    fn call_depth_limit_is_enforced() {
+        let mut env = env_with_limits(ResourceLimits {
+            max_call_depth: 0,
+            ..Default::default()
+        });
+
+        env.init_contract_with_snippet("callee", "(define-public (foo) (ok u1))")
+            .expect("Failed to init contract.");
+
+        let snippet = format!(
+            "(contract-call? '{}.callee foo)",
+            StandardPrincipalData::transient()
+        );
+        assert_eq!(
+            env.init_contract_with_snippet("caller", &snippet),
+            Err(Error::Runtime(
+                RuntimeErrorType::MaxStackDepthReached,
+                Some(Vec::new())
+            ))
+        );
+    }
+
+    #[test]
+    fn call_depth_within_limit() {
+        let mut env = env_with_limits(ResourceLimits {
+            max_call_depth: 2,
+            ..Default::default()
+        });
+
+        env.init_contract_with_snippet("callee", "(define-public (foo) (ok u1))")
+            .expect("Failed to init contract.");
+
+        let snippet = format!(
+            "(contract-call? '{}.callee foo)",
+            StandardPrincipalData::transient()
+        );
+        assert_eq!(
+            env.init_contract_with_snippet("caller", &snippet),
+            Ok(Some(Value::okay(Value::UInt(1)).unwrap()))
+        );
+    }
+
+    fn env_with_call_chain(max_call_depth: usize) -> TestEnvironment {
+        let mut env = env_with_limits(ResourceLimits {
+            max_call_depth,
+            ..Default::default()
+        });
+        let issuer = StandardPrincipalData::transient();
+        env.init_contract_with_snippet("one", "(define-public (one) (ok u1))")
+            .expect("Failed to init contract.");
+        env.init_contract_with_snippet(
+            "two",
+            &format!("(define-public (two) (contract-call? '{issuer}.one one))"),
+        )
+        .expect("Failed to init contract.");
+        env.init_contract_with_snippet(
+            "three",
+            &format!("(define-public (three) (contract-call? '{issuer}.two two))"),
+        )
+        .expect("Failed to init contract.");
+        env
+    }
+
+    #[test]
+    fn call_depth_limit_applies_to_nested_calls() {
+        let mut env = env_with_call_chain(1);
+        let err = env
+            .call_contract_function("three", "three", &[], None)
+            .expect_err("call depth limit should be hit");
+        assert!(
+            matches!(
+                err,
+                Error::Runtime(RuntimeErrorType::MaxStackDepthReached, _)
+            ),
+            "unexpected error: {err:?}"
+        );
+
+        let mut env = env_with_call_chain(2);
+        assert_eq!(
+            env.call_contract_function("three", "three", &[], None),
+            Ok(Value::okay(Value::UInt(1)).unwrap())
+        );
+    }
+}
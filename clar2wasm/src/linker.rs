@@ -1,21 +1,24 @@
+use clarity::types::StacksEpochId;
 use clarity::vm::analysis::CheckErrors;
 use clarity::vm::callables::{DefineType, DefinedFunction};
-use clarity::vm::costs::{constants as cost_constants, CostTracker};
-use clarity::vm::database::{ClarityDatabase, STXBalance, StoreType};
-use clarity::vm::errors::{Error, RuntimeErrorType, WasmError};
+use clarity::vm::costs::{constants as cost_constants, CostTracker, LimitedCostTracker};
+use clarity::vm::database::{ClarityDatabase, DataMapMetadata, STXBalance, StoreType};
+use clarity::vm::errors::{Error, InterpreterError, RuntimeErrorType, WasmError};
 use clarity::vm::functions::crypto::{pubkey_to_address_v1, pubkey_to_address_v2};
+use clarity::vm::types::serialization::TypePrefix;
 use clarity::vm::types::{
     AssetIdentifier, BuffData, BufferLength, FunctionType, ListTypeData, PrincipalData,
     SequenceData, SequenceSubtype, StacksAddressExtensions, TraitIdentifier, TupleData,
     TupleTypeSignature, TypeSignature, BUFF_1, BUFF_32, BUFF_33,
 };
-use clarity::vm::{ClarityName, ClarityVersion, Environment, SymbolicExpression, Value};
+use clarity::vm::{ClarityName, ClarityVersion, SymbolicExpression, Value};
 use stacks_common::types::chainstate::StacksBlockId;
 use stacks_common::util::hash::{Keccak256Hash, Sha512Sum, Sha512Trunc256Sum};
 use stacks_common::util::secp256k1::{secp256k1_recover, secp256k1_verify, Secp256k1PublicKey};
-use wasmtime::{Caller, Engine, Instance, Linker, Memory, Module, Store};
 
 use crate::initialize::ClarityWasmContext;
+use crate::runtime::{Caller, Engine, Instance, Linker, Memory, Module, Store};
+use crate::type_table;
 use crate::wasm_utils::*;
 
 /// Link the host interface functions for into the Wasm module.
@@ -146,14 +149,8 @@ fn link_define_variable_fn(linker: &mut Linker<ClarityWasmContext>) -> Result<()
                     (value_offset, value_length) =
                         read_indirect_offset_and_length(memory, &mut caller, value_offset)?;
                 }
-                let value = read_from_wasm(
-                    memory,
-                    &mut caller,
-                    &value_type,
-                    value_offset,
-                    value_length,
-                    epoch,
-                )?;
+                let value =
+                    read_from_wasm(memory, &mut caller, &value_type, value_offset, value_length)?;
 
                 caller
                     .data_mut()
@@ -605,6 +602,10 @@ fn link_impl_trait_fn(linker: &mut Linker<ClarityWasmContext>) -> Result<(), Err
 
 /// Link host interface function, `get_variable`, into the Wasm module.
 /// This function is called for all variable lookups (`var-get`).
+///
+/// The value is loaded like the interpreter does, sanitized for the current
+/// epoch, and written to the buffer of the caller in its consensus
+/// serialization. The function returns its length.
 fn link_get_variable_fn(linker: &mut Linker<ClarityWasmContext>) -> Result<(), Error> {
     linker
         .func_wrap(
@@ -614,7 +615,7 @@ fn link_get_variable_fn(linker: &mut Linker<ClarityWasmContext>) -> Result<(), E
              name_offset: i32,
              name_length: i32,
              return_offset: i32,
-             _return_length: i32| {
+             return_length: i32| {
                 // Get the memory from the caller
                 let memory = caller
                     .get_export("memory")
@@ -626,59 +627,36 @@ fn link_get_variable_fn(linker: &mut Linker<ClarityWasmContext>) -> Result<(), E
                     read_identifier_from_wasm(memory, &mut caller, name_offset, name_length)?;
 
                 let contract = caller.data().contract_context().contract_identifier.clone();
-                let epoch = caller.data_mut().global_context.epoch_id;
 
-                // Retrieve the metadata for this variable
                 let data_types = caller
                     .data()
                     .contract_context()
                     .meta_data_var
                     .get(var_name.as_str())
-                    .ok_or(CheckErrors::NoSuchDataVariable(var_name.to_string()))?
+                    .ok_or(Error::Unchecked(CheckErrors::NoSuchDataVariable(
+                        var_name.to_string(),
+                    )))?
                     .clone();
 
-                // We would like to call `lookup_variable_with_size`, but since it
-                // returns `Ok(none)` even if the variable is missing, we have no way
-                // to distinguish between a valid `none` and a missing variable.
-                // So here we replicate `lookup_variable_with_size` impl.
                 let key = ClarityDatabase::make_key_for_trip(
                     &contract,
                     StoreType::Variable,
                     var_name.as_str(),
                 );
-                let fetch_result = caller.data_mut().global_context.database.get_value(
-                    &key,
-                    &data_types.value_type,
-                    &epoch,
+                let value = load_serialized(&mut caller, &key, &data_types.value_type)?.ok_or(
+                    Error::Unchecked(CheckErrors::NoSuchDataVariable(var_name.to_string())),
                 )?;
 
                 // TODO: Include this cost
-                // let _result_size = match &fetch_result {
-                //     Ok(data) => data.serialized_byte_len,
-                //     Err(_e) => data_types.value_type.size()? as u64,
-                // };
-                // runtime_cost(ClarityCostFunction::FetchVar, env, result_size)?;
-
-                let value = fetch_result.map(|data| data.value).ok_or(Error::Unchecked(
-                    CheckErrors::NoSuchDataVariable(var_name.to_string()),
-                ))?;
-
-                let memory = caller
-                    .get_export("memory")
-                    .and_then(|export| export.into_memory())
-                    .ok_or(Error::Wasm(WasmError::MemoryNotFound))?;
+                // runtime_cost(ClarityCostFunction::FetchVar, env, value.len())?;
 
-                write_to_wasm(
+                Ok(write_serialized(
                     &mut caller,
                     memory,
-                    &data_types.value_type,
-                    return_offset,
-                    return_offset + get_type_size(&data_types.value_type),
                     &value,
-                    true,
-                )?;
-
-                Ok(())
+                    return_offset,
+                    return_length,
+                )?)
             },
         )
         .map(|_| ())
@@ -692,6 +670,9 @@ fn link_get_variable_fn(linker: &mut Linker<ClarityWasmContext>) -> Result<(), E
 
 /// Link host interface function, `set_variable`, into the Wasm module.
 /// This function is called for all variable assignments (`var-set`).
+///
+/// The value is the consensus serialization written by the caller. It is
+/// stored, and its memory use charged, like the interpreter does.
 fn link_set_variable_fn(linker: &mut Linker<ClarityWasmContext>) -> Result<(), Error> {
     linker
         .func_wrap(
@@ -700,16 +681,16 @@ fn link_set_variable_fn(linker: &mut Linker<ClarityWasmContext>) -> Result<(), E
             |mut caller: Caller<'_, ClarityWasmContext>,
              name_offset: i32,
              name_length: i32,
-             mut value_offset: i32,
-             mut value_length: i32| {
+             value_offset: i32,
+             value_length: i32| {
+                caller.data().check_writable()?;
+
                 // Get the memory from the caller
                 let memory = caller
                     .get_export("memory")
                     .and_then(|export| export.into_memory())
                     .ok_or(Error::Wasm(WasmError::MemoryNotFound))?;
 
-                let epoch = caller.data_mut().global_context.epoch_id;
-
                 // Retrieve the variable name for this identifier
                 let var_name =
                     read_identifier_from_wasm(memory, &mut caller, name_offset, name_length)?;
@@ -733,25 +714,19 @@ fn link_set_variable_fn(linker: &mut Linker<ClarityWasmContext>) -> Result<(), E
                 //     data_types.value_type.size(),
                 // )?;
 
-                // Read in the value from the Wasm memory
-                if is_in_memory_type(&data_types.value_type) {
-                    (value_offset, value_length) =
-                        read_indirect_offset_and_length(memory, &mut caller, value_offset)?;
-                }
-                let value = read_from_wasm(
-                    memory,
+                // Read in the serialized value from the Wasm memory
+                let value = read_serialized_value(
                     &mut caller,
+                    memory,
                     &data_types.value_type,
                     value_offset,
                     value_length,
-                    epoch,
                 )?;
 
-                // TODO: Include this cost
-                // env.add_memory(value.get_memory_use())?;
-
                 // Store the variable in the global context
-                caller.data_mut().global_context.database.set_variable(
+                let epoch = caller.data().global_context.epoch_id;
+                let memory_use = memory_use_v200(epoch, &[&value])?;
+                let stored = caller.data_mut().global_context.database.set_variable(
                     &contract,
                     var_name.as_str(),
                     value,
@@ -759,6 +734,12 @@ fn link_set_variable_fn(linker: &mut Linker<ClarityWasmContext>) -> Result<(), E
                     &epoch,
                 )?;
 
+                caller
+                    .data_mut()
+                    .global_context
+                    .add_memory(memory_use.unwrap_or(stored.serialized_byte_len))
+                    .map_err(Error::from)?;
+
                 Ok(())
             },
         )
@@ -1167,8 +1148,6 @@ fn link_stx_get_balance_fn(linker: &mut Linker<ClarityWasmContext>) -> Result<()
                     .and_then(|export| export.into_memory())
                     .ok_or(Error::Wasm(WasmError::MemoryNotFound))?;
 
-                let epoch = caller.data_mut().global_context.epoch_id;
-
                 // Read the principal from the Wasm memory
                 let value = read_from_wasm(
                     memory,
@@ -1176,7 +1155,6 @@ fn link_stx_get_balance_fn(linker: &mut Linker<ClarityWasmContext>) -> Result<()
                     &TypeSignature::PrincipalType,
                     principal_offset,
                     principal_length,
-                    epoch,
                 )?;
                 let principal = value_as_principal(&value)?;
 
@@ -1218,8 +1196,6 @@ fn link_stx_account_fn(linker: &mut Linker<ClarityWasmContext>) -> Result<(), Er
                     .and_then(|export| export.into_memory())
                     .ok_or(Error::Wasm(WasmError::MemoryNotFound))?;
 
-                let epoch = caller.data_mut().global_context.epoch_id;
-
                 // Read the principal from the Wasm memory
                 let value = read_from_wasm(
                     memory,
@@ -1227,7 +1203,6 @@ fn link_stx_account_fn(linker: &mut Linker<ClarityWasmContext>) -> Result<(), Er
                     &TypeSignature::PrincipalType,
                     principal_offset,
                     principal_length,
-                    epoch,
                 )?;
                 let principal = value_as_principal(&value)?;
 
@@ -1294,6 +1269,8 @@ fn link_stx_burn_fn(linker: &mut Linker<ClarityWasmContext>) -> Result<(), Error
              amount_hi: i64,
              principal_offset: i32,
              principal_length: i32| {
+                caller.data().check_writable()?;
+
                 let amount = ((amount_hi as u128) << 64) | ((amount_lo as u64) as u128);
 
                 // Get the memory from the caller
@@ -1302,8 +1279,6 @@ fn link_stx_burn_fn(linker: &mut Linker<ClarityWasmContext>) -> Result<(), Error
                     .and_then(|export| export.into_memory())
                     .ok_or(Error::Wasm(WasmError::MemoryNotFound))?;
 
-                let epoch = caller.data_mut().global_context.epoch_id;
-
                 // Read the principal from the Wasm memory
                 let value = read_from_wasm(
                     memory,
@@ -1311,7 +1286,6 @@ fn link_stx_burn_fn(linker: &mut Linker<ClarityWasmContext>) -> Result<(), Error
                     &TypeSignature::PrincipalType,
                     principal_offset,
                     principal_length,
-                    epoch,
                 )?;
                 let from = value_as_principal(&value)?;
 
@@ -1392,6 +1366,8 @@ fn link_stx_transfer_fn(linker: &mut Linker<ClarityWasmContext>) -> Result<(), E
              recipient_length: i32,
              memo_offset: i32,
              memo_length: i32| {
+                caller.data().check_writable()?;
+
                 let amount = ((amount_hi as u128) << 64) | ((amount_lo as u64) as u128);
 
                 // Get the memory from the caller
@@ -1400,8 +1376,6 @@ fn link_stx_transfer_fn(linker: &mut Linker<ClarityWasmContext>) -> Result<(), E
                     .and_then(|export| export.into_memory())
                     .ok_or(Error::Wasm(WasmError::MemoryNotFound))?;
 
-                let epoch = caller.data_mut().global_context.epoch_id;
-
                 // Read the sender principal from the Wasm memory
                 let value = read_from_wasm(
                     memory,
@@ -1409,7 +1383,6 @@ fn link_stx_transfer_fn(linker: &mut Linker<ClarityWasmContext>) -> Result<(), E
                     &TypeSignature::PrincipalType,
                     sender_offset,
                     sender_length,
-                    epoch,
                 )?;
                 let sender = value_as_principal(&value)?;
 
@@ -1420,7 +1393,6 @@ fn link_stx_transfer_fn(linker: &mut Linker<ClarityWasmContext>) -> Result<(), E
                     &TypeSignature::PrincipalType,
                     recipient_offset,
                     recipient_length,
-                    epoch,
                 )?;
                 let recipient = value_as_principal(&value)?;
 
@@ -1434,7 +1406,6 @@ fn link_stx_transfer_fn(linker: &mut Linker<ClarityWasmContext>) -> Result<(), E
                         )),
                         memo_offset,
                         memo_length,
-                        epoch,
                     )?;
                     value_as_buffer(value)?
                 } else {
@@ -1584,7 +1555,6 @@ fn link_ft_get_balance_fn(linker: &mut Linker<ClarityWasmContext>) -> Result<(),
 
                 let contract_identifier =
                     caller.data().contract_context().contract_identifier.clone();
-                let epoch = caller.data_mut().global_context.epoch_id;
 
                 // Read the owner principal from the Wasm memory
                 let value = read_from_wasm(
@@ -1593,7 +1563,6 @@ fn link_ft_get_balance_fn(linker: &mut Linker<ClarityWasmContext>) -> Result<(),
                     &TypeSignature::PrincipalType,
                     owner_offset,
                     owner_length,
-                    epoch,
                 )?;
                 let owner = value_as_principal(&value)?;
 
@@ -1638,6 +1607,8 @@ fn link_ft_burn_fn(linker: &mut Linker<ClarityWasmContext>) -> Result<(), Error>
              amount_hi: i64,
              sender_offset: i32,
              sender_length: i32| {
+                caller.data().check_writable()?;
+
                 // runtime_cost(ClarityCostFunction::FtBurn, env, 0)?;
 
                 // Get the memory from the caller
@@ -1648,7 +1619,6 @@ fn link_ft_burn_fn(linker: &mut Linker<ClarityWasmContext>) -> Result<(), Error>
 
                 let contract_identifier =
                     caller.data().contract_context().contract_identifier.clone();
-                let epoch = caller.data_mut().global_context.epoch_id;
 
                 // Retrieve the token name
                 let name =
@@ -1665,7 +1635,6 @@ fn link_ft_burn_fn(linker: &mut Linker<ClarityWasmContext>) -> Result<(), Error>
                     &TypeSignature::PrincipalType,
                     sender_offset,
                     sender_length,
-                    epoch,
                 )?;
                 let burner = value_as_principal(&value)?;
 
@@ -1766,6 +1735,8 @@ fn link_ft_mint_fn(linker: &mut Linker<ClarityWasmContext>) -> Result<(), Error>
              amount_hi: i64,
              sender_offset: i32,
              sender_length: i32| {
+                caller.data().check_writable()?;
+
                 // runtime_cost(ClarityCostFunction::FtBurn, env, 0)?;
 
                 // Get the memory from the caller
@@ -1776,7 +1747,6 @@ fn link_ft_mint_fn(linker: &mut Linker<ClarityWasmContext>) -> Result<(), Error>
 
                 let contract_identifier =
                     caller.data().contract_context().contract_identifier.clone();
-                let epoch = caller.data_mut().global_context.epoch_id;
 
                 // Retrieve the token name
                 let name =
@@ -1793,7 +1763,6 @@ fn link_ft_mint_fn(linker: &mut Linker<ClarityWasmContext>) -> Result<(), Error>
                     &TypeSignature::PrincipalType,
                     sender_offset,
                     sender_length,
-                    epoch,
                 )?;
                 let to_principal = value_as_principal(&value)?;
 
@@ -1891,6 +1860,8 @@ fn link_ft_transfer_fn(linker: &mut Linker<ClarityWasmContext>) -> Result<(), Er
              sender_length: i32,
              recipient_offset: i32,
              recipient_length: i32| {
+                caller.data().check_writable()?;
+
                 // runtime_cost(ClarityCostFunction::FtTransfer, env, 0)?;
 
                 // Get the memory from the caller
@@ -1902,8 +1873,6 @@ fn link_ft_transfer_fn(linker: &mut Linker<ClarityWasmContext>) -> Result<(), Er
                 let contract_identifier =
                     caller.data().contract_context().contract_identifier.clone();
 
-                let epoch = caller.data_mut().global_context.epoch_id;
-
                 // Retrieve the token name
                 let name =
                     read_identifier_from_wasm(memory, &mut caller, name_offset, name_length)?;
@@ -1919,7 +1888,6 @@ fn link_ft_transfer_fn(linker: &mut Linker<ClarityWasmContext>) -> Result<(), Er
                     &TypeSignature::PrincipalType,
                     sender_offset,
                     sender_length,
-                    epoch,
                 )?;
                 let from_principal = value_as_principal(&value)?;
 
@@ -1930,7 +1898,6 @@ fn link_ft_transfer_fn(linker: &mut Linker<ClarityWasmContext>) -> Result<(), Er
                     &TypeSignature::PrincipalType,
                     recipient_offset,
                     recipient_length,
-                    epoch,
                 )?;
                 let to_principal = value_as_principal(&value)?;
 
@@ -2074,7 +2041,6 @@ fn link_nft_get_owner_fn(linker: &mut Linker<ClarityWasmContext>) -> Result<(),
 
                 let contract_identifier =
                     caller.data().contract_context().contract_identifier.clone();
-                let epoch = caller.data_mut().global_context.epoch_id;
 
                 // Retrieve the token name
                 let name =
@@ -2102,7 +2068,6 @@ fn link_nft_get_owner_fn(linker: &mut Linker<ClarityWasmContext>) -> Result<(),
                     expected_asset_type,
                     asset_offset,
                     asset_length,
-                    epoch,
                 )?;
 
                 let _asset_size = asset.serialized_size()? as u64;
@@ -2166,6 +2131,8 @@ fn link_nft_burn_fn(linker: &mut Linker<ClarityWasmContext>) -> Result<(), Error
              mut asset_length: i32,
              sender_offset: i32,
              sender_length: i32| {
+                caller.data().check_writable()?;
+
                 // Get the memory from the caller
                 let memory = caller
                     .get_export("memory")
@@ -2203,7 +2170,6 @@ fn link_nft_burn_fn(linker: &mut Linker<ClarityWasmContext>) -> Result<(), Error
                     expected_asset_type,
                     asset_offset,
                     asset_length,
-                    epoch,
                 )?;
 
                 // Read the sender principal from the Wasm memory
@@ -2213,7 +2179,6 @@ fn link_nft_burn_fn(linker: &mut Linker<ClarityWasmContext>) -> Result<(), Error
                     &TypeSignature::PrincipalType,
                     sender_offset,
                     sender_length,
-                    epoch,
                 )?;
                 let sender_principal = value_as_principal(&value)?;
 
@@ -2305,6 +2270,8 @@ fn link_nft_mint_fn(linker: &mut Linker<ClarityWasmContext>) -> Result<(), Error
              mut asset_length: i32,
              recipient_offset: i32,
              recipient_length: i32| {
+                caller.data().check_writable()?;
+
                 // Get the memory from the caller
                 let memory = caller
                     .get_export("memory")
@@ -2342,7 +2309,6 @@ fn link_nft_mint_fn(linker: &mut Linker<ClarityWasmContext>) -> Result<(), Error
                     expected_asset_type,
                     asset_offset,
                     asset_length,
-                    epoch,
                 )?;
 
                 // Read the recipient principal from the Wasm memory
@@ -2352,7 +2318,6 @@ fn link_nft_mint_fn(linker: &mut Linker<ClarityWasmContext>) -> Result<(), Error
                     &TypeSignature::PrincipalType,
                     recipient_offset,
                     recipient_length,
-                    epoch,
                 )?;
                 let to_principal = value_as_principal(&value)?;
 
@@ -2435,6 +2400,8 @@ fn link_nft_transfer_fn(linker: &mut Linker<ClarityWasmContext>) -> Result<(), E
              sender_length: i32,
              recipient_offset: i32,
              recipient_length: i32| {
+                caller.data().check_writable()?;
+
                 // Get the memory from the caller
                 let memory = caller
                     .get_export("memory")
@@ -2472,7 +2439,6 @@ fn link_nft_transfer_fn(linker: &mut Linker<ClarityWasmContext>) -> Result<(), E
                     expected_asset_type,
                     asset_offset,
                     asset_length,
-                    epoch,
                 )?;
 
                 // Read the sender principal from the Wasm memory
@@ -2482,7 +2448,6 @@ fn link_nft_transfer_fn(linker: &mut Linker<ClarityWasmContext>) -> Result<(), E
                     &TypeSignature::PrincipalType,
                     sender_offset,
                     sender_length,
-                    epoch,
                 )?;
                 let from_principal = value_as_principal(&value)?;
 
@@ -2493,7 +2458,6 @@ fn link_nft_transfer_fn(linker: &mut Linker<ClarityWasmContext>) -> Result<(), E
                     &TypeSignature::PrincipalType,
                     recipient_offset,
                     recipient_length,
-                    epoch,
                 )?;
                 let to_principal = value_as_principal(&value)?;
 
@@ -2595,6 +2559,10 @@ fn link_nft_transfer_fn(linker: &mut Linker<ClarityWasmContext>) -> Result<(), E
 
 /// Link host interface function, `map_get`, into the Wasm module.
 /// This function is called for the `map-get?` expression.
+///
+/// The value of the entry is written to the buffer of the caller in its
+/// consensus serialization, and the function returns its length, or 0 if the
+/// map has no entry for the key.
 fn link_map_get_fn(linker: &mut Linker<ClarityWasmContext>) -> Result<(), Error> {
     linker
         .func_wrap(
@@ -2603,78 +2571,43 @@ fn link_map_get_fn(linker: &mut Linker<ClarityWasmContext>) -> Result<(), Error>
             |mut caller: Caller<'_, ClarityWasmContext>,
              name_offset: i32,
              name_length: i32,
-             mut key_offset: i32,
-             mut key_length: i32,
+             key_offset: i32,
+             key_length: i32,
              return_offset: i32,
-             _return_length: i32| {
+             return_length: i32| {
                 // Get the memory from the caller
                 let memory = caller
                     .get_export("memory")
                     .and_then(|export| export.into_memory())
                     .ok_or(Error::Wasm(WasmError::MemoryNotFound))?;
 
-                // Retrieve the map name
-                let map_name =
-                    read_identifier_from_wasm(memory, &mut caller, name_offset, name_length)?;
-
-                let contract = caller.data().contract_context().contract_identifier.clone();
-                let epoch = caller.data_mut().global_context.epoch_id;
-
-                // Retrieve the metadata for this map
-                let data_types = caller
-                    .data()
-                    .contract_context()
-                    .meta_data_map
-                    .get(map_name.as_str())
-                    .ok_or(CheckErrors::NoSuchMap(map_name.to_string()))?
-                    .clone();
-
-                // Read in the key from the Wasm memory
-                if is_in_memory_type(&data_types.key_type) {
-                    (key_offset, key_length) =
-                        read_indirect_offset_and_length(memory, &mut caller, key_offset)?;
-                }
-                let key = read_from_wasm(
-                    memory,
+                let (map_name, data_types, key) = read_map_key(
                     &mut caller,
-                    &data_types.key_type,
+                    memory,
+                    name_offset,
+                    name_length,
                     key_offset,
                     key_length,
-                    epoch,
                 )?;
-
-                let result = caller
-                    .data_mut()
-                    .global_context
-                    .database
-                    .fetch_entry_with_size(&contract, &map_name, &key, &data_types, &epoch);
-
-                let _result_size = match &result {
-                    Ok(data) => data.serialized_byte_len,
-                    Err(_e) => (data_types.value_type.size()? + data_types.key_type.size()?) as u64,
-                };
+                let key = map_entry_key(&caller, map_name.as_str(), &key);
 
                 // runtime_cost(ClarityCostFunction::FetchEntry, env, result_size)?;
 
-                let value = result.map(|data| data.value)?;
-
-                let memory = caller
-                    .get_export("memory")
-                    .and_then(|export| export.into_memory())
-                    .ok_or(Error::Wasm(WasmError::MemoryNotFound))?;
-
-                let ty = TypeSignature::OptionalType(Box::new(data_types.value_type));
-                write_to_wasm(
-                    &mut caller,
-                    memory,
-                    &ty,
-                    return_offset,
-                    return_offset + get_type_size(&ty),
-                    &value,
-                    true,
-                )?;
-
-                Ok(())
+                // Like the interpreter, map entries are stored as `(some value)`,
+                // and deleted entries as `none`.
+                let stored_type = TypeSignature::new_option(data_types.value_type)?;
+                match load_serialized(&mut caller, &key, &stored_type)? {
+                    Some(entry) if entry.first() == Some(&(TypePrefix::OptionalSome as u8)) => {
+                        Ok(write_serialized(
+                            &mut caller,
+                            memory,
+                            &entry[1..],
+                            return_offset,
+                            return_length,
+                        )?)
+                    }
+                    _ => Ok(0),
+                }
             },
         )
         .map(|_| ())
@@ -2696,13 +2629,11 @@ fn link_map_set_fn(linker: &mut Linker<ClarityWasmContext>) -> Result<(), Error>
             |mut caller: Caller<'_, ClarityWasmContext>,
              name_offset: i32,
              name_length: i32,
-             mut key_offset: i32,
-             mut key_length: i32,
-             mut value_offset: i32,
-             mut value_length: i32| {
-                if caller.data().global_context.is_read_only() {
-                    return Err(CheckErrors::WriteAttemptedInReadOnly.into());
-                }
+             key_offset: i32,
+             key_length: i32,
+             value_offset: i32,
+             value_length: i32| {
+                caller.data().check_writable()?;
 
                 // Get the memory from the caller
                 let memory = caller
@@ -2710,53 +2641,29 @@ fn link_map_set_fn(linker: &mut Linker<ClarityWasmContext>) -> Result<(), Error>
                     .and_then(|export| export.into_memory())
                     .ok_or(Error::Wasm(WasmError::MemoryNotFound))?;
 
-                let epoch = caller.data_mut().global_context.epoch_id;
-
-                // Retrieve the map name
-                let map_name =
-                    read_identifier_from_wasm(memory, &mut caller, name_offset, name_length)?;
-
-                let contract = caller.data().contract_context().contract_identifier.clone();
-
-                let data_types = caller
-                    .data()
-                    .contract_context()
-                    .meta_data_map
-                    .get(map_name.as_str())
-                    .ok_or(Error::Unchecked(CheckErrors::NoSuchMap(
-                        map_name.to_string(),
-                    )))?
-                    .clone();
-
-                // Read in the key from the Wasm memory
-                if is_in_memory_type(&data_types.key_type) {
-                    (key_offset, key_length) =
-                        read_indirect_offset_and_length(memory, &mut caller, key_offset)?;
-                }
-                let key = read_from_wasm(
-                    memory,
+                let (map_name, data_types, key) = read_map_key(
                     &mut caller,
-                    &data_types.key_type,
+                    memory,
+                    name_offset,
+                    name_length,
                     key_offset,
                     key_length,
-                    epoch,
                 )?;
+                let key = deserialize_value(&key, &data_types.key_type)?;
 
-                // Read in the value from the Wasm memory
-                if is_in_memory_type(&data_types.value_type) {
-                    (value_offset, value_length) =
-                        read_indirect_offset_and_length(memory, &mut caller, value_offset)?;
-                }
-                let value = read_from_wasm(
-                    memory,
+                // Read in the serialized value from the Wasm memory
+                let value = read_serialized_value(
                     &mut caller,
+                    memory,
                     &data_types.value_type,
                     value_offset,
                     value_length,
-                    epoch,
                 )?;
 
                 // Store the value in the map in the global context
+                let contract = caller.data().contract_context().contract_identifier.clone();
+                let epoch = caller.data().global_context.epoch_id;
+                let memory_use = memory_use_v200(epoch, &[&key, &value])?;
                 let result = caller.data_mut().global_context.database.set_entry(
                     &contract,
                     map_name.as_str(),
@@ -2764,27 +2671,17 @@ fn link_map_set_fn(linker: &mut Linker<ClarityWasmContext>) -> Result<(), Error>
                     value,
                     &data_types,
                     &epoch,
-                );
-
-                let result_size = match &result {
-                    Ok(data) => data.serialized_byte_len,
-                    Err(_e) => (data_types.value_type.size()? + data_types.key_type.size()?) as u64,
-                };
+                )?;
 
                 // runtime_cost(ClarityCostFunction::SetEntry, env, result_size)?;
 
                 caller
                     .data_mut()
                     .global_context
-                    .add_memory(result_size)
+                    .add_memory(memory_use.unwrap_or(result.serialized_byte_len))
                     .map_err(Error::from)?;
 
-                let value = result.map(|data| data.value)?;
-                if let Value::Bool(true) = value {
-                    Ok(1i32)
-                } else {
-                    Ok(0i32)
-                }
+                Ok(matches!(result.value, Value::Bool(true)) as i32)
             },
         )
         .map(|_| ())
@@ -2806,13 +2703,11 @@ fn link_map_insert_fn(linker: &mut Linker<ClarityWasmContext>) -> Result<(), Err
             |mut caller: Caller<'_, ClarityWasmContext>,
              name_offset: i32,
              name_length: i32,
-             mut key_offset: i32,
-             mut key_length: i32,
-             mut value_offset: i32,
-             mut value_length: i32| {
-                if caller.data().global_context.is_read_only() {
-                    return Err(CheckErrors::WriteAttemptedInReadOnly.into());
-                }
+             key_offset: i32,
+             key_length: i32,
+             value_offset: i32,
+             value_length: i32| {
+                caller.data().check_writable()?;
 
                 // Get the memory from the caller
                 let memory = caller
@@ -2820,53 +2715,29 @@ fn link_map_insert_fn(linker: &mut Linker<ClarityWasmContext>) -> Result<(), Err
                     .and_then(|export| export.into_memory())
                     .ok_or(Error::Wasm(WasmError::MemoryNotFound))?;
 
-                let epoch = caller.data_mut().global_context.epoch_id;
-
-                // Retrieve the map name
-                let map_name =
-                    read_identifier_from_wasm(memory, &mut caller, name_offset, name_length)?;
-
-                let contract = caller.data().contract_context().contract_identifier.clone();
-
-                let data_types = caller
-                    .data()
-                    .contract_context()
-                    .meta_data_map
-                    .get(map_name.as_str())
-                    .ok_or(Error::Unchecked(CheckErrors::NoSuchMap(
-                        map_name.to_string(),
-                    )))?
-                    .clone();
-
-                // Read in the key from the Wasm memory
-                if is_in_memory_type(&data_types.key_type) {
-                    (key_offset, key_length) =
-                        read_indirect_offset_and_length(memory, &mut caller, key_offset)?;
-                }
-                let key = read_from_wasm(
-                    memory,
+                let (map_name, data_types, key) = read_map_key(
                     &mut caller,
-                    &data_types.key_type,
+                    memory,
+                    name_offset,
+                    name_length,
                     key_offset,
                     key_length,
-                    epoch,
                 )?;
+                let key = deserialize_value(&key, &data_types.key_type)?;
 
-                // Read in the value from the Wasm memory
-                if is_in_memory_type(&data_types.value_type) {
-                    (value_offset, value_length) =
-                        read_indirect_offset_and_length(memory, &mut caller, value_offset)?;
-                }
-                let value = read_from_wasm(
-                    memory,
+                // Read in the serialized value from the Wasm memory
+                let value = read_serialized_value(
                     &mut caller,
+                    memory,
                     &data_types.value_type,
                     value_offset,
                     value_length,
-                    epoch,
                 )?;
 
-                // Insert the value into the map
+                // Insert the value into the map, unless the key is present
+                let contract = caller.data().contract_context().contract_identifier.clone();
+                let epoch = caller.data().global_context.epoch_id;
+                let memory_use = memory_use_v200(epoch, &[&key, &value])?;
                 let result = caller.data_mut().global_context.database.insert_entry(
                     &contract,
                     map_name.as_str(),
@@ -2874,27 +2745,17 @@ fn link_map_insert_fn(linker: &mut Linker<ClarityWasmContext>) -> Result<(), Err
                     value,
                     &data_types,
                     &epoch,
-                );
-
-                let result_size = match &result {
-                    Ok(data) => data.serialized_byte_len,
-                    Err(_e) => (data_types.value_type.size()? + data_types.key_type.size()?) as u64,
-                };
+                )?;
 
                 // runtime_cost(ClarityCostFunction::SetEntry, env, result_size)?;
 
                 caller
                     .data_mut()
                     .global_context
-                    .add_memory(result_size)
+                    .add_memory(memory_use.unwrap_or(result.serialized_byte_len))
                     .map_err(Error::from)?;
 
-                let value = result.map(|data| data.value)?;
-                if let Value::Bool(true) = value {
-                    Ok(1i32)
-                } else {
-                    Ok(0i32)
-                }
+                Ok(matches!(result.value, Value::Bool(true)) as i32)
             },
         )
         .map(|_| ())
@@ -2916,11 +2777,9 @@ fn link_map_delete_fn(linker: &mut Linker<ClarityWasmContext>) -> Result<(), Err
             |mut caller: Caller<'_, ClarityWasmContext>,
              name_offset: i32,
              name_length: i32,
-             mut key_offset: i32,
-             mut key_length: i32| {
-                if caller.data().global_context.is_read_only() {
-                    return Err(CheckErrors::WriteAttemptedInReadOnly.into());
-                }
+             key_offset: i32,
+             key_length: i32| {
+                caller.data().check_writable()?;
 
                 // Get the memory from the caller
                 let memory = caller
@@ -2928,65 +2787,37 @@ fn link_map_delete_fn(linker: &mut Linker<ClarityWasmContext>) -> Result<(), Err
                     .and_then(|export| export.into_memory())
                     .ok_or(Error::Wasm(WasmError::MemoryNotFound))?;
 
-                // Retrieve the map name
-                let map_name =
-                    read_identifier_from_wasm(memory, &mut caller, name_offset, name_length)?;
-
-                let contract = caller.data().contract_context().contract_identifier.clone();
-                let epoch = caller.data_mut().global_context.epoch_id;
-
-                let data_types = caller
-                    .data()
-                    .contract_context()
-                    .meta_data_map
-                    .get(map_name.as_str())
-                    .ok_or(Error::Unchecked(CheckErrors::NoSuchMap(
-                        map_name.to_string(),
-                    )))?
-                    .clone();
-
-                // Read in the key from the Wasm memory
-                if is_in_memory_type(&data_types.key_type) {
-                    (key_offset, key_length) =
-                        read_indirect_offset_and_length(memory, &mut caller, key_offset)?;
-                }
-                let key = read_from_wasm(
-                    memory,
+                let (map_name, data_types, key) = read_map_key(
                     &mut caller,
-                    &data_types.key_type,
+                    memory,
+                    name_offset,
+                    name_length,
                     key_offset,
                     key_length,
-                    epoch,
                 )?;
+                let key = deserialize_value(&key, &data_types.key_type)?;
 
-                // Delete the key from the map in the global context
+                // Delete the key from the map, if it is present
+                let contract = caller.data().contract_context().contract_identifier.clone();
+                let epoch = caller.data().global_context.epoch_id;
+                let memory_use = memory_use_v200(epoch, &[&key])?;
                 let result = caller.data_mut().global_context.database.delete_entry(
                     &contract,
                     map_name.as_str(),
                     &key,
                     &data_types,
                     &epoch,
-                );
-
-                let result_size = match &result {
-                    Ok(data) => data.serialized_byte_len,
-                    Err(_e) => (data_types.value_type.size()? + data_types.key_type.size()?) as u64,
-                };
+                )?;
 
                 // runtime_cost(ClarityCostFunction::SetEntry, env, result_size)?;
 
                 caller
                     .data_mut()
                     .global_context
-                    .add_memory(result_size)
+                    .add_memory(memory_use.unwrap_or(result.serialized_byte_len))
                     .map_err(Error::from)?;
 
-                let value = result.map(|data| data.value)?;
-                if let Value::Bool(true) = value {
-                    Ok(1i32)
-                } else {
-                    Ok(0i32)
-                }
+                Ok(matches!(result.value, Value::Bool(true)) as i32)
             },
         )
         .map(|_| ())
@@ -2998,6 +2829,134 @@ fn link_map_delete_fn(linker: &mut Linker<ClarityWasmContext>) -> Result<(), Err
         })
 }
 
+/// Load the value of type `ty` stored at `key`, if any, and return its
+/// consensus serialization. Like the interpreter, the value is sanitized for
+/// the current epoch.
+fn load_serialized(
+    caller: &mut Caller<'_, ClarityWasmContext>,
+    key: &str,
+    ty: &TypeSignature,
+) -> Result<Option<Vec<u8>>, Error> {
+    let epoch = caller.data().global_context.epoch_id;
+    caller
+        .data_mut()
+        .global_context
+        .database
+        .get_value(key, ty, &epoch)?
+        .map(|stored| {
+            stored.value.serialize_to_vec().map_err(|e| {
+                Error::Interpreter(InterpreterError::Expect(format!(
+                    "stored value can't be serialized: {e}"
+                )))
+            })
+        })
+        .transpose()
+}
+
+/// Deserialize the consensus serialization of a value of type `ty`.
+fn deserialize_value(bytes: &[u8], ty: &TypeSignature) -> Result<Value, Error> {
+    Value::deserialize_read(&mut &bytes[..], Some(ty), false).map_err(|e| {
+        Error::Interpreter(InterpreterError::Expect(format!(
+            "invalid serialized value: {e}"
+        )))
+    })
+}
+
+/// Read the consensus serialization of a value of type `ty` written by the
+/// caller.
+fn read_serialized_value(
+    caller: &mut Caller<'_, ClarityWasmContext>,
+    memory: Memory,
+    ty: &TypeSignature,
+    offset: i32,
+    length: i32,
+) -> Result<Value, Error> {
+    check_serialized_size(ty, length)?;
+    let bytes = read_bytes_from_wasm(memory, caller, offset, length)?;
+    deserialize_value(&bytes, ty)
+}
+
+/// The memory charged by the interpreter of Stacks 2.0 for storing `values`,
+/// which is their memory use. From Stacks 2.05, the interpreter charges the
+/// size of the stored data reported by the database instead, and this returns
+/// `None`.
+fn memory_use_v200(epoch: StacksEpochId, values: &[&Value]) -> Result<Option<u64>, Error> {
+    if epoch >= StacksEpochId::Epoch2_05 {
+        return Ok(None);
+    }
+    values
+        .iter()
+        .try_fold(0u64, |total, value| Ok(total + value.get_memory_use()?))
+        .map(Some)
+}
+
+/// Write a stored consensus serialization to a buffer of `length` bytes of
+/// the caller, returning its length.
+fn write_serialized(
+    caller: &mut Caller<'_, ClarityWasmContext>,
+    memory: Memory,
+    value: &[u8],
+    offset: i32,
+    length: i32,
+) -> Result<i32, Error> {
+    if value.len() > length as usize {
+        return Err(Error::Interpreter(InterpreterError::Expect(
+            "stored value is larger than its type allows".to_string(),
+        )));
+    }
+    memory
+        .write(caller, offset as usize, value)
+        .map_err(|e| Error::Wasm(WasmError::Runtime(e.into())))?;
+    Ok(value.len() as i32)
+}
+
+/// Check that `length` bytes written by the caller can be the consensus
+/// serialization of a value of type `ty`.
+fn check_serialized_size(ty: &TypeSignature, length: i32) -> Result<(), Error> {
+    if length < 0 || length as u32 > ty.max_serialized_size()? {
+        return Err(Error::Unchecked(CheckErrors::ValueTooLarge));
+    }
+    Ok(())
+}
+
+/// Read the name of a map and the consensus serialization of a key from the
+/// memory, and return the name and metadata of the map and the key.
+fn read_map_key(
+    caller: &mut Caller<'_, ClarityWasmContext>,
+    memory: Memory,
+    name_offset: i32,
+    name_length: i32,
+    key_offset: i32,
+    key_length: i32,
+) -> Result<(String, DataMapMetadata, Vec<u8>), Error> {
+    let map_name = read_identifier_from_wasm(memory, caller, name_offset, name_length)?;
+
+    let data_types = caller
+        .data()
+        .contract_context()
+        .meta_data_map
+        .get(map_name.as_str())
+        .ok_or(Error::Unchecked(CheckErrors::NoSuchMap(
+            map_name.to_string(),
+        )))?
+        .clone();
+
+    check_serialized_size(&data_types.key_type, key_length)?;
+    let key = read_bytes_from_wasm(memory, caller, key_offset, key_length)?;
+    Ok((map_name, data_types, key))
+}
+
+/// The key in the database of the entry of the map `map_name` at the
+/// serialized `key`.
+fn map_entry_key(caller: &Caller<'_, ClarityWasmContext>, map_name: &str, key: &[u8]) -> String {
+    ClarityDatabase::make_key_for_quad(
+        &caller.data().contract_context().contract_identifier,
+        StoreType::DataMap,
+        map_name,
+        &hex::encode(key),
+    )
+}
+
 fn check_height_valid(
     caller: &mut Caller<'_, ClarityWasmContext>,
     memory: Memory,
@@ -4313,10 +4272,9 @@ fn link_contract_call_fn(linker: &mut Linker<ClarityWasmContext>) -> Result<(),
              _args_length: i32,
              return_offset: i32,
              _return_length: i32| {
-                // the second part of the contract_call cost (i.e., the load contract cost)
-                //   is checked in `execute_contract`, and the function _application_ cost
-                //   is checked in callables::DefinedFunction::execute_apply.
-                // runtime_cost(ClarityCostFunction::ContractCall, env, 0)?;
+                // The `ContractCall` cost is charged by the cost code of the
+                // caller; the load contract and function application costs of
+                // the callee are charged in `call_contract_from_wasm`.
 
                 // Get the memory from the caller
                 let memory = caller
@@ -4324,8 +4282,6 @@ fn link_contract_call_fn(linker: &mut Linker<ClarityWasmContext>) -> Result<(),
                     .and_then(|export| export.into_memory())
                     .ok_or(Error::Wasm(WasmError::MemoryNotFound))?;
 
-                let epoch = caller.data_mut().global_context.epoch_id;
-
                 // Read the contract identifier from the Wasm memory
                 let contract_val = read_from_wasm(
                     memory,
@@ -4333,7 +4289,6 @@ fn link_contract_call_fn(linker: &mut Linker<ClarityWasmContext>) -> Result<(),
                     &TypeSignature::PrincipalType,
                     contract_offset,
                     contract_length,
-                    epoch,
                 )?;
                 let contract_id = match &contract_val {
                     Value::Principal(PrincipalData::Contract(contract_id)) => contract_id,
@@ -4350,6 +4305,13 @@ fn link_contract_call_fn(linker: &mut Linker<ClarityWasmContext>) -> Result<(),
                     function_length,
                 )?;
 
+                // Refuse to go deeper than the configured maximum call depth
+                let call_depth = caller.data().call_stack.depth();
+                caller
+                    .data()
+                    .resource_limits()
+                    .check_call_depth(call_depth)?;
+
                 // Retrieve the contract context for the contract we're calling
                 let mut contract = caller
                     .data_mut()
@@ -4372,8 +4334,7 @@ fn link_contract_call_fn(linker: &mut Linker<ClarityWasmContext>) -> Result<(),
                 let mut arg_offset = args_offset;
                 // Read the arguments from the Wasm memory
                 for arg_ty in function.get_arg_types() {
-                    let arg =
-                        read_from_wasm_indirect(memory, &mut caller, arg_ty, arg_offset, epoch)?;
+                    let arg = read_from_wasm_indirect(memory, &mut caller, arg_ty, arg_offset)?;
                     args_sizes.push(arg.size()? as u64);
                     args.push(arg);
 
@@ -4386,11 +4347,7 @@ fn link_contract_call_fn(linker: &mut Linker<ClarityWasmContext>) -> Result<(),
                     .contract_identifier
                     .clone()
                     .into();
-                caller.data_mut().push_caller(caller_contract.clone());
-
-                let mut call_stack = caller.data().call_stack.clone();
-                let sender = caller.data().sender.clone();
-                let sponsor = caller.data().sponsor.clone();
+                let options = caller.data().execution_options();
 
                 let short_circuit_cost = caller
                     .data_mut()
@@ -4402,22 +4359,30 @@ fn link_contract_call_fn(linker: &mut Linker<ClarityWasmContext>) -> Result<(),
                         &args_sizes,
                     )?;
 
-                let mut env = Environment {
-                    global_context: caller.data_mut().global_context,
-                    contract_context: &contract.contract_context,
-                    call_stack: &mut call_stack,
-                    sender,
-                    caller: Some(caller_contract),
-                    sponsor,
-                };
-
-                let result = if short_circuit_cost {
-                    env.run_free(|free_env| {
-                        free_env.execute_contract_from_wasm(contract_id, &function_name, &args)
-                    })
-                } else {
-                    env.execute_contract_from_wasm(contract_id, &function_name, &args)
-                }?;
+                // The callee runs with the runtime of this crate, in the same
+                // global context and call stack as the caller.
+                let data = caller.data_mut();
+                let cost_track = short_circuit_cost.then(|| {
+                    std::mem::replace(
+                        &mut data.global_context.cost_track,
+                        LimitedCostTracker::new_free(),
+                    )
+                });
+                let result = call_contract_from_wasm(
+                    data.global_context,
+                    &contract.contract_context,
+                    function,
+                    &args,
+                    data.call_stack,
+                    data.sender.clone(),
+                    Some(caller_contract),
+                    data.sponsor.clone(),
+                    options,
+                );
+                if let Some(cost_track) = cost_track {
+                    data.global_context.cost_track = cost_track;
+                }
+                let result = result?;
 
                 // Write the result to the return buffer
                 let return_ty = if trait_id_length == 0 {
@@ -4558,6 +4523,40 @@ fn link_roll_back_call_fn(linker: &mut Linker<ClarityWasmContext>) -> Result<(),
         })
 }
 
+/// Returns the type with id `type_id` in the type table of the module, looking
+/// up the decoded table on first use.
+fn type_from_table(
+    caller: &mut Caller<'_, ClarityWasmContext>,
+    memory: Memory,
+    type_id: i32,
+) -> Result<TypeSignature, Error> {
+    if caller.data().type_table.is_none() {
+        let mut global = |name: &str| {
+            caller
+                .get_export(name)
+                .and_then(|export| export.into_global())
+                .and_then(|global| global.get(&mut *caller).i32())
+                .ok_or_else(|| Error::Wasm(WasmError::GlobalNotFound(name.to_string())))
+        };
+        let offset = global("type-table-offset")?;
+        let length = global("type-table-length")?;
+        let bytes = read_bytes_from_wasm(memory, &mut *caller, offset, length)?;
+        let table = type_table::decoded_table(&bytes)?;
+        caller.data_mut().type_table = Some(table);
+    }
+    caller
+        .data()
+        .type_table
+        .as_ref()
+        .and_then(|types| types.get(type_id as usize))
+        .cloned()
+        .ok_or_else(|| {
+            Error::Wasm(WasmError::WasmGeneratorError(format!(
+                "type {type_id} not found in the type table"
+            )))
+        })
+}
+
 /// Link host interface function, `print`, into the Wasm module.
 /// This function is called for all contract print statements (`print`).
 fn link_print_fn(linker: &mut Linker<ClarityWasmContext>) -> Result<(), Error> {
@@ -4568,8 +4567,7 @@ fn link_print_fn(linker: &mut Linker<ClarityWasmContext>) -> Result<(), Error> {
             |mut caller: Caller<'_, ClarityWasmContext>,
              value_offset: i32,
              _value_length: i32,
-             serialized_ty_offset: i32,
-             serialized_ty_length: i32| {
+             type_id: i32| {
                 // runtime_cost(ClarityCostFunction::Print, env, input.size())?;
 
                 // Get the memory from the caller
@@ -4578,19 +4576,9 @@ fn link_print_fn(linker: &mut Linker<ClarityWasmContext>) -> Result<(), Error> {
                     .and_then(|export| export.into_memory())
                     .ok_or(Error::Wasm(WasmError::MemoryNotFound))?;
 
-                let serialized_ty = read_identifier_from_wasm(
-                    memory,
-                    &mut caller,
-                    serialized_ty_offset,
-                    serialized_ty_length,
-                )?;
-
-                let epoch = caller.data().global_context.epoch_id;
-                let version = caller.data().contract_context().get_clarity_version();
-
-                let value_ty = signature_from_string(&serialized_ty, *version, epoch)?;
+                let value_ty = type_from_table(&mut caller, memory, type_id)?;
                 let clarity_val =
-                    read_from_wasm_indirect(memory, &mut caller, &value_ty, value_offset, epoch)?;
+                    read_from_wasm_indirect(memory, &mut caller, &value_ty, value_offset)?;
 
                 caller.data_mut().register_print_event(clarity_val)?;
 
@@ -4618,7 +4606,6 @@ fn link_enter_at_block_fn(linker: &mut Linker<ClarityWasmContext>) -> Result<(),
                     .get_export("memory")
                     .and_then(|export| export.into_memory())
                     .ok_or(Error::Wasm(WasmError::MemoryNotFound))?;
-                let epoch = caller.data_mut().global_context.epoch_id;
 
                 let block_hash = read_from_wasm(
                     memory,
@@ -4626,7 +4613,6 @@ fn link_enter_at_block_fn(linker: &mut Linker<ClarityWasmContext>) -> Result<(),
                     &BUFF_32,
                     block_hash_offset,
                     block_hash_length,
-                    epoch,
                 )?;
 
                 let bhh = match block_hash {
@@ -4989,8 +4975,6 @@ fn link_principal_of_fn(linker: &mut Linker<ClarityWasmContext>) -> Result<(), E
                     .and_then(|export| export.into_memory())
                     .ok_or(Error::Wasm(WasmError::MemoryNotFound))?;
 
-                let epoch = caller.data_mut().global_context.epoch_id;
-
                 // Read the public key from the memory
                 let key_val = read_from_wasm(
                     memory,
@@ -4998,7 +4982,6 @@ fn link_principal_of_fn(linker: &mut Linker<ClarityWasmContext>) -> Result<(), E
                     &BUFF_33.clone(),
                     key_offset,
                     key_length,
-                    epoch,
                 )?;
 
                 let pub_key = match key_val {
@@ -5074,8 +5057,6 @@ fn link_save_constant_fn(linker: &mut Linker<ClarityWasmContext>) -> Result<(),
                     .and_then(|export| export.into_memory())
                     .ok_or(Error::Wasm(WasmError::MemoryNotFound))?;
 
-                let epoch = caller.data_mut().global_context.epoch_id;
-
                 // Get constant name from the memory.
                 let const_name =
                     read_identifier_from_wasm(memory, &mut caller, name_offset, name_length)?;
@@ -5089,8 +5070,7 @@ fn link_save_constant_fn(linker: &mut Linker<ClarityWasmContext>) -> Result<(),
                     .get_variable_type(const_name.as_str())
                     .ok_or(Error::Wasm(WasmError::DefinesNotFound))?;
 
-                let value =
-                    read_from_wasm_indirect(memory, &mut caller, value_ty, value_offset, epoch)?;
+                let value = read_from_wasm_indirect(memory, &mut caller, value_ty, value_offset)?;
 
                 // Insert constant name and expression value into a persistent data structure.
                 caller
@@ -5302,6 +5282,7 @@ pub fn dummy_linker(engine: &Engine) -> Result<Linker<()>, wasmtime::Error> {
         "get_variable",
         |_name_offset: i32, _name_length: i32, _return_offset: i32, _return_length: i32| {
             println!("var-get");
+            Ok(0i32)
         },
     )?;
 
@@ -5316,10 +5297,7 @@ pub fn dummy_linker(engine: &Engine) -> Result<Linker<()>, wasmtime::Error> {
     linker.func_wrap(
         "clarity",
         "print",
-        |_value_offset: i32,
-         _value_length: i32,
-         _serialized_ty_offset: i32,
-         _serialized_ty_length: i32| {
+        |_value_offset: i32, _value_length: i32, _type_id: i32| {
             println!("print");
         },
     )?;
@@ -5551,7 +5529,7 @@ pub fn dummy_linker(engine: &Engine) -> Result<Linker<()>, wasmtime::Error> {
          _key_offset: i32,
          _key_length: i32,
          _return_offset: i32,
-         _return_length: i32| { Ok(()) },
+         _return_length: i32| { Ok(0i32) },
     )?;
 
     linker.func_wrap(
@@ -5904,7 +5882,7 @@ pub fn dummy_linker(engine: &Engine) -> Result<Linker<()>, wasmtime::Error> {
 
 /// the standard.wat file and link in all of the host interface functions.
 pub fn load_stdlib() -> Result<(Instance, Store<()>), wasmtime::Error> {
-    let standard_lib = include_str!("standard/standard.wat");
+    let standard_lib: &[u8] = include_bytes!("standard/standard.wasm");
     let engine = Engine::default();
     let mut store = Store::new(&engine, ());
     let linker = dummy_linker(&engine)?;
@@ -5912,3 +5890,152 @@ pub fn load_stdlib() -> Result<(Instance, Store<()>), wasmtime::Error> {
     let instance = linker.instantiate(&mut store, &module)?;
     Ok((instance, store))
 }
+
+#[cfg(test)]
+mod tests {
+    use clarity::consts::CHAIN_ID_TESTNET;
+    use clarity::vm::contexts::GlobalContext;
+    use clarity::vm::costs::LimitedCostTracker;
+    use clarity::vm::types::{QualifiedContractIdentifier, StandardPrincipalData};
+    use clarity::vm::{CallStack, ContractContext};
+    use walrus::{FunctionBuilder, ValType};
+
+    use super::*;
+    use crate::datastore::{BurnDatastore, Datastore, StacksConstants};
+    use crate::tools::TestConfig;
+
+    /// Builds a module exporting a function `write`, which calls the host
+    /// function `import` with zeroed arguments, bypassing the compiler.
+    fn raw_host_call(import: &str, params: &[ValType], results: &[ValType]) -> Vec<u8> {
+        let mut module = walrus::Module::default();
+        let ty = module.types.add(params, results);
+        let (host_func, _) = module.add_import_func("clarity", import, ty);
+        let memory = module.memories.add_local(false, 1, None);
+        module.exports.add("memory", memory);
+
+        let mut builder = FunctionBuilder::new(&mut module.types, &[], &[]);
+        let mut body = builder.func_body();
+        for param in params {
+            match param {
+                ValType::I64 => body.i64_const(0),
+                _ => body.i32_const(0),
+            };
+        }
+        body.call(host_func);
+        for _ in results {
+            body.drop();
+        }
+        let write = builder.finish(vec![], &mut module.funcs);
+        module.exports.add("write", write);
+
+        module.emit_wasm()
+    }
+
+    /// Calls the host function `import` from a raw module, in a context which
+    /// is read-only if `read_only` is set, and returns the resulting error.
+    fn call_raw_host_import(
+        import: &str,
+        params: &[ValType],
+        results: &[ValType],
+        read_only: bool,
+    ) -> Error {
+        let mut datastore = Datastore::new();
+        let burn_datastore = BurnDatastore::new(StacksConstants::default());
+        let conn = ClarityDatabase::new(&mut datastore, &burn_datastore, &burn_datastore);
+        let mut global_context = GlobalContext::new(
+            false,
+            CHAIN_ID_TESTNET,
+            conn,
+            LimitedCostTracker::new_free(),
+            TestConfig::latest_epoch(),
+        );
+        if read_only {
+            global_context.begin_read_only();
+        } else {
+            global_context.begin();
+        }
+
+        let contract_context = ContractContext::new(
+            QualifiedContractIdentifier::new(StandardPrincipalData::transient(), "raw".into()),
+            TestConfig::clarity_version(),
+        );
+        let mut call_stack = CallStack::new();
+        let context = ClarityWasmContext::new_run(
+            &mut global_context,
+            &contract_context,
+            &mut call_stack,
+            Some(StandardPrincipalData::transient().into()),
+            None,
+            None,
+            None,
+        );
+
+        let engine = Engine::default();
+        let module = Module::from_binary(&engine, &raw_host_call(import, params, results))
+            .expect("failed to load module");
+        let mut store = Store::new(&engine, context);
+        let mut linker = Linker::new(&engine);
+        link_host_functions(&mut linker).expect("failed to link host functions");
+        let instance = linker
+            .instantiate(&mut store, &module)
+            .expect("failed to instantiate module");
+
+        let write = instance
+            .get_func(&mut store, "write")
+            .expect("write function not found");
+        let err = write
+            .call(&mut store, &[], &mut [])
+            .expect_err("host call should fail");
+        err.downcast::<Error>().expect("unexpected error type")
+    }
+
+    fn is_write_in_read_only(err: &Error) -> bool {
+        matches!(err, Error::Unchecked(CheckErrors::WriteAttemptedInReadOnly))
+    }
+
+    #[test]
+    fn writes_are_rejected_in_read_only_context() {
+        use ValType::{I32, I64};
+
+        let ret = [I32, I32, I64, I64];
+        let writes: &[(&str, &[ValType], &[ValType])] = &[
+            ("set_variable", &[I32; 4], &[]),
+            ("map_set", &[I32; 6], &[I32]),
+            ("map_insert", &[I32; 6], &[I32]),
+            ("map_delete", &[I32; 4], &[I32]),
+            ("stx_burn", &[I64, I64, I32, I32], &ret),
+            (
+                "stx_transfer",
+                &[I64, I64, I32, I32, I32, I32, I32, I32],
+                &ret,
+            ),
+            ("ft_burn", &[I32, I32, I64, I64, I32, I32], &ret),
+            ("ft_mint", &[I32, I32, I64, I64, I32, I32], &ret),
+            (
+                "ft_transfer",
+                &[I32, I32, I64, I64, I32, I32, I32, I32],
+                &ret,
+            ),
+            ("nft_burn", &[I32; 6], &ret),
+            ("nft_mint", &[I32; 6], &ret),
+            ("nft_transfer", &[I32; 8], &ret),
+        ];
+
+        for (import, params, results) in writes {
+            let err = call_raw_host_import(import, params, results, true);
+            assert!(is_write_in_read_only(&err), "{import}: {err:?}");
+        }
+    }
+
+    #[test]
+    fn writes_are_checked_only_in_read_only_context() {
+        use ValType::I32;
+
+        // The call goes past the check, and fails on the missing variable.
+        let err = call_raw_host_import("set_variable", &[I32; 4], &[], false);
+        assert!(
+            matches!(err, Error::Unchecked(CheckErrors::NoSuchDataVariable(ref name)) if name.is_empty()),
+            "{err:?}"
+        );
+    }
+}
@@ -0,0 +1,822 @@
+//! Marshalling of Clarity values between the memory of a contract and the host.
+//!
+//! The representation of the values of a type is described by a [Layout],
+//! computed once from its type signature: the size of the representation, the
+//! offsets of its parts, and which parts are stored indirectly, as an offset
+//! and a length pointing to their content. The layouts of the types crossing
+//! between the host and the contracts are cached, see [Layout::of].
+//!
+//! Reading a value walks its layout over a borrowed slice of the memory, and
+//! hands the parts of the value to a [ValueVisitor]. Buffers and strings are
+//! handed as slices of the memory, so a visitor only allocates what it keeps.
+//! [ValueBuilder] is the visitor building a [Value]. Writing a value walks its
+//! layout over a mutable slice of the memory, without intermediate buffers.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use clarity::vm::analysis::CheckErrors;
+use clarity::vm::errors::{Error, WasmError};
+use clarity::vm::types::signatures::CallableSubtype;
+use clarity::vm::types::{
+    CallableData, CharType, PrincipalData, QualifiedContractIdentifier, SequenceData,
+    SequenceSubtype, StandardPrincipalData, StringSubtype, TraitIdentifier, TupleData,
+    TypeSignature,
+};
+use clarity::vm::{ClarityName, ContractName, Value};
+
+use crate::cache::ContentCache;
+use crate::runtime::Val;
+use crate::wasm_utils::{PRINCIPAL_HASH_BYTES, PRINCIPAL_VERSION_BYTES, STANDARD_PRINCIPAL_BYTES};
+
+/// Size of the representation of in-memory values: an offset and a length.
+const INDIRECT_SIZE: i32 = 8;
+
+/// Maximum number of layouts cached on a thread.
+const MAX_CACHED_LAYOUTS: usize = 4096;
+
+thread_local! {
+    /// The layouts computed by [Layout::of], by type.
+    static LAYOUTS: RefCell<ContentCache<Rc<Layout>>> =
+        RefCell::new(ContentCache::new(MAX_CACHED_LAYOUTS));
+}
+
+/// The representation of the values of a type in the memory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Layout {
+    /// Size of the representation, as returned by
+    /// [get_type_size](crate::wasm_utils::get_type_size).
+    size: i32,
+    /// Number of Wasm values of the representation.
+    vals: usize,
+    kind: Kind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Kind {
+    Int,
+    UInt,
+    Bool,
+    /// The placeholder for a value which cannot exist, like the value of a
+    /// `none`.
+    NoType,
+    Principal,
+    /// A contract implementing a trait.
+    Callable(TraitIdentifier),
+    Buffer,
+    StringAscii,
+    StringUtf8,
+    List(Box<Layout>),
+    /// The indicator is followed by the value.
+    Optional(Box<Layout>),
+    /// The indicator is followed by the ok value, then by the err value.
+    Response(Box<Layout>, Box<Layout>),
+    /// The fields with their offsets, in the order of the type signature.
+    Tuple(Vec<(ClarityName, i32, Layout)>),
+}
+
+impl Layout {
+    pub fn new(ty: &TypeSignature) -> Result<Self, Error> {
+        let (size, vals, kind) = match ty {
+            TypeSignature::IntType => (16, 2, Kind::Int),
+            TypeSignature::UIntType => (16, 2, Kind::UInt),
+            TypeSignature::BoolType => (4, 1, Kind::Bool),
+            TypeSignature::NoType => (4, 1, Kind::NoType),
+            TypeSignature::CallableType(CallableSubtype::Trait(trait_identifier)) => {
+                (INDIRECT_SIZE, 2, Kind::Callable(trait_identifier.clone()))
+            }
+            TypeSignature::PrincipalType
+            | TypeSignature::CallableType(_)
+            | TypeSignature::TraitReferenceType(_) => (INDIRECT_SIZE, 2, Kind::Principal),
+            TypeSignature::SequenceType(SequenceSubtype::BufferType(_)) => {
+                (INDIRECT_SIZE, 2, Kind::Buffer)
+            }
+            TypeSignature::SequenceType(SequenceSubtype::StringType(StringSubtype::ASCII(_))) => {
+                (INDIRECT_SIZE, 2, Kind::StringAscii)
+            }
+            TypeSignature::SequenceType(SequenceSubtype::StringType(StringSubtype::UTF8(_))) => {
+                (INDIRECT_SIZE, 2, Kind::StringUtf8)
+            }
+            TypeSignature::SequenceType(SequenceSubtype::ListType(list)) => (
+                INDIRECT_SIZE,
+                2,
+                Kind::List(Box::new(Layout::new(list.get_list_item_type())?)),
+            ),
+            TypeSignature::OptionalType(inner) => {
+                let inner = Layout::new(inner)?;
+                (
+                    4 + inner.size,
+                    1 + inner.vals,
+                    Kind::Optional(Box::new(inner)),
+                )
+            }
+            TypeSignature::ResponseType(types) => {
+                let ok = Layout::new(&types.0)?;
+                let err = Layout::new(&types.1)?;
+                (
+                    4 + ok.size + err.size,
+                    1 + ok.vals + err.vals,
+                    Kind::Response(Box::new(ok), Box::new(err)),
+                )
+            }
+            TypeSignature::TupleType(tuple) => {
+                let mut size = 0;
+                let mut vals = 0;
+                let mut fields = Vec::with_capacity(tuple.get_type_map().len());
+                for (name, ty) in tuple.get_type_map() {
+                    let field = Layout::new(ty)?;
+                    let offset = size;
+                    size += field.size;
+                    vals += field.vals;
+                    fields.push((name.clone(), offset, field));
+                }
+                (size, vals, Kind::Tuple(fields))
+            }
+            TypeSignature::ListUnionType(_) => {
+                return Err(Error::Wasm(WasmError::InvalidListUnionTypeInValue))
+            }
+        };
+        Ok(Self { size, vals, kind })
+    }
+
+    /// Returns the layout of `ty`, cached on this thread (see [crate::cache]).
+    pub fn of(ty: &TypeSignature) -> Result<Rc<Self>, Error> {
+        LAYOUTS.with(|layouts| {
+            layouts
+                .borrow_mut()
+                .get_or_try_insert(ty, || Self::new(ty).map(Rc::new))
+        })
+    }
+
+    /// Size of the representation of a value.
+    pub fn size(&self) -> i32 {
+        self.size
+    }
+
+    /// Number of Wasm values representing a value on the stack.
+    pub fn vals(&self) -> usize {
+        self.vals
+    }
+
+    /// Whether the representation is an offset and a length pointing to the
+    /// content of the value.
+    pub fn is_in_memory(&self) -> bool {
+        matches!(
+            self.kind,
+            Kind::Principal
+                | Kind::Callable(_)
+                | Kind::Buffer
+                | Kind::StringAscii
+                | Kind::StringUtf8
+                | Kind::List(_)
+        )
+    }
+}
+
+/// Receives the parts of a value read by a [Layout].
+///
+/// Composite values are visited after their parts, which are handed back to
+/// the visitor as the outputs of their own visits.
+pub trait ValueVisitor {
+    type Output;
+
+    fn int(&mut self, value: i128) -> Result<Self::Output, Error>;
+
+    fn uint(&mut self, value: u128) -> Result<Self::Output, Error>;
+
+    fn bool(&mut self, value: bool) -> Result<Self::Output, Error>;
+
+    /// A principal. The contract name is empty for standard principals, and
+    /// the trait identifier is only set for callable contracts.
+    fn principal(
+        &mut self,
+        version: u8,
+        hash: &[u8; PRINCIPAL_HASH_BYTES],
+        contract_name: &str,
+        trait_identifier: Option<&TraitIdentifier>,
+    ) -> Result<Self::Output, Error>;
+
+    fn buffer(&mut self, bytes: &[u8]) -> Result<Self::Output, Error>;
+
+    fn string_ascii(&mut self, bytes: &[u8]) -> Result<Self::Output, Error>;
+
+    /// A UTF-8 string, as the big-endian unicode scalars of its characters.
+    fn string_utf8(&mut self, scalars: &[u8]) -> Result<Self::Output, Error>;
+
+    fn list(&mut self, elements: Vec<Self::Output>) -> Result<Self::Output, Error>;
+
+    fn optional(&mut self, value: Option<Self::Output>) -> Result<Self::Output, Error>;
+
+    fn response(&mut self, committed: bool, value: Self::Output) -> Result<Self::Output, Error>;
+
+    fn tuple(&mut self, fields: Vec<(&ClarityName, Self::Output)>) -> Result<Self::Output, Error>;
+}
+
+/// Builds the [Value] read by a [Layout].
+pub struct ValueBuilder;
+
+impl ValueVisitor for ValueBuilder {
+    type Output = Value;
+
+    fn int(&mut self, value: i128) -> Result<Value, Error> {
+        Ok(Value::Int(value))
+    }
+
+    fn uint(&mut self, value: u128) -> Result<Value, Error> {
+        Ok(Value::UInt(value))
+    }
+
+    fn bool(&mut self, value: bool) -> Result<Value, Error> {
+        Ok(Value::Bool(value))
+    }
+
+    fn principal(
+        &mut self,
+        version: u8,
+        hash: &[u8; PRINCIPAL_HASH_BYTES],
+        contract_name: &str,
+        trait_identifier: Option<&TraitIdentifier>,
+    ) -> Result<Value, Error> {
+        let issuer = StandardPrincipalData::new(version, *hash)?;
+        if contract_name.is_empty() {
+            return Ok(Value::Principal(PrincipalData::Standard(issuer)));
+        }
+        let contract_identifier = QualifiedContractIdentifier {
+            issuer,
+            name: ContractName::try_from(contract_name.to_owned())?,
+        };
+        Ok(match trait_identifier {
+            Some(trait_identifier) => Value::CallableContract(CallableData {
+                contract_identifier,
+                trait_identifier: Some(trait_identifier.clone()),
+            }),
+            None => Value::Principal(PrincipalData::Contract(contract_identifier)),
+        })
+    }
+
+    fn buffer(&mut self, bytes: &[u8]) -> Result<Value, Error> {
+        Value::buff_from(bytes.to_vec())
+    }
+
+    fn string_ascii(&mut self, bytes: &[u8]) -> Result<Value, Error> {
+        Value::string_ascii_from_bytes(bytes.to_vec())
+    }
+
+    fn string_utf8(&mut self, scalars: &[u8]) -> Result<Value, Error> {
+        Value::string_utf8_from_unicode_scalars(scalars.to_vec())
+    }
+
+    fn list(&mut self, elements: Vec<Value>) -> Result<Value, Error> {
+        Value::cons_list_unsanitized(elements)
+    }
+
+    fn optional(&mut self, value: Option<Value>) -> Result<Value, Error> {
+        match value {
+            Some(value) => {
+                Value::some(value).map_err(|_| Error::Wasm(WasmError::ValueTypeMismatch))
+            }
+            None => Ok(Value::none()),
+        }
+    }
+
+    fn response(&mut self, committed: bool, value: Value) -> Result<Value, Error> {
+        if committed {
+            Value::okay(value)
+        } else {
+            Value::error(value)
+        }
+        .map_err(|_| Error::Wasm(WasmError::ValueTypeMismatch))
+    }
+
+    fn tuple(&mut self, fields: Vec<(&ClarityName, Value)>) -> Result<Value, Error> {
+        Ok(Value::Tuple(TupleData::from_data(
+            fields
+                .into_iter()
+                .map(|(name, value)| (name.clone(), value))
+                .collect(),
+        )?))
+    }
+}
+
+fn out_of_bounds() -> Error {
+    Error::Wasm(WasmError::Runtime(wasmtime::Error::msg(
+        "out of bounds memory access",
+    )))
+}
+
+/// The range of `length` bytes at `offset`, if it fits in `memory_len`.
+fn range(memory_len: usize, offset: i32, length: usize) -> Result<std::ops::Range<usize>, Error> {
+    let start = usize::try_from(offset).map_err(|_| out_of_bounds())?;
+    let end = start.checked_add(length).ok_or_else(out_of_bounds)?;
+    if end > memory_len {
+        return Err(out_of_bounds());
+    }
+    Ok(start..end)
+}
+
+fn bytes_at(memory: &[u8], offset: i32, length: i32) -> Result<&[u8], Error> {
+    let length = usize::try_from(length).map_err(|_| out_of_bounds())?;
+    Ok(&memory[range(memory.len(), offset, length)?])
+}
+
+fn array_at<const N: usize>(memory: &[u8], offset: i32) -> Result<[u8; N], Error> {
+    let mut array = [0; N];
+    array.copy_from_slice(&memory[range(memory.len(), offset, N)?]);
+    Ok(array)
+}
+
+fn i32_at(memory: &[u8], offset: i32) -> Result<i32, Error> {
+    array_at(memory, offset).map(i32::from_le_bytes)
+}
+
+fn put(memory: &mut [u8], offset: i32, bytes: &[u8]) -> Result<(), Error> {
+    let range = range(memory.len(), offset, bytes.len())?;
+    memory[range].copy_from_slice(bytes);
+    Ok(())
+}
+
+fn val_i32(vals: &[Val], index: usize) -> Result<i32, Error> {
+    vals.get(index)
+        .and_then(|val| val.i32())
+        .ok_or(Error::Wasm(WasmError::ValueTypeMismatch))
+}
+
+fn val_i128(vals: &[Val], index: usize) -> Result<i128, Error> {
+    let lower = vals.get(index).and_then(|val| val.i64());
+    let upper = vals.get(index + 1).and_then(|val| val.i64());
+    match (lower, upper) {
+        (Some(lower), Some(upper)) => Ok(((upper as i128) << 64) | (lower as u64) as i128),
+        _ => Err(Error::Wasm(WasmError::ValueTypeMismatch)),
+    }
+}
+
+impl Layout {
+    /// Visits the value whose representation is at `offset` in `memory`.
+    pub fn visit<V: ValueVisitor>(
+        &self,
+        memory: &[u8],
+        offset: i32,
+        visitor: &mut V,
+    ) -> Result<V::Output, Error> {
+        if self.is_in_memory() {
+            let content_offset = i32_at(memory, offset)?;
+            let length = i32_at(memory, offset + 4)?;
+            self.visit_content(memory, content_offset, length, visitor)
+        } else {
+            self.visit_content(memory, offset, self.size, visitor)
+        }
+    }
+
+    /// Visits the value at `offset` in `memory`, spanning `length` bytes. For
+    /// in-memory values, this is their content instead of their
+    /// representation.
+    pub fn visit_content<V: ValueVisitor>(
+        &self,
+        memory: &[u8],
+        offset: i32,
+        length: i32,
+        visitor: &mut V,
+    ) -> Result<V::Output, Error> {
+        match &self.kind {
+            Kind::Int => visitor.int(i128::from_le_bytes(array_at(memory, offset)?)),
+            Kind::UInt => visitor.uint(u128::from_le_bytes(array_at(memory, offset)?)),
+            Kind::Bool => visitor.bool(i32_at(memory, offset)? != 0),
+            Kind::NoType => Err(Error::Wasm(WasmError::InvalidNoTypeInValue)),
+            Kind::Principal | Kind::Callable(_) => {
+                // A version byte, the hash, and the contract name prefixed by
+                // its length as a byte.
+                let standard = bytes_at(memory, offset, STANDARD_PRINCIPAL_BYTES as i32)?;
+                let hash = standard
+                    [PRINCIPAL_VERSION_BYTES..PRINCIPAL_VERSION_BYTES + PRINCIPAL_HASH_BYTES]
+                    .try_into()
+                    .map_err(|_| out_of_bounds())?;
+                let name = bytes_at(
+                    memory,
+                    offset + STANDARD_PRINCIPAL_BYTES as i32,
+                    standard[STANDARD_PRINCIPAL_BYTES - 1] as i32,
+                )?;
+                let name = std::str::from_utf8(name)
+                    .map_err(|e| Error::Wasm(WasmError::Runtime(e.into())))?;
+                let trait_identifier = match &self.kind {
+                    Kind::Callable(trait_identifier) => Some(trait_identifier),
+                    _ => None,
+                };
+                visitor.principal(standard[0], hash, name, trait_identifier)
+            }
+            Kind::Buffer => visitor.buffer(bytes_at(memory, offset, length)?),
+            Kind::StringAscii => visitor.string_ascii(bytes_at(memory, offset, length)?),
+            Kind::StringUtf8 => visitor.string_utf8(bytes_at(memory, offset, length)?),
+            Kind::List(element) => {
+                // The representations of the elements are contiguous.
+                let elements = (0..length / element.size)
+                    .map(|i| element.visit(memory, offset + i * element.size, visitor))
+                    .collect::<Result<Vec<_>, _>>()?;
+                visitor.list(elements)
+            }
+            Kind::Optional(inner) => match i32_at(memory, offset)? {
+                0 => visitor.optional(None),
+                1 => {
+                    let value = inner.visit(memory, offset + 4, visitor)?;
+                    visitor.optional(Some(value))
+                }
+                indicator => Err(Error::Wasm(WasmError::InvalidIndicator(indicator))),
+            },
+            Kind::Response(ok, err) => match i32_at(memory, offset)? {
+                0 => {
+                    let value = err.visit(memory, offset + 4 + ok.size, visitor)?;
+                    visitor.response(false, value)
+                }
+                1 => {
+                    let value = ok.visit(memory, offset + 4, visitor)?;
+                    visitor.response(true, value)
+                }
+                indicator => Err(Error::Wasm(WasmError::InvalidIndicator(indicator))),
+            },
+            Kind::Tuple(fields) => {
+                let fields = fields
+                    .iter()
+                    .map(|(name, field_offset, field)| {
+                        Ok((name, field.visit(memory, offset + field_offset, visitor)?))
+                    })
+                    .collect::<Result<Vec<_>, Error>>()?;
+                visitor.tuple(fields)
+            }
+        }
+    }
+
+    /// Visits the value represented by the Wasm values `vals`, reading the
+    /// content of in-memory values from `memory`.
+    ///
+    /// Returns `None` for a `NoType` value, which is a placeholder that
+    /// should not be used.
+    pub fn visit_vals<V: ValueVisitor>(
+        &self,
+        vals: &[Val],
+        memory: &[u8],
+        visitor: &mut V,
+    ) -> Result<Option<V::Output>, Error> {
+        let output = match &self.kind {
+            Kind::Int => visitor.int(val_i128(vals, 0)?)?,
+            Kind::UInt => visitor.uint(val_i128(vals, 0)? as u128)?,
+            Kind::Bool => visitor.bool(val_i32(vals, 0)? != 0)?,
+            Kind::NoType => return Ok(None),
+            Kind::Principal
+            | Kind::Callable(_)
+            | Kind::Buffer
+            | Kind::StringAscii
+            | Kind::StringUtf8
+            | Kind::List(_) => {
+                self.visit_content(memory, val_i32(vals, 0)?, val_i32(vals, 1)?, visitor)?
+            }
+            Kind::Optional(inner) => {
+                if val_i32(vals, 0)? == 1 {
+                    let value = inner
+                        .visit_vals(&vals[1..], memory, visitor)?
+                        .ok_or(Error::Unchecked(CheckErrors::CouldNotDetermineType))?;
+                    visitor.optional(Some(value))?
+                } else {
+                    visitor.optional(None)?
+                }
+            }
+            Kind::Response(ok, err) => {
+                if val_i32(vals, 0)? == 1 {
+                    let value =
+                        ok.visit_vals(&vals[1..], memory, visitor)?
+                            .ok_or(Error::Unchecked(
+                                CheckErrors::CouldNotDetermineResponseOkType,
+                            ))?;
+                    visitor.response(true, value)?
+                } else {
+                    let value = err
+                        .visit_vals(&vals[1 + ok.vals..], memory, visitor)?
+                        .ok_or(Error::Unchecked(
+                            CheckErrors::CouldNotDetermineResponseErrType,
+                        ))?;
+                    visitor.response(false, value)?
+                }
+            }
+            Kind::Tuple(fields) => {
+                let mut index = 0;
+                let mut values = Vec::with_capacity(fields.len());
+                for (name, _, field) in fields {
+                    let value = field
+                        .visit_vals(&vals[index..], memory, visitor)?
+                        .ok_or_else(|| {
+                            Error::Unchecked(CheckErrors::BadTupleConstruction(format!(
+                                "Failed to convert Wasm value into Clarity value for field `{}`",
+                                name
+                            )))
+                        })?;
+                    values.push((name, value));
+                    index += field.vals;
+                }
+                visitor.tuple(values)?
+            }
+        };
+        Ok(Some(output))
+    }
+
+    /// Writes `value` to `memory`: its representation at `offset`, and the
+    /// content of in-memory values at `in_mem_offset`. The representation of
+    /// an in-memory value is only written if `include_repr` is true.
+    ///
+    /// Returns the number of bytes written at `offset` and at `in_mem_offset`.
+    pub fn write(
+        &self,
+        memory: &mut [u8],
+        offset: i32,
+        in_mem_offset: i32,
+        value: &Value,
+        include_repr: bool,
+    ) -> Result<(i32, i32), Error> {
+        let in_mem_written = match (&self.kind, value) {
+            (Kind::Int, Value::Int(i)) => {
+                put(memory, offset, &i.to_le_bytes())?;
+                0
+            }
+            (Kind::UInt, Value::UInt(u)) => {
+                put(memory, offset, &u.to_le_bytes())?;
+                0
+            }
+            (Kind::Bool, Value::Bool(b)) => {
+                put(memory, offset, &(*b as u32).to_le_bytes())?;
+                0
+            }
+            (Kind::NoType, _) => {
+                put(memory, offset, &[0; 4])?;
+                0
+            }
+            (Kind::Principal | Kind::Callable(_), Value::Principal(principal)) => {
+                let (issuer, name) = match principal {
+                    PrincipalData::Standard(issuer) => (issuer, ""),
+                    PrincipalData::Contract(contract) => (&contract.issuer, contract.name.as_str()),
+                };
+                write_principal(memory, in_mem_offset, issuer, name)?
+            }
+            (Kind::Principal | Kind::Callable(_), Value::CallableContract(callable)) => {
+                let contract = &callable.contract_identifier;
+                write_principal(
+                    memory,
+                    in_mem_offset,
+                    &contract.issuer,
+                    contract.name.as_str(),
+                )?
+            }
+            (Kind::Buffer, Value::Sequence(SequenceData::Buffer(buffer))) => {
+                put(memory, in_mem_offset, &buffer.data)?;
+                buffer.data.len() as i32
+            }
+            (Kind::StringAscii, Value::Sequence(SequenceData::String(CharType::ASCII(string)))) => {
+                put(memory, in_mem_offset, &string.data)?;
+                string.data.len() as i32
+            }
+            (Kind::StringUtf8, Value::Sequence(SequenceData::String(CharType::UTF8(string)))) => {
+                // Each item is the UTF-8 encoding of a character, written as
+                // its big-endian unicode scalar.
+                let mut written = 0;
+                for item in &string.data {
+                    let item = std::str::from_utf8(item)
+                        .map_err(|e| Error::Wasm(WasmError::UnableToWriteMemory(e.into())))?;
+                    for c in item.chars() {
+                        put(memory, in_mem_offset + written, &(c as u32).to_be_bytes())?;
+                        written += 4;
+                    }
+                }
+                written
+            }
+            (Kind::List(element), Value::Sequence(SequenceData::List(list))) => {
+                // The representations of the elements are written first, and
+                // followed by their contents.
+                let contents_offset = in_mem_offset + list.data.len() as i32 * element.size;
+                let mut reprs_written = 0;
+                let mut contents_written = 0;
+                for item in &list.data {
+                    let (written, in_mem_written) = element.write(
+                        memory,
+                        in_mem_offset + reprs_written,
+                        contents_offset + contents_written,
+                        item,
+                        true,
+                    )?;
+                    reprs_written += written;
+                    contents_written += in_mem_written;
+                }
+                if include_repr {
+                    put(memory, offset, &in_mem_offset.to_le_bytes())?;
+                    put(memory, offset + 4, &reprs_written.to_le_bytes())?;
+                }
+                return Ok((
+                    if include_repr { INDIRECT_SIZE } else { 0 },
+                    reprs_written + contents_written,
+                ));
+            }
+            (Kind::Optional(inner), Value::Optional(optional)) => {
+                put(
+                    memory,
+                    offset,
+                    &(optional.data.is_some() as i32).to_le_bytes(),
+                )?;
+                match &optional.data {
+                    Some(value) => {
+                        inner
+                            .write(memory, offset + 4, in_mem_offset, value, true)?
+                            .1
+                    }
+                    None => 0,
+                }
+            }
+            (Kind::Response(ok, err), Value::Response(response)) => {
+                put(memory, offset, &(response.committed as i32).to_le_bytes())?;
+                let (layout, value_offset) = if response.committed {
+                    (ok, offset + 4)
+                } else {
+                    (err, offset + 4 + ok.size)
+                };
+                layout
+                    .write(memory, value_offset, in_mem_offset, &response.data, true)?
+                    .1
+            }
+            (Kind::Tuple(fields), Value::Tuple(tuple)) => {
+                let mut in_mem_written = 0;
+                for (name, field_offset, field) in fields {
+                    let value = tuple
+                        .data_map
+                        .get(name)
+                        .ok_or(Error::Wasm(WasmError::ValueTypeMismatch))?;
+                    in_mem_written += field
+                        .write(
+                            memory,
+                            offset + field_offset,
+                            in_mem_offset + in_mem_written,
+                            value,
+                            true,
+                        )?
+                        .1;
+                }
+                in_mem_written
+            }
+            _ => return Err(Error::Wasm(WasmError::ValueTypeMismatch)),
+        };
+
+        if !self.is_in_memory() {
+            return Ok((self.size, in_mem_written));
+        }
+        if !include_repr {
+            return Ok((0, in_mem_written));
+        }
+        put(memory, offset, &in_mem_offset.to_le_bytes())?;
+        put(memory, offset + 4, &in_mem_written.to_le_bytes())?;
+        Ok((INDIRECT_SIZE, in_mem_written))
+    }
+
+    /// Number of bytes written at `in_mem_offset` by [Layout::write] for
+    /// `value`, which must be of the type of the layout.
+    pub fn content_size(&self, value: &Value) -> usize {
+        match (&self.kind, value) {
+            (Kind::Principal | Kind::Callable(_), Value::Principal(principal)) => match principal {
+                PrincipalData::Standard(_) => STANDARD_PRINCIPAL_BYTES,
+                PrincipalData::Contract(contract) => STANDARD_PRINCIPAL_BYTES + contract.name.len(),
+            },
+            (Kind::Principal | Kind::Callable(_), Value::CallableContract(callable)) => {
+                STANDARD_PRINCIPAL_BYTES + callable.contract_identifier.name.len()
+            }
+            (Kind::Buffer, Value::Sequence(SequenceData::Buffer(buffer))) => buffer.data.len(),
+            (Kind::StringAscii, Value::Sequence(SequenceData::String(CharType::ASCII(string)))) => {
+                string.data.len()
+            }
+            (Kind::StringUtf8, Value::Sequence(SequenceData::String(CharType::UTF8(string)))) => {
+                4 * string.data.len()
+            }
+            (Kind::List(element), Value::Sequence(SequenceData::List(list))) => list
+                .data
+                .iter()
+                .map(|item| element.size as usize + element.content_size(item))
+                .sum(),
+            (Kind::Optional(inner), Value::Optional(optional)) => optional
+                .data
+                .as_deref()
+                .map_or(0, |value| inner.content_size(value)),
+            (Kind::Response(ok, err), Value::Response(response)) => {
+                let layout = if response.committed { ok } else { err };
+                layout.content_size(&response.data)
+            }
+            (Kind::Tuple(fields), Value::Tuple(tuple)) => fields
+                .iter()
+                .filter_map(|(name, _, field)| {
+                    tuple
+                        .data_map
+                        .get(name)
+                        .map(|value| field.content_size(value))
+                })
+                .sum(),
+            _ => 0,
+        }
+    }
+}
+
+/// Writes the content of a principal at `offset`, and returns its length.
+fn write_principal(
+    memory: &mut [u8],
+    offset: i32,
+    issuer: &StandardPrincipalData,
+    contract_name: &str,
+) -> Result<i32, Error> {
+    put(memory, offset, &[issuer.version()])?;
+    put(memory, offset + PRINCIPAL_VERSION_BYTES as i32, &issuer.1)?;
+    put(
+        memory,
+        offset + STANDARD_PRINCIPAL_BYTES as i32 - 1,
+        &[contract_name.len() as u8],
+    )?;
+    put(
+        memory,
+        offset + STANDARD_PRINCIPAL_BYTES as i32,
+        contract_name.as_bytes(),
+    )?;
+    Ok((STANDARD_PRINCIPAL_BYTES + contract_name.len()) as i32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wasm_utils::{get_type_in_memory_size, get_type_size, signature_from_string};
+
+    fn parse(ty: &str) -> TypeSignature {
+        signature_from_string(
+            ty,
+            clarity::vm::ClarityVersion::latest(),
+            clarity::types::StacksEpochId::latest(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn layout_matches_type_size() {
+        for ty in [
+            "int",
+            "bool",
+            "principal",
+            "(optional (buff 3))",
+            "(response (tuple (a int) (b (list 2 principal))) (string-utf8 3))",
+        ] {
+            let ty = parse(ty);
+            assert_eq!(Layout::new(&ty).unwrap().size(), get_type_size(&ty));
+        }
+    }
+
+    #[test]
+    fn layouts_are_cached() {
+        let ty = parse("(response (list 3 principal) uint)");
+        let layout = Layout::of(&ty).unwrap();
+        assert_eq!(*layout, Layout::new(&ty).unwrap());
+        assert!(Rc::ptr_eq(&layout, &Layout::of(&ty).unwrap()));
+    }
+
+    #[test]
+    fn tuple_fields_are_at_their_offsets() {
+        let ty = parse("(tuple (a bool) (b int) (c (string-ascii 4)))");
+        let value = Value::from(
+            TupleData::from_data(vec![
+                ("a".into(), Value::Bool(true)),
+                ("b".into(), Value::Int(-2)),
+                (
+                    "c".into(),
+                    Value::string_ascii_from_bytes(b"abc".to_vec()).unwrap(),
+                ),
+            ])
+            .unwrap(),
+        );
+        let layout = Layout::new(&ty).unwrap();
+        let mut memory = vec![0; get_type_in_memory_size(&ty, true) as usize];
+
+        assert_eq!(
+            layout.write(&mut memory, 0, 28, &value, true).unwrap(),
+            (28, 3)
+        );
+        assert_eq!(memory[0..4], 1u32.to_le_bytes());
+        assert_eq!(memory[4..20], (-2i128).to_le_bytes());
+        assert_eq!(memory[20..24], 28i32.to_le_bytes());
+        assert_eq!(memory[24..28], 3i32.to_le_bytes());
+        assert_eq!(&memory[28..31], b"abc");
+        assert_eq!(layout.visit(&memory, 0, &mut ValueBuilder).unwrap(), value);
+    }
+
+    #[test]
+    fn reads_out_of_bounds_are_errors() {
+        let layout = Layout::new(&parse("(buff 4)")).unwrap();
+        let mut memory = vec![0; 8];
+        put(&mut memory, 0, &4i32.to_le_bytes()).unwrap();
+        put(&mut memory, 4, &8i32.to_le_bytes()).unwrap();
+        assert!(layout.visit(&memory, 0, &mut ValueBuilder).is_err());
+        assert!(layout.visit(&memory, 4, &mut ValueBuilder).is_err());
+    }
+
+    #[test]
+    fn writes_out_of_bounds_are_errors() {
+        let layout = Layout::new(&TypeSignature::IntType).unwrap();
+        let mut memory = vec![0; 8];
+        assert!(layout
+            .write(&mut memory, 0, 0, &Value::Int(1), true)
+            .is_err());
+    }
+}
@@ -0,0 +1,662 @@
+//! Evaluation of transaction post-conditions against compiled executions.
+//!
+//! Post-conditions are checked against the asset events emitted by a call,
+//! following the semantics of stacks-core: STX and fungible token conditions
+//! compare the amount sent (transferred or burned) by a principal, and
+//! non-fungible token conditions check whether a given asset left the
+//! principal. In [PostConditionMode::Deny], every asset sent must also be
+//! covered by a post-condition.
+//!
+//! [call_with_post_conditions] wraps a compiled call, and rolls back its
+//! effects when the post-conditions are not met.
+
+use std::collections::HashMap;
+
+use clarity::vm::contexts::GlobalContext;
+use clarity::vm::errors::Error;
+use clarity::vm::events::{FTEventType, NFTEventType, STXEventType, StacksTransactionEvent};
+use clarity::vm::types::{AssetIdentifier, PrincipalData};
+use clarity::vm::Value;
+
+/// Whether assets may be sent without being covered by a post-condition.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PostConditionMode {
+    Allow,
+    Deny,
+}
+
+/// Comparison between the amount sent and the amount of a condition.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FungibleConditionCode {
+    SentEq,
+    SentGt,
+    SentGe,
+    SentLt,
+    SentLe,
+}
+
+impl FungibleConditionCode {
+    /// Returns `true` if `amount_sent` satisfies the condition on `amount`.
+    pub fn check(&self, amount: u128, amount_sent: u128) -> bool {
+        match self {
+            Self::SentEq => amount_sent == amount,
+            Self::SentGt => amount_sent > amount,
+            Self::SentGe => amount_sent >= amount,
+            Self::SentLt => amount_sent < amount,
+            Self::SentLe => amount_sent <= amount,
+        }
+    }
+}
+
+/// Whether a non-fungible token must or must not be sent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NonfungibleConditionCode {
+    Sent,
+    NotSent,
+}
+
+impl NonfungibleConditionCode {
+    /// Returns `true` if the condition holds, given whether the asset was sent.
+    pub fn check(&self, sent: bool) -> bool {
+        match self {
+            Self::Sent => sent,
+            Self::NotSent => !sent,
+        }
+    }
+}
+
+/// A single post-condition on the assets sent by `principal`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PostCondition {
+    Stx {
+        principal: PrincipalData,
+        code: FungibleConditionCode,
+        amount: u128,
+    },
+    Fungible {
+        principal: PrincipalData,
+        asset: AssetIdentifier,
+        code: FungibleConditionCode,
+        amount: u128,
+    },
+    Nonfungible {
+        principal: PrincipalData,
+        asset: AssetIdentifier,
+        value: Value,
+        code: NonfungibleConditionCode,
+    },
+}
+
+/// The post-conditions of a transaction.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PostConditions {
+    pub mode: PostConditionMode,
+    pub conditions: Vec<PostCondition>,
+}
+
+/// The reason why a set of post-conditions was not met.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PostConditionViolation {
+    /// The post-condition at this index does not hold.
+    ConditionFailed(usize, PostCondition),
+    /// STX were sent by a principal without a post-condition (deny mode).
+    UncheckedStx(PrincipalData),
+    /// A fungible token was sent by a principal without a post-condition
+    /// (deny mode).
+    UncheckedFungible(PrincipalData, AssetIdentifier),
+    /// A non-fungible token was sent by a principal without a post-condition
+    /// (deny mode).
+    UncheckedNonfungible(PrincipalData, AssetIdentifier, Value),
+}
+
+/// Assets sent, either transferred or burned, by each principal.
+#[derive(Debug, Default)]
+pub struct SentAssets {
+    stx: HashMap<PrincipalData, u128>,
+    tokens: HashMap<PrincipalData, HashMap<AssetIdentifier, u128>>,
+    nfts: HashMap<PrincipalData, HashMap<AssetIdentifier, Vec<Value>>>,
+}
+
+impl SentAssets {
+    /// Collects the assets sent in `events`. Mints, locks and non-asset events
+    /// are ignored.
+    pub fn from_events<'a>(events: impl IntoIterator<Item = &'a StacksTransactionEvent>) -> Self {
+        let mut sent = Self::default();
+        for event in events {
+            match event {
+                StacksTransactionEvent::STXEvent(STXEventType::STXTransferEvent(data)) => {
+                    sent.add_stx(&data.sender, data.amount)
+                }
+                StacksTransactionEvent::STXEvent(STXEventType::STXBurnEvent(data)) => {
+                    sent.add_stx(&data.sender, data.amount)
+                }
+                StacksTransactionEvent::FTEvent(FTEventType::FTTransferEvent(data)) => {
+                    sent.add_token(&data.sender, &data.asset_identifier, data.amount)
+                }
+                StacksTransactionEvent::FTEvent(FTEventType::FTBurnEvent(data)) => {
+                    sent.add_token(&data.sender, &data.asset_identifier, data.amount)
+                }
+                StacksTransactionEvent::NFTEvent(NFTEventType::NFTTransferEvent(data)) => {
+                    sent.add_nft(&data.sender, &data.asset_identifier, &data.value)
+                }
+                StacksTransactionEvent::NFTEvent(NFTEventType::NFTBurnEvent(data)) => {
+                    sent.add_nft(&data.sender, &data.asset_identifier, &data.value)
+                }
+                _ => {}
+            }
+        }
+        sent
+    }
+
+    // Amounts are bounded by the total supply of each asset, which is itself
+    // enforced by the VM, so the sums cannot overflow in practice.
+    fn add_stx(&mut self, principal: &PrincipalData, amount: u128) {
+        let total = self.stx.entry(principal.clone()).or_default();
+        *total = total.saturating_add(amount);
+    }
+
+    fn add_token(&mut self, principal: &PrincipalData, asset: &AssetIdentifier, amount: u128) {
+        let total = self
+            .tokens
+            .entry(principal.clone())
+            .or_default()
+            .entry(asset.clone())
+            .or_default();
+        *total = total.saturating_add(amount);
+    }
+
+    fn add_nft(&mut self, principal: &PrincipalData, asset: &AssetIdentifier, value: &Value) {
+        self.nfts
+            .entry(principal.clone())
+            .or_default()
+            .entry(asset.clone())
+            .or_default()
+            .push(value.clone());
+    }
+
+    /// Amount of STX sent by `principal`.
+    pub fn stx(&self, principal: &PrincipalData) -> u128 {
+        self.stx.get(principal).copied().unwrap_or(0)
+    }
+
+    /// Amount of the fungible token `asset` sent by `principal`.
+    pub fn tokens(&self, principal: &PrincipalData, asset: &AssetIdentifier) -> u128 {
+        self.tokens
+            .get(principal)
+            .and_then(|assets| assets.get(asset))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Returns `true` if `principal` sent the non-fungible token `value` of
+    /// `asset`.
+    pub fn has_sent_nft(
+        &self,
+        principal: &PrincipalData,
+        asset: &AssetIdentifier,
+        value: &Value,
+    ) -> bool {
+        self.nfts
+            .get(principal)
+            .and_then(|assets| assets.get(asset))
+            .is_some_and(|values| values.contains(value))
+    }
+}
+
+impl PostConditions {
+    pub fn new(mode: PostConditionMode, conditions: Vec<PostCondition>) -> Self {
+        Self { mode, conditions }
+    }
+
+    /// Checks the post-conditions against the assets sent in `events`.
+    pub fn check<'a>(
+        &self,
+        events: impl IntoIterator<Item = &'a StacksTransactionEvent>,
+    ) -> Result<(), PostConditionViolation> {
+        self.check_sent_assets(&SentAssets::from_events(events))
+    }
+
+    /// Checks the post-conditions against the assets sent by a call.
+    pub fn check_sent_assets(&self, sent: &SentAssets) -> Result<(), PostConditionViolation> {
+        for (index, condition) in self.conditions.iter().enumerate() {
+            let holds = match condition {
+                PostCondition::Stx {
+                    principal,
+                    code,
+                    amount,
+                } => code.check(*amount, sent.stx(principal)),
+                PostCondition::Fungible {
+                    principal,
+                    asset,
+                    code,
+                    amount,
+                } => code.check(*amount, sent.tokens(principal, asset)),
+                PostCondition::Nonfungible {
+                    principal,
+                    asset,
+                    value,
+                    code,
+                } => code.check(sent.has_sent_nft(principal, asset, value)),
+            };
+            if !holds {
+                return Err(PostConditionViolation::ConditionFailed(
+                    index,
+                    condition.clone(),
+                ));
+            }
+        }
+
+        if self.mode == PostConditionMode::Deny {
+            self.check_all_sent_assets_covered(sent)?;
+        }
+        Ok(())
+    }
+
+    fn check_all_sent_assets_covered(
+        &self,
+        sent: &SentAssets,
+    ) -> Result<(), PostConditionViolation> {
+        for principal in sent.stx.keys() {
+            let covered = self.conditions.iter().any(|condition| {
+                matches!(condition, PostCondition::Stx { principal: p, .. } if p == principal)
+            });
+            if !covered {
+                return Err(PostConditionViolation::UncheckedStx(principal.clone()));
+            }
+        }
+
+        for (principal, assets) in &sent.tokens {
+            for asset in assets.keys() {
+                let covered = self.conditions.iter().any(|condition| {
+                    matches!(
+                        condition,
+                        PostCondition::Fungible { principal: p, asset: a, .. }
+                            if p == principal && a == asset
+                    )
+                });
+                if !covered {
+                    return Err(PostConditionViolation::UncheckedFungible(
+                        principal.clone(),
+                        asset.clone(),
+                    ));
+                }
+            }
+        }
+
+        for (principal, assets) in &sent.nfts {
+            for (asset, values) in assets {
+                for value in values {
+                    let covered = self.conditions.iter().any(|condition| {
+                        matches!(
+                            condition,
+                            PostCondition::Nonfungible { principal: p, asset: a, value: v, .. }
+                                if p == principal && a == asset && v == value
+                        )
+                    });
+                    if !covered {
+                        return Err(PostConditionViolation::UncheckedNonfungible(
+                            principal.clone(),
+                            asset.clone(),
+                            value.clone(),
+                        ));
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Values returned by the calls wrapped in [call_with_post_conditions].
+pub trait CallResult {
+    /// Returns `true` if the call returned an `err` response, whose effects
+    /// are not committed.
+    fn is_err_response(&self) -> bool;
+}
+
+impl CallResult for Value {
+    fn is_err_response(&self) -> bool {
+        matches!(self, Value::Response(response) if !response.committed)
+    }
+}
+
+impl CallResult for Option<Value> {
+    fn is_err_response(&self) -> bool {
+        self.as_ref().is_some_and(Value::is_err_response)
+    }
+}
+
+/// Outcome of a call checked against post-conditions.
+#[derive(Debug)]
+pub struct CheckedCall<T> {
+    /// The value returned by the call.
+    pub result: T,
+    /// The events emitted by the call, including the ones which were rolled
+    /// back.
+    pub events: Vec<StacksTransactionEvent>,
+    /// The first post-condition violation found. When set, all the effects of
+    /// the call were rolled back.
+    pub violation: Option<PostConditionViolation>,
+    committed: bool,
+}
+
+impl<T> CheckedCall<T> {
+    /// Returns `true` if the effects of the call were kept: the call did not
+    /// return an `err` response, and its post-conditions were met.
+    pub fn is_committed(&self) -> bool {
+        self.committed
+    }
+}
+
+/// Runs `call` in a new transaction of `global_context` and checks the
+/// post-conditions against the events it emitted. The transaction is
+/// committed if they are met, and rolled back otherwise. A call returning an
+/// `err` response is rolled back as well, after its post-conditions are
+/// evaluated.
+///
+/// `call` is typically a closure around [crate::wasm_utils::call_function] or
+/// [crate::initialize::initialize_contract]. If it fails, the transaction is
+/// rolled back and the error is returned, as stacks-core aborts the
+/// transaction without evaluating the post-conditions.
+pub fn call_with_post_conditions<'b, T, F>(
+    global_context: &mut GlobalContext<'b>,
+    post_conditions: &PostConditions,
+    call: F,
+) -> Result<CheckedCall<T>, Error>
+where
+    T: CallResult,
+    F: FnOnce(&mut GlobalContext<'b>) -> Result<T, Error>,
+{
+    global_context.begin();
+    let result = match call(global_context) {
+        Ok(result) => result,
+        Err(e) => {
+            global_context.roll_back()?;
+            return Err(e);
+        }
+    };
+
+    let events = global_context
+        .event_batches
+        .last()
+        .map(|batch| batch.events.clone())
+        .unwrap_or_default();
+    let violation = post_conditions.check(&events).err();
+
+    let committed = violation.is_none() && !result.is_err_response();
+    if committed {
+        global_context.commit()?;
+    } else {
+        global_context.roll_back()?;
+    }
+
+    Ok(CheckedCall {
+        result,
+        events,
+        violation,
+        committed,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use clarity::vm::contexts::CallStack;
+    use clarity::vm::types::{QualifiedContractIdentifier, StandardPrincipalData};
+
+    use super::*;
+    use crate::initialize::ExecutionOptions;
+    use crate::tools::TestEnvironment;
+    use crate::wasm_utils::call_function_with_options;
+
+    const RECIPIENT: &str = "'S1G2081040G2081040G2081040G208105NK8PE5";
+
+    fn sender() -> PrincipalData {
+        StandardPrincipalData::transient().into()
+    }
+
+    fn asset(name: &str) -> AssetIdentifier {
+        AssetIdentifier {
+            contract_identifier: QualifiedContractIdentifier::new(
+                StandardPrincipalData::transient(),
+                "snippet".into(),
+            ),
+            asset_name: name.into(),
+        }
+    }
+
+    fn events_of(snippet: &str) -> Vec<StacksTransactionEvent> {
+        let mut env = TestEnvironment::default();
+        env.evaluate(snippet).expect("Failed to init contract.");
+        env.get_events()
+            .iter()
+            .flat_map(|batch| batch.events.iter().cloned())
+            .collect()
+    }
+
+    #[test]
+    fn stx_conditions() {
+        let events = events_of(&format!(
+            "(try! (stx-transfer? u100 tx-sender {RECIPIENT})) (stx-burn? u20 tx-sender)"
+        ));
+        let stx = |code, amount| {
+            PostConditions::new(
+                PostConditionMode::Deny,
+                vec![PostCondition::Stx {
+                    principal: sender(),
+                    code,
+                    amount,
+                }],
+            )
+        };
+
+        assert_eq!(
+            stx(FungibleConditionCode::SentEq, 120).check(&events),
+            Ok(())
+        );
+        assert_eq!(
+            stx(FungibleConditionCode::SentLe, 200).check(&events),
+            Ok(())
+        );
+        assert!(matches!(
+            stx(FungibleConditionCode::SentLt, 120).check(&events),
+            Err(PostConditionViolation::ConditionFailed(0, _))
+        ));
+    }
+
+    #[test]
+    fn deny_mode_requires_covering_all_sent_assets() {
+        let events = events_of(&format!("(stx-transfer? u100 tx-sender {RECIPIENT})"));
+
+        let no_conditions = |mode| PostConditions::new(mode, vec![]);
+        assert_eq!(
+            no_conditions(PostConditionMode::Allow).check(&events),
+            Ok(())
+        );
+        assert_eq!(
+            no_conditions(PostConditionMode::Deny).check(&events),
+            Err(PostConditionViolation::UncheckedStx(sender()))
+        );
+    }
+
+    #[test]
+    fn fungible_conditions_ignore_mints() {
+        let events = events_of(&format!(
+            "(define-fungible-token foo)
+             (try! (ft-mint? foo u50 tx-sender))
+             (try! (ft-transfer? foo u10 tx-sender {RECIPIENT}))
+             (ft-burn? foo u5 tx-sender)"
+        ));
+
+        let conditions = PostConditions::new(
+            PostConditionMode::Deny,
+            vec![PostCondition::Fungible {
+                principal: sender(),
+                asset: asset("foo"),
+                code: FungibleConditionCode::SentEq,
+                amount: 15,
+            }],
+        );
+        assert_eq!(conditions.check(&events), Ok(()));
+
+        let other_asset = PostConditions::new(
+            PostConditionMode::Deny,
+            vec![PostCondition::Fungible {
+                principal: sender(),
+                asset: asset("bar"),
+                code: FungibleConditionCode::SentGe,
+                amount: 0,
+            }],
+        );
+        assert_eq!(
+            other_asset.check(&events),
+            Err(PostConditionViolation::UncheckedFungible(
+                sender(),
+                asset("foo")
+            ))
+        );
+    }
+
+    #[test]
+    fn nonfungible_conditions() {
+        let events = events_of(&format!(
+            "(define-non-fungible-token foo uint)
+             (try! (nft-mint? foo u1 tx-sender))
+             (try! (nft-mint? foo u2 tx-sender))
+             (nft-transfer? foo u1 tx-sender {RECIPIENT})"
+        ));
+        let nft = |value, code| PostCondition::Nonfungible {
+            principal: sender(),
+            asset: asset("foo"),
+            value,
+            code,
+        };
+
+        let conditions = PostConditions::new(
+            PostConditionMode::Deny,
+            vec![
+                nft(Value::UInt(1), NonfungibleConditionCode::Sent),
+                nft(Value::UInt(2), NonfungibleConditionCode::NotSent),
+            ],
+        );
+        assert_eq!(conditions.check(&events), Ok(()));
+
+        let conditions = PostConditions::new(
+            PostConditionMode::Allow,
+            vec![nft(Value::UInt(1), NonfungibleConditionCode::NotSent)],
+        );
+        assert!(matches!(
+            conditions.check(&events),
+            Err(PostConditionViolation::ConditionFailed(0, _))
+        ));
+
+        assert_eq!(
+            PostConditions::new(PostConditionMode::Deny, vec![]).check(&events),
+            Err(PostConditionViolation::UncheckedNonfungible(
+                sender(),
+                asset("foo"),
+                Value::UInt(1)
+            ))
+        );
+    }
+
+    /// Calls `function` of a contract sending STX with `post_conditions`, and
+    /// returns the checked call with the balance of the recipient afterwards.
+    fn checked_call(
+        function: &str,
+        post_conditions: &PostConditions,
+    ) -> (CheckedCall<Value>, Value) {
+        let mut env = TestEnvironment::default();
+        env.init_contract_with_snippet(
+            "pc",
+            &format!(
+                r#"
+                (define-public (send)
+                    (stx-transfer? u100 tx-sender {RECIPIENT}))
+                (define-public (send-and-fail)
+                    (begin
+                        (try! (stx-transfer? u100 tx-sender {RECIPIENT}))
+                        (err u1)))
+                (define-read-only (received)
+                    (stx-get-balance {RECIPIENT}))
+                "#
+            ),
+        )
+        .expect("Failed to init contract.");
+
+        let checked = env
+            .with_global_context(|global_context, contracts| {
+                call_with_post_conditions(global_context, post_conditions, |global_context| {
+                    call_function_with_options(
+                        function,
+                        &[],
+                        global_context,
+                        &contracts["pc"],
+                        &mut CallStack::new(),
+                        Some(sender()),
+                        Some(sender()),
+                        None,
+                        ExecutionOptions::default(),
+                    )
+                })
+            })
+            .expect("Failed to call function.");
+        let received = env
+            .call_contract_function("pc", "received", &[], None)
+            .expect("Failed to read balance.");
+        (checked, received)
+    }
+
+    fn sent(code: FungibleConditionCode) -> PostConditions {
+        PostConditions::new(
+            PostConditionMode::Deny,
+            vec![PostCondition::Stx {
+                principal: sender(),
+                code,
+                amount: 100,
+            }],
+        )
+    }
+
+    #[test]
+    fn call_meeting_post_conditions_is_committed() {
+        let (checked, received) = checked_call("send", &sent(FungibleConditionCode::SentEq));
+        assert_eq!(checked.result, Value::okay_true());
+        assert_eq!(checked.violation, None);
+        assert!(checked.is_committed());
+        assert_eq!(received, Value::UInt(100));
+    }
+
+    #[test]
+    fn call_violating_post_conditions_is_rolled_back() {
+        let (checked, received) = checked_call("send", &sent(FungibleConditionCode::SentLt));
+        assert_eq!(checked.result, Value::okay_true());
+        assert!(matches!(
+            checked.violation,
+            Some(PostConditionViolation::ConditionFailed(0, _))
+        ));
+        assert!(!checked.is_committed());
+        assert_eq!(checked.events.len(), 1);
+        assert_eq!(received, Value::UInt(0));
+    }
+
+    #[test]
+    fn call_returning_err_is_rolled_back() {
+        let (checked, received) =
+            checked_call("send-and-fail", &sent(FungibleConditionCode::SentLt));
+        assert_eq!(checked.result, Value::err_uint(1));
+        // The post-conditions are still evaluated against the events.
+        assert!(matches!(
+            checked.violation,
+            Some(PostConditionViolation::ConditionFailed(0, _))
+        ));
+        assert!(!checked.is_committed());
+        assert_eq!(received, Value::UInt(0));
+
+        let (checked, received) =
+            checked_call("send-and-fail", &sent(FungibleConditionCode::SentEq));
+        assert_eq!(checked.violation, None);
+        assert!(!checked.is_committed());
+        assert_eq!(received, Value::UInt(0));
+    }
+}
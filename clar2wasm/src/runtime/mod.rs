@@ -0,0 +1,69 @@
+//! Abstraction over the WebAssembly engine running compiled contracts.
+//!
+//! The host interface, the instantiation of contracts and the conversion of
+//! values between Clarity and Wasm are written against the types re-exported
+//! from this module. By default, they are backed by wasmtime. Enabling the
+//! `wasmi` feature switches to an interpreter-based engine instead, which does
+//! not generate any native code at runtime.
+//!
+//! Both backends expose the same API, modeled after wasmtime's, and report
+//! errors as [wasmtime::Error] so that [crate::error_mapping] does not depend on
+//! the selected engine.
+
+#[cfg(not(feature = "wasmi"))]
+mod wasmtime_backend;
+#[cfg(not(feature = "wasmi"))]
+pub use wasmtime_backend::*;
+
+#[cfg(feature = "wasmi")]
+mod wasmi_backend;
+#[cfg(feature = "wasmi")]
+pub use wasmi_backend::*;
+
+#[cfg(test)]
+mod tests {
+    use clarity::vm::Value;
+
+    use super::*;
+    use crate::tools::{crosscheck, crosscheck_multi_contract};
+
+    #[test]
+    fn backend_name_matches_feature() {
+        let expected = if cfg!(feature = "wasmi") {
+            "wasmi"
+        } else {
+            "wasmtime"
+        };
+        assert_eq!(BACKEND_NAME, expected);
+    }
+
+    #[test]
+    fn placeholders_match_value_types() {
+        assert!(matches!(placeholder_for_type(ValType::I32), Val::I32(0)));
+        assert!(matches!(placeholder_for_type(ValType::I64), Val::I64(0)));
+    }
+
+    #[test]
+    fn contract_runs_on_selected_backend() {
+        crosscheck(
+            "(define-data-var counter int 1) (var-set counter (+ (var-get counter) 41)) (var-get counter)",
+            Ok(Some(Value::Int(42))),
+        );
+    }
+
+    #[test]
+    fn contract_call_runs_on_selected_backend() {
+        crosscheck_multi_contract(
+            &[
+                (
+                    "callee".into(),
+                    "(define-data-var counter int 1)
+                     (define-public (bump (n int))
+                       (begin (var-set counter (+ (var-get counter) n)) (ok (var-get counter))))",
+                ),
+                ("caller".into(), "(contract-call? .callee bump 41)"),
+            ],
+            Ok(Some(Value::okay(Value::Int(42)).unwrap())),
+        );
+    }
+}
@@ -0,0 +1,606 @@
+//! Runtime backend based on wasmi, an interpreter which never generates native
+//! code at runtime.
+//!
+//! wasmi's API is close to wasmtime's, but its errors and the way host
+//! functions report failures differ. The wrappers in this module adapt it to
+//! the API expected by the rest of the crate: host functions return a
+//! [wasmtime::Result], and every error is converted back to a [wasmtime::Error]
+//! with traps represented as [wasmtime::Trap].
+//!
+//! wasmi cannot precompile modules, so [Module::serialize] returns the Wasm
+//! binary itself. Contracts deployed with one backend must therefore be run
+//! with the same backend. Contracts reached with `contract-call?` run with
+//! this crate's runtime too, and thus with this backend.
+//!
+//! wasmtime remains a dependency: the errors are reported as wasmtime errors,
+//! and the [GlobalContext] of the interpreter owns a wasmtime engine, which is
+//! left unused.
+
+use std::fmt;
+use std::sync::OnceLock;
+
+use clarity::vm::contexts::GlobalContext;
+use clarity::vm::errors::{Error, WasmError};
+use wasmi::core::{HostError, TrapCode};
+use wasmi::errors::{LinkerError, MemoryError, TableError};
+pub use wasmi::{AsContext, AsContextMut, Engine, GlobalType, Mutability, Store, Val, ValType};
+use wasmi::{StackLimits, StoreContext, StoreContextMut, WasmRet, WasmTy};
+
+use crate::initialize::ExecutionOptions;
+use crate::limits::{Limiter, ResourceLimits};
+
+/// Name of the selected backend.
+pub const BACKEND_NAME: &str = "wasmi";
+
+/// Initial height of the value stack, in values, of a dedicated engine.
+const INITIAL_VALUE_STACK_HEIGHT: usize = 1024;
+
+/// Returns the engine running the contracts of `global_context` with
+/// `options`.
+///
+/// The engine of the global context is a wasmtime engine, so unless the
+/// options have an engine, a shared wasmi engine is used instead.
+pub fn engine(_global_context: &GlobalContext, options: &ExecutionOptions) -> Engine {
+    static ENGINE: OnceLock<Engine> = OnceLock::new();
+
+    options
+        .engine
+        .clone()
+        .unwrap_or_else(|| ENGINE.get_or_init(Engine::default).clone())
+}
+
+/// Builds an engine enforcing the limits which are applied by the engine
+/// itself, if `limits` need one. wasmi bounds its value stack in number of
+/// values rather than in bytes, so the maximum Wasm stack size is converted
+/// accordingly.
+pub fn limited_engine(limits: &ResourceLimits) -> Result<Option<Engine>, Error> {
+    let Some(max_wasm_stack) = limits.max_wasm_stack else {
+        return Ok(None);
+    };
+
+    let max_height = (max_wasm_stack / std::mem::size_of::<u64>()).max(1);
+    let stack_limits = StackLimits::new(
+        INITIAL_VALUE_STACK_HEIGHT.min(max_height),
+        max_height,
+        max_height,
+    )
+    .map_err(|e| {
+        Error::Wasm(WasmError::UnableToLoadModule(wasmtime::Error::msg(
+            e.to_string(),
+        )))
+    })?;
+
+    let mut config = wasmi::Config::default();
+    config.set_stack_limits(stack_limits);
+    Ok(Some(Engine::new(&config)))
+}
+
+/// Returns a placeholder value for Wasm type `ty`.
+pub fn placeholder_for_type(ty: ValType) -> Val {
+    Val::default(ty)
+}
+
+/// Returns the names of the Wasm functions active when `error` was raised.
+///
+/// wasmi doesn't capture backtraces, so the list is always empty.
+pub fn backtrace(_error: &wasmtime::Error) -> Vec<String> {
+    Vec::new()
+}
+
+/// Returns the placeholders receiving the results of a call to `func`.
+pub fn result_placeholders(func: &Func, store: impl AsContext) -> Vec<Val> {
+    func.0
+        .ty(store)
+        .results()
+        .iter()
+        .copied()
+        .map(placeholder_for_type)
+        .collect()
+}
+
+/// Error raised by a host function, carried through the executed Wasm code.
+#[derive(Debug)]
+struct HostTrap(wasmtime::Error);
+
+impl fmt::Display for HostTrap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+impl HostError for HostTrap {}
+
+/// Converts an error returned by wasmi into the representation used by
+/// wasmtime, which [crate::error_mapping] knows how to resolve.
+fn into_error(e: wasmi::Error) -> wasmtime::Error {
+    if let Some(trap) = e.as_trap_code().and_then(trap_from_code) {
+        return trap.into();
+    }
+    if e.downcast_ref::<HostTrap>().is_some() {
+        return e
+            .downcast::<HostTrap>()
+            .map_or_else(|| wasmtime::Error::msg("invalid host error"), |trap| trap.0);
+    }
+    wasmtime::Error::msg(e.to_string())
+}
+
+fn trap_from_code(code: TrapCode) -> Option<wasmtime::Trap> {
+    use wasmtime::Trap;
+
+    Some(match code {
+        TrapCode::UnreachableCodeReached => Trap::UnreachableCodeReached,
+        TrapCode::MemoryOutOfBounds => Trap::MemoryOutOfBounds,
+        TrapCode::TableOutOfBounds => Trap::TableOutOfBounds,
+        TrapCode::IndirectCallToNull => Trap::IndirectCallToNull,
+        TrapCode::IntegerDivisionByZero => Trap::IntegerDivisionByZero,
+        TrapCode::IntegerOverflow => Trap::IntegerOverflow,
+        TrapCode::BadConversionToInteger => Trap::BadConversionToInteger,
+        TrapCode::StackOverflow => Trap::StackOverflow,
+        TrapCode::BadSignature => Trap::BadSignature,
+        TrapCode::OutOfFuel => Trap::OutOfFuel,
+        TrapCode::GrowthOperationLimited => return None,
+    })
+}
+
+/// Error raised when accessing a linear memory out of its bounds.
+#[derive(Debug)]
+pub struct MemoryAccessError(MemoryError);
+
+impl fmt::Display for MemoryAccessError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+impl std::error::Error for MemoryAccessError {}
+
+/// A compiled Wasm module, along with its binary.
+#[derive(Debug, Clone)]
+pub struct Module {
+    inner: wasmi::Module,
+    binary: Vec<u8>,
+}
+
+impl Module {
+    /// Compiles a Wasm module from its binary.
+    pub fn new(engine: &Engine, binary: impl AsRef<[u8]>) -> wasmtime::Result<Self> {
+        Self::from_binary(engine, binary.as_ref())
+    }
+
+    /// Compiles a Wasm module from its binary.
+    pub fn from_binary(engine: &Engine, binary: &[u8]) -> wasmtime::Result<Self> {
+        Ok(Self {
+            inner: wasmi::Module::new(engine, binary).map_err(into_error)?,
+            binary: binary.to_vec(),
+        })
+    }
+
+    /// Returns the bytes to store in order to load this module again.
+    pub fn serialize(&self) -> wasmtime::Result<Vec<u8>> {
+        Ok(self.binary.clone())
+    }
+
+    /// Loads a module from the output of [Module::serialize].
+    ///
+    /// # Safety
+    ///
+    /// The bytes are validated like any other Wasm binary, this function is
+    /// only `unsafe` to share the signature of wasmtime's.
+    pub unsafe fn deserialize(engine: &Engine, bytes: impl AsRef<[u8]>) -> wasmtime::Result<Self> {
+        Self::from_binary(engine, bytes.as_ref())
+    }
+}
+
+/// An instantiated Wasm module.
+#[derive(Debug, Clone, Copy)]
+pub struct Instance(wasmi::Instance);
+
+impl Instance {
+    pub fn get_export(&self, store: impl AsContext, name: &str) -> Option<Extern> {
+        self.0.get_export(store, name).map(Extern::from)
+    }
+
+    pub fn get_func(&self, store: impl AsContext, name: &str) -> Option<Func> {
+        self.0.get_func(store, name).map(Func)
+    }
+
+    pub fn get_global(&self, store: impl AsContext, name: &str) -> Option<Global> {
+        self.0.get_global(store, name).map(Global)
+    }
+
+    pub fn get_memory(&self, store: impl AsContext, name: &str) -> Option<Memory> {
+        self.0.get_memory(store, name).map(Memory)
+    }
+}
+
+/// A Wasm function.
+#[derive(Debug, Clone, Copy)]
+pub struct Func(wasmi::Func);
+
+impl Func {
+    pub fn call(
+        &self,
+        store: impl AsContextMut,
+        params: &[Val],
+        results: &mut [Val],
+    ) -> wasmtime::Result<()> {
+        self.0.call(store, params, results).map_err(into_error)
+    }
+}
+
+/// A Wasm global.
+#[derive(Debug, Clone, Copy)]
+pub struct Global(wasmi::Global);
+
+impl Global {
+    pub fn new(store: impl AsContextMut, ty: GlobalType, val: Val) -> wasmtime::Result<Self> {
+        Ok(Self(wasmi::Global::new(store, val, ty.mutability())))
+    }
+
+    pub fn get(&self, store: impl AsContext) -> Val {
+        self.0.get(store)
+    }
+
+    pub fn set(&self, store: impl AsContextMut, val: Val) -> wasmtime::Result<()> {
+        self.0
+            .set(store, val)
+            .map_err(|e| wasmtime::Error::msg(e.to_string()))
+    }
+}
+
+/// A Wasm linear memory.
+#[derive(Debug, Clone, Copy)]
+pub struct Memory(wasmi::Memory);
+
+impl Memory {
+    pub fn read(
+        &self,
+        store: impl AsContext,
+        offset: usize,
+        buffer: &mut [u8],
+    ) -> Result<(), MemoryAccessError> {
+        self.0
+            .read(store, offset, buffer)
+            .map_err(MemoryAccessError)
+    }
+
+    pub fn write(
+        &self,
+        store: impl AsContextMut,
+        offset: usize,
+        buffer: &[u8],
+    ) -> Result<(), MemoryAccessError> {
+        self.0
+            .write(store, offset, buffer)
+            .map_err(MemoryAccessError)
+    }
+
+    pub fn data<'a, T: 'a>(&self, store: impl Into<StoreContext<'a, T>>) -> &'a [u8] {
+        self.0.data(store)
+    }
+
+    pub fn data_mut<'a, T: 'a>(&self, store: impl Into<StoreContextMut<'a, T>>) -> &'a mut [u8] {
+        self.0.data_mut(store)
+    }
+
+    pub fn data_size(&self, store: impl AsContext) -> usize {
+        self.0.data(store.as_context()).len()
+    }
+}
+
+/// An item exported by an instance or defined in a linker.
+#[derive(Debug, Clone)]
+pub enum Extern {
+    Func(Func),
+    Global(Global),
+    Memory(Memory),
+    Table(wasmi::Table),
+}
+
+impl Extern {
+    pub fn into_func(self) -> Option<Func> {
+        match self {
+            Extern::Func(func) => Some(func),
+            _ => None,
+        }
+    }
+
+    pub fn into_global(self) -> Option<Global> {
+        match self {
+            Extern::Global(global) => Some(global),
+            _ => None,
+        }
+    }
+
+    pub fn into_memory(self) -> Option<Memory> {
+        match self {
+            Extern::Memory(memory) => Some(memory),
+            _ => None,
+        }
+    }
+}
+
+impl From<wasmi::Extern> for Extern {
+    fn from(item: wasmi::Extern) -> Self {
+        match item {
+            wasmi::Extern::Func(func) => Extern::Func(Func(func)),
+            wasmi::Extern::Global(global) => Extern::Global(Global(global)),
+            wasmi::Extern::Memory(memory) => Extern::Memory(Memory(memory)),
+            wasmi::Extern::Table(table) => Extern::Table(table),
+        }
+    }
+}
+
+impl From<Extern> for wasmi::Extern {
+    fn from(item: Extern) -> Self {
+        match item {
+            Extern::Func(func) => wasmi::Extern::Func(func.0),
+            Extern::Global(global) => wasmi::Extern::Global(global.0),
+            Extern::Memory(memory) => wasmi::Extern::Memory(memory.0),
+            Extern::Table(table) => wasmi::Extern::Table(table),
+        }
+    }
+}
+
+impl From<Global> for Extern {
+    fn from(global: Global) -> Self {
+        Extern::Global(global)
+    }
+}
+
+impl From<Memory> for Extern {
+    fn from(memory: Memory) -> Self {
+        Extern::Memory(memory)
+    }
+}
+
+/// The context of a call to a host function.
+pub struct Caller<'a, T>(wasmi::Caller<'a, T>);
+
+impl<T> Caller<'_, T> {
+    pub fn get_export(&mut self, name: &str) -> Option<Extern> {
+        self.0.get_export(name).map(Extern::from)
+    }
+
+    pub fn data(&self) -> &T {
+        self.0.data()
+    }
+
+    pub fn data_mut(&mut self) -> &mut T {
+        self.0.data_mut()
+    }
+}
+
+impl<T> AsContext for Caller<'_, T> {
+    type Data = T;
+
+    fn as_context(&self) -> StoreContext<'_, T> {
+        self.0.as_context()
+    }
+}
+
+impl<T> AsContextMut for Caller<'_, T> {
+    fn as_context_mut(&mut self) -> StoreContextMut<'_, T> {
+        self.0.as_context_mut()
+    }
+}
+
+/// Links host functions and instantiates modules.
+pub struct Linker<T>(wasmi::Linker<T>);
+
+impl<T> Linker<T> {
+    pub fn new(engine: &Engine) -> Self {
+        Self(wasmi::Linker::new(engine))
+    }
+
+    /// Defines a host function, see [IntoHostFunc].
+    pub fn func_wrap<Params, Results>(
+        &mut self,
+        module: &str,
+        name: &str,
+        func: impl IntoHostFunc<T, Params, Results>,
+    ) -> wasmtime::Result<&mut Self> {
+        func.link(&mut self.0, module, name).map_err(link_error)?;
+        Ok(self)
+    }
+
+    pub fn define(
+        &mut self,
+        _store: impl AsContext<Data = T>,
+        module: &str,
+        name: &str,
+        item: impl Into<Extern>,
+    ) -> wasmtime::Result<&mut Self> {
+        self.0
+            .define(module, name, wasmi::Extern::from(item.into()))
+            .map_err(link_error)?;
+        Ok(self)
+    }
+
+    pub fn get(&self, store: impl AsContext<Data = T>, module: &str, name: &str) -> Option<Extern> {
+        self.0.get(store, module, name).map(Extern::from)
+    }
+
+    pub fn instantiate(
+        &self,
+        mut store: impl AsContextMut<Data = T>,
+        module: &Module,
+    ) -> wasmtime::Result<Instance> {
+        self.0
+            .instantiate(&mut store, &module.inner)
+            .and_then(|instance| instance.start(&mut store))
+            .map(Instance)
+            .map_err(into_error)
+    }
+}
+
+fn link_error(e: LinkerError) -> wasmtime::Error {
+    wasmtime::Error::msg(e.to_string())
+}
+
+/// Values returned by host functions.
+///
+/// Host functions may return Wasm values directly, or a [wasmtime::Result]
+/// when they can fail.
+pub trait HostResults {
+    #[doc(hidden)]
+    type Ok;
+
+    #[doc(hidden)]
+    fn into_result(self) -> wasmtime::Result<Self::Ok>;
+}
+
+impl<R> HostResults for wasmtime::Result<R>
+where
+    Result<R, wasmi::Error>: WasmRet,
+{
+    type Ok = R;
+
+    fn into_result(self) -> wasmtime::Result<R> {
+        self
+    }
+}
+
+macro_rules! impl_host_results {
+    ($($ty:ty),*) => {
+        $(
+            impl HostResults for $ty {
+                type Ok = $ty;
+
+                fn into_result(self) -> wasmtime::Result<$ty> {
+                    Ok(self)
+                }
+            }
+        )*
+    };
+}
+
+impl_host_results!((), i32, i64);
+
+macro_rules! impl_host_results_tuple {
+    ($($ty:ident)*) => {
+        impl<$($ty: WasmTy),*> HostResults for ($($ty,)*) {
+            type Ok = ($($ty,)*);
+
+            fn into_result(self) -> wasmtime::Result<Self::Ok> {
+                Ok(self)
+            }
+        }
+    };
+}
+
+impl_host_results_tuple!(T1 T2);
+impl_host_results_tuple!(T1 T2 T3);
+impl_host_results_tuple!(T1 T2 T3 T4);
+
+/// Marker for host functions taking a [Caller] as first argument.
+pub struct WithCaller;
+
+/// Closures which can be linked as host functions with [Linker::func_wrap].
+///
+/// Like with wasmtime, host functions optionally take a [Caller] as first
+/// argument, followed by their Wasm parameters.
+pub trait IntoHostFunc<T, Params, Results>: Send + Sync + 'static {
+    #[doc(hidden)]
+    fn link(
+        self,
+        linker: &mut wasmi::Linker<T>,
+        module: &str,
+        name: &str,
+    ) -> Result<(), LinkerError>;
+}
+
+macro_rules! impl_into_host_func {
+    ($($arg:ident)*) => {
+        #[allow(non_snake_case)]
+        impl<T, F, $($arg,)* R> IntoHostFunc<T, (WithCaller, $($arg,)*), R> for F
+        where
+            F: Fn(Caller<'_, T>, $($arg),*) -> R + Send + Sync + 'static,
+            $($arg: WasmTy,)*
+            R: HostResults,
+            Result<R::Ok, wasmi::Error>: WasmRet,
+        {
+            fn link(
+                self,
+                linker: &mut wasmi::Linker<T>,
+                module: &str,
+                name: &str,
+            ) -> Result<(), LinkerError> {
+                linker.func_wrap(
+                    module,
+                    name,
+                    move |caller: wasmi::Caller<'_, T>, $($arg: $arg),*| {
+                        (self)(Caller(caller), $($arg),*)
+                            .into_result()
+                            .map_err(|e| wasmi::Error::host(HostTrap(e)))
+                    },
+                )?;
+                Ok(())
+            }
+        }
+
+        #[allow(non_snake_case)]
+        impl<T, F, $($arg,)* R> IntoHostFunc<T, ($($arg,)*), R> for F
+        where
+            F: Fn($($arg),*) -> R + Send + Sync + 'static,
+            $($arg: WasmTy,)*
+            R: HostResults,
+            Result<R::Ok, wasmi::Error>: WasmRet,
+        {
+            fn link(
+                self,
+                linker: &mut wasmi::Linker<T>,
+                module: &str,
+                name: &str,
+            ) -> Result<(), LinkerError> {
+                linker.func_wrap(
+                    module,
+                    name,
+                    move |_: wasmi::Caller<'_, T>, $($arg: $arg),*| {
+                        (self)($($arg),*)
+                            .into_result()
+                            .map_err(|e| wasmi::Error::host(HostTrap(e)))
+                    },
+                )?;
+                Ok(())
+            }
+        }
+    };
+}
+
+impl_into_host_func!();
+impl_into_host_func!(A1);
+impl_into_host_func!(A1 A2);
+impl_into_host_func!(A1 A2 A3);
+impl_into_host_func!(A1 A2 A3 A4);
+impl_into_host_func!(A1 A2 A3 A4 A5);
+impl_into_host_func!(A1 A2 A3 A4 A5 A6);
+impl_into_host_func!(A1 A2 A3 A4 A5 A6 A7);
+impl_into_host_func!(A1 A2 A3 A4 A5 A6 A7 A8);
+impl_into_host_func!(A1 A2 A3 A4 A5 A6 A7 A8 A9);
+impl_into_host_func!(A1 A2 A3 A4 A5 A6 A7 A8 A9 A10);
+impl_into_host_func!(A1 A2 A3 A4 A5 A6 A7 A8 A9 A10 A11);
+impl_into_host_func!(A1 A2 A3 A4 A5 A6 A7 A8 A9 A10 A11 A12);
+impl_into_host_func!(A1 A2 A3 A4 A5 A6 A7 A8 A9 A10 A11 A12 A13);
+impl_into_host_func!(A1 A2 A3 A4 A5 A6 A7 A8 A9 A10 A11 A12 A13 A14);
+impl_into_host_func!(A1 A2 A3 A4 A5 A6 A7 A8 A9 A10 A11 A12 A13 A14 A15);
+
+impl wasmi::ResourceLimiter for Limiter {
+    fn memory_growing(
+        &mut self,
+        _current: usize,
+        desired: usize,
+        maximum: Option<usize>,
+    ) -> Result<bool, MemoryError> {
+        Ok(self.check_memory_growth(desired, maximum))
+    }
+
+    fn table_growing(
+        &mut self,
+        _current: u32,
+        desired: u32,
+        maximum: Option<u32>,
+    ) -> Result<bool, TableError> {
+        Ok(maximum.is_none_or(|max| desired <= max))
+    }
+}
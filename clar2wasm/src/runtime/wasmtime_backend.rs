@@ -0,0 +1,90 @@
+//! Runtime backend based on wasmtime, compiling contracts to native code.
+
+use clarity::vm::contexts::GlobalContext;
+use clarity::vm::errors::{Error, WasmError};
+pub use wasmtime::{
+    AsContext, AsContextMut, Caller, Engine, Extern, Func, Global, GlobalType, Instance, Linker,
+    Memory, Module, Mutability, Store, Val, ValType,
+};
+
+use crate::initialize::ExecutionOptions;
+use crate::limits::{Limiter, ResourceLimits};
+
+/// Name of the selected backend.
+pub const BACKEND_NAME: &str = "wasmtime";
+
+/// Returns the engine running the contracts of `global_context` with
+/// `options`: the engine of the options if they have one, or else the engine
+/// of the global context, which is the one used by the rest of the Clarity VM.
+pub fn engine(global_context: &GlobalContext, options: &ExecutionOptions) -> Engine {
+    options
+        .engine
+        .clone()
+        .unwrap_or_else(|| global_context.engine.clone())
+}
+
+/// Builds an engine enforcing the limits which are applied by the engine
+/// itself (see [ResourceLimits::configure_engine]), if `limits` need one.
+pub fn limited_engine(limits: &ResourceLimits) -> Result<Option<Engine>, Error> {
+    if limits.max_wasm_stack.is_none() {
+        return Ok(None);
+    }
+    let mut config = wasmtime::Config::default();
+    limits.configure_engine(&mut config);
+    Engine::new(&config)
+        .map(Some)
+        .map_err(|e| Error::Wasm(WasmError::UnableToLoadModule(e)))
+}
+
+/// Returns a placeholder value for Wasm type `ty`.
+pub fn placeholder_for_type(ty: ValType) -> Val {
+    match ty {
+        ValType::I32 => Val::I32(0),
+        ValType::I64 => Val::I64(0),
+        ValType::F32 => Val::F32(0),
+        ValType::F64 => Val::F64(0),
+        ValType::V128 => Val::V128(0.into()),
+        ValType::ExternRef => Val::ExternRef(None),
+        ValType::FuncRef => Val::FuncRef(None),
+    }
+}
+
+/// Returns the names of the Wasm functions active when `error` was raised,
+/// from the innermost, if the engine captured a backtrace.
+pub fn backtrace(error: &wasmtime::Error) -> Vec<String> {
+    error
+        .downcast_ref::<wasmtime::WasmBacktrace>()
+        .map(|backtrace| {
+            backtrace
+                .frames()
+                .iter()
+                .filter_map(|frame| frame.func_name().map(str::to_owned))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Returns the placeholders receiving the results of a call to `func`.
+pub fn result_placeholders(func: &Func, store: impl AsContext) -> Vec<Val> {
+    func.ty(store).results().map(placeholder_for_type).collect()
+}
+
+impl wasmtime::ResourceLimiter for Limiter {
+    fn memory_growing(
+        &mut self,
+        _current: usize,
+        desired: usize,
+        maximum: Option<usize>,
+    ) -> wasmtime::Result<bool> {
+        Ok(self.check_memory_growth(desired, maximum))
+    }
+
+    fn table_growing(
+        &mut self,
+        _current: u32,
+        desired: u32,
+        maximum: Option<u32>,
+    ) -> wasmtime::Result<bool> {
+        Ok(maximum.is_none_or(|max| desired <= max))
+    }
+}
@@ -8,6 +8,13 @@ use walrus::{InstrSeqBuilder, LocalId, MemoryId, ValType};
 
 use crate::wasm_generator::{clar2wasm_ty, GeneratorError, WasmGenerator};
 
+/// The size of the largest consensus serialization of a value of type `ty`.
+pub(crate) fn max_serialized_size(ty: &TypeSignature) -> Result<i32, GeneratorError> {
+    ty.max_serialized_size()
+        .map(|size| size as i32)
+        .map_err(|e| GeneratorError::TypeError(format!("cannot serialize type {ty}: {e}")))
+}
+
 impl WasmGenerator {
     /// Serialize an integer (`int` or `uint`) to memory using consensus
     /// serialization. Leaves the length of the data written on the top of the
@@ -941,6 +948,25 @@ impl WasmGenerator {
         }
     }
 
+    /// Serialize the value of type `ty` on the top of the data stack to a new
+    /// buffer on the call stack, large enough for any value of this type.
+    /// Returns the locals holding the offset and the length of the
+    /// serialization, which is how values are handed to the host for storage.
+    pub(crate) fn serialize_to_call_stack(
+        &mut self,
+        builder: &mut InstrSeqBuilder,
+        ty: &TypeSignature,
+    ) -> Result<(LocalId, LocalId), GeneratorError> {
+        let size = max_serialized_size(ty)?;
+        let offset = self.create_call_stack_buffer(builder, size);
+
+        let length = self.module.locals.add(ValType::I32);
+        self.serialize_to_memory(builder, offset, 0, ty)?;
+        builder.local_set(length);
+
+        Ok((offset, length))
+    }
+
     /// Generate the computation to determine the size of the value currently on top of the stack.
     /// The generated code will push on the stack the original value and its serialization size as i32.
     pub fn serialization_size(
@@ -38,15 +38,15 @@
     (import "clarity" "get_variable" (func $stdlib.get_variable (param $name_offset i32)
                                                          (param $name_length i32)
                                                          (param $return_offset i32)
-                                                         (param $return_length i32)))
+                                                         (param $return_length i32)
+                                                         (result i32)))
     (import "clarity" "set_variable" (func $stdlib.set_variable (param $name_offset i32)
                                                          (param $name_length i32)
                                                          (param $value_offset i32)
                                                          (param $value_length i32)))
     (import "clarity" "print" (func $stdlib.print (param $value_offset i32)
                                                   (param $value_length i32)
-                                                  (param $serialized_ty_offset i32)
-                                                  (param $serialized_ty_length i32)))
+                                                  (param $type_id i32)))
     (import "clarity" "enter_as_contract" (func $stdlib.enter_as_contract))
     (import "clarity" "exit_as_contract" (func $stdlib.exit_as_contract))
     (import "clarity" "enter_at_block" (func $stdlib.enter_at_block (param $block_hash_offset i32)
@@ -139,7 +139,8 @@
                                                (param $key_offset i32)
                                                (param $key_length i32)
                                                (param $return_offset i32)
-                                               (param $return_length i32)))
+                                               (param $return_length i32)
+                                               (result i32)))
     (import "clarity" "map_set" (func $stdlib.map_set (param $name_offset i32)
                                                (param $name_length i32)
                                                (param $key_offset i32)
@@ -344,8 +345,10 @@
     (global $runtime-error-arg-offset (mut i32) (i32.const -1))
     (global $runtime-error-arg-len (mut i32) (i32.const -1))
     (global $runtime-error-value-offset (mut i32) (i32.const -1))
-    (global $runtime-error-type-ser-offset (mut i32) (i32.const -1))
-    (global $runtime-error-type-ser-len (mut i32) (i32.const -1))
+    (global $runtime-error-type-id (mut i32) (i32.const -1))
+    ;; Location of the type table in the literal memory, set by the generator
+    (global $type-table-offset i32 (i32.const 0))
+    (global $type-table-length i32 (i32.const 0))
 
     ;; (sha256) initial hash values: first 32 bits of the fractional parts of the square roots of the first 8 primes 2..19
     (data (i32.const 0) "\67\e6\09\6a\85\ae\67\bb\72\f3\6e\3c\3a\f5\4f\a5\7f\52\0e\51\8c\68\05\9b\ab\d9\83\1f\19\cd\e0\5b")
@@ -3952,8 +3955,9 @@
     (export "runtime-error-arg-offset" (global $runtime-error-arg-offset))
     (export "runtime-error-arg-len" (global $runtime-error-arg-len))
     (export "runtime-error-value-offset" (global $runtime-error-value-offset))
-    (export "runtime-error-type-ser-offset" (global $runtime-error-type-ser-offset))
-    (export "runtime-error-type-ser-len" (global $runtime-error-type-ser-len))
+    (export "runtime-error-type-id" (global $runtime-error-type-id))
+    (export "type-table-offset" (global $type-table-offset))
+    (export "type-table-length" (global $type-table-length))
 
     ;; Functions
     (export "stdlib.add-uint" (func $stdlib.add-uint))
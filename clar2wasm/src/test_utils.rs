@@ -8,13 +8,13 @@ use clarity::vm::types::{
 };
 use clarity::vm::{ClarityVersion, Value};
 use walrus::{FunctionBuilder, InstrSeqBuilder};
-use wasmtime::{Engine, Module, Store};
 
 use crate::linker::dummy_linker;
+use crate::runtime::{self, Engine, Module, Store};
 use crate::wasm_generator::{
     add_placeholder_for_clarity_type, clar2wasm_ty, GeneratorError, WasmGenerator,
 };
-use crate::wasm_utils::{placeholder_for_type, wasm_to_clarity_value};
+use crate::wasm_utils::wasm_to_clarity_value;
 
 impl WasmGenerator {
     /// Creates an empty WasmGenerator.
@@ -188,11 +188,7 @@ impl WasmGenerator {
             .get_func(&mut store, ".top-level")
             .expect("cannot find .top-level function");
 
-        let mut result: Vec<_> = top_level
-            .ty(&mut store)
-            .results()
-            .map(placeholder_for_type)
-            .collect();
+        let mut result = runtime::result_placeholders(&top_level, &mut store);
 
         top_level
             .call(&mut store, &[], &mut result)
@@ -202,16 +198,9 @@ impl WasmGenerator {
             .get_memory(&mut store, "memory")
             .expect("couldn't find memory");
 
-        wasm_to_clarity_value(
-            return_ty,
-            0,
-            &result,
-            memory,
-            &mut store,
-            StacksEpochId::latest(),
-        )
-        .expect("error in execution")
-        .0
-        .expect("no value computed???")
+        wasm_to_clarity_value(return_ty, 0, &result, memory, &mut store)
+            .expect("error in execution")
+            .0
+            .expect("no value computed???")
     }
 }
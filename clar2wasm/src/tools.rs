@@ -3,26 +3,32 @@
 //! in production.
 #![allow(clippy::expect_used, clippy::unwrap_used)]
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::sync::LazyLock;
 
+use clarity::boot_util::boot_code_id;
 use clarity::consts::{CHAIN_ID_MAINNET, CHAIN_ID_TESTNET};
 use clarity::types::StacksEpochId;
-use clarity::vm::analysis::run_analysis;
+use clarity::vm::analysis::{run_analysis, ContractAnalysis};
 use clarity::vm::ast::build_ast;
-use clarity::vm::contexts::{EventBatch, GlobalContext};
+use clarity::vm::contexts::{CallStack, Environment, EventBatch, GlobalContext};
 use clarity::vm::contracts::Contract;
-use clarity::vm::costs::LimitedCostTracker;
-use clarity::vm::database::ClarityDatabase;
+use clarity::vm::costs::{ExecutionCost, LimitedCostTracker};
+use clarity::vm::database::{ClarityDatabase, SpecialCaseHandler};
 use clarity::vm::errors::{CheckErrors, Error, WasmError};
 use clarity::vm::events::{SmartContractEventData, StacksTransactionEvent};
 use clarity::vm::types::{PrincipalData, QualifiedContractIdentifier, StandardPrincipalData};
 use clarity::vm::{eval_all, ClarityVersion, ContractContext, ContractName, Value};
 use regex::Regex;
 
-use crate::compile;
+use crate::coverage::{CoverageMap, SharedCoverage};
 use crate::datastore::{BurnDatastore, Datastore, StacksConstants};
-use crate::initialize::initialize_contract;
+use crate::events::SharedEventSubscriber;
+use crate::initialize::{initialize_contract_with_options, ExecutionOptions};
+use crate::limits::ResourceLimits;
+use crate::runtime::{self, Engine};
+use crate::wasm_utils::call_function_with_options;
+use crate::{compile_with_options, GeneratorOptions};
 
 #[derive(Clone)]
 pub struct TestEnvironment {
@@ -32,8 +38,18 @@ pub struct TestEnvironment {
     datastore: Datastore,
     burn_datastore: BurnDatastore,
     cost_tracker: LimitedCostTracker,
+    /// Memory charged to the cost tracker by the last transaction.
+    memory_use: u64,
     events: Vec<EventBatch>,
     network: Network,
+    resource_limits: ResourceLimits,
+    /// Engine enforcing the resource limits, shared by all the contracts of
+    /// the environment.
+    engine: Option<Engine>,
+    event_subscribers: Vec<SharedEventSubscriber>,
+    coverage: Option<SharedCoverage>,
+    coverage_maps: HashMap<String, CoverageMap>,
+    dynamic_memory: bool,
 }
 
 impl TestEnvironment {
@@ -67,8 +83,15 @@ impl TestEnvironment {
             datastore,
             burn_datastore,
             cost_tracker,
+            memory_use: 0,
             events: vec![],
             network: Network::Testnet,
+            resource_limits: ResourceLimits::default(),
+            engine: None,
+            event_subscribers: vec![],
+            coverage: None,
+            coverage_maps: HashMap::new(),
+            dynamic_memory: false,
         }
     }
 
@@ -162,19 +185,36 @@ impl TestEnvironment {
             StandardPrincipalData::transient(),
             (*contract_name).into(),
         );
+        self.init_contract_with_id(&contract_id, snippet)
+    }
+
+    /// Deploy a compiled contract with any issuer. Contracts which are not
+    /// deployed by the transient principal are named by their full identifier
+    /// in the other methods of the environment.
+    pub fn init_contract_with_id(
+        &mut self,
+        contract_id: &QualifiedContractIdentifier,
+        snippet: &str,
+    ) -> Result<Option<Value>, Error> {
+        let contract_id = contract_id.clone();
+        let contract_name = contract_key(&contract_id);
 
         let mut compile_result = self
             .datastore
             .as_analysis_db()
             .execute(|analysis_db| {
-                compile(
+                compile_with_options(
                     snippet,
                     &contract_id,
                     LimitedCostTracker::new_free(),
                     self.version,
                     self.epoch,
                     analysis_db,
-                    false,
+                    GeneratorOptions {
+                        coverage: self.coverage.is_some(),
+                        dynamic_memory: self.dynamic_memory,
+                        ..GeneratorOptions::default()
+                    },
                 )
                 .map_err(|e| CheckErrors::Expects(format!("Compilation failure {e:?}")))
             })
@@ -189,10 +229,131 @@ impl TestEnvironment {
 
         let mut contract_context = ContractContext::new(contract_id.clone(), self.version);
         // compile_result.module.emit_wasm_file("test.wasm").unwrap();
-        contract_context.set_wasm_module(compile_result.module.emit_wasm());
+        let wasm = compile_result.module.emit_wasm();
+        if let Some(map) = CoverageMap::from_wasm(&wasm) {
+            self.coverage_maps.insert(contract_name.clone(), map);
+        }
+        contract_context.set_wasm_module(wasm);
+
+        let options = self.execution_options();
+        let return_val = self.with_global_context(|global_context, _| {
+            global_context
+                .execute(|g| g.database.insert_contract_hash(&contract_id, snippet))
+                .expect("Failed to insert contract hash.");
+
+            let return_val = initialize_contract_with_options(
+                global_context,
+                &mut contract_context,
+                None,
+                &compile_result.contract_analysis,
+                options,
+            )?;
+
+            let data_size = contract_context.data_size;
+            global_context.database.insert_contract(
+                &contract_id,
+                Contract {
+                    contract_context: contract_context.clone(),
+                },
+            )?;
+            global_context
+                .database
+                .set_contract_data_size(&contract_id, data_size)
+                .expect("Failed to set contract data size.");
+            Ok(return_val)
+        })?;
+
+        self.contract_contexts
+            .insert(contract_name, contract_context);
+
+        Ok(return_val)
+    }
 
-        let mut cost_tracker = LimitedCostTracker::new_free();
-        std::mem::swap(&mut self.cost_tracker, &mut cost_tracker);
+    pub fn evaluate(&mut self, snippet: &str) -> Result<Option<Value>, Error> {
+        self.init_contract_with_snippet("snippet", snippet)
+    }
+
+    /// Call a public or read-only function of a contract deployed in this
+    /// environment, as a transaction sent by `sender` (the transient principal
+    /// by default).
+    ///
+    /// Contracts deployed with [TestEnvironment::init_contract_with_snippet] are
+    /// run compiled, those deployed with
+    /// [TestEnvironment::interpret_contract_with_snippet] are interpreted.
+    pub fn call_contract_function(
+        &mut self,
+        contract_name: &str,
+        function_name: &str,
+        args: &[Value],
+        sender: Option<PrincipalData>,
+    ) -> Result<Value, Error> {
+        let sender =
+            sender.unwrap_or_else(|| PrincipalData::from(StandardPrincipalData::transient()));
+        let options = self.execution_options();
+
+        self.with_global_context(|global_context, contract_contexts| {
+            let contract_context = contract_contexts
+                .get(contract_name)
+                .ok_or_else(|| CheckErrors::NoSuchContract(contract_name.to_string()))?;
+            let function = contract_context
+                .lookup_function(function_name)
+                .filter(|f| f.is_public())
+                .ok_or_else(|| {
+                    CheckErrors::NoSuchPublicFunction(
+                        contract_context.contract_identifier.to_string(),
+                        function_name.to_string(),
+                    )
+                })?;
+
+            let is_compiled = contract_context.with_wasm_module(|_| Ok(())).is_ok();
+            let mut call_stack = CallStack::new();
+            if is_compiled {
+                global_context.begin();
+                let result = call_function_with_options(
+                    function_name,
+                    args,
+                    global_context,
+                    contract_context,
+                    &mut call_stack,
+                    Some(sender.clone()),
+                    Some(sender),
+                    None,
+                    options,
+                );
+                // Mimic a transaction: a public function returning an `err` does not
+                // commit its changes.
+                match &result {
+                    Ok(Value::Response(response)) if !response.committed => {
+                        global_context.roll_back()?
+                    }
+                    Ok(_) => global_context.commit().map(|_| ())?,
+                    Err(_) => global_context.roll_back()?,
+                }
+                result
+            } else {
+                let mut env = Environment::new(
+                    global_context,
+                    contract_context,
+                    &mut call_stack,
+                    Some(sender.clone()),
+                    Some(sender),
+                    None,
+                );
+                env.execute_function_as_transaction(&function, args, None, false)
+            }
+        })
+    }
+
+    /// Run `f` in a transaction over the state of this environment, with the
+    /// deployed contracts. The transaction and its events are kept if `f`
+    /// succeeds. The cost tracker of the environment is lent to the global
+    /// context and given back on every path.
+    pub(crate) fn with_global_context<T>(
+        &mut self,
+        f: impl FnOnce(&mut GlobalContext, &HashMap<String, ContractContext>) -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        let cost_tracker =
+            std::mem::replace(&mut self.cost_tracker, LimitedCostTracker::new_free());
 
         let conn = ClarityDatabase::new(
             &mut self.datastore,
@@ -208,53 +369,188 @@ impl TestEnvironment {
         let mut global_context =
             GlobalContext::new(is_mainnet, chain_id, conn, cost_tracker, self.epoch);
         global_context.begin();
-        global_context
-            .execute(|g| g.database.insert_contract_hash(&contract_id, snippet))
-            .expect("Failed to insert contract hash.");
-
-        let return_val = initialize_contract(
-            &mut global_context,
-            &mut contract_context,
-            None,
-            &compile_result.contract_analysis,
-        )?;
-
-        let data_size = contract_context.data_size;
-        global_context.database.insert_contract(
-            &contract_id,
-            Contract {
-                contract_context: contract_context.clone(),
-            },
-        )?;
-        global_context
-            .database
-            .set_contract_data_size(&contract_id, data_size)
-            .expect("Failed to set contract data size.");
-
-        let (_, events) = global_context.commit().unwrap();
+
+        let memory_before = global_context.cost_track.get_memory();
+        let result = f(&mut global_context, &self.contract_contexts);
+        self.memory_use = global_context
+            .cost_track
+            .get_memory()
+            .saturating_sub(memory_before);
+        let result = result.and_then(|value| {
+            let (_, events) = global_context.commit()?;
+            Ok((value, events))
+        });
+        self.cost_tracker = global_context.cost_track;
+
+        let (value, events) = result?;
         if let Some(events) = events {
             self.events.push(events);
         }
-        self.cost_tracker = global_context.cost_track;
-
-        self.contract_contexts
-            .insert(contract_name.to_string(), contract_context);
-
-        Ok(return_val)
-    }
-
-    pub fn evaluate(&mut self, snippet: &str) -> Result<Option<Value>, Error> {
-        self.init_contract_with_snippet("snippet", snippet)
+        Ok(value)
     }
 
     pub fn get_contract_context(&self, contract_name: &str) -> Option<&ContractContext> {
         self.contract_contexts.get(contract_name)
     }
 
+    /// Load the analysis of a contract deployed in this environment.
+    pub fn get_contract_analysis(&mut self, contract_name: &str) -> Option<ContractAnalysis> {
+        let contract_id = self
+            .contract_contexts
+            .get(contract_name)?
+            .contract_identifier
+            .clone();
+        let epoch = self.epoch;
+        self.datastore
+            .as_analysis_db()
+            .execute(|analysis_db| analysis_db.load_contract(&contract_id, &epoch))
+            .ok()
+            .flatten()
+    }
+
     pub fn get_events(&self) -> &Vec<EventBatch> {
         &self.events
     }
 
+    /// Dump the data-vars, map entries, token balances and supplies, NFT owners
+    /// and STX balances stored at the current chain tip.
+    pub fn dump_state(&self) -> StateDump {
+        StateDump(
+            self.datastore
+                .entries()
+                .filter_map(|(key, value)| describe_entry(key, value))
+                .collect(),
+        )
+    }
+
+    /// Set the resource limits enforced when running compiled contracts.
+    pub fn set_resource_limits(&mut self, limits: ResourceLimits) {
+        self.resource_limits = limits;
+        self.engine = runtime::limited_engine(&limits).expect("Failed to build the engine.");
+    }
+
+    /// Compile the contracts from now on to grow their memory on demand.
+    pub fn enable_dynamic_memory(&mut self) {
+        self.dynamic_memory = true;
+    }
+
+    /// Instrument the contracts compiled from now on with coverage counters,
+    /// and return the counters collected when running them.
+    pub fn enable_coverage(&mut self) -> SharedCoverage {
+        self.coverage.get_or_insert_with(Default::default).clone()
+    }
+
+    /// Like [TestEnvironment::enable_coverage], adding the counters to
+    /// `coverage`, which can be shared between environments.
+    pub fn share_coverage(&mut self, coverage: SharedCoverage) {
+        self.coverage = Some(coverage);
+    }
+
+    /// The coverage map of an instrumented contract.
+    pub fn coverage_map(&self, contract_name: &str) -> Option<&CoverageMap> {
+        self.coverage_maps.get(contract_name)
+    }
+
+    /// An LCOV record of the coverage of an instrumented contract, whose source
+    /// file is `source_file`.
+    pub fn coverage_report(&self, contract_name: &str, source_file: &str) -> Option<String> {
+        let map = self.coverage_maps.get(contract_name)?;
+        let contract_id = &self
+            .contract_contexts
+            .get(contract_name)?
+            .contract_identifier;
+        let coverage = self.coverage.as_ref()?.borrow();
+        Some(map.lcov(source_file, coverage.get(contract_id)))
+    }
+
+    fn execution_options(&self) -> ExecutionOptions {
+        ExecutionOptions {
+            limits: self.resource_limits,
+            engine: self.engine.clone(),
+            subscribers: self.event_subscribers.clone(),
+            coverage: self.coverage.clone(),
+        }
+    }
+
+    /// Meter the contracts with the cost contracts of the chain, deployed at
+    /// their boot addresses if they are not yet.
+    ///
+    /// Compiled contracts only charge the costs of their host calls, such as
+    /// contract calls, to the cost tracker.
+    pub fn enable_cost_tracking(&mut self) -> Result<(), Error> {
+        let (epoch, is_mainnet) = (self.epoch, matches!(self.network, Network::Mainnet));
+        for (name, source, version) in COST_CONTRACTS {
+            let (source, version) = match name {
+                "costs-2" if !is_mainnet => (COSTS_2_TESTNET, version),
+                "costs-3" if epoch < StacksEpochId::Epoch21 => continue,
+                _ => (source, version),
+            };
+            let contract_id = boot_code_id(name, is_mainnet);
+            if self
+                .contract_contexts
+                .contains_key(&contract_key(&contract_id))
+            {
+                continue;
+            }
+            let test_version = std::mem::replace(&mut self.version, version);
+            let deployed = self.interpret_contract_with_id(&contract_id, source);
+            self.version = test_version;
+            deployed?;
+        }
+
+        let mut conn = ClarityDatabase::new(
+            &mut self.datastore,
+            &self.burn_datastore,
+            &self.burn_datastore,
+        );
+        self.cost_tracker = execute(&mut conn, |database| {
+            LimitedCostTracker::new_max_limit(database, epoch, is_mainnet).map_err(Error::from)
+        })?;
+        Ok(())
+    }
+
+    /// The costs charged so far to the cost tracker of the environment.
+    pub fn total_cost(&self) -> ExecutionCost {
+        self.cost_tracker.get_total()
+    }
+
+    /// The memory charged to the cost tracker by the last transaction of the
+    /// environment, such as a call, before it was committed.
+    pub fn memory_use(&self) -> u64 {
+        self.memory_use
+    }
+
+    /// Set the handler run after each successful `contract-call?`, as a node
+    /// does for the PoX contracts.
+    pub fn set_cc_special_cases_handler(&mut self, handler: SpecialCaseHandler) {
+        self.datastore.set_cc_special_cases_handler(handler);
+    }
+
+    /// Attach a subscriber notified of the events emitted by compiled contracts.
+    pub fn subscribe(&mut self, subscriber: SharedEventSubscriber) {
+        self.event_subscribers.push(subscriber);
+    }
+
+    /// Credit `amount` microSTX to `recipient`, increasing the liquid supply.
+    pub fn credit_stx(&mut self, recipient: &PrincipalData, amount: u128) -> Result<(), Error> {
+        let mut conn = ClarityDatabase::new(
+            &mut self.datastore,
+            &self.burn_datastore,
+            &self.burn_datastore,
+        );
+        execute(&mut conn, |database| {
+            let mut snapshot = database.get_stx_balance_snapshot(recipient)?;
+            snapshot.credit(amount)?;
+            snapshot.save()?;
+            database.increment_ustx_liquid_supply(amount)
+        })
+    }
+
+    /// Height of the chain tip.
+    pub fn block_height(&self) -> u32 {
+        self.datastore.chain_height()
+    }
+
     pub fn advance_chain_tip(&mut self, count: u32) -> u32 {
         self.burn_datastore.advance_chain_tip(count);
         self.datastore.advance_chain_tip(count)
@@ -269,34 +565,47 @@ impl TestEnvironment {
             StandardPrincipalData::transient(),
             (*contract_name).into(),
         );
+        self.interpret_contract_with_id(&contract_id, snippet)
+    }
 
-        let mut cost_tracker = LimitedCostTracker::new_free();
-        std::mem::swap(&mut self.cost_tracker, &mut cost_tracker);
+    /// Like [TestEnvironment::init_contract_with_id], for an interpreted
+    /// contract.
+    pub fn interpret_contract_with_id(
+        &mut self,
+        contract_id: &QualifiedContractIdentifier,
+        snippet: &str,
+    ) -> Result<Option<Value>, Error> {
+        let contract_id = contract_id.clone();
+        let contract_name = contract_key(&contract_id);
 
         let mut contract_analysis = self.datastore.as_analysis_db().execute(|analysis_db| {
             // Parse the contract
             let ast = build_ast(
                 &contract_id,
                 snippet,
-                &mut self.cost_tracker,
+                &mut LimitedCostTracker::new_free(),
                 self.version,
                 self.epoch,
             )
             .map_err(|e| Error::Wasm(WasmError::WasmGeneratorError(format!("{e:?}"))))?;
 
-            // Run the analysis passes
+            // Run the analysis passes, handing the cost tracker back on failure
             run_analysis(
                 &contract_id,
                 &ast.expressions,
                 analysis_db,
                 false,
-                cost_tracker,
+                std::mem::replace(&mut self.cost_tracker, LimitedCostTracker::new_free()),
                 self.epoch,
                 self.version,
                 true,
             )
-            .map_err(|(e, _)| Error::Wasm(WasmError::WasmGeneratorError(format!("{e:?}"))))
+            .map_err(|(e, cost_tracker)| {
+                self.cost_tracker = cost_tracker;
+                Error::Wasm(WasmError::WasmGeneratorError(format!("{e:?}")))
+            })
         })?;
+        self.cost_tracker = contract_analysis.cost_track.take().unwrap();
 
         self.datastore
             .as_analysis_db()
@@ -305,57 +614,34 @@ impl TestEnvironment {
 
         let mut contract_context = ContractContext::new(contract_id.clone(), self.version);
 
-        let conn = ClarityDatabase::new(
-            &mut self.datastore,
-            &self.burn_datastore,
-            &self.burn_datastore,
-        );
+        let result = self.with_global_context(|global_context, _| {
+            global_context
+                .database
+                .insert_contract_hash(&contract_id, snippet)
+                .expect("Failed to insert contract hash.");
 
-        let (is_mainnet, chain_id) = match self.network {
-            Network::Mainnet => (true, CHAIN_ID_MAINNET),
-            Network::Testnet => (false, CHAIN_ID_TESTNET),
-        };
-
-        let mut global_context = GlobalContext::new(
-            is_mainnet,
-            chain_id,
-            conn,
-            contract_analysis.cost_track.take().unwrap(),
-            self.epoch,
-        );
-        global_context.begin();
+            let result = eval_all(
+                &contract_analysis.expressions,
+                &mut contract_context,
+                global_context,
+                None,
+            )?;
 
-        global_context
-            .database
-            .insert_contract_hash(&contract_id, snippet)
-            .expect("Failed to insert contract hash.");
-
-        let result = eval_all(
-            &contract_analysis.expressions,
-            &mut contract_context,
-            &mut global_context,
-            None,
-        )?;
-
-        global_context.database.insert_contract(
-            &contract_id,
-            Contract {
-                contract_context: contract_context.clone(),
-            },
-        )?;
-        global_context
-            .database
-            .set_contract_data_size(&contract_id, contract_context.data_size)
-            .expect("Failed to set contract data size.");
-
-        let (_, events) = global_context.commit().unwrap();
-        if let Some(events) = events {
-            self.events.push(events);
-        }
-        self.cost_tracker = global_context.cost_track;
+            global_context.database.insert_contract(
+                &contract_id,
+                Contract {
+                    contract_context: contract_context.clone(),
+                },
+            )?;
+            global_context
+                .database
+                .set_contract_data_size(&contract_id, contract_context.data_size)
+                .expect("Failed to set contract data size.");
+            Ok(result)
+        })?;
 
         self.contract_contexts
-            .insert(contract_name.to_owned(), contract_context);
+            .insert(contract_name, contract_context);
 
         Ok(result)
     }
@@ -365,6 +651,44 @@ impl TestEnvironment {
     }
 }
 
+/// The cost contracts of the chain, with the Clarity version they are
+/// deployed with.
+const COST_CONTRACTS: [(&str, &str, ClarityVersion); 4] = [
+    (
+        "cost-voting",
+        include_str!("../tests/contracts/boot-contracts/cost-voting.clar"),
+        ClarityVersion::Clarity1,
+    ),
+    (
+        "costs",
+        include_str!("../tests/contracts/boot-contracts/costs.clar"),
+        ClarityVersion::Clarity1,
+    ),
+    (
+        "costs-2",
+        include_str!("../tests/contracts/boot-contracts/costs-2.clar"),
+        ClarityVersion::Clarity1,
+    ),
+    (
+        "costs-3",
+        include_str!("../tests/contracts/boot-contracts/costs-3.clar"),
+        ClarityVersion::Clarity2,
+    ),
+];
+
+/// The `costs-2` contract deployed on testnet.
+const COSTS_2_TESTNET: &str =
+    include_str!("../tests/contracts/boot-contracts/costs-2-testnet.clar");
+
+/// The name of a contract in a [TestEnvironment].
+fn contract_key(contract_id: &QualifiedContractIdentifier) -> String {
+    if contract_id.issuer == StandardPrincipalData::transient() {
+        contract_id.name.to_string()
+    } else {
+        contract_id.to_string()
+    }
+}
+
 impl Default for TestEnvironment {
     fn default() -> Self {
         Self::new(StacksEpochId::Epoch31, ClarityVersion::Clarity3)
@@ -520,6 +844,7 @@ impl CrossEvalResult {
             self.env_interpreted.get_events(),
             self.env_compiled.get_events(),
         );
+        compare_state(&self.env_interpreted, &self.env_compiled, snippet);
     }
 }
 
@@ -692,6 +1017,38 @@ pub fn crosscheck_with_epoch(
     }
 }
 
+/// Deploy `snippet` compiled and interpreted in `epoch`, with cost tracking,
+/// and assert that each call of the public `functions`, in order, returns the
+/// same value and charges the same memory.
+pub fn crosscheck_calls_memory_use(snippet: &str, functions: &[&str], epoch: StacksEpochId) {
+    let run = |compiled: bool| {
+        let mut env = TestEnvironment::new(epoch, ClarityVersion::default_for_epoch(epoch));
+        env.enable_cost_tracking()
+            .expect("Failed to enable cost tracking.");
+        if compiled {
+            env.init_contract_with_snippet("contract", snippet)
+        } else {
+            env.interpret_contract_with_snippet("contract", snippet)
+        }
+        .expect("Failed to deploy contract.");
+
+        functions
+            .iter()
+            .map(|function| {
+                let value = env.call_contract_function("contract", function, &[], None);
+                (value, env.memory_use())
+            })
+            .collect::<Vec<_>>()
+    };
+
+    let interpreted = run(false);
+    assert_eq!(
+        run(true),
+        interpreted,
+        "compiled calls diverge from the interpreter in {epoch}"
+    );
+}
+
 pub fn crosscheck_with_clarity_version(
     snippet: &str,
     expected: Result<Option<Value>, Error>,
@@ -725,15 +1082,40 @@ pub fn crosscheck_multi_contract(
     contracts: &[(ContractName, &str)],
     expected: Result<Option<Value>, Error>,
 ) {
+    let compiled_results = crosscheck_multi_contract_compare_only(contracts);
+
+    // compare with expected final value
+    let final_value = compiled_results.last().unwrap_or(&Ok(None));
+    assert_eq!(
+        final_value, &expected,
+        "final value is not the expected {final_value:?}"
+    );
+}
+
+/// Deploys the contracts in order with both the compiler and the interpreter,
+/// asserting that each contract returns the same result and that they emit
+/// the same events. Returns the results of the compiled contracts.
+pub fn crosscheck_multi_contract_compare_only(
+    contracts: &[(ContractName, &str)],
+) -> Vec<Result<Option<Value>, Error>> {
+    crosscheck_multi_contract_with_env(contracts, TestEnvironment::default())
+}
+
+/// Like [crosscheck_multi_contract_compare_only], deploying the contracts in
+/// two copies of `env`.
+pub fn crosscheck_multi_contract_with_env(
+    contracts: &[(ContractName, &str)],
+    env: TestEnvironment,
+) -> Vec<Result<Option<Value>, Error>> {
     // compiled version
-    let mut compiled_env = TestEnvironment::default();
+    let mut compiled_env = env.clone();
     let compiled_results: Vec<_> = contracts
         .iter()
         .map(|(name, snippet)| compiled_env.init_contract_with_snippet(name, snippet))
         .collect();
 
     // interpreted version
-    let mut interpreted_env = TestEnvironment::default();
+    let mut interpreted_env = env;
     let interpreted_results: Vec<_> = contracts
         .iter()
         .map(|(name, snippet)| interpreted_env.interpret_contract_with_snippet(name, snippet))
@@ -751,14 +1133,14 @@ pub fn crosscheck_multi_contract(
         );
     }
 
-    // compare with expected final value
-    let final_value = compiled_results.last().unwrap_or(&Ok(None));
-    assert_eq!(
-        final_value, &expected,
-        "final value is not the expected {final_value:?}"
+    compare_events(interpreted_env.get_events(), compiled_env.get_events());
+    compare_state(
+        &interpreted_env,
+        &compiled_env,
+        "in multi-contract crosscheck",
     );
 
-    compare_events(interpreted_env.get_events(), compiled_env.get_events());
+    compiled_results
 }
 
 // TODO: This function is a temporary solution until issue #421 is addressed.
@@ -784,18 +1166,92 @@ pub fn crosscheck_expect_failure(snippet: &str) {
     );
 }
 
+/// A readable view of the state of a [TestEnvironment], keyed by a description
+/// of each stored entry (eg. `data-var S1G2...snippet.counter`).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StateDump(pub BTreeMap<String, String>);
+
+impl StateDump {
+    /// Lists the entries differing between `self` and `other`, or returns `None`
+    /// if both states are identical.
+    pub fn diff(&self, other: &StateDump) -> Option<String> {
+        let keys: BTreeSet<_> = self.0.keys().chain(other.0.keys()).collect();
+        let lines: Vec<_> = keys
+            .into_iter()
+            .filter_map(|key| match (self.0.get(key), other.0.get(key)) {
+                (Some(a), Some(b)) if a == b => None,
+                (a, b) => Some(format!(
+                    "  {key}\n    - {}\n    + {}",
+                    a.map_or("<absent>", String::as_str),
+                    b.map_or("<absent>", String::as_str)
+                )),
+            })
+            .collect();
+        (!lines.is_empty()).then(|| lines.join("\n"))
+    }
+}
+
+/// Turns a key-value pair of the datastore into a readable description of the
+/// entry and its value. Returns `None` for entries which are not contract state.
+fn describe_entry(key: &str, value: &str) -> Option<(String, String)> {
+    let decode = |hex: &str| {
+        Value::try_deserialize_hex_untyped(hex).map_or_else(|_| hex.to_string(), |v| v.to_string())
+    };
+
+    let parts: Vec<_> = key.split("::").collect();
+    let description = match parts.as_slice() {
+        ["vm", contract, kind, name] => match kind.parse::<u8>().ok()? {
+            1 => format!("data-var {contract}.{name}"),
+            3 => format!("ft-supply {contract}.{name}"),
+            _ => return None,
+        },
+        ["vm", contract, kind, name, item] => match kind.parse::<u8>().ok()? {
+            0 => format!("map {contract}.{name}[{}]", decode(item)),
+            2 => format!("ft-balance {contract}.{name}[{item}]"),
+            4 => format!("nft-owner {contract}.{name}[{}]", decode(item)),
+            _ => return None,
+        },
+        ["vm-account", principal, "20"] => format!("stx-balance {principal}"),
+        _ => return None,
+    };
+    Some((description, decode(value)))
+}
+
+/// Checks that both environments end up with the same contract state.
+fn compare_state(interpreted: &TestEnvironment, compiled: &TestEnvironment, context: &str) {
+    if let Some(diff) = interpreted.dump_state().diff(&compiled.dump_state()) {
+        panic!("Compiled and interpreted states diverge! {context}\n(- interpreted, + compiled)\n{diff}");
+    }
+}
+
 fn compare_events(events_a: &[EventBatch], events_b: &[EventBatch]) {
+    if let Some(divergence) = events_divergence(events_a, events_b) {
+        panic!("{divergence}");
+    }
+}
+
+/// Describes the first difference between two lists of event batches, or returns
+/// `None` if they are equivalent.
+pub fn events_divergence(events_a: &[EventBatch], events_b: &[EventBatch]) -> Option<String> {
     // `SmartContractEvent` `value` could differ but resulting in the same serialized
     // data (eg, serializing a `CallableContract` results in a contract principal)
-    assert_eq!(
-        events_a.len(),
-        events_b.len(),
-        "events batches size mismatch"
-    );
+    if events_a.len() != events_b.len() {
+        return Some(format!(
+            "events batches size mismatch: {} != {}",
+            events_a.len(),
+            events_b.len()
+        ));
+    }
     for (EventBatch { events: batch_a }, EventBatch { events: batch_b }) in
         events_a.iter().zip(events_b.iter())
     {
-        assert_eq!(batch_a.len(), batch_b.len(), "events batch size mismatch");
+        if batch_a.len() != batch_b.len() {
+            return Some(format!(
+                "events batch size mismatch: {} != {}",
+                batch_a.len(),
+                batch_b.len()
+            ));
+        }
         for (a, b) in batch_a.iter().zip(batch_b.iter()) {
             if let (
                 StacksTransactionEvent::SmartContractEvent(SmartContractEventData {
@@ -808,20 +1264,26 @@ fn compare_events(events_a: &[EventBatch], events_b: &[EventBatch]) {
                 }),
             ) = (a, b)
             {
-                assert_eq!(key_a, key_b, "events key mismatch");
+                if key_a != key_b {
+                    return Some(format!("events key mismatch: {key_a:?} != {key_b:?}"));
+                }
 
                 let mut value_a_ser = vec![];
-                value_a.serialize_write(&mut value_a_ser).unwrap();
-
                 let mut value_b_ser = vec![];
-                value_b.serialize_write(&mut value_b_ser).unwrap();
+                let serialized = value_a.serialize_write(&mut value_a_ser).is_ok()
+                    && value_b.serialize_write(&mut value_b_ser).is_ok();
 
-                assert_eq!(value_a_ser, value_b_ser, "events serialized value mismatch");
-            } else {
-                assert_eq!(a, b, "events mismatch")
+                if !serialized || value_a_ser != value_b_ser {
+                    return Some(format!(
+                        "events serialized value mismatch: {value_a} != {value_b}"
+                    ));
+                }
+            } else if a != b {
+                return Some(format!("events mismatch: {a:?} != {b:?}"));
             }
         }
     }
+    None
 }
 
 #[derive(Debug, Clone)]
@@ -868,6 +1330,114 @@ mod tests {
         assert_eq!(evaluate("(+ 1 2)"), Ok(Some(Value::Int(3))));
     }
 
+    #[test]
+    fn test_contract_with_issuer() {
+        let contract_id =
+            QualifiedContractIdentifier::parse("SP000000000000000000002Q6VF78.issued").unwrap();
+        let source = r#"
+            (define-read-only (issuer) (as-contract tx-sender))
+            (define-read-only (caller) contract-caller)
+        "#;
+        let sender = PrincipalData::parse("SM2J6ZY48GV1EZ5V2V5RB9MP66SW86PYKKQVX8X0G").unwrap();
+
+        let mut env = TestEnvironment::default();
+        env.init_contract_with_id(&contract_id, source)
+            .expect("Failed to init contract.");
+        env.interpret_contract_with_id(
+            &QualifiedContractIdentifier::parse("SP000000000000000000002Q6VF78.interpreted")
+                .unwrap(),
+            source,
+        )
+        .expect("Failed to interpret contract.");
+
+        assert!(env.get_contract_context("issued").is_none());
+        for name in [
+            "SP000000000000000000002Q6VF78.issued",
+            "SP000000000000000000002Q6VF78.interpreted",
+        ] {
+            assert_eq!(
+                env.call_contract_function(name, "caller", &[], Some(sender.clone())),
+                Ok(Value::Principal(sender.clone()))
+            );
+        }
+        assert_eq!(
+            env.call_contract_function("SP000000000000000000002Q6VF78.issued", "issuer", &[], None),
+            Ok(Value::Principal(contract_id.into()))
+        );
+    }
+
+    #[test]
+    fn compiled_contract_calls_are_charged() {
+        let mut env = TestEnvironment::default();
+        env.enable_cost_tracking()
+            .expect("Failed to enable cost tracking.");
+        env.init_contract_with_snippet("callee", "(define-read-only (add) (+ 1 2 3))")
+            .expect("Failed to init contract.");
+        env.init_contract_with_snippet(
+            "caller",
+            "(define-read-only (call) (contract-call? .callee add))",
+        )
+        .expect("Failed to init contract.");
+
+        let before = env.total_cost();
+        assert_eq!(
+            env.call_contract_function("caller", "call", &[], None),
+            Ok(Value::Int(6))
+        );
+        assert!(env.total_cost().runtime > before.runtime);
+    }
+
+    #[test]
+    fn test_dump_state() {
+        let mut env = TestEnvironment::default();
+        env.evaluate(
+            r#"
+            (define-data-var counter int 1)
+            (define-map m uint bool)
+            (define-fungible-token ft)
+            (define-non-fungible-token nft uint)
+            (var-set counter 2)
+            (map-insert m u1 true)
+            (ft-mint? ft u10 tx-sender)
+            (nft-mint? nft u3 tx-sender)
+            "#,
+        )
+        .expect("Failed to init contract.");
+
+        let contract = format!("{}.snippet", StandardPrincipalData::transient());
+        let sender = PrincipalData::from(StandardPrincipalData::transient());
+        let state = env.dump_state().0;
+        assert_eq!(state[&format!("data-var {contract}.counter")], "2");
+        assert_eq!(state[&format!("map {contract}.m[u1]")], "(some true)");
+        assert!(state.contains_key(&format!("ft-supply {contract}.ft")));
+        assert!(state.contains_key(&format!("ft-balance {contract}.ft[{sender}]")));
+        assert_eq!(
+            state[&format!("nft-owner {contract}.nft[u3]")],
+            Value::Principal(sender).to_string()
+        );
+    }
+
+    #[test]
+    fn test_state_diff() {
+        let env = TestEnvironment::default();
+
+        let mut env_a = env.clone();
+        env_a
+            .evaluate("(define-data-var v int 1)")
+            .expect("Failed to init contract.");
+        let mut env_b = env;
+        env_b
+            .evaluate("(define-data-var v int 2)")
+            .expect("Failed to init contract.");
+
+        assert_eq!(env_a.dump_state().diff(&env_a.dump_state()), None);
+        let diff = env_a
+            .dump_state()
+            .diff(&env_b.dump_state())
+            .expect("states should differ");
+        assert!(diff.contains("snippet.v\n    - 1\n    + 2"), "{diff}");
+    }
+
     #[cfg(not(feature = "test-clarity-v1"))]
     #[test]
     fn test_compare_events() {
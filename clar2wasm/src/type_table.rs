@@ -0,0 +1,296 @@
+//! Binary descriptors of the types of the values handed to the host.
+//!
+//! Some host functions read a value from the memory without knowing its type,
+//! like the value of a `print` or the value of a short return. The generator
+//! interns those types in a [TypeTable], written to the literal memory of the
+//! module and located by the `type-table-offset` and `type-table-length`
+//! globals. The host receives the index of a type in the table instead of
+//! parsing a type signature at each call. The decoded tables are cached by
+//! the hash of their encoding (see [crate::cache]), so the table of a module is
+//! decoded once per thread.
+//!
+//! The table starts with the number of types, followed by their descriptors.
+//! A descriptor is a tag byte, followed by the lengths and descriptors of the
+//! type parameters. Lengths are little-endian `u32`, tuple field names are
+//! prefixed by their length as a byte.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use clarity::vm::errors::{Error, WasmError};
+use clarity::vm::types::signatures::StringUTF8Length;
+use clarity::vm::types::{
+    BufferLength, ListTypeData, SequenceSubtype, StringSubtype, TupleTypeSignature, TypeSignature,
+};
+use clarity::vm::ClarityName;
+
+use crate::cache::ContentCache;
+
+const INT: u8 = 0;
+const UINT: u8 = 1;
+const BOOL: u8 = 2;
+const PRINCIPAL: u8 = 3;
+const BUFFER: u8 = 4;
+const STRING_ASCII: u8 = 5;
+const STRING_UTF8: u8 = 6;
+const LIST: u8 = 7;
+const OPTIONAL: u8 = 8;
+const RESPONSE: u8 = 9;
+const TUPLE: u8 = 10;
+
+/// Maximum number of decoded tables cached on a thread.
+const MAX_CACHED_TABLES: usize = 1024;
+
+/// A decoded table, shared between the calls to its contract.
+pub(crate) type DecodedTable = Rc<Vec<TypeSignature>>;
+
+thread_local! {
+    /// The tables decoded on this thread, by their encoding.
+    static DECODED_TABLES: RefCell<ContentCache<DecodedTable>> =
+        RefCell::new(ContentCache::new(MAX_CACHED_TABLES));
+}
+
+/// The types interned by the generator, indexed by their id.
+#[derive(Debug, Default)]
+pub(crate) struct TypeTable {
+    ids: HashMap<TypeSignature, u32>,
+    descriptors: Vec<u8>,
+}
+
+impl TypeTable {
+    /// Returns the id of `ty`, adding it to the table if needed.
+    pub fn id(&mut self, ty: &TypeSignature) -> u32 {
+        if let Some(id) = self.ids.get(ty) {
+            return *id;
+        }
+        encode(ty, &mut self.descriptors);
+        let id = self.ids.len() as u32;
+        self.ids.insert(ty.clone(), id);
+        id
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    /// The encoded table, as written in the memory of the module.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = (self.ids.len() as u32).to_le_bytes().to_vec();
+        bytes.extend_from_slice(&self.descriptors);
+        bytes
+    }
+}
+
+fn push_len(len: u32, out: &mut Vec<u8>) {
+    out.extend_from_slice(&len.to_le_bytes());
+}
+
+/// Appends the descriptor of `ty` to `out`.
+///
+/// Callables, trait references and their unions are principals in memory,
+/// and are described as such. `NoType` has the size of a `bool`.
+fn encode(ty: &TypeSignature, out: &mut Vec<u8>) {
+    match ty {
+        TypeSignature::IntType => out.push(INT),
+        TypeSignature::UIntType => out.push(UINT),
+        TypeSignature::BoolType | TypeSignature::NoType => out.push(BOOL),
+        TypeSignature::PrincipalType
+        | TypeSignature::CallableType(_)
+        | TypeSignature::ListUnionType(_)
+        | TypeSignature::TraitReferenceType(_) => out.push(PRINCIPAL),
+        TypeSignature::SequenceType(SequenceSubtype::BufferType(len)) => {
+            out.push(BUFFER);
+            push_len(u32::from(len), out);
+        }
+        TypeSignature::SequenceType(SequenceSubtype::StringType(StringSubtype::ASCII(len))) => {
+            out.push(STRING_ASCII);
+            push_len(u32::from(len), out);
+        }
+        TypeSignature::SequenceType(SequenceSubtype::StringType(StringSubtype::UTF8(len))) => {
+            out.push(STRING_UTF8);
+            push_len(u32::from(len), out);
+        }
+        TypeSignature::SequenceType(SequenceSubtype::ListType(list)) => {
+            out.push(LIST);
+            push_len(list.get_max_len(), out);
+            encode(list.get_list_item_type(), out);
+        }
+        TypeSignature::OptionalType(inner) => {
+            out.push(OPTIONAL);
+            encode(inner, out);
+        }
+        TypeSignature::ResponseType(types) => {
+            out.push(RESPONSE);
+            encode(&types.0, out);
+            encode(&types.1, out);
+        }
+        TypeSignature::TupleType(tuple) => {
+            out.push(TUPLE);
+            push_len(tuple.get_type_map().len() as u32, out);
+            for (name, ty) in tuple.get_type_map() {
+                out.push(name.len() as u8);
+                out.extend_from_slice(name.as_bytes());
+                encode(ty, out);
+            }
+        }
+    }
+}
+
+fn invalid(reason: &str) -> Error {
+    Error::Wasm(WasmError::WasmGeneratorError(format!(
+        "invalid type table: {reason}"
+    )))
+}
+
+/// Reads the next `n` bytes of `bytes`.
+fn take<'a>(bytes: &mut &'a [u8], n: usize) -> Result<&'a [u8], Error> {
+    if bytes.len() < n {
+        return Err(invalid("unexpected end"));
+    }
+    let (taken, rest) = bytes.split_at(n);
+    *bytes = rest;
+    Ok(taken)
+}
+
+fn take_len(bytes: &mut &[u8]) -> Result<u32, Error> {
+    let len = take(bytes, 4)?;
+    Ok(u32::from_le_bytes([len[0], len[1], len[2], len[3]]))
+}
+
+fn decode(bytes: &mut &[u8]) -> Result<TypeSignature, Error> {
+    let ty = match take(bytes, 1)?[0] {
+        INT => TypeSignature::IntType,
+        UINT => TypeSignature::UIntType,
+        BOOL => TypeSignature::BoolType,
+        PRINCIPAL => TypeSignature::PrincipalType,
+        BUFFER => TypeSignature::SequenceType(SequenceSubtype::BufferType(BufferLength::try_from(
+            take_len(bytes)?,
+        )?)),
+        STRING_ASCII => TypeSignature::SequenceType(SequenceSubtype::StringType(
+            StringSubtype::ASCII(BufferLength::try_from(take_len(bytes)?)?),
+        )),
+        STRING_UTF8 => TypeSignature::SequenceType(SequenceSubtype::StringType(
+            StringSubtype::UTF8(StringUTF8Length::try_from(take_len(bytes)?)?),
+        )),
+        LIST => {
+            let max_len = take_len(bytes)?;
+            TypeSignature::SequenceType(SequenceSubtype::ListType(ListTypeData::new_list(
+                decode(bytes)?,
+                max_len,
+            )?))
+        }
+        OPTIONAL => TypeSignature::OptionalType(Box::new(decode(bytes)?)),
+        RESPONSE => {
+            let ok = decode(bytes)?;
+            let err = decode(bytes)?;
+            TypeSignature::ResponseType(Box::new((ok, err)))
+        }
+        TUPLE => {
+            let count = take_len(bytes)?;
+            let mut fields = Vec::new();
+            for _ in 0..count {
+                let len = take(bytes, 1)?[0] as usize;
+                let name = String::from_utf8(take(bytes, len)?.to_vec())
+                    .map_err(|_| invalid("tuple field name is not UTF-8"))?;
+                fields.push((ClarityName::try_from(name)?, decode(bytes)?));
+            }
+            TypeSignature::TupleType(TupleTypeSignature::try_from(fields)?)
+        }
+        tag => return Err(invalid(&format!("unknown tag {tag}"))),
+    };
+    Ok(ty)
+}
+
+/// Decodes a table encoded by [TypeTable::to_bytes].
+fn decode_table(mut bytes: &[u8]) -> Result<Vec<TypeSignature>, Error> {
+    let count = take_len(&mut bytes)?;
+    let types = (0..count)
+        .map(|_| decode(&mut bytes))
+        .collect::<Result<Vec<_>, _>>()?;
+    if !bytes.is_empty() {
+        return Err(invalid("trailing bytes"));
+    }
+    Ok(types)
+}
+
+/// Returns the table encoded as `bytes`, decoding it only if it isn't cached.
+pub(crate) fn decoded_table(bytes: &[u8]) -> Result<DecodedTable, Error> {
+    DECODED_TABLES.with(|tables| {
+        tables
+            .borrow_mut()
+            .get_or_try_insert(bytes, || decode_table(bytes).map(Rc::new))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wasm_utils::signature_from_string;
+
+    fn parse(ty: &str) -> TypeSignature {
+        signature_from_string(
+            ty,
+            clarity::vm::ClarityVersion::latest(),
+            clarity::types::StacksEpochId::latest(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn table_round_trips() {
+        let types: Vec<_> = [
+            "int",
+            "uint",
+            "bool",
+            "principal",
+            "(buff 32)",
+            "(string-ascii 10)",
+            "(string-utf8 7)",
+            "(list 5 (optional uint))",
+            "(response (tuple (a int) (b (list 2 principal))) (string-ascii 3))",
+        ]
+        .into_iter()
+        .map(parse)
+        .collect();
+
+        let mut table = TypeTable::default();
+        let ids: Vec<_> = types.iter().map(|ty| table.id(ty)).collect();
+        assert_eq!(ids, (0..types.len() as u32).collect::<Vec<_>>());
+
+        assert_eq!(decode_table(&table.to_bytes()).unwrap(), types);
+    }
+
+    #[test]
+    fn types_are_interned_once() {
+        let mut table = TypeTable::default();
+        assert!(table.is_empty());
+        let int = table.id(&TypeSignature::IntType);
+        let bool = table.id(&TypeSignature::BoolType);
+        assert_eq!(table.id(&TypeSignature::IntType), int);
+        assert_ne!(int, bool);
+        // Tag of each type, after the number of types.
+        assert_eq!(table.to_bytes(), [2, 0, 0, 0, INT, BOOL]);
+    }
+
+    #[test]
+    fn invalid_tables_are_rejected() {
+        assert!(decode_table(&[]).is_err());
+        assert!(decode_table(&[1, 0, 0, 0]).is_err());
+        assert!(decode_table(&[1, 0, 0, 0, 42]).is_err());
+        assert!(decode_table(&[1, 0, 0, 0, INT, INT]).is_err());
+    }
+
+    #[test]
+    fn decoded_tables_are_cached_by_encoding() {
+        let ints = [1, 0, 0, 0, INT];
+        let bools = [1, 0, 0, 0, BOOL];
+
+        let first = decoded_table(&ints).unwrap();
+        assert!(Rc::ptr_eq(&first, &decoded_table(&ints).unwrap()));
+        assert_eq!(
+            *decoded_table(&bools).unwrap(),
+            vec![TypeSignature::BoolType]
+        );
+    }
+}
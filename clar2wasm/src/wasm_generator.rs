@@ -6,6 +6,7 @@ use std::rc::Rc;
 
 use clarity::vm::analysis::ContractAnalysis;
 use clarity::vm::diagnostic::DiagnosableError;
+use clarity::vm::representations::Span;
 use clarity::vm::types::signatures::{CallableSubtype, StringUTF8Length, BUFF_1};
 use clarity::vm::types::{
     ASCIIData, CharType, FixedFunction, FunctionType, ListTypeData, PrincipalData, SequenceData,
@@ -22,10 +23,12 @@ use walrus::{
 };
 
 use crate::cost::{ChargeContext, WordCharge};
+use crate::coverage::{CoverageInstrumentation, Probe};
 use crate::error_mapping::ErrorMap;
+use crate::type_table::TypeTable;
 use crate::wasm_utils::{
-    check_argument_count, get_type_in_memory_size, get_type_size, signature_from_string,
-    trait_identifier_as_bytes, ArgumentCountCheck, PRINCIPAL_BYTES_MAX,
+    check_argument_count, get_type_in_memory_size, get_type_size, trait_identifier_as_bytes,
+    ArgumentCountCheck, PRINCIPAL_BYTES_MAX,
 };
 use crate::{check_args, debug_msg, words};
 
@@ -52,6 +55,8 @@ pub struct WasmGenerator {
     pub(crate) early_return_block_id: Option<InstrSeqId>,
     /// The type of the current function.
     pub(crate) current_function_type: Option<FixedFunction>,
+    /// The name of the current function, `None` at the top level.
+    pub(crate) current_function_name: Option<ClarityName>,
     /// The types of defined data-vars
     pub(crate) datavars_types: HashMap<ClarityName, TypeSignature>,
     /// The types of (key, value) in defined maps
@@ -62,6 +67,18 @@ pub struct WasmGenerator {
     pub(crate) used_traits: HashMap<TraitIdentifier, (u32, u32)>,
     /// The names of defined functions
     pub(crate) defined_functions: HashSet<String>,
+    /// The bodies of the private functions inlined in sequence loops, see
+    /// [crate::inlining].
+    pub(crate) inlinable_functions: HashMap<ClarityName, SymbolicExpression>,
+    /// The functions being inlined in the current function, from the
+    /// outermost.
+    pub(crate) inlined_functions: Vec<ClarityName>,
+    /// The global holding the frames of the inlined functions, added with the
+    /// first inlined call.
+    pub(crate) inlined_frames: Option<GlobalId>,
+    /// The types of the values read by the host without a type, see
+    /// [crate::type_table].
+    pub(crate) type_table: TypeTable,
 
     /// The locals for the current function.
     pub(crate) bindings: Bindings,
@@ -69,11 +86,20 @@ pub struct WasmGenerator {
     /// Emits cost tracking code if set.
     pub(crate) cost_context: Option<ChargeContext>,
 
+    /// Emits coverage counters if set.
+    pub(crate) coverage: Option<CoverageInstrumentation>,
+
+    /// Uses the bulk-memory instructions if set, see [crate::bulk_memory].
+    pub(crate) bulk_memory: bool,
+
+    /// Grows the memory on demand if set, see [crate::dynamic_memory].
+    pub(crate) dynamic_memory: bool,
+
     /// Size of the current function's stack frame.
     frame_size: i32,
     /// Size of the maximum extra work space required by the stdlib functions
     /// to be available on the stack.
-    max_work_space: u32,
+    pub(crate) max_work_space: u32,
     local_pool: Rc<RefCell<HashMap<ValType, Vec<LocalId>>>>,
 }
 
@@ -124,6 +150,42 @@ pub enum GeneratorError {
     InternalError(String),
     TypeError(String),
     ArgumentCountMismatch,
+    /// An error raised while lowering the expression at `span`, the innermost
+    /// one with a location in the source.
+    Located {
+        error: Box<GeneratorError>,
+        span: Span,
+        /// The word or function called by the expression, if it is a call.
+        word: Option<ClarityName>,
+    },
+}
+
+impl GeneratorError {
+    /// Attaches the location of `expr` to the error, unless it already has one
+    /// or `expr` was not parsed from the source.
+    pub fn located(self, expr: &SymbolicExpression) -> Self {
+        if matches!(self, GeneratorError::Located { .. }) || expr.span.start_line == 0 {
+            return self;
+        }
+        let word = expr
+            .match_list()
+            .and_then(|list| list.first())
+            .and_then(SymbolicExpression::match_atom)
+            .cloned();
+        GeneratorError::Located {
+            error: Box::new(self),
+            span: expr.span.clone(),
+            word,
+        }
+    }
+
+    /// The location of the error in the source, if known.
+    pub fn span(&self) -> Option<&Span> {
+        match self {
+            GeneratorError::Located { span, .. } => Some(span),
+            _ => None,
+        }
+    }
 }
 
 pub enum FunctionKind {
@@ -139,6 +201,12 @@ impl DiagnosableError for GeneratorError {
             GeneratorError::InternalError(msg) => format!("Internal error: {msg}"),
             GeneratorError::TypeError(msg) => format!("Type error: {msg}"),
             GeneratorError::ArgumentCountMismatch => "Argument count mismatch".to_string(),
+            GeneratorError::Located {
+                error,
+                word: Some(word),
+                ..
+            } => format!("{} (while lowering `{word}`)", error.message()),
+            GeneratorError::Located { error, .. } => error.message(),
         }
     }
 
@@ -334,8 +402,12 @@ impl WasmGenerator {
             constants: HashMap::new(),
             bindings: Bindings::new(),
             cost_context: None,
+            coverage: None,
+            bulk_memory: true,
+            dynamic_memory: false,
             early_return_block_id: None,
             current_function_type: None,
+            current_function_name: None,
             frame_size: 0,
             max_work_space: 0,
             datavars_types: HashMap::new(),
@@ -344,6 +416,10 @@ impl WasmGenerator {
             nft_types: HashMap::new(),
             used_traits: HashMap::new(),
             defined_functions: HashSet::new(),
+            inlinable_functions: HashMap::new(),
+            inlined_functions: Vec::new(),
+            inlined_frames: None,
+            type_table: TypeTable::default(),
         })
     }
 
@@ -369,8 +445,14 @@ impl WasmGenerator {
             .next()
             .ok_or_else(|| GeneratorError::InternalError("No Memory found".to_owned()))?;
 
-        let total_memory_bytes =
-            self.literal_memory_end + (self.frame_size as u32) + self.max_work_space;
+        // A module growing its memory on demand allocates its stack frames
+        // when they are needed.
+        let frame_size = if self.dynamic_memory {
+            0
+        } else {
+            self.frame_size as u32
+        };
+        let total_memory_bytes = self.literal_memory_end + frame_size + self.max_work_space;
         let pages_required = total_memory_bytes / (64 * 1024);
         let remainder = total_memory_bytes % (64 * 1024);
 
@@ -379,6 +461,22 @@ impl WasmGenerator {
         Ok(())
     }
 
+    /// Writes the type table in the literal memory, and points the
+    /// `type-table-offset` and `type-table-length` globals to it.
+    fn write_type_table(&mut self) -> Result<(), GeneratorError> {
+        if self.type_table.is_empty() {
+            return Ok(());
+        }
+        let (offset, length) = self.add_bytes_literal(&self.type_table.to_bytes())?;
+        for (name, value) in [("type-table-offset", offset), ("type-table-length", length)] {
+            let global = get_global(&self.module, name)?;
+            self.module.globals.get_mut(global).kind = walrus::GlobalKind::Local(
+                walrus::InitExpr::Value(walrus::ir::Value::I32(value as i32)),
+            );
+        }
+        Ok(())
+    }
+
     pub fn generate(mut self) -> Result<Module, GeneratorError> {
         let expressions = std::mem::take(&mut self.contract_analysis.expressions);
 
@@ -410,6 +508,14 @@ impl WasmGenerator {
         let top_level = current_function.finish(vec![], &mut self.module.funcs);
         self.module.exports.add(".top-level", top_level);
 
+        self.finish_coverage();
+        if !self.bulk_memory {
+            self.lower_bulk_memory()?;
+        }
+        if self.dynamic_memory {
+            self.grow_memory_on_demand()?;
+        }
+        self.write_type_table()?;
         self.set_memory_pages()?;
 
         // Update the initial value of the stack-pointer to point beyond the
@@ -438,12 +544,16 @@ impl WasmGenerator {
     ) -> Result<(), GeneratorError> {
         match &expr.expr {
             SymbolicExpressionType::Atom(name) => self.visit_atom(builder, expr, name),
-            SymbolicExpressionType::List(exprs) => self.traverse_list(builder, expr, exprs),
+            SymbolicExpressionType::List(exprs) => {
+                self.count_expression(builder, expr)?;
+                self.traverse_list(builder, expr, exprs)
+            }
             SymbolicExpressionType::LiteralValue(value) => {
                 self.visit_literal_value(builder, expr, value)
             }
             _ => Ok(()),
         }
+        .map_err(|e| e.located(expr))
     }
 
     fn traverse_list(
@@ -563,6 +673,7 @@ impl WasmGenerator {
         };
 
         self.current_function_type = Some(function_type.clone());
+        self.current_function_name = Some(name.clone());
 
         // Call the host interface to save this function
         // Arguments are kind (already pushed) and name (offset, length)
@@ -664,8 +775,9 @@ impl WasmGenerator {
         // Restore the top-level locals map.
         self.bindings = top_level_locals;
 
-        // Reset the return type and early block to None
+        // Reset the return type, name and early block to None
         self.current_function_type = None;
+        self.current_function_name = None;
         self.early_return_block_id = None;
 
         Ok(func_builder.finish(param_locals, &mut self.module.funcs))
@@ -690,31 +802,14 @@ impl WasmGenerator {
                 let (val_offset, _) = self.create_call_stack_local(builder, ty, false, true);
                 self.write_to_memory(builder, val_offset, 0, ty)?;
 
-                let serialized_ty = self.type_for_serialization(ty).to_string();
-
-                // Validate serialized type
-                signature_from_string(
-                    &serialized_ty,
-                    self.contract_analysis.clarity_version,
-                    self.contract_analysis.epoch,
-                )
-                .map_err(|e| {
-                    GeneratorError::TypeError(format!("type cannot be deserialized: {e:?}"))
-                })?;
-
-                let (type_ser_offset, type_ser_len) =
-                    self.add_clarity_string_literal(&CharType::ASCII(ASCIIData {
-                        data: serialized_ty.into_bytes(),
-                    }))?;
+                let type_id = self.type_table.id(ty);
 
                 // Set runtime error globals
                 builder
                     .local_get(val_offset)
                     .global_set(get_global(&self.module, "runtime-error-value-offset")?)
-                    .i32_const(type_ser_offset as i32)
-                    .global_set(get_global(&self.module, "runtime-error-type-ser-offset")?)
-                    .i32_const(type_ser_len as i32)
-                    .global_set(get_global(&self.module, "runtime-error-type-ser-len")?)
+                    .i32_const(type_id as i32)
+                    .global_set(get_global(&self.module, "runtime-error-type-id")?)
                     .i32_const(runtime_error as i32)
                     .call(self.func_by_name("stdlib.runtime-error"));
             }
@@ -805,104 +900,70 @@ impl WasmGenerator {
         Ok(())
     }
 
+    /// Adds `data` to the literal memory, unless `entry` was already added,
+    /// and returns its offset and length.
+    ///
+    /// Literals are never written to, so all the literals with the same
+    /// contents, whatever they represent, share the same memory.
+    fn add_literal_entry(
+        &mut self,
+        entry: LiteralMemoryEntry,
+        data: Vec<u8>,
+    ) -> Result<(u32, u32), GeneratorError> {
+        let len = data.len() as u32;
+        if let Some(offset) = self.literal_memory_offset.get(&entry) {
+            return Ok((*offset, len));
+        }
+
+        let memory = self.get_memory()?;
+        let offset = self.literal_memory_end;
+        self.module.data.add(
+            DataKind::Active(ActiveData {
+                memory,
+                location: walrus::ActiveDataLocation::Absolute(offset),
+            }),
+            data,
+        );
+        self.literal_memory_end += len;
+
+        self.literal_memory_offset.insert(entry, offset);
+
+        Ok((offset, len))
+    }
+
     /// Adds a new string literal into the memory, and returns the offset and length.
     pub(crate) fn add_clarity_string_literal(
         &mut self,
         s: &CharType,
     ) -> Result<(u32, u32), GeneratorError> {
-        // If this string has already been saved in the literal memory,
-        // just return the offset and length.
-        let (data, entry) = match s {
-            CharType::ASCII(s) => {
-                let entry = LiteralMemoryEntry::Ascii(s.to_string());
-                if let Some(offset) = self.literal_memory_offset.get(&entry) {
-                    return Ok((*offset, s.data.len() as u32));
-                }
-                (s.data.clone(), entry)
-            }
+        let (entry, data) = match s {
+            CharType::ASCII(s) => (LiteralMemoryEntry::Ascii(s.to_string()), s.data.clone()),
             CharType::UTF8(u) => {
                 let data_str = String::from_utf8(u.data.iter().flatten().cloned().collect())
                     .map_err(|_e| {
                         GeneratorError::InternalError("Invalid UTF-8 sequence".to_owned())
                     })?;
-                let entry = LiteralMemoryEntry::Utf8(data_str.clone());
-                if let Some(offset) = self.literal_memory_offset.get(&entry) {
-                    return Ok((*offset, u.data.len() as u32 * 4));
-                }
                 // Convert the string into 4-byte big-endian unicode scalar values.
                 let data = data_str
                     .chars()
                     .flat_map(|c| (c as u32).to_be_bytes())
                     .collect();
-                (data, entry)
+                (LiteralMemoryEntry::Utf8(data_str), data)
             }
         };
-        let memory = self.get_memory()?;
-        let offset = self.literal_memory_end;
-        let len = data.len() as u32;
-        self.module.data.add(
-            DataKind::Active(ActiveData {
-                memory,
-                location: walrus::ActiveDataLocation::Absolute(offset),
-            }),
-            data,
-        );
-        self.literal_memory_end += len;
-
-        // Save the offset in the literal memory for this string
-        self.literal_memory_offset.insert(entry, offset);
-
-        Ok((offset, len))
+        self.add_literal_entry(entry, data)
     }
 
     /// Adds a new string literal into the memory for an identifier
     pub(crate) fn add_string_literal(&mut self, name: &str) -> Result<(u32, u32), GeneratorError> {
-        // If this identifier has already been saved in the literal memory,
-        // just return the offset and length.
-        let entry = LiteralMemoryEntry::Ascii(name.to_string());
-        if let Some(offset) = self.literal_memory_offset.get(&entry) {
-            return Ok((*offset, name.len() as u32));
-        }
-
-        let memory = self.get_memory()?;
-        let offset = self.literal_memory_end;
-        let len = name.len() as u32;
-        self.module.data.add(
-            DataKind::Active(ActiveData {
-                memory,
-                location: walrus::ActiveDataLocation::Absolute(offset),
-            }),
+        self.add_literal_entry(
+            LiteralMemoryEntry::Ascii(name.to_string()),
             name.as_bytes().to_vec(),
-        );
-        self.literal_memory_end += name.len() as u32;
-
-        // Save the offset in the literal memory for this identifier
-        self.literal_memory_offset.insert(entry, offset);
-
-        Ok((offset, len))
+        )
     }
 
     pub(crate) fn add_bytes_literal(&mut self, bytes: &[u8]) -> Result<(u32, u32), GeneratorError> {
-        let entry = LiteralMemoryEntry::Bytes(bytes.into());
-        if let Some(offset) = self.literal_memory_offset.get(&entry) {
-            return Ok((*offset, bytes.len() as u32));
-        }
-
-        let memory = self.get_memory()?;
-        let offset = self.literal_memory_end;
-        let len = bytes.len() as u32;
-        self.module.data.add(
-            DataKind::Active(ActiveData {
-                memory,
-                location: walrus::ActiveDataLocation::Absolute(offset),
-            }),
-            bytes.to_vec(),
-        );
-        self.literal_memory_end += len;
-
-        self.literal_memory_offset.insert(entry, offset);
-
-        Ok((offset, len))
+        self.add_literal_entry(LiteralMemoryEntry::Bytes(bytes.into()), bytes.to_vec())
     }
 
     /// Adds a serialized [TraitIdentifier] to the wasm memory.
@@ -964,25 +1025,24 @@ impl WasmGenerator {
                 )))
             }
         };
-        let memory = self.get_memory()?;
-        let offset = self.literal_memory_end;
-        let len = data.len() as u32;
-        self.module.data.add(
-            DataKind::Active(ActiveData {
-                memory,
-                location: walrus::ActiveDataLocation::Absolute(offset),
-            }),
-            data.clone(),
-        );
-        self.literal_memory_end += data.len() as u32;
-
-        Ok((offset, len))
+        self.add_bytes_literal(&data)
     }
 
     pub(crate) fn block_from_expr(
         &mut self,
         builder: &mut InstrSeqBuilder,
         expr: &SymbolicExpression,
+    ) -> Result<InstrSeqId, GeneratorError> {
+        self.branch_block_from_expr(builder, expr, None)
+    }
+
+    /// Like [WasmGenerator::block_from_expr], counting the executions of the
+    /// block as `branch` if coverage is enabled.
+    pub(crate) fn branch_block_from_expr(
+        &mut self,
+        builder: &mut InstrSeqBuilder,
+        expr: &SymbolicExpression,
+        branch: Option<Probe>,
     ) -> Result<InstrSeqId, GeneratorError> {
         let return_type = clar2wasm_ty(self.get_expr_type(expr).ok_or_else(|| {
             GeneratorError::TypeError("Expression results must be typed".to_owned())
@@ -993,6 +1053,7 @@ impl WasmGenerator {
             &[],
             &return_type,
         ));
+        self.count_branch(&mut block, branch)?;
         self.traverse_expr(&mut block, expr)?;
 
         Ok(block.id())
@@ -1021,6 +1082,19 @@ impl WasmGenerator {
             (false, false) => unreachable!("must include either repr or value"),
         };
 
+        (self.create_call_stack_buffer(builder, size), size)
+    }
+
+    /// Reserve `size` bytes on the call stack, adjusting the stack pointer and
+    /// tracking the current function's frame size accordingly.
+    ///
+    /// Returns a local which is a pointer to the beginning of the allocated
+    /// stack space.
+    pub(crate) fn create_call_stack_buffer(
+        &mut self,
+        builder: &mut InstrSeqBuilder,
+        size: i32,
+    ) -> LocalId {
         // Save the offset (current stack pointer) into a local
         let offset = self.module.locals.add(ValType::I32);
         builder
@@ -1044,7 +1118,28 @@ impl WasmGenerator {
         // [  ]
         self.frame_size += size;
 
-        (offset, size)
+        offset
+    }
+
+    /// Return a local pointing to a scratch buffer of `size` bytes in the
+    /// work space, `reserved` bytes past the stack pointer. The stack pointer
+    /// is left as is, so the buffer is shared by all the expressions using the
+    /// work space, and must be read before `reserved` bytes are pushed on the
+    /// call stack.
+    pub(crate) fn work_space_buffer(
+        &mut self,
+        builder: &mut InstrSeqBuilder,
+        reserved: i32,
+        size: i32,
+    ) -> LocalId {
+        let offset = self.module.locals.add(ValType::I32);
+        builder
+            .global_get(self.stack_pointer)
+            .i32_const(reserved)
+            .binop(BinaryOp::I32Add)
+            .local_set(offset);
+        self.ensure_work_space((reserved + size) as u32);
+        offset
     }
 
     pub(crate) fn borrow_local(&mut self, ty: ValType) -> BorrowedLocal {
@@ -1829,13 +1924,17 @@ impl WasmGenerator {
                     .binop(BinaryOp::I32Add)
                     .local_set(copy_offset);
 
+                // elements without in-memory parts are copied with the list
+                let elem_ty = ltd.get_list_item_type();
+                if !has_in_memory_type(elem_ty) {
+                    return Ok(());
+                }
+
                 // now we will iterate through the list elements, copy the in-memory parts and update the pointers
                 let copy_loop = {
                     let mut loop_ = builder.dangling_instr_seq(None);
                     let loop_id = loop_.id();
 
-                    let elem_ty = ltd.get_list_item_type();
-
                     let size = self.read_from_memory(&mut loop_, *offset, 0, elem_ty)?;
                     let elem_locals = self.save_to_locals(&mut loop_, elem_ty, true);
 
@@ -2182,7 +2281,7 @@ impl WasmGenerator {
 }
 
 /// Returns true if a composed type has an inner in-memory type.
-fn has_in_memory_type(ty: &TypeSignature) -> bool {
+pub(crate) fn has_in_memory_type(ty: &TypeSignature) -> bool {
     match ty {
         TypeSignature::OptionalType(opt) => has_in_memory_type(opt),
         TypeSignature::ResponseType(resp) => {
@@ -2240,16 +2339,20 @@ mod tests {
     use clarity::vm::analysis::AnalysisDatabase;
     use clarity::vm::costs::LimitedCostTracker;
     use clarity::vm::database::MemoryBackingStore;
+    use clarity::vm::diagnostic::DiagnosableError;
     use clarity::vm::errors::{CheckErrors, Error};
+    use clarity::vm::representations::Span;
     use clarity::vm::types::{QualifiedContractIdentifier, StandardPrincipalData, TupleData};
-    use clarity::vm::{ClarityVersion, Value};
+    use clarity::vm::{ClarityVersion, SymbolicExpression, Value};
     use walrus::Module;
 
+    use super::GeneratorError;
     // Tests that don't relate to specific words
     use crate::{
         compile,
         tools::{crosscheck, evaluate},
         wasm_generator::END_OF_STANDARD_DATA,
+        CompileError,
     };
 
     #[test]
@@ -2480,4 +2583,110 @@ mod tests {
             );
         }
     }
+
+    fn compile_snippet(snippet: &str) -> Result<crate::CompileResult, CompileError> {
+        compile(
+            snippet,
+            &QualifiedContractIdentifier::new(StandardPrincipalData::transient(), ("tmp").into()),
+            LimitedCostTracker::new_free(),
+            ClarityVersion::Clarity2,
+            StacksEpochId::Epoch25,
+            &mut AnalysisDatabase::new(&mut MemoryBackingStore::new()),
+            false,
+        )
+    }
+
+    fn literal_data_size(snippet: &str) -> usize {
+        let module = compile_snippet(snippet).unwrap().module;
+        module.data.iter().map(|d| d.value.len()).sum()
+    }
+
+    fn initial_stack_pointer(snippet: &str) -> i32 {
+        let module = compile_snippet(snippet).unwrap().module;
+        let stack_pointer = super::get_global(&module, "stack-pointer").unwrap();
+        match module.globals.get(stack_pointer).kind {
+            walrus::GlobalKind::Local(walrus::InitExpr::Value(walrus::ir::Value::I32(sp))) => sp,
+            _ => panic!("the stack pointer should be initialized with a constant"),
+        }
+    }
+
+    #[test]
+    fn equal_literals_share_memory() {
+        let principal = "'ST1PQHQKV0RJXZFY1DGX8MNSNYVE3VGZJSRTPGZGM.contract";
+        assert_eq!(
+            literal_data_size(&format!("(is-eq {principal} tx-sender)")),
+            literal_data_size(&format!("(is-eq {principal} {principal} tx-sender)"))
+        );
+        // Equal constants share their contents and the reference to them.
+        assert_eq!(
+            literal_data_size(r#"(define-constant a "hello") (define-constant b "world")"#),
+            literal_data_size(r#"(define-constant a "hello") (define-constant b "hello")"#)
+                + "world".len()
+                + 8
+        );
+    }
+
+    #[test]
+    fn data_var_initial_values_are_not_kept_in_memory() {
+        // Only the name of the second variable is added to the literal memory.
+        assert_eq!(
+            initial_stack_pointer("(define-data-var a int 1) (define-data-var b int 2)"),
+            initial_stack_pointer("(define-data-var a int 1)") + 1
+        );
+    }
+
+    #[test]
+    fn generator_errors_keep_the_innermost_location() {
+        let span = |line| Span {
+            start_line: line,
+            start_column: 3,
+            end_line: line,
+            end_column: 12,
+        };
+        let mut inner = SymbolicExpression::list(vec![
+            SymbolicExpression::atom("fold".into()),
+            SymbolicExpression::atom("items".into()),
+        ]);
+        inner.span = span(2);
+        let mut outer = SymbolicExpression::list(vec![
+            SymbolicExpression::atom("begin".into()),
+            inner.clone(),
+        ]);
+        outer.span = span(1);
+
+        let error = GeneratorError::NotImplemented
+            .located(&inner)
+            .located(&outer);
+        assert_eq!(error.span(), Some(&span(2)));
+        assert_eq!(error.message(), "Not implemented (while lowering `fold`)");
+
+        // Expressions which were not parsed from the source have no location.
+        let synthetic = SymbolicExpression::atom("items".into());
+        assert_eq!(
+            GeneratorError::NotImplemented.located(&synthetic).span(),
+            None
+        );
+    }
+
+    #[test]
+    fn compile_errors_report_their_phase() {
+        let error = compile_snippet("(define-read-only (foo) u1").unwrap_err();
+        assert!(matches!(error, CompileError::Parse { .. }), "{error:?}");
+
+        let source = "(define-read-only (foo)\n  (+ u1 1))";
+        let error = compile_snippet(source).unwrap_err();
+        assert!(matches!(error, CompileError::Analysis { .. }), "{error:?}");
+        assert_eq!(error.phase(), "analysis");
+
+        let rendered = error.render(source);
+        assert!(
+            rendered.starts_with("analysis failed\nerror: "),
+            "{rendered}"
+        );
+        assert!(rendered.contains("2 |   (+ u1 1))\n"), "{rendered}");
+        assert!(
+            rendered.contains("\n  | ") && rendered.contains('^'),
+            "{rendered}"
+        );
+    }
 }
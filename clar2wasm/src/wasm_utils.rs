@@ -2,23 +2,29 @@
 
 use clarity::vm::analysis::CheckErrors;
 use clarity::vm::ast::{build_ast_with_rules, ASTRules};
-use clarity::vm::contexts::GlobalContext;
+use clarity::vm::callables::DefinedFunction;
+use clarity::vm::contexts::{Environment, GlobalContext};
+use clarity::vm::costs::cost_functions::ClarityCostFunction;
+use clarity::vm::costs::{runtime_cost, CostErrors};
 use clarity::vm::errors::{Error, WasmError};
-use clarity::vm::types::signatures::CallableSubtype;
 use clarity::vm::types::{
-    ASCIIData, BuffData, BufferLength, CallableData, CharType, ListData, OptionalData,
-    PrincipalData, QualifiedContractIdentifier, ResponseData, SequenceData, SequenceSubtype,
-    SequencedValue, StandardPrincipalData, StringSubtype, TraitIdentifier, TupleData,
-    TypeSignature,
+    ASCIIData, BuffData, CallableData, CharType, ListData, OptionalData, PrincipalData,
+    QualifiedContractIdentifier, ResponseData, SequenceData, SequenceSubtype,
+    StandardPrincipalData, StringSubtype, TraitIdentifier, TupleData, TypeSignature,
 };
 use clarity::vm::{CallStack, ClarityName, ClarityVersion, ContractContext, ContractName, Value};
 use stacks_common::types::StacksEpochId;
 use walrus::{GlobalId, InstrSeqBuilder};
-use wasmtime::{AsContextMut, Linker, Memory, Module, Store, Val, ValType};
 
+use crate::coverage::collect_coverage;
+use crate::dynamic_memory::SET_STACK_POINTER;
 use crate::error_mapping::{self, ErrorMap};
-use crate::initialize::ClarityWasmContext;
+use crate::initialize::{ClarityWasmContext, ExecutionOptions};
+use crate::limits::ResourceLimits;
 use crate::linker::link_host_functions;
+use crate::marshal::{Layout, ValueBuilder};
+pub use crate::runtime::placeholder_for_type;
+use crate::runtime::{self, AsContextMut, Linker, Memory, Module, Store, Val, ValType};
 use crate::wasm_generator::{GeneratorError, WasmGenerator};
 use crate::CostLinker;
 
@@ -94,248 +100,14 @@ pub fn wasm_to_clarity_value(
     buffer: &[Val],
     memory: Memory,
     store: &mut impl AsContextMut,
-    epoch: StacksEpochId,
 ) -> Result<(Option<Value>, usize), Error> {
-    match type_sig {
-        TypeSignature::IntType => {
-            let lower = buffer[value_index]
-                .i64()
-                .ok_or(Error::Wasm(WasmError::ValueTypeMismatch))?;
-            let upper = buffer[value_index + 1]
-                .i64()
-                .ok_or(Error::Wasm(WasmError::ValueTypeMismatch))?;
-            Ok((
-                Some(Value::Int(((upper as i128) << 64) | (lower as u64) as i128)),
-                2,
-            ))
-        }
-        TypeSignature::UIntType => {
-            let lower = buffer[value_index]
-                .i64()
-                .ok_or(Error::Wasm(WasmError::ValueTypeMismatch))?;
-            let upper = buffer[value_index + 1]
-                .i64()
-                .ok_or(Error::Wasm(WasmError::ValueTypeMismatch))?;
-            Ok((
-                Some(Value::UInt(
-                    ((upper as u128) << 64) | (lower as u64) as u128,
-                )),
-                2,
-            ))
-        }
-        TypeSignature::BoolType => Ok((
-            Some(Value::Bool(
-                buffer[value_index]
-                    .i32()
-                    .ok_or(Error::Wasm(WasmError::ValueTypeMismatch))?
-                    != 0,
-            )),
-            1,
-        )),
-        TypeSignature::OptionalType(optional) => {
-            let value_types = clar2wasm_ty(optional);
-            Ok((
-                if buffer[value_index]
-                    .i32()
-                    .ok_or(Error::Wasm(WasmError::ValueTypeMismatch))?
-                    == 1
-                {
-                    let (value, _) = wasm_to_clarity_value(
-                        optional,
-                        value_index + 1,
-                        buffer,
-                        memory,
-                        store,
-                        epoch,
-                    )?;
-                    Some(Value::some(value.ok_or(Error::Unchecked(
-                        CheckErrors::CouldNotDetermineType,
-                    ))?)?)
-                } else {
-                    Some(Value::none())
-                },
-                1 + value_types.len(),
-            ))
-        }
-        TypeSignature::ResponseType(response) => {
-            let ok_types = clar2wasm_ty(&response.0);
-            let err_types = clar2wasm_ty(&response.1);
-
-            Ok((
-                if buffer[value_index]
-                    .i32()
-                    .ok_or(Error::Wasm(WasmError::ValueTypeMismatch))?
-                    == 1
-                {
-                    let (ok, _) = wasm_to_clarity_value(
-                        &response.0,
-                        value_index + 1,
-                        buffer,
-                        memory,
-                        store,
-                        epoch,
-                    )?;
-                    Some(Value::okay(ok.ok_or(Error::Unchecked(
-                        CheckErrors::CouldNotDetermineResponseOkType,
-                    ))?)?)
-                } else {
-                    let (err, _) = wasm_to_clarity_value(
-                        &response.1,
-                        value_index + 1 + ok_types.len(),
-                        buffer,
-                        memory,
-                        store,
-                        epoch,
-                    )?;
-                    Some(Value::error(err.ok_or(Error::Unchecked(
-                        CheckErrors::CouldNotDetermineResponseErrType,
-                    ))?)?)
-                },
-                1 + ok_types.len() + err_types.len(),
-            ))
-        }
-        TypeSignature::SequenceType(SequenceSubtype::StringType(StringSubtype::ASCII(_))) => {
-            let offset = buffer[value_index]
-                .i32()
-                .ok_or(Error::Wasm(WasmError::ValueTypeMismatch))?;
-            let length = buffer[value_index + 1]
-                .i32()
-                .ok_or(Error::Wasm(WasmError::ValueTypeMismatch))?;
-            let mut string_buffer: Vec<u8> = vec![0; length as usize];
-            memory
-                .read(store, offset as usize, &mut string_buffer)
-                .map_err(|e| Error::Wasm(WasmError::UnableToReadMemory(e.into())))?;
-            Ok((Some(Value::string_ascii_from_bytes(string_buffer)?), 2))
-        }
-        // A `NoType` will be a dummy value that should not be used.
-        TypeSignature::NoType => Ok((None, 1)),
-        TypeSignature::SequenceType(SequenceSubtype::StringType(StringSubtype::UTF8(_))) => {
-            let offset = buffer[value_index]
-                .i32()
-                .ok_or(Error::Wasm(WasmError::ValueTypeMismatch))?;
-            let length = buffer[value_index + 1]
-                .i32()
-                .ok_or(Error::Wasm(WasmError::ValueTypeMismatch))?;
-            let mut string_buffer: Vec<u8> = vec![0; length as usize];
-            memory
-                .read(store, offset as usize, &mut string_buffer)
-                .map_err(|e| Error::Wasm(WasmError::UnableToReadMemory(e.into())))?;
-            Ok((
-                Some(Value::string_utf8_from_unicode_scalars(string_buffer)?),
-                2,
-            ))
-        }
-        TypeSignature::SequenceType(SequenceSubtype::BufferType(_buffer_length)) => {
-            let offset = buffer[value_index]
-                .i32()
-                .ok_or(Error::Wasm(WasmError::ValueTypeMismatch))?;
-            let length = buffer[value_index + 1]
-                .i32()
-                .ok_or(Error::Wasm(WasmError::ValueTypeMismatch))?;
-            let mut buff: Vec<u8> = vec![0; length as usize];
-            memory
-                .read(store, offset as usize, &mut buff)
-                .map_err(|e| Error::Wasm(WasmError::UnableToReadMemory(e.into())))?;
-            Ok((Some(Value::buff_from(buff)?), 2))
-        }
-        TypeSignature::SequenceType(SequenceSubtype::ListType(_)) => {
-            let offset = buffer[value_index]
-                .i32()
-                .ok_or(Error::Wasm(WasmError::ValueTypeMismatch))?;
-            let length = buffer[value_index + 1]
-                .i32()
-                .ok_or(Error::Wasm(WasmError::ValueTypeMismatch))?;
-
-            let value = read_from_wasm(memory, store, type_sig, offset, length, epoch)?;
-            Ok((Some(value), 2))
-        }
-        TypeSignature::PrincipalType
-        | TypeSignature::CallableType(_)
-        | TypeSignature::TraitReferenceType(_) => {
-            let offset = buffer[value_index]
-                .i32()
-                .ok_or(Error::Wasm(WasmError::ValueTypeMismatch))?;
-            let mut principal_bytes: [u8; 1 + PRINCIPAL_HASH_BYTES] = [0; 1 + PRINCIPAL_HASH_BYTES];
-            memory
-                .read(
-                    store.as_context_mut(),
-                    offset as usize,
-                    &mut principal_bytes,
-                )
-                .map_err(|e| Error::Wasm(WasmError::UnableToReadMemory(e.into())))?;
-            let mut buffer: [u8; CONTRACT_NAME_LENGTH_BYTES] = [0; CONTRACT_NAME_LENGTH_BYTES];
-            memory
-                .read(store.as_context_mut(), offset as usize + 21, &mut buffer)
-                .map_err(|e| Error::Wasm(WasmError::UnableToReadMemory(e.into())))?;
-            let standard = StandardPrincipalData::new(
-                principal_bytes[0],
-                principal_bytes[1..].try_into().map_err(|_| {
-                    Error::Wasm(WasmError::WasmGeneratorError(
-                        "Could not decode principal".into(),
-                    ))
-                })?,
-            )?;
-            let contract_name_length = buffer[0] as usize;
-            if contract_name_length == 0 {
-                Ok((Some(Value::Principal(PrincipalData::Standard(standard))), 2))
-            } else {
-                let mut contract_name: Vec<u8> = vec![0; contract_name_length];
-                memory
-                    .read(
-                        store,
-                        (offset + STANDARD_PRINCIPAL_BYTES as i32) as usize,
-                        &mut contract_name,
-                    )
-                    .map_err(|e| Error::Wasm(WasmError::UnableToReadMemory(e.into())))?;
-                let qualified_id = QualifiedContractIdentifier {
-                    issuer: standard,
-                    name: ContractName::try_from(
-                        String::from_utf8(contract_name)
-                            .map_err(|e| Error::Wasm(WasmError::UnableToReadIdentifier(e)))?,
-                    )?,
-                };
-                Ok((
-                    Some(
-                        if let TypeSignature::CallableType(CallableSubtype::Trait(
-                            trait_identifier,
-                        )) = type_sig
-                        {
-                            Value::CallableContract(CallableData {
-                                contract_identifier: qualified_id,
-                                trait_identifier: Some(trait_identifier.clone()),
-                            })
-                        } else {
-                            Value::Principal(PrincipalData::Contract(qualified_id))
-                        },
-                    ),
-                    2,
-                ))
-            }
-        }
-        TypeSignature::TupleType(t) => {
-            let mut index = value_index;
-            let mut data_map = Vec::new();
-            for (name, ty) in t.get_type_map() {
-                let (value, increment) =
-                    wasm_to_clarity_value(ty, index, buffer, memory, store, epoch)?;
-                data_map.push((
-                    name.clone(),
-                    value.ok_or_else(|| {
-                        Error::Unchecked(CheckErrors::BadTupleConstruction(format!(
-                            "Failed to convert Wasm value into Clarity value for field `{}`",
-                            name
-                        )))
-                    })?,
-                ));
-                index += increment;
-            }
-            let tuple = TupleData::from_data(data_map)?;
-            Ok((Some(tuple.into()), index - value_index))
-        }
-        TypeSignature::ListUnionType(_subtypes) => {
-            Err(Error::Wasm(WasmError::InvalidListUnionTypeInValue))
-        }
-    }
+    let layout = Layout::of(type_sig)?;
+    let value = layout.visit_vals(
+        &buffer[value_index..],
+        memory.data(store.as_context()),
+        &mut ValueBuilder,
+    )?;
+    Ok((value, layout.vals()))
 }
 
 /// Read a value from the Wasm memory at `offset` with `length` given the
@@ -348,18 +120,9 @@ pub fn read_from_wasm_indirect(
     memory: Memory,
     store: &mut impl AsContextMut,
     ty: &TypeSignature,
-    mut offset: i32,
-    epoch: StacksEpochId,
+    offset: i32,
 ) -> Result<Value, Error> {
-    let mut length = get_type_size(ty);
-
-    // For in-memory types, first read the offset and length from the memory,
-    // then read the actual value.
-    if is_in_memory_type(ty) {
-        (offset, length) = read_indirect_offset_and_length(memory, store, offset)?;
-    };
-
-    read_from_wasm(memory, store, ty, offset, length, epoch)
+    Layout::of(ty)?.visit(memory.data(store.as_context()), offset, &mut ValueBuilder)
 }
 
 /// Read a value from the Wasm memory at `offset` with `length`, given the
@@ -370,235 +133,13 @@ pub fn read_from_wasm(
     ty: &TypeSignature,
     offset: i32,
     length: i32,
-    epoch: StacksEpochId,
 ) -> Result<Value, Error> {
-    match ty {
-        TypeSignature::UIntType => {
-            debug_assert!(
-                length == 16,
-                "expected uint length to be 16 bytes, found {length}"
-            );
-            let mut buffer: [u8; 8] = [0; 8];
-            memory
-                .read(store.as_context_mut(), offset as usize, &mut buffer)
-                .map_err(|e| Error::Wasm(WasmError::Runtime(e.into())))?;
-            let low = u64::from_le_bytes(buffer) as u128;
-            memory
-                .read(store, (offset + 8) as usize, &mut buffer)
-                .map_err(|e| Error::Wasm(WasmError::Runtime(e.into())))?;
-            let high = u64::from_le_bytes(buffer) as u128;
-            Ok(Value::UInt((high << 64) | low))
-        }
-        TypeSignature::IntType => {
-            debug_assert!(
-                length == 16,
-                "expected int length to be 16 bytes, found {length}"
-            );
-            let mut buffer: [u8; 8] = [0; 8];
-            memory
-                .read(store.as_context_mut(), offset as usize, &mut buffer)
-                .map_err(|e| Error::Wasm(WasmError::Runtime(e.into())))?;
-            let low = u64::from_le_bytes(buffer) as u128;
-            memory
-                .read(store, (offset + 8) as usize, &mut buffer)
-                .map_err(|e| Error::Wasm(WasmError::Runtime(e.into())))?;
-            let high = u64::from_le_bytes(buffer) as u128;
-            Ok(Value::Int(((high << 64) | low) as i128))
-        }
-        TypeSignature::SequenceType(SequenceSubtype::StringType(StringSubtype::ASCII(
-            type_length,
-        ))) => {
-            debug_assert!(
-                type_length >= &BufferLength::try_from(length as u32)?,
-                "expected string length to be less than the type length"
-            );
-            let mut buffer: Vec<u8> = vec![0; length as usize];
-            memory
-                .read(store, offset as usize, &mut buffer)
-                .map_err(|e| Error::Wasm(WasmError::Runtime(e.into())))?;
-            Value::string_ascii_from_bytes(buffer)
-        }
-        TypeSignature::SequenceType(SequenceSubtype::StringType(StringSubtype::UTF8(_s))) => {
-            let mut buffer: Vec<u8> = vec![0; length as usize];
-            memory
-                .read(store, offset as usize, &mut buffer)
-                .map_err(|e| Error::Wasm(WasmError::Runtime(e.into())))?;
-            Value::string_utf8_from_unicode_scalars(buffer)
-        }
-        TypeSignature::PrincipalType
-        | TypeSignature::CallableType(_)
-        | TypeSignature::TraitReferenceType(_) => {
-            debug_assert!(
-                length >= STANDARD_PRINCIPAL_BYTES as i32 && length <= PRINCIPAL_BYTES_MAX as i32
-            );
-            let mut current_offset = offset as usize;
-            let mut version: [u8; PRINCIPAL_VERSION_BYTES] = [0; PRINCIPAL_VERSION_BYTES];
-            let mut hash: [u8; PRINCIPAL_HASH_BYTES] = [0; PRINCIPAL_HASH_BYTES];
-            memory
-                .read(store.as_context_mut(), current_offset, &mut version)
-                .map_err(|e| Error::Wasm(WasmError::Runtime(e.into())))?;
-            current_offset += PRINCIPAL_VERSION_BYTES;
-            memory
-                .read(store.as_context_mut(), current_offset, &mut hash)
-                .map_err(|e| Error::Wasm(WasmError::Runtime(e.into())))?;
-            current_offset += PRINCIPAL_HASH_BYTES;
-            let principal = StandardPrincipalData::new(version[0], hash)?;
-            let mut contract_length_buf: [u8; CONTRACT_NAME_LENGTH_BYTES] =
-                [0; CONTRACT_NAME_LENGTH_BYTES];
-            memory
-                .read(
-                    store.as_context_mut(),
-                    current_offset,
-                    &mut contract_length_buf,
-                )
-                .map_err(|e| Error::Wasm(WasmError::Runtime(e.into())))?;
-            current_offset += CONTRACT_NAME_LENGTH_BYTES;
-            let contract_length = contract_length_buf[0];
-            if contract_length == 0 {
-                Ok(Value::Principal(principal.into()))
-            } else {
-                let mut contract_name: Vec<u8> = vec![0; contract_length as usize];
-                memory
-                    .read(store, current_offset, &mut contract_name)
-                    .map_err(|e| Error::Wasm(WasmError::Runtime(e.into())))?;
-                let contract_name = String::from_utf8(contract_name)
-                    .map_err(|e| Error::Wasm(WasmError::Runtime(e.into())))?;
-                let qualified_id = QualifiedContractIdentifier {
-                    issuer: principal,
-                    name: ContractName::try_from(contract_name)?,
-                };
-                Ok(
-                    if let TypeSignature::CallableType(CallableSubtype::Trait(trait_identifier)) =
-                        ty
-                    {
-                        Value::CallableContract(CallableData {
-                            contract_identifier: qualified_id,
-                            trait_identifier: Some(trait_identifier.clone()),
-                        })
-                    } else {
-                        Value::Principal(PrincipalData::Contract(qualified_id))
-                    },
-                )
-            }
-        }
-        TypeSignature::SequenceType(SequenceSubtype::BufferType(_b)) => {
-            let mut buffer: Vec<u8> = vec![0; length as usize];
-            memory
-                .read(store, offset as usize, &mut buffer)
-                .map_err(|e| Error::Wasm(WasmError::Runtime(e.into())))?;
-            Value::buff_from(buffer)
-        }
-        TypeSignature::SequenceType(SequenceSubtype::ListType(list)) => {
-            let elem_ty = list.get_list_item_type();
-            let elem_length = get_type_size(elem_ty);
-            let end = offset + length;
-            let mut buffer: Vec<Value> = Vec::new();
-            let mut current_offset = offset;
-            while current_offset < end {
-                let elem = read_from_wasm_indirect(memory, store, elem_ty, current_offset, epoch)?;
-                buffer.push(elem);
-                current_offset += elem_length;
-            }
-            Value::cons_list_unsanitized(buffer)
-        }
-        TypeSignature::BoolType => {
-            debug_assert!(
-                length == 4,
-                "expected bool length to be 4 bytes, found {length}"
-            );
-            let mut buffer: [u8; 4] = [0; 4];
-            memory
-                .read(store.as_context_mut(), offset as usize, &mut buffer)
-                .map_err(|e| Error::Wasm(WasmError::Runtime(e.into())))?;
-            let bool_val = u32::from_le_bytes(buffer);
-            Ok(Value::Bool(bool_val != 0))
-        }
-        TypeSignature::TupleType(type_sig) => {
-            let mut data = Vec::new();
-            let mut current_offset = offset;
-            for (field_key, field_ty) in type_sig.get_type_map() {
-                let field_length = get_type_size(field_ty);
-                let field_value =
-                    read_from_wasm_indirect(memory, store, field_ty, current_offset, epoch)?;
-                data.push((field_key.clone(), field_value));
-                current_offset += field_length;
-            }
-            Ok(Value::Tuple(TupleData::from_data(data)?))
-        }
-        TypeSignature::ResponseType(response_type) => {
-            let mut current_offset = offset;
-
-            // Read the indicator
-            let mut indicator_bytes = [0u8; 4];
-            memory
-                .read(
-                    store.as_context_mut(),
-                    current_offset as usize,
-                    &mut indicator_bytes,
-                )
-                .map_err(|e| Error::Wasm(WasmError::Runtime(e.into())))?;
-            current_offset += 4;
-            let indicator = i32::from_le_bytes(indicator_bytes);
-
-            // Read the ok or err value, depending on the indicator
-            match indicator {
-                0 => {
-                    current_offset += get_type_size(&response_type.0);
-                    let err_value = read_from_wasm_indirect(
-                        memory,
-                        store,
-                        &response_type.1,
-                        current_offset,
-                        epoch,
-                    )?;
-                    Value::error(err_value).map_err(|_| Error::Wasm(WasmError::ValueTypeMismatch))
-                }
-                1 => {
-                    let ok_value = read_from_wasm_indirect(
-                        memory,
-                        store,
-                        &response_type.0,
-                        current_offset,
-                        epoch,
-                    )?;
-                    Value::okay(ok_value).map_err(|_| Error::Wasm(WasmError::ValueTypeMismatch))
-                }
-                _ => Err(Error::Wasm(WasmError::InvalidIndicator(indicator))),
-            }
-        }
-        TypeSignature::OptionalType(type_sig) => {
-            let mut current_offset = offset;
-
-            // Read the indicator
-            let mut indicator_bytes = [0u8; 4];
-            memory
-                .read(
-                    store.as_context_mut(),
-                    current_offset as usize,
-                    &mut indicator_bytes,
-                )
-                .map_err(|e| Error::Wasm(WasmError::Runtime(e.into())))?;
-            current_offset += 4;
-            let indicator = i32::from_le_bytes(indicator_bytes);
-
-            match indicator {
-                0 => Ok(Value::none()),
-                1 => {
-                    let value =
-                        read_from_wasm_indirect(memory, store, type_sig, current_offset, epoch)?;
-                    Ok(
-                        Value::some(value)
-                            .map_err(|_| Error::Wasm(WasmError::ValueTypeMismatch))?,
-                    )
-                }
-                _ => Err(Error::Wasm(WasmError::InvalidIndicator(indicator))),
-            }
-        }
-        TypeSignature::NoType => Err(Error::Wasm(WasmError::InvalidNoTypeInValue)),
-        TypeSignature::ListUnionType(_subtypes) => {
-            Err(Error::Wasm(WasmError::InvalidListUnionTypeInValue))
-        }
-    }
+    Layout::of(ty)?.visit_content(
+        memory.data(store.as_context()),
+        offset,
+        length,
+        &mut ValueBuilder,
+    )
 }
 
 pub fn read_indirect_offset_and_length(
@@ -715,19 +256,6 @@ pub fn get_type_in_memory_size(ty: &TypeSignature, include_repr: bool) -> i32 {
     }
 }
 
-/// Push a placeholder value for Wasm type `ty` onto the data stack.
-pub fn placeholder_for_type(ty: ValType) -> Val {
-    match ty {
-        ValType::I32 => Val::I32(0),
-        ValType::I64 => Val::I64(0),
-        ValType::F32 => Val::F32(0),
-        ValType::F64 => Val::F64(0),
-        ValType::V128 => Val::V128(0.into()),
-        ValType::ExternRef => Val::ExternRef(None),
-        ValType::FuncRef => Val::FuncRef(None),
-    }
-}
-
 /// Write a value to the Wasm memory at `offset` given the provided Clarity
 /// `TypeSignature`.
 ///
@@ -744,349 +272,13 @@ pub fn write_to_wasm(
     value: &Value,
     include_repr: bool,
 ) -> Result<(i32, i32), Error> {
-    match ty {
-        TypeSignature::IntType => {
-            let mut buffer: [u8; 8] = [0; 8];
-            let i = value_as_i128(value)?;
-            let high = (i >> 64) as u64;
-            let low = (i & 0xffff_ffff_ffff_ffff) as u64;
-            buffer.copy_from_slice(&low.to_le_bytes());
-            memory
-                .write(&mut store, offset as usize, &buffer)
-                .map_err(|e| Error::Wasm(WasmError::UnableToWriteMemory(e.into())))?;
-            buffer.copy_from_slice(&high.to_le_bytes());
-            memory
-                .write(&mut store, (offset + 8) as usize, &buffer)
-                .map_err(|e| Error::Wasm(WasmError::UnableToWriteMemory(e.into())))?;
-            Ok((16, 0))
-        }
-        TypeSignature::UIntType => {
-            let mut buffer: [u8; 8] = [0; 8];
-            let i = value_as_u128(value)?;
-            let high = (i >> 64) as u64;
-            let low = (i & 0xffff_ffff_ffff_ffff) as u64;
-            buffer.copy_from_slice(&low.to_le_bytes());
-            memory
-                .write(&mut store, offset as usize, &buffer)
-                .map_err(|e| Error::Wasm(WasmError::UnableToWriteMemory(e.into())))?;
-            buffer.copy_from_slice(&high.to_le_bytes());
-            memory
-                .write(&mut store, (offset + 8) as usize, &buffer)
-                .map_err(|e| Error::Wasm(WasmError::UnableToWriteMemory(e.into())))?;
-            Ok((16, 0))
-        }
-        TypeSignature::SequenceType(SequenceSubtype::BufferType(_length)) => {
-            let buffdata = value_as_buffer(value.clone())?;
-            let mut written = 0;
-            let mut in_mem_written = 0;
-
-            // Write the value to `in_mem_offset`
-            memory
-                .write(
-                    &mut store,
-                    (in_mem_offset + in_mem_written) as usize,
-                    &buffdata.data,
-                )
-                .map_err(|e| Error::Wasm(WasmError::UnableToWriteMemory(e.into())))?;
-            in_mem_written += buffdata.data.len() as i32;
-
-            if include_repr {
-                // Write the representation (offset and length) of the value to
-                // `offset`.
-                let offset_buffer = in_mem_offset.to_le_bytes();
-                memory
-                    .write(&mut store, (offset) as usize, &offset_buffer)
-                    .map_err(|e| Error::Wasm(WasmError::UnableToWriteMemory(e.into())))?;
-                written += 4;
-                let len_buffer = in_mem_written.to_le_bytes();
-                memory
-                    .write(&mut store, (offset + written) as usize, &len_buffer)
-                    .map_err(|e| Error::Wasm(WasmError::UnableToWriteMemory(e.into())))?;
-                written += 4;
-            }
-
-            Ok((written, in_mem_written))
-        }
-        TypeSignature::SequenceType(SequenceSubtype::StringType(string_subtype)) => {
-            let string = match string_subtype {
-                StringSubtype::ASCII(_length) => value_as_string_ascii(value.clone())?.data,
-                StringSubtype::UTF8(_length) => {
-                    let Value::Sequence(SequenceData::String(CharType::UTF8(utf8_data))) = value
-                    else {
-                        unreachable!("A string-utf8 type should contain a string-utf8 value")
-                    };
-                    String::from_utf8(utf8_data.items().iter().flatten().copied().collect())
-                        .map_err(|e| Error::Wasm(WasmError::UnableToWriteMemory(e.into())))?
-                        .chars()
-                        .flat_map(|c| (c as u32).to_be_bytes())
-                        .collect()
-                }
-            };
-            let mut written = 0;
-            let mut in_mem_written = 0;
-
-            // Write the value to `in_mem_offset`
-            memory
-                .write(
-                    &mut store,
-                    (in_mem_offset + in_mem_written) as usize,
-                    &string,
-                )
-                .map_err(|e| Error::Wasm(WasmError::UnableToWriteMemory(e.into())))?;
-            in_mem_written += string.len() as i32;
-
-            if include_repr {
-                // Write the representation (offset and length) of the value to
-                // `offset`.
-                let offset_buffer = in_mem_offset.to_le_bytes();
-                memory
-                    .write(&mut store, (offset) as usize, &offset_buffer)
-                    .map_err(|e| Error::Wasm(WasmError::UnableToWriteMemory(e.into())))?;
-                written += 4;
-                let len_buffer = in_mem_written.to_le_bytes();
-                memory
-                    .write(&mut store, (offset + written) as usize, &len_buffer)
-                    .map_err(|e| Error::Wasm(WasmError::UnableToWriteMemory(e.into())))?;
-                written += 4;
-            }
-
-            Ok((written, in_mem_written))
-        }
-        TypeSignature::SequenceType(SequenceSubtype::ListType(list)) => {
-            let mut written = 0;
-            let list_data = value_as_list(value)?;
-            let elem_ty = list.get_list_item_type();
-            // For a list, the values are written to the memory at
-            // `in_mem_offset`, and the representation (offset and length) is
-            // written to the memory at `offset`. The `in_mem_offset` for the
-            // list elements should be after their representations.
-            let val_offset = in_mem_offset;
-            let val_in_mem_offset =
-                in_mem_offset + list_data.data.len() as i32 * get_type_size(elem_ty);
-            let mut val_written = 0;
-            let mut val_in_mem_written = 0;
-            for elem in &list_data.data {
-                let (new_written, new_in_mem_written) = write_to_wasm(
-                    store.as_context_mut(),
-                    memory,
-                    elem_ty,
-                    val_offset + val_written,
-                    val_in_mem_offset + val_in_mem_written,
-                    elem,
-                    true,
-                )?;
-                val_written += new_written;
-                val_in_mem_written += new_in_mem_written;
-            }
-
-            if include_repr {
-                // Write the representation (offset and length) of the value to
-                // `offset`.
-                let offset_buffer = in_mem_offset.to_le_bytes();
-                memory
-                    .write(&mut store, (offset) as usize, &offset_buffer)
-                    .map_err(|e| Error::Wasm(WasmError::UnableToWriteMemory(e.into())))?;
-                written += 4;
-                let len_buffer = val_written.to_le_bytes();
-                memory
-                    .write(&mut store, (offset + 4) as usize, &len_buffer)
-                    .map_err(|e| Error::Wasm(WasmError::UnableToWriteMemory(e.into())))?;
-                written += 4;
-            }
-
-            Ok((written, val_written + val_in_mem_written))
-        }
-        TypeSignature::ResponseType(inner_types) => {
-            let mut written = 0;
-            let mut in_mem_written = 0;
-            let res = value_as_response(value)?;
-            let indicator = if res.committed { 1i32 } else { 0i32 };
-            let indicator_bytes = indicator.to_le_bytes();
-            memory
-                .write(&mut store, (offset) as usize, &indicator_bytes)
-                .map_err(|e| Error::Wasm(WasmError::UnableToWriteMemory(e.into())))?;
-            written += 4;
-
-            if res.committed {
-                let (new_written, new_in_mem_written) = write_to_wasm(
-                    store,
-                    memory,
-                    &inner_types.0,
-                    offset + written,
-                    in_mem_offset,
-                    &res.data,
-                    true,
-                )?;
-                written += new_written;
-                in_mem_written += new_in_mem_written;
-
-                // Skip space for the err value
-                written += get_type_size(&inner_types.1);
-            } else {
-                // Skip space for the ok value
-                written += get_type_size(&inner_types.0);
-
-                let (new_written, new_in_mem_written) = write_to_wasm(
-                    store,
-                    memory,
-                    &inner_types.1,
-                    offset + written,
-                    in_mem_offset,
-                    &res.data,
-                    true,
-                )?;
-                written += new_written;
-                in_mem_written += new_in_mem_written;
-            }
-            Ok((written, in_mem_written))
-        }
-        TypeSignature::BoolType => {
-            let bool_val = value_as_bool(value)?;
-            let val = if bool_val { 1u32 } else { 0u32 };
-            let val_bytes = val.to_le_bytes();
-            memory
-                .write(&mut store, (offset) as usize, &val_bytes)
-                .map_err(|e| Error::Wasm(WasmError::UnableToWriteMemory(e.into())))?;
-            Ok((4, 0))
-        }
-        TypeSignature::NoType => {
-            let val_bytes = [0u8; 4];
-            memory
-                .write(&mut store, (offset) as usize, &val_bytes)
-                .map_err(|e| Error::Wasm(WasmError::UnableToWriteMemory(e.into())))?;
-            Ok((4, 0))
-        }
-        TypeSignature::OptionalType(inner_ty) => {
-            let mut written = 0;
-            let mut in_mem_written = 0;
-            let opt_data = value_as_optional(value)?;
-            let indicator = if opt_data.data.is_some() { 1i32 } else { 0i32 };
-            let indicator_bytes = indicator.to_le_bytes();
-            memory
-                .write(&mut store, (offset) as usize, &indicator_bytes)
-                .map_err(|e| Error::Wasm(WasmError::UnableToWriteMemory(e.into())))?;
-            written += 4;
-            if let Some(inner) = opt_data.data.as_ref() {
-                let (new_written, new_in_mem_written) = write_to_wasm(
-                    store,
-                    memory,
-                    inner_ty,
-                    offset + written,
-                    in_mem_offset,
-                    inner,
-                    true,
-                )?;
-                written += new_written;
-                in_mem_written += new_in_mem_written;
-            } else {
-                written += get_type_size(inner_ty);
-            }
-            Ok((written, in_mem_written))
-        }
-        TypeSignature::PrincipalType
-        | TypeSignature::CallableType(_)
-        | TypeSignature::TraitReferenceType(_) => {
-            let principal = value_as_principal(value)?;
-            let (standard, contract_name) = match principal {
-                PrincipalData::Standard(s) => (s, ""),
-                PrincipalData::Contract(contract_identifier) => (
-                    &contract_identifier.issuer,
-                    contract_identifier.name.as_str(),
-                ),
-            };
-            let mut written = 0;
-            let mut in_mem_written = 0;
-
-            // Write the value to in_mem_offset
-            memory
-                .write(
-                    &mut store,
-                    (in_mem_offset + in_mem_written) as usize,
-                    &[standard.version()],
-                )
-                .map_err(|e| Error::Wasm(WasmError::UnableToWriteMemory(e.into())))?;
-            in_mem_written += 1;
-            memory
-                .write(
-                    &mut store,
-                    (in_mem_offset + in_mem_written) as usize,
-                    &standard.1,
-                )
-                .map_err(|e| Error::Wasm(WasmError::UnableToWriteMemory(e.into())))?;
-            in_mem_written += standard.1.len() as i32;
-            if !contract_name.is_empty() {
-                let len_buffer = [contract_name.len() as u8];
-                memory
-                    .write(
-                        &mut store,
-                        (in_mem_offset + in_mem_written) as usize,
-                        &len_buffer,
-                    )
-                    .map_err(|e| Error::Wasm(WasmError::UnableToWriteMemory(e.into())))?;
-                in_mem_written += 1;
-                let bytes = contract_name.as_bytes();
-                memory
-                    .write(&mut store, (in_mem_offset + in_mem_written) as usize, bytes)
-                    .map_err(|e| Error::Wasm(WasmError::UnableToWriteMemory(e.into())))?;
-                in_mem_written += bytes.len() as i32;
-            } else {
-                let len_buffer = [0u8];
-                memory
-                    .write(
-                        &mut store,
-                        (in_mem_offset + in_mem_written) as usize,
-                        &len_buffer,
-                    )
-                    .map_err(|e| Error::Wasm(WasmError::UnableToWriteMemory(e.into())))?;
-                in_mem_written += 1;
-            }
-
-            if include_repr {
-                // Write the representation (offset and length of the value) to the
-                // offset
-                let offset_buffer = in_mem_offset.to_le_bytes();
-                memory
-                    .write(&mut store, (offset) as usize, &offset_buffer)
-                    .map_err(|e| Error::Wasm(WasmError::UnableToWriteMemory(e.into())))?;
-                written += 4;
-                let len_buffer = in_mem_written.to_le_bytes();
-                memory
-                    .write(&mut store, (offset + written) as usize, &len_buffer)
-                    .map_err(|e| Error::Wasm(WasmError::UnableToWriteMemory(e.into())))?;
-                written += 4;
-            }
-
-            Ok((written, in_mem_written))
-        }
-        TypeSignature::TupleType(type_sig) => {
-            let tuple_data = value_as_tuple(value)?;
-            let mut written = 0;
-            let mut in_mem_written = 0;
-
-            for (key, val_type) in type_sig.get_type_map() {
-                let val = tuple_data
-                    .data_map
-                    .get(key)
-                    .ok_or(Error::Wasm(WasmError::ValueTypeMismatch))?;
-                let (new_written, new_in_mem_written) = write_to_wasm(
-                    store.as_context_mut(),
-                    memory,
-                    val_type,
-                    offset + written,
-                    in_mem_offset + in_mem_written,
-                    val,
-                    true,
-                )?;
-                written += new_written;
-                in_mem_written += new_in_mem_written;
-            }
-
-            Ok((written, in_mem_written))
-        }
-        TypeSignature::ListUnionType(_) => {
-            unreachable!("not a value type")
-        }
-    }
+    Layout::of(ty)?.write(
+        memory.data_mut(store.as_context_mut()),
+        offset,
+        in_mem_offset,
+        value,
+        include_repr,
+    )
 }
 
 pub fn value_as_bool(value: &Value) -> Result<bool, Error> {
@@ -1252,10 +444,98 @@ pub fn call_function<'a>(
     caller: Option<PrincipalData>,
     sponsor: Option<PrincipalData>,
 ) -> Result<Value, Error> {
-    let epoch = global_context.epoch_id;
-    let clarity_version = *contract_context.get_clarity_version();
-    let engine = global_context.engine.clone();
-    let context = ClarityWasmContext::new_run(
+    call_function_with_limits(
+        function_name,
+        args,
+        global_context,
+        contract_context,
+        call_stack,
+        sender,
+        caller,
+        sponsor,
+        ResourceLimits::default(),
+    )
+}
+
+/// Same as [call_function], but enforcing the given resource limits.
+#[allow(clippy::too_many_arguments)]
+pub fn call_function_with_limits<'a>(
+    function_name: &str,
+    args: &[Value],
+    global_context: &'a mut GlobalContext,
+    contract_context: &'a ContractContext,
+    call_stack: &'a mut CallStack,
+    sender: Option<PrincipalData>,
+    caller: Option<PrincipalData>,
+    sponsor: Option<PrincipalData>,
+    limits: ResourceLimits,
+) -> Result<Value, Error> {
+    call_function_with_options(
+        function_name,
+        args,
+        global_context,
+        contract_context,
+        call_stack,
+        sender,
+        caller,
+        sponsor,
+        ExecutionOptions::with_limits(limits)?,
+    )
+}
+
+/// Same as [call_function], but applying the given execution options.
+#[allow(clippy::too_many_arguments)]
+pub fn call_function_with_options<'a>(
+    function_name: &str,
+    args: &[Value],
+    global_context: &'a mut GlobalContext,
+    contract_context: &'a ContractContext,
+    call_stack: &'a mut CallStack,
+    sender: Option<PrincipalData>,
+    caller: Option<PrincipalData>,
+    sponsor: Option<PrincipalData>,
+    options: ExecutionOptions,
+) -> Result<Value, Error> {
+    // A read-only function runs in a read-only frame of the global context,
+    // so that the contracts it calls cannot write either.
+    let read_only = contract_context
+        .lookup_function(function_name)
+        .ok_or(CheckErrors::UndefinedFunction(function_name.to_string()))?
+        .is_read_only();
+    if read_only {
+        global_context.begin_read_only();
+    }
+    let result = run_function(
+        function_name,
+        args,
+        global_context,
+        contract_context,
+        call_stack,
+        sender,
+        caller,
+        sponsor,
+        options,
+    );
+    if read_only {
+        global_context.roll_back()?;
+    }
+    result
+}
+
+#[allow(clippy::too_many_arguments)]
+fn run_function<'a>(
+    function_name: &str,
+    args: &[Value],
+    global_context: &'a mut GlobalContext,
+    contract_context: &'a ContractContext,
+    call_stack: &'a mut CallStack,
+    sender: Option<PrincipalData>,
+    caller: Option<PrincipalData>,
+    sponsor: Option<PrincipalData>,
+    options: ExecutionOptions,
+) -> Result<Value, Error> {
+    let engine = runtime::engine(global_context, &options);
+    let mut context = ClarityWasmContext::new_run(
         global_context,
         contract_context,
         call_stack,
@@ -1264,6 +544,7 @@ pub fn call_function<'a>(
         sponsor,
         None,
     );
+    context.apply_options(options);
 
     let func_types = context
         .contract_context()
@@ -1276,6 +557,7 @@ pub fn call_function<'a>(
                 .map_err(|e| Error::Wasm(WasmError::UnableToLoadModule(e)))
         })?;
     let mut store = Store::new(&engine, context);
+    store.limiter(|context| &mut context.limiter);
     let mut linker = Linker::new(&engine);
 
     // Link in the host interface functions.
@@ -1284,9 +566,9 @@ pub fn call_function<'a>(
         .define_cost_globals(&mut store)
         .map_err(|e| Error::Wasm(WasmError::UnableToLoadModule(e)))?;
 
-    let instance = linker
-        .instantiate(&mut store, &module)
-        .map_err(|e| Error::Wasm(WasmError::UnableToLoadModule(e)))?;
+    let instance = linker.instantiate(&mut store, &module).map_err(|e| {
+        error_mapping::resolve_instantiation_error(e, &mut store.data_mut().limiter)
+    })?;
 
     // Call the specified function
     let func = instance
@@ -1299,7 +581,7 @@ pub fn call_function<'a>(
         .ok_or(Error::Wasm(WasmError::GlobalNotFound(
             "stack-pointer".to_string(),
         )))?;
-    let mut offset = stack_pointer
+    let offset = stack_pointer
         .get(&mut store)
         .i32()
         .ok_or(Error::Wasm(WasmError::ValueTypeMismatch))?;
@@ -1308,22 +590,24 @@ pub fn call_function<'a>(
         .get_memory(&mut store, "memory")
         .ok_or(Error::Wasm(WasmError::MemoryNotFound))?;
 
-    // Determine how much space is needed for arguments
-    let mut arg_size = 0;
-    for arg in func_types.get_arg_types() {
-        arg_size += get_type_in_memory_size(arg, false);
+    // A module growing its memory on demand sets its stack pointer with a
+    // function, which grows the memory if needed.
+    let set_stack_pointer = instance.get_func(&mut store, SET_STACK_POINTER);
+
+    // A module growing its memory on demand grows it once, to fit the
+    // arguments, before they are written.
+    if let Some(set_stack_pointer) = set_stack_pointer {
+        let end = offset as u64 + arguments_size(func_types.get_arg_types(), args)?;
+        let end = u32::try_from(end)
+            .map_err(|_| CostErrors::MemoryBalanceExceeded(end, u32::MAX as u64 + 1))?;
+        set_stack_pointer
+            .call(&mut store, &[Val::I32(end as i32)], &mut [])
+            .map_err(|e| error_mapping::resolve_error(e, instance, &mut store))?;
     }
-    let mut in_mem_offset = offset + arg_size;
 
-    // Convert the args into wasmtime values
-    let mut wasm_args = vec![];
-    for (arg, ty) in args.iter().zip(func_types.get_arg_types()) {
-        let (arg_vec, new_offset, new_in_mem_offset) =
-            pass_argument_to_wasm(memory, &mut store, ty, arg, offset, in_mem_offset)?;
-        wasm_args.extend(arg_vec);
-        offset = new_offset;
-        in_mem_offset = new_in_mem_offset;
-    }
+    // Convert the args into Wasm values
+    let (wasm_args, in_mem_offset) =
+        pass_arguments_to_wasm(memory, &mut store, func_types.get_arg_types(), args, offset)?;
 
     // Reserve stack space for the return value, if necessary.
     let return_type = func_types
@@ -1335,24 +619,209 @@ pub fn call_function<'a>(
 
     // Update the stack pointer after space is reserved for the arguments and
     // return values.
-    stack_pointer
-        .set(&mut store, Val::I32(offset))
-        .map_err(|e| Error::Wasm(WasmError::Runtime(e)))?;
+    match set_stack_pointer {
+        Some(set_stack_pointer) => set_stack_pointer
+            .call(&mut store, &[Val::I32(offset)], &mut [])
+            .map_err(|e| error_mapping::resolve_error(e, instance, &mut store))?,
+        None => stack_pointer
+            .set(&mut store, Val::I32(offset))
+            .map_err(|e| Error::Wasm(WasmError::Runtime(e)))?,
+    }
 
     // Call the function
-    func.call(&mut store, &wasm_args, &mut results)
-        .map_err(|e| {
-            error_mapping::resolve_error(e, instance, &mut store, &epoch, &clarity_version)
-        })?;
+    let call_result = func
+        .call(&mut store, &wasm_args, &mut results)
+        .map_err(|e| error_mapping::resolve_error(e, instance, &mut store));
+    // The counters are collected even if the call failed, but the error of the
+    // call takes precedence over a failure to collect them.
+    let coverage = collect_coverage(&instance, &mut store);
+    call_result?;
+    coverage?;
 
     // If the function returns a value, translate it into a Clarity `Value`
-    wasm_to_clarity_value(&return_type, 0, &results, memory, &mut &mut store, epoch)
+    wasm_to_clarity_value(&return_type, 0, &results, memory, &mut &mut store)
         .map(|(val, _offset)| val)
         .and_then(|option_value| {
             option_value.ok_or_else(|| Error::Wasm(WasmError::ExpectedReturnValue))
         })
 }
 
+/// Call a public or read-only function of another contract from a running
+/// contract, as `contract-call?` does.
+///
+/// A compiled callee runs with this crate's runtime, with the same execution
+/// options as the caller, and a callee without a Wasm module is interpreted.
+/// The call is a nested transaction, rolled back if the callee fails or
+/// returns an `err`. As in the interpreter, the load and application costs
+/// of the callee are charged here, and the special cases handler of the
+/// database (used by the PoX contracts) runs after a successful call.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn call_contract_from_wasm(
+    global_context: &mut GlobalContext,
+    contract_context: &ContractContext,
+    function: &DefinedFunction,
+    args: &[Value],
+    call_stack: &mut CallStack,
+    sender: Option<PrincipalData>,
+    caller: Option<PrincipalData>,
+    sponsor: Option<PrincipalData>,
+    options: ExecutionOptions,
+) -> Result<Value, Error> {
+    let contract_id = &contract_context.contract_identifier;
+    if !function.is_public() {
+        return Err(CheckErrors::NoSuchPublicFunction(
+            contract_id.to_string(),
+            function.get_name().to_string(),
+        )
+        .into());
+    }
+
+    let identifier = function.get_identifier();
+    if call_stack.contains(&identifier) {
+        return Err(CheckErrors::CircularReference(vec![identifier.to_string()]).into());
+    }
+
+    let contract_size = global_context.database.get_contract_size(contract_id)?;
+    runtime_cost(
+        ClarityCostFunction::LoadContract,
+        &mut global_context.cost_track,
+        contract_size,
+    )?;
+    global_context.add_memory(contract_size)?;
+
+    call_stack.insert(&identifier, true);
+    let result = if contract_context.with_wasm_module(|_| Ok(())).is_ok() {
+        call_compiled_function_as_transaction(
+            global_context,
+            contract_context,
+            function,
+            args,
+            call_stack,
+            sender.clone(),
+            caller,
+            sponsor.clone(),
+            options,
+        )
+    } else {
+        Environment::new(
+            global_context,
+            contract_context,
+            call_stack,
+            sender.clone(),
+            caller,
+            sponsor.clone(),
+        )
+        .execute_function_as_transaction(function, args, None, false)
+    };
+    let result = call_stack
+        .remove(&identifier, true)
+        .and(result)
+        .and_then(|value| {
+            if let Some(handler) = global_context.database.get_cc_special_cases_handler() {
+                handler(
+                    global_context,
+                    sender.as_ref(),
+                    sponsor.as_ref(),
+                    contract_id,
+                    function.get_name().as_str(),
+                    args,
+                    &value,
+                )?;
+            }
+            Ok(value)
+        });
+
+    global_context.drop_memory(contract_size)?;
+    result
+}
+
+/// Run a compiled public function in a nested transaction, charging the
+/// application costs of [DefinedFunction::execute_apply] first.
+#[allow(clippy::too_many_arguments)]
+fn call_compiled_function_as_transaction(
+    global_context: &mut GlobalContext,
+    contract_context: &ContractContext,
+    function: &DefinedFunction,
+    args: &[Value],
+    call_stack: &mut CallStack,
+    sender: Option<PrincipalData>,
+    caller: Option<PrincipalData>,
+    sponsor: Option<PrincipalData>,
+    options: ExecutionOptions,
+) -> Result<Value, Error> {
+    let arg_types = function.get_arg_types();
+    runtime_cost(
+        ClarityCostFunction::UserFunctionApplication,
+        &mut global_context.cost_track,
+        arg_types.len(),
+    )?;
+    for arg_type in arg_types {
+        runtime_cost(
+            ClarityCostFunction::InnerTypeCheckCost,
+            &mut global_context.cost_track,
+            arg_type.size()?,
+        )?;
+    }
+
+    global_context.begin();
+    let result = call_function_with_options(
+        &function.get_name(),
+        args,
+        global_context,
+        contract_context,
+        call_stack,
+        sender,
+        caller,
+        sponsor,
+        options,
+    );
+    match &result {
+        Ok(Value::Response(response)) if !response.committed => global_context.roll_back()?,
+        Ok(_) => global_context.commit().map(|_| ())?,
+        Err(_) => global_context.roll_back()?,
+    }
+    result
+}
+
+/// Upper bound of the number of bytes written in the memory by
+/// [pass_arguments_to_wasm] for `args`.
+fn arguments_size(arg_types: &[TypeSignature], args: &[Value]) -> Result<u64, Error> {
+    let mut size = 0;
+    for (ty, arg) in arg_types.iter().zip(args) {
+        size += get_type_in_memory_size(ty, false) as u64;
+        size += Layout::of(ty)?.content_size(arg) as u64;
+    }
+    Ok(size)
+}
+
+/// Convert the arguments of a function into Wasm `Val`s, writing them at
+/// `offset`, followed by the content of their in-memory values. Return the
+/// `Val`s and the offset following the arguments.
+fn pass_arguments_to_wasm(
+    memory: Memory,
+    mut store: impl AsContextMut,
+    arg_types: &[TypeSignature],
+    args: &[Value],
+    mut offset: i32,
+) -> Result<(Vec<Val>, i32), Error> {
+    // Determine how much space is needed for arguments
+    let mut arg_size = 0;
+    for arg in arg_types {
+        arg_size += get_type_in_memory_size(arg, false);
+    }
+    let mut in_mem_offset = offset + arg_size;
+
+    let mut wasm_args = vec![];
+    for (arg, ty) in args.iter().zip(arg_types) {
+        let (arg_vec, new_offset, new_in_mem_offset) =
+            pass_argument_to_wasm(memory, &mut store, ty, arg, offset, in_mem_offset)?;
+        wasm_args.extend(arg_vec);
+        offset = new_offset;
+        in_mem_offset = new_in_mem_offset;
+    }
+    Ok((wasm_args, in_mem_offset))
+}
+
 /// Convert a Clarity `Value` into one or more Wasm `Val`. If this value
 /// requires writing into the Wasm memory, write it to the provided `offset`.
 /// Return a vector of `Val`s that can be passed to a Wasm function, and the
@@ -1,12 +1,236 @@
 use clarity::vm::types::TypeSignature;
 use clarity::vm::ClarityName;
-use walrus::ValType;
+use walrus::ir::{BinaryOp, Const, Instr, UnaryOp, Value};
+use walrus::{InstrSeqBuilder, LocalId, ValType};
 
 use super::{SimpleWord, Word};
 use crate::cost::WordCharge;
 use crate::error_mapping::ErrorMap;
 use crate::wasm_generator::{GeneratorError, WasmGenerator};
 
+/// Half of a 128-bit operand, either saved in a local or known at compile
+/// time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Half {
+    Local(LocalId),
+    Const(i64),
+}
+
+impl Half {
+    fn push(self, builder: &mut InstrSeqBuilder) {
+        match self {
+            Half::Local(local) => builder.local_get(local),
+            Half::Const(value) => builder.i64_const(value),
+        };
+    }
+}
+
+/// A 128-bit operand of an inlined operation.
+#[derive(Debug, Clone, Copy)]
+pub(super) struct Int128 {
+    lo: Half,
+    hi: Half,
+}
+
+impl Int128 {
+    /// Saves the 128-bit integer on top of the stack to new locals.
+    fn save(generator: &mut WasmGenerator, builder: &mut InstrSeqBuilder) -> Self {
+        let lo = generator.module.locals.add(ValType::I64);
+        let hi = generator.module.locals.add(ValType::I64);
+        builder.local_set(hi).local_set(lo);
+        Self {
+            lo: Half::Local(lo),
+            hi: Half::Local(hi),
+        }
+    }
+}
+
+/// Takes the two 128-bit operands on top of the stack. When the second one
+/// was just pushed as constants, those are removed from the instructions and
+/// used directly.
+pub(super) fn take_operands(
+    generator: &mut WasmGenerator,
+    builder: &mut InstrSeqBuilder,
+) -> (Int128, Int128) {
+    let constant = match builder.instrs() {
+        [.., (
+            Instr::Const(Const {
+                value: Value::I64(lo),
+            }),
+            _,
+        ), (
+            Instr::Const(Const {
+                value: Value::I64(hi),
+            }),
+            _,
+        )] => Some((*lo, *hi)),
+        _ => None,
+    };
+    let b = match constant {
+        Some((lo, hi)) => {
+            let len = builder.instrs().len();
+            builder.instrs_mut().truncate(len - 2);
+            Int128 {
+                lo: Half::Const(lo),
+                hi: Half::Const(hi),
+            }
+        }
+        None => Int128::save(generator, builder),
+    };
+    let a = Int128::save(generator, builder);
+    (a, b)
+}
+
+/// Pushes the comparison of two 128-bit integers as an `i32`: the high halves
+/// are compared with `hi_op`, unless they are equal, in which case the low
+/// halves are compared with `lo_op`.
+pub(super) fn compare(
+    builder: &mut InstrSeqBuilder,
+    a: Int128,
+    b: Int128,
+    lo_op: BinaryOp,
+    hi_op: BinaryOp,
+) {
+    a.lo.push(builder);
+    b.lo.push(builder);
+    builder.binop(lo_op);
+    a.hi.push(builder);
+    b.hi.push(builder);
+    builder.binop(hi_op);
+    a.hi.push(builder);
+    b.hi.push(builder);
+    builder.binop(BinaryOp::I64Eq).select(None);
+}
+
+/// Raises `error` if the `i32` on top of the stack is not zero.
+fn trap_if(generator: &mut WasmGenerator, builder: &mut InstrSeqBuilder, error: ErrorMap) {
+    let runtime_error = generator.func_by_name("stdlib.runtime-error");
+    builder.if_else(
+        None,
+        |then| {
+            then.i32_const(error as i32).call(runtime_error);
+        },
+        |_| {},
+    );
+}
+
+/// Adds the two 128-bit integers on top of the stack inline, instead of
+/// calling `stdlib.add-int` or `stdlib.add-uint`.
+fn add_inline(generator: &mut WasmGenerator, builder: &mut InstrSeqBuilder, signed: bool) {
+    let (a, b) = take_operands(generator, builder);
+    let sum_lo = generator.module.locals.add(ValType::I64);
+    let sum_hi = generator.module.locals.add(ValType::I64);
+
+    a.lo.push(builder);
+    b.lo.push(builder);
+    builder.binop(BinaryOp::I64Add).local_tee(sum_lo);
+    // The low halves carry if their sum wrapped around.
+    a.lo.push(builder);
+    builder.binop(BinaryOp::I64LtU).unop(UnaryOp::I64ExtendUI32);
+    a.hi.push(builder);
+    builder.binop(BinaryOp::I64Add);
+    if b.hi != Half::Const(0) {
+        b.hi.push(builder);
+        builder.binop(BinaryOp::I64Add);
+    }
+    builder.local_set(sum_hi);
+
+    match b.hi {
+        // Only the carry is added to the high half of `a`, which overflows
+        // if it wraps around.
+        Half::Const(0) => {
+            builder.local_get(sum_hi);
+            a.hi.push(builder);
+            builder.binop(if signed {
+                BinaryOp::I64LtS
+            } else {
+                BinaryOp::I64LtU
+            });
+        }
+        // The operands have the same sign, and the sum the other one.
+        _ if signed => {
+            a.hi.push(builder);
+            builder.local_get(sum_hi).binop(BinaryOp::I64Xor);
+            b.hi.push(builder);
+            builder
+                .local_get(sum_hi)
+                .binop(BinaryOp::I64Xor)
+                .binop(BinaryOp::I64And)
+                .i64_const(0)
+                .binop(BinaryOp::I64LtS);
+        }
+        // The sum is smaller than `a`.
+        _ => {
+            let sum = Int128 {
+                lo: Half::Local(sum_lo),
+                hi: Half::Local(sum_hi),
+            };
+            compare(builder, sum, a, BinaryOp::I64LtU, BinaryOp::I64LtU);
+        }
+    }
+    trap_if(generator, builder, ErrorMap::ArithmeticOverflow);
+
+    builder.local_get(sum_lo).local_get(sum_hi);
+}
+
+/// Subtracts the two 128-bit integers on top of the stack inline, instead of
+/// calling `stdlib.sub-int` or `stdlib.sub-uint`.
+fn sub_inline(generator: &mut WasmGenerator, builder: &mut InstrSeqBuilder, signed: bool) {
+    let (a, b) = take_operands(generator, builder);
+    let diff_lo = generator.module.locals.add(ValType::I64);
+    let diff_hi = generator.module.locals.add(ValType::I64);
+
+    a.lo.push(builder);
+    b.lo.push(builder);
+    builder.binop(BinaryOp::I64Sub).local_set(diff_lo);
+    a.hi.push(builder);
+    if b.hi != Half::Const(0) {
+        b.hi.push(builder);
+        builder.binop(BinaryOp::I64Sub);
+    }
+    // The low halves borrow if `b` is the larger one.
+    a.lo.push(builder);
+    b.lo.push(builder);
+    builder
+        .binop(BinaryOp::I64LtU)
+        .unop(UnaryOp::I64ExtendUI32)
+        .binop(BinaryOp::I64Sub)
+        .local_set(diff_hi);
+
+    match b.hi {
+        // Only the borrow is subtracted from the high half of `a`, which
+        // underflows if it wraps around.
+        Half::Const(0) => {
+            builder.local_get(diff_hi);
+            a.hi.push(builder);
+            builder.binop(if signed {
+                BinaryOp::I64GtS
+            } else {
+                BinaryOp::I64GtU
+            });
+        }
+        // The operands have different signs, and the difference has the
+        // sign of `b`.
+        _ if signed => {
+            a.hi.push(builder);
+            b.hi.push(builder);
+            builder.binop(BinaryOp::I64Xor);
+            a.hi.push(builder);
+            builder
+                .local_get(diff_hi)
+                .binop(BinaryOp::I64Xor)
+                .binop(BinaryOp::I64And)
+                .i64_const(0)
+                .binop(BinaryOp::I64LtS);
+        }
+        // `a` is smaller than `b`.
+        _ => compare(builder, a, b, BinaryOp::I64LtU, BinaryOp::I64LtU),
+    }
+    trap_if(generator, builder, ErrorMap::ArithmeticUnderflow);
+
+    builder.local_get(diff_lo).local_get(diff_hi);
+}
+
 fn simple_typed_one_call(
     word: &impl Word,
     generator: &mut WasmGenerator,
@@ -50,17 +274,16 @@ impl SimpleWord for Add {
         return_type: &TypeSignature,
     ) -> Result<(), GeneratorError> {
         if arg_types.len() > 1 {
-            let type_suffix = match return_type {
-                TypeSignature::IntType => "int",
-                TypeSignature::UIntType => "uint",
+            let signed = match return_type {
+                TypeSignature::IntType => true,
+                TypeSignature::UIntType => false,
                 _ => {
                     return Err(GeneratorError::TypeError(
                         "invalid type for arithmetic".to_string(),
                     ));
                 }
             };
-            let func = generator.func_by_name(&format!("stdlib.add-{type_suffix}"));
-            builder.call(func);
+            add_inline(generator, builder, signed);
         }
         Ok(())
     }
@@ -83,9 +306,8 @@ impl SimpleWord for Sub {
         arg_types: &[TypeSignature],
         return_type: &TypeSignature,
     ) -> Result<(), GeneratorError> {
-        let func = match return_type {
+        match return_type {
             TypeSignature::IntType => {
-                let type_suffix = "int";
                 if arg_types.len() == 1 {
                     // Locals declaration.
                     let op_lo = generator.module.locals.add(ValType::I64);
@@ -98,17 +320,17 @@ impl SimpleWord for Sub {
                     builder.local_get(op_lo).local_get(op_hi);
                 }
 
-                generator.func_by_name(&format!("stdlib.sub-{type_suffix}"))
+                sub_inline(generator, builder, true);
             }
             TypeSignature::UIntType => {
-                let type_suffix = "uint";
                 if arg_types.len() == 1 {
                     // unary 'uint' subtraction:
                     // throws an underflow runtime error.
                     builder.i32_const(ErrorMap::ArithmeticUnderflow as i32);
-                    generator.func_by_name("stdlib.runtime-error")
+                    let func = generator.func_by_name("stdlib.runtime-error");
+                    builder.call(func);
                 } else {
-                    generator.func_by_name(&format!("stdlib.sub-{type_suffix}"))
+                    sub_inline(generator, builder, false);
                 }
             }
             _ => {
@@ -116,9 +338,7 @@ impl SimpleWord for Sub {
                     "invalid type for arithmetic".to_string(),
                 ));
             }
-        };
-
-        builder.call(func);
+        }
 
         Ok(())
     }
@@ -503,4 +723,79 @@ mod tests {
             Ok(Some(Value::Int(1076))),
         );
     }
+
+    #[test]
+    fn add_small_constant_overflow() {
+        crosscheck(
+            "(+ 170141183460469231731687303715884105727 1)",
+            Err(Error::Runtime(
+                RuntimeErrorType::ArithmeticOverflow,
+                Some(Vec::new()),
+            )),
+        );
+        crosscheck(
+            "(+ u340282366920938463463374607431768211455 u0)",
+            Ok(Some(Value::UInt(u128::MAX))),
+        );
+    }
+
+    #[test]
+    fn add_carries_to_high_half() {
+        crosscheck(
+            "(+ u18446744073709551615 u1)",
+            Ok(Some(Value::UInt(1 << 64))),
+        );
+        crosscheck(
+            "(+ -1 -18446744073709551616)",
+            Ok(Some(Value::Int(-(1 << 64) - 1))),
+        );
+    }
+
+    #[test]
+    fn add_negative_overflow() {
+        crosscheck(
+            "(+ -170141183460469231731687303715884105728 -1)",
+            Err(Error::Runtime(
+                RuntimeErrorType::ArithmeticOverflow,
+                Some(Vec::new()),
+            )),
+        );
+    }
+
+    #[test]
+    fn sub_small_constant_underflow() {
+        crosscheck(
+            "(- -170141183460469231731687303715884105728 1)",
+            Err(Error::Runtime(
+                RuntimeErrorType::ArithmeticUnderflow,
+                Some(Vec::new()),
+            )),
+        );
+        crosscheck(
+            "(- u18446744073709551616 u1)",
+            Ok(Some(Value::UInt(u64::MAX as u128))),
+        );
+        crosscheck(
+            "(- u18446744073709551615 u18446744073709551616)",
+            Err(Error::Runtime(
+                RuntimeErrorType::ArithmeticUnderflow,
+                Some(Vec::new()),
+            )),
+        );
+    }
+
+    #[test]
+    fn sub_non_constant_operands() {
+        crosscheck(
+            "
+(define-data-var a int 170141183460469231731687303715884105727)
+(define-data-var b int -1)
+(- (var-get a) (var-get b))
+",
+            Err(Error::Runtime(
+                RuntimeErrorType::ArithmeticUnderflow,
+                Some(Vec::new()),
+            )),
+        );
+    }
 }
@@ -1,12 +1,18 @@
 use clarity::vm::types::{SequenceSubtype, StringSubtype, TypeSignature};
 use clarity::vm::ClarityName;
+use walrus::ir::BinaryOp;
 
+use super::arithmetic::{compare, take_operands};
 use super::{SimpleWord, Word};
 use crate::cost::WordCharge;
 use crate::wasm_generator::{GeneratorError, WasmGenerator};
 
 trait CmpWord: SimpleWord {
     fn fn_name(&self) -> &'static str;
+
+    /// The operators comparing the low halves and the high halves of two
+    /// 128-bit integers.
+    fn int_ops(&self, signed: bool) -> (BinaryOp, BinaryOp);
 }
 
 fn traverse_comparison(
@@ -16,15 +22,22 @@ fn traverse_comparison(
     arg_types: &[TypeSignature],
     _return_type: &TypeSignature,
 ) -> Result<(), GeneratorError> {
+    let ty = &arg_types[0];
+
+    // Integers are compared inline.
+    if let TypeSignature::IntType | TypeSignature::UIntType = ty {
+        let (a, b) = take_operands(generator, builder);
+        word.charge(generator, builder, arg_types.len() as u32)?;
+        let (lo_op, hi_op) = word.int_ops(ty == &TypeSignature::IntType);
+        compare(builder, a, b, lo_op, hi_op);
+        return Ok(());
+    }
+
     word.charge(generator, builder, arg_types.len() as u32)?;
 
     let name = word.fn_name();
 
-    let ty = &arg_types[0];
-
     let type_suffix = match ty {
-        TypeSignature::IntType => "int",
-        TypeSignature::UIntType => "uint",
         // same function for buffer and string-ascii
         TypeSignature::SequenceType(SequenceSubtype::StringType(StringSubtype::ASCII(_)))
         | TypeSignature::SequenceType(SequenceSubtype::BufferType(_)) => "buff",
@@ -79,6 +92,14 @@ impl CmpWord for CmpLess {
     fn fn_name(&self) -> &'static str {
         "lt"
     }
+
+    fn int_ops(&self, signed: bool) -> (BinaryOp, BinaryOp) {
+        if signed {
+            (BinaryOp::I64LtU, BinaryOp::I64LtS)
+        } else {
+            (BinaryOp::I64LtU, BinaryOp::I64LtU)
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -106,6 +127,14 @@ impl CmpWord for CmpLeq {
     fn fn_name(&self) -> &'static str {
         "le"
     }
+
+    fn int_ops(&self, signed: bool) -> (BinaryOp, BinaryOp) {
+        if signed {
+            (BinaryOp::I64LeU, BinaryOp::I64LeS)
+        } else {
+            (BinaryOp::I64LeU, BinaryOp::I64LeU)
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -133,6 +162,14 @@ impl CmpWord for CmpGreater {
     fn fn_name(&self) -> &'static str {
         "gt"
     }
+
+    fn int_ops(&self, signed: bool) -> (BinaryOp, BinaryOp) {
+        if signed {
+            (BinaryOp::I64GtU, BinaryOp::I64GtS)
+        } else {
+            (BinaryOp::I64GtU, BinaryOp::I64GtU)
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -160,4 +197,12 @@ impl CmpWord for CmpGeq {
     fn fn_name(&self) -> &'static str {
         "ge"
     }
+
+    fn int_ops(&self, signed: bool) -> (BinaryOp, BinaryOp) {
+        if signed {
+            (BinaryOp::I64GeU, BinaryOp::I64GeS)
+        } else {
+            (BinaryOp::I64GeU, BinaryOp::I64GeU)
+        }
+    }
 }
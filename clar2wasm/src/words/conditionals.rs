@@ -5,6 +5,7 @@ use walrus::{InstrSeqBuilder, LocalId, ValType};
 
 use super::{ComplexWord, SimpleWord, Word};
 use crate::cost::WordCharge;
+use crate::coverage::BranchBlock;
 use crate::error_mapping::ErrorMap;
 use crate::wasm_generator::{
     add_placeholder_for_clarity_type, drop_value, ArgumentsExt, GeneratorError,
@@ -132,18 +133,37 @@ impl<'a> ShortReturnable<'a> {
     }
 
     /// Generates the handling of a ShortReturn error.
+    ///
+    /// With coverage enabled, the short-return is counted as the branch 1 of
+    /// `branches`, and continuing as the branch 0.
     fn handle_short_return(
         &self,
         generator: &mut WasmGenerator,
         builder: &mut InstrSeqBuilder,
-        condition: impl FnMut(&mut InstrSeqBuilder),
+        branches: Option<BranchBlock>,
+        mut condition: impl FnMut(&mut InstrSeqBuilder),
     ) -> Result<(), GeneratorError> {
+        if let Some(branches) = branches {
+            let mut short_return = builder.dangling_instr_seq(None);
+            generator.count_branch(&mut short_return, Some(branches.branch(1)))?;
+            let short_return_id = short_return.id();
+            let empty_id = builder.dangling_instr_seq(None).id();
+
+            condition(builder);
+            builder.instr(IfElse {
+                consequent: short_return_id,
+                alternative: empty_id,
+            });
+        }
+
         match generator.get_current_function_return_type() {
             Some(return_ty) => {
-                self.handle_short_return_function(generator, builder, return_ty, condition)
+                self.handle_short_return_function(generator, builder, return_ty, &mut condition)?
             }
-            None => self.handle_short_return_top_level(generator, builder, condition),
+            None => self.handle_short_return_top_level(generator, builder, &mut condition)?,
         }
+
+        generator.count_branch(builder, branches.map(|branches| branches.branch(0)))
     }
 
     /// Generates the handling of a ShortReturn error when we are not in a function.
@@ -298,8 +318,17 @@ impl ComplexWord for If {
         generator.set_expr_type(true_branch, expr_ty.clone())?;
         generator.set_expr_type(false_branch, expr_ty)?;
 
-        let id_true = generator.block_from_expr(builder, true_branch)?;
-        let id_false = generator.block_from_expr(builder, false_branch)?;
+        let branches = generator.coverage_branches(expr);
+        let id_true = generator.branch_block_from_expr(
+            builder,
+            true_branch,
+            branches.map(|b| b.branch(0)),
+        )?;
+        let id_false = generator.branch_block_from_expr(
+            builder,
+            false_branch,
+            branches.map(|b| b.branch(1)),
+        )?;
 
         generator.traverse_expr(builder, conditional)?;
 
@@ -326,7 +355,7 @@ impl ComplexWord for Match {
         &self,
         generator: &mut WasmGenerator,
         builder: &mut walrus::InstrSeqBuilder,
-        _expr: &SymbolicExpression,
+        expr: &SymbolicExpression,
         args: &[SymbolicExpression],
     ) -> Result<(), GeneratorError> {
         self.charge(generator, builder, 0)?;
@@ -334,7 +363,7 @@ impl ComplexWord for Match {
         // WORKAROUND: we'll have to set the types of arguments to the type of expression,
         //             since the typechecker didn't do it for us
         let expr_ty = generator
-            .get_expr_type(_expr)
+            .get_expr_type(expr)
             .ok_or_else(|| {
                 GeneratorError::TypeError("match expression should have a type".to_owned())
             })?
@@ -356,6 +385,7 @@ impl ComplexWord for Match {
         // save the current set of named locals, for later restoration
         let saved_bindings = generator.bindings.clone();
 
+        let branches = generator.coverage_branches(expr);
         generator.traverse_expr(builder, match_on)?;
 
         match generator.get_expr_type(match_on).cloned() {
@@ -373,12 +403,20 @@ impl ComplexWord for Match {
                     .bindings
                     .insert(success_binding.clone(), *inner_type, some_locals);
 
-                let some_block = generator.block_from_expr(builder, success_body)?;
+                let some_block = generator.branch_block_from_expr(
+                    builder,
+                    success_body,
+                    branches.map(|b| b.branch(0)),
+                )?;
 
                 // we can restore early, since the none branch does not bind anything
                 generator.bindings = saved_bindings;
 
-                let none_block = generator.block_from_expr(builder, none_body)?;
+                let none_block = generator.branch_block_from_expr(
+                    builder,
+                    none_body,
+                    branches.map(|b| b.branch(1)),
+                )?;
 
                 builder.instr(ir::IfElse {
                     consequent: some_block,
@@ -410,7 +448,11 @@ impl ComplexWord for Match {
                 generator
                     .bindings
                     .insert(success_binding.clone(), ok_ty.clone(), ok_locals);
-                let ok_block = generator.block_from_expr(builder, success_body)?;
+                let ok_block = generator.branch_block_from_expr(
+                    builder,
+                    success_body,
+                    branches.map(|b| b.branch(0)),
+                )?;
 
                 // restore named locals
                 generator.bindings.clone_from(&saved_bindings);
@@ -420,7 +462,11 @@ impl ComplexWord for Match {
                     .bindings
                     .insert(err_binding.clone(), err_ty.clone(), err_locals);
 
-                let err_block = generator.block_from_expr(builder, err_body)?;
+                let err_block = generator.branch_block_from_expr(
+                    builder,
+                    err_body,
+                    branches.map(|b| b.branch(1)),
+                )?;
 
                 // restore named locals again
                 generator.bindings = saved_bindings;
@@ -551,7 +597,11 @@ impl ComplexWord for Filter {
                 };
                 generator.duck_type(&mut loop_, list_elem_ty, &arg_ty)?;
             }
-            loop_.call(generator.func_by_name(discriminator.as_str()));
+            if generator.is_inlinable(discriminator) {
+                generator.inline_call(&mut loop_, discriminator)?;
+            } else {
+                loop_.call(generator.func_by_name(discriminator.as_str()));
+            }
         }
         // [ Discriminator result (bool) ]
 
@@ -839,7 +889,8 @@ impl ComplexWord for Unwrap {
             ErrorMap::ShortReturnExpectedValue,
         );
 
-        short_returnable_throw.handle_short_return(generator, builder, |instrs| {
+        let branches = generator.coverage_branches(expr);
+        short_returnable_throw.handle_short_return(generator, builder, branches, |instrs| {
             // we need to short-return if the variant is `none` or `err`
             instrs.local_get(variant).unop(UnaryOp::I32Eqz);
         })?;
@@ -925,7 +976,8 @@ impl ComplexWord for UnwrapErr {
             ErrorMap::ShortReturnExpectedValue,
         );
 
-        short_returnable_throw.handle_short_return(generator, builder, |instrs| {
+        let branches = generator.coverage_branches(expr);
+        short_returnable_throw.handle_short_return(generator, builder, branches, |instrs| {
             // we need to short-return if the variant is `ok`
             instrs.local_get(variant);
         })?;
@@ -951,7 +1003,7 @@ impl ComplexWord for Asserts {
         &self,
         generator: &mut WasmGenerator,
         builder: &mut walrus::InstrSeqBuilder,
-        _expr: &SymbolicExpression,
+        expr: &SymbolicExpression,
         args: &[SymbolicExpression],
     ) -> Result<(), GeneratorError> {
         check_args!(generator, builder, 2, args.len(), ArgumentCountCheck::Exact);
@@ -983,7 +1035,8 @@ impl ComplexWord for Asserts {
             ErrorMap::ShortReturnAssertionFailure,
         );
 
-        short_returnable_thrown.handle_short_return(generator, builder, |instrs| {
+        let branches = generator.coverage_branches(expr);
+        short_returnable_thrown.handle_short_return(generator, builder, branches, |instrs| {
             // we need to short return if predicate is false.
             instrs.local_get(predicate).unop(UnaryOp::I32Eqz);
         })?;
@@ -1009,7 +1062,7 @@ impl ComplexWord for Try {
         &self,
         generator: &mut WasmGenerator,
         builder: &mut walrus::InstrSeqBuilder,
-        _expr: &SymbolicExpression,
+        expr: &SymbolicExpression,
         args: &[SymbolicExpression],
     ) -> Result<(), GeneratorError> {
         check_args!(generator, builder, 1, args.len(), ArgumentCountCheck::Exact);
@@ -1027,7 +1080,8 @@ impl ComplexWord for Try {
         let (short_returnable_value, variant) =
             ShortReturnable::new(generator, builder, &input_ty)?;
 
-        short_returnable_value.handle_short_return(generator, builder, |instrs| {
+        let branches = generator.coverage_branches(expr);
+        short_returnable_value.handle_short_return(generator, builder, branches, |instrs| {
             // we need to short-return if the variant is `none` or `err`
             instrs.local_get(variant).unop(UnaryOp::I32Eqz);
         })?;
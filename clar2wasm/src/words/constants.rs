@@ -1,5 +1,5 @@
 use clarity::vm::{ClarityName, SymbolicExpression, SymbolicExpressionType};
-use walrus::{ActiveData, DataKind, ValType};
+use walrus::ValType;
 
 use super::{ComplexWord, Word};
 use crate::check_args;
@@ -52,21 +52,13 @@ impl ComplexWord for DefineConstant {
             // Literals of in-memory types should write (offset, len) to memory,
             // so that their representation is consistent with in-memory non-literals.
             if is_in_memory_type(&value_ty) {
-                let ref_offset = generator.literal_memory_end;
-                generator.literal_memory_end += 8; // offset + len bytes
-
-                let memory = generator.get_memory()?;
-                generator.module.data.add(
-                    DataKind::Active(ActiveData {
-                        memory,
-                        location: walrus::ActiveDataLocation::Absolute(ref_offset),
-                    }),
-                    value_offset
+                let (ref_offset, _) = generator.add_bytes_literal(
+                    &value_offset
                         .to_le_bytes()
                         .into_iter()
                         .chain(value_length.to_le_bytes())
-                        .collect(),
-                );
+                        .collect::<Vec<u8>>(),
+                )?;
 
                 // update offset to point to reference
                 value_offset = ref_offset;
@@ -84,7 +84,6 @@ impl ComplexWord for ContractCall {
             // Push an empty trait name first
             builder.i32_const(0).i32_const(0);
             // Push the contract identifier onto the stack
-            // TODO(#111): These should be tracked for reuse, similar to the string literals
             let (id_offset, id_length) =
                 generator.add_literal(&contract_identifier.clone().into())?;
             builder
@@ -199,9 +198,16 @@ impl ComplexWord for ContractCall {
 
 #[cfg(test)]
 mod tests {
+    use clarity::vm::contexts::{CallStack, GlobalContext};
+    use clarity::vm::errors::{CheckErrors, Error, RuntimeErrorType};
+    use clarity::vm::types::{PrincipalData, QualifiedContractIdentifier};
     use clarity::vm::Value;
 
-    use crate::tools::{crosscheck_multi_contract, evaluate, TestEnvironment};
+    use crate::initialize::ExecutionOptions;
+    use crate::tools::{
+        crosscheck_multi_contract, crosscheck_multi_contract_with_env, evaluate, TestEnvironment,
+    };
+    use crate::wasm_utils::call_function_with_options;
 
     #[test]
     fn as_contract_less_than_one_arg() {
@@ -841,6 +847,58 @@ mod tests {
         );
     }
 
+    #[test]
+    fn read_only_caller_cannot_write_in_callee() {
+        let mut env = TestEnvironment::default();
+        env.init_contract_with_snippet(
+            "contract-callee",
+            r#"
+(define-data-var counter int 0)
+(define-public (bump)
+    (begin (var-set counter (+ (var-get counter) 1)) (ok (var-get counter)))
+)
+            "#,
+        )
+        .expect("Failed to init contract.");
+        env.init_contract_with_snippet(
+            "contract-caller",
+            "(define-public (poke) (contract-call? .contract-callee bump))",
+        )
+        .expect("Failed to init contract.");
+
+        // The analysis rejects a `define-read-only` calling `bump`, so the
+        // read-only frame a read-only caller runs in is opened here, around
+        // a public function calling it.
+        let err = env
+            .with_global_context(|global_context, contract_contexts| {
+                global_context.begin_read_only();
+                let result = call_function_with_options(
+                    "poke",
+                    &[],
+                    global_context,
+                    &contract_contexts["contract-caller"],
+                    &mut CallStack::new(),
+                    None,
+                    None,
+                    None,
+                    ExecutionOptions::default(),
+                );
+                global_context.roll_back()?;
+                result
+            })
+            .expect_err("write should be rejected");
+        assert!(
+            matches!(err, Error::Unchecked(CheckErrors::WriteAttemptedInReadOnly)),
+            "unexpected error: {err:?}"
+        );
+
+        // The value is unchanged.
+        assert_eq!(
+            env.init_contract_with_snippet("check-value", "(contract-call? .contract-callee bump)"),
+            Ok(Some(Value::okay(Value::Int(1)).unwrap()))
+        );
+    }
+
     /// This is the same test as [multi_dynamic_define_impl_call], but it checks that it still works
     /// when we deal with the linked functions defined in stacks-core (duplication issue).
     #[test]
@@ -884,4 +942,120 @@ mod tests {
             Ok(Some(Value::okay_true())),
         );
     }
+
+    #[test]
+    fn compiled_caller_calls_interpreted_callee() {
+        let mut env = TestEnvironment::default();
+        env.interpret_contract_with_snippet(
+            "contract-callee",
+            r#"
+(define-data-var counter uint u0)
+(define-public (bump)
+    (begin (var-set counter (+ (var-get counter) u1)) (ok (var-get counter)))
+)
+            "#,
+        )
+        .expect("Failed to interpret contract.");
+
+        let result = env
+            .init_contract_with_snippet("contract-caller", "(contract-call? .contract-callee bump)")
+            .expect("Failed to init contract.");
+        assert_eq!(result, Some(Value::okay(Value::UInt(1)).unwrap()));
+    }
+
+    #[test]
+    fn contract_call_charges_argument_type_checks() {
+        const CALLEE: &str = r#"
+(define-public (small (a (buff 1))) (ok true))
+(define-public (large (a (buff 1000))) (ok true))
+        "#;
+        const CALLER: &str = r#"
+(define-public (call-small) (contract-call? .contract-callee small 0x01))
+(define-public (call-large) (contract-call? .contract-callee large 0x01))
+        "#;
+
+        // Both calls only differ by the type of the argument of the callee,
+        // whose check is charged when the callee is applied.
+        let type_check_cost = |compiled: bool| {
+            let mut env = TestEnvironment::default();
+            env.enable_cost_tracking()
+                .expect("Failed to enable cost tracking.");
+            for (name, snippet) in [("contract-callee", CALLEE), ("contract-caller", CALLER)] {
+                if compiled {
+                    env.init_contract_with_snippet(name, snippet)
+                } else {
+                    env.interpret_contract_with_snippet(name, snippet)
+                }
+                .expect("Failed to deploy contract.");
+            }
+
+            let mut call_cost = |function| {
+                let before = env.total_cost();
+                assert_eq!(
+                    env.call_contract_function("contract-caller", function, &[], None),
+                    Ok(Value::okay_true())
+                );
+                env.total_cost().runtime - before.runtime
+            };
+            let large = call_cost("call-large");
+            let small = call_cost("call-small");
+            large - small
+        };
+
+        let interpreted = type_check_cost(false);
+        assert!(interpreted > 0);
+        assert_eq!(type_check_cost(true), interpreted);
+    }
+
+    /// Stand-in for the PoX special cases of a node: a successful `stack-stx`
+    /// fails the call as if the account was already locked.
+    fn reject_stacking(
+        _global_context: &mut GlobalContext,
+        _sender: Option<&PrincipalData>,
+        _sponsor: Option<&PrincipalData>,
+        contract_id: &QualifiedContractIdentifier,
+        function_name: &str,
+        _args: &[Value],
+        result: &Value,
+    ) -> Result<(), Error> {
+        match result {
+            Value::Response(response)
+                if response.committed
+                    && contract_id.name.as_str() == "pox-4"
+                    && function_name == "stack-stx" =>
+            {
+                Err(RuntimeErrorType::PoxAlreadyLocked.into())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    #[test]
+    fn contract_call_runs_special_cases() {
+        let mut env = TestEnvironment::default();
+        env.set_cc_special_cases_handler(&reject_stacking);
+
+        let pox = r#"
+(define-public (stack-stx (amount uint)) (ok amount))
+(define-public (delegate-stx (amount uint)) (ok amount))
+        "#;
+        let results = crosscheck_multi_contract_with_env(
+            &[
+                ("pox-4".into(), pox),
+                (
+                    "delegate".into(),
+                    "(contract-call? .pox-4 delegate-stx u100)",
+                ),
+                ("stack".into(), "(contract-call? .pox-4 stack-stx u100)"),
+            ],
+            env,
+        );
+        assert_eq!(
+            results[1..],
+            [
+                Ok(Some(Value::okay(Value::UInt(100)).unwrap())),
+                Err(RuntimeErrorType::PoxAlreadyLocked.into()),
+            ]
+        );
+    }
 }
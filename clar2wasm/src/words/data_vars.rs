@@ -1,3 +1,4 @@
+use clarity::vm::clarity_wasm::{get_type_in_memory_size, get_type_size};
 use clarity::vm::types::TypeSignature;
 use clarity::vm::{ClarityName, SymbolicExpression};
 use walrus::ValType;
@@ -5,6 +6,7 @@ use walrus::ValType;
 use super::{ComplexWord, Word};
 use crate::check_args;
 use crate::cost::WordCharge;
+use crate::serialize::max_serialized_size;
 use crate::wasm_generator::{ArgumentsExt, GeneratorError, LiteralMemoryEntry, WasmGenerator};
 use crate::wasm_utils::{check_argument_count, ArgumentCountCheck};
 
@@ -50,21 +52,17 @@ impl ComplexWord for DefineDataVar {
         // data stack)
         generator.traverse_expr(builder, initial)?;
 
-        // The initial value can be placed on the top of the memory, since at
-        // the top-level, we have not set up the call stack yet.
+        // The initial value is only read by the host when defining the
+        // variable, so it is written to the work space above the stack
+        // pointer instead of being kept in memory.
         let offset = generator.module.locals.add(ValType::I32);
         builder
-            .i32_const(generator.literal_memory_end as i32)
+            .global_get(generator.stack_pointer)
             .local_set(offset);
 
         // Write the initial value to the memory, to be read by the host.
         let size = generator.write_to_memory(builder, offset, 0, &ty)?;
-
-        // Increment the literal memory end
-        // FIXME: These initial values do not need to be saved in the literal
-        //        memory forever... we just need them once, when .top-level
-        //        is called.
-        generator.literal_memory_end += size;
+        generator.ensure_work_space(size);
 
         // Push the name onto the data stack
         builder
@@ -142,21 +140,18 @@ impl ComplexWord for SetDataVar {
             .ok_or_else(|| GeneratorError::InternalError(format!("variable not found: {name}")))?;
         let id_length = name.len();
 
-        // Create space on the call stack to write the value
-        let (offset, size) = generator.create_call_stack_local(builder, &ty, true, false);
-
-        self.charge(generator, builder, size as u32)?;
+        self.charge(generator, builder, get_type_size(&ty) as u32)?;
 
-        // Write the value to the memory, to be read by the host
-        generator.write_to_memory(builder, offset, 0, &ty)?;
+        // Serialize the value to the call stack, to be stored by the host
+        let (offset, length) = generator.serialize_to_call_stack(builder, &ty)?;
 
         // Push the identifier offset and length onto the data stack
         builder
             .i32_const(id_offset as i32)
             .i32_const(id_length as i32);
 
-        // Push the offset and size to the data stack
-        builder.local_get(offset).i32_const(size);
+        // Push the offset and length of the serialized value to the data stack
+        builder.local_get(offset).local_get(length);
 
         // Call the host interface function, `set_variable`
         builder.call(
@@ -204,24 +199,28 @@ impl ComplexWord for GetDataVar {
             .ok_or_else(|| GeneratorError::TypeError(format!("variable not found: {name}")))?;
         let id_length = name.len();
 
-        // Create a new local to hold the result on the call stack
         let ty = generator
             .get_expr_type(expr)
             .ok_or_else(|| {
                 GeneratorError::TypeError("var-get expression must be typed".to_owned())
             })?
             .clone();
-        let (offset, size) = generator.create_call_stack_local(builder, &ty, true, true);
+        let size = get_type_in_memory_size(&ty, true) + get_type_size(&ty);
 
         self.charge(generator, builder, size as u32)?;
 
+        // Use the work space for the serialized value, past the room needed
+        // for the deserialized one.
+        let buffer_size = max_serialized_size(&ty)?;
+        let offset = generator.work_space_buffer(builder, size, buffer_size);
+
         // Push the identifier offset and length onto the data stack
         builder
             .i32_const(id_offset as i32)
             .i32_const(id_length as i32);
 
-        // Push the offset and size to the data stack
-        builder.local_get(offset).i32_const(size);
+        // Push the offset and size of the buffer to the data stack
+        builder.local_get(offset).i32_const(buffer_size);
 
         // Call the host interface function, `get_variable`
         builder.call(
@@ -234,9 +233,12 @@ impl ComplexWord for GetDataVar {
                 })?,
         );
 
-        // Host interface fills the result into the specified memory. Read it
-        // back out, and place the value on the data stack.
-        generator.read_from_memory(builder, offset, 0, &ty)?;
+        // Host interface writes the stored bytes into the buffer and returns
+        // their length. Deserialize them, and place the value on the data
+        // stack.
+        let length = generator.module.locals.add(ValType::I32);
+        builder.local_set(length);
+        generator.deserialize_stored_value(builder, offset, length, &ty)?;
 
         Ok(())
     }
@@ -244,11 +246,13 @@ impl ComplexWord for GetDataVar {
 
 #[cfg(test)]
 mod tests {
+    use clarity::types::StacksEpochId;
     use clarity::vm::errors::{CheckErrors, Error};
     use clarity::vm::Value;
 
     use crate::tools::{
-        crosscheck, crosscheck_expect_failure, crosscheck_with_clarity_version, evaluate,
+        crosscheck, crosscheck_calls_memory_use, crosscheck_expect_failure,
+        crosscheck_with_clarity_version, evaluate, TestConfig,
     };
 
     //
@@ -366,6 +370,23 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_var_set_in_memory_type() {
+        crosscheck(
+            r#"
+(define-data-var names (list 2 (string-utf8 5)) (list u"ab"))
+
+(define-public (simple)
+  (begin
+    (var-set names (unwrap-panic (as-max-len? (append (var-get names) u"\u{e9}t\u{e9}") u2)))
+    (ok (var-get names))))
+
+(simple)
+"#,
+            evaluate(r#"(ok (list u"ab" u"\u{e9}t\u{e9}"))"#),
+        );
+    }
+
     #[test]
     fn validate_define_data_var() {
         // Reserved keyword
@@ -393,4 +414,26 @@ mod tests {
             clarity::vm::ClarityVersion::Clarity1,
         );
     }
+
+    #[test]
+    fn var_storage_matches_interpreter_across_epochs() {
+        let snippet = "
+            (define-data-var v {l: (list 3 (optional uint)), s: (string-ascii 8)}
+                {l: (list), s: \"\"})
+            (define-public (set-var)
+                (ok (var-set v {l: (list (some u1) none), s: \"abcdefgh\"})))
+            (define-public (get-var)
+                (ok (var-get v)))
+        ";
+        for epoch in [
+            StacksEpochId::Epoch20,
+            StacksEpochId::Epoch2_05,
+            StacksEpochId::Epoch21,
+            StacksEpochId::Epoch22,
+            StacksEpochId::Epoch23,
+            TestConfig::latest_epoch(),
+        ] {
+            crosscheck_calls_memory_use(snippet, &["set-var", "get-var"], epoch);
+        }
+    }
 }
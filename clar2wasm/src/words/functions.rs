@@ -48,6 +48,7 @@ impl ComplexWord for DefinePrivateFunction {
             generator.traverse_define_function(builder, name, body, FunctionKind::Private)?;
         generator.module.exports.add(name.as_str(), function_id);
         generator.defined_functions.insert(name.to_string());
+        generator.register_inlinable_function(name, body);
 
         Ok(())
     }
@@ -1,11 +1,17 @@
+use clarity::vm::clarity_wasm::{get_type_in_memory_size, get_type_size};
 use clarity::vm::types::TypeSignature;
 use clarity::vm::{ClarityName, SymbolicExpression};
+use walrus::ir::{IfElse, InstrSeqType, UnaryOp};
 use walrus::ValType;
 
 use super::{ComplexWord, Word};
 use crate::check_args;
 use crate::cost::WordCharge;
-use crate::wasm_generator::{ArgumentsExt, GeneratorError, LiteralMemoryEntry, WasmGenerator};
+use crate::serialize::max_serialized_size;
+use crate::wasm_generator::{
+    add_placeholder_for_clarity_type, clar2wasm_ty, ArgumentsExt, GeneratorError,
+    LiteralMemoryEntry, WasmGenerator,
+};
 use crate::wasm_utils::{check_argument_count, ArgumentCountCheck};
 
 #[derive(Debug)]
@@ -110,46 +116,78 @@ impl ComplexWord for MapGet {
             .i32_const(id_offset as i32)
             .i32_const(id_length as i32);
 
-        // Create space on the call stack to write the key
         let ty = generator
             .get_expr_type(key)
             .ok_or_else(|| {
                 GeneratorError::TypeError("map-set value expression must be typed".to_owned())
             })?
             .clone();
-        let (key_offset, key_size) = generator.create_call_stack_local(builder, &ty, true, false);
 
         // Push the key to the data stack
         generator.traverse_expr(builder, key)?;
 
-        // Write the key to the memory (it's already on the data stack)
-        generator.write_to_memory(builder, key_offset, 0, &ty)?;
+        // Serialize the key, which the host uses as-is to locate the entry
+        let (key_offset, key_length) = generator.serialize_to_call_stack(builder, &ty)?;
 
-        // Push the key offset and size to the data stack
-        builder.local_get(key_offset).i32_const(key_size);
+        // Push the key offset and length to the data stack
+        builder.local_get(key_offset).local_get(key_length);
 
-        // Create a new local to hold the result on the call stack
         let ty = generator
             .get_expr_type(expr)
             .ok_or_else(|| {
                 GeneratorError::TypeError("map-get? expression must be typed".to_owned())
             })?
             .clone();
-        let (return_offset, size) = generator.create_call_stack_local(builder, &ty, true, true);
+        let TypeSignature::OptionalType(value_ty) = &ty else {
+            return Err(GeneratorError::TypeError(
+                "map-get? expression must be an optional type".to_owned(),
+            ));
+        };
 
-        let return_size = generator.module.locals.add(ValType::I32);
-        builder.i32_const(size).local_set(return_size);
-        self.charge(generator, builder, return_size)?;
+        let size = get_type_in_memory_size(&ty, true) + get_type_size(&ty);
+        self.charge(generator, builder, size as u32)?;
 
-        // Push the return value offset and size to the data stack
-        builder.local_get(return_offset).local_get(return_size);
+        // Use the work space for the serialized value of the entry, past the
+        // room needed for the deserialized one.
+        let buffer_size = max_serialized_size(value_ty)?;
+        let value_size = get_type_in_memory_size(value_ty, true) + get_type_size(value_ty);
+        let return_offset = generator.work_space_buffer(builder, value_size, buffer_size);
+
+        // Push the buffer offset and size to the data stack
+        builder.local_get(return_offset).i32_const(buffer_size);
 
         // Call the host-interface function, `map_get`
         builder.call(generator.func_by_name("stdlib.map_get"));
 
-        // Host interface fills the result into the specified memory. Read it
-        // back out, and place the value on the data stack.
-        generator.read_from_memory(builder, return_offset, 0, &ty)?;
+        // Host interface writes the serialized value of the entry into the
+        // buffer and returns its length, or 0 if there is no entry.
+        let return_length = generator.module.locals.add(ValType::I32);
+        builder.local_set(return_length);
+
+        let result_ty = InstrSeqType::new(&mut generator.module.types, &[], &clar2wasm_ty(&ty));
+        let none_id = {
+            let mut none = builder.dangling_instr_seq(result_ty);
+            add_placeholder_for_clarity_type(&mut none, &ty);
+            none.id()
+        };
+        let some_id = {
+            let mut some = builder.dangling_instr_seq(result_ty);
+            some.i32_const(1);
+            generator.deserialize_stored_value(
+                &mut some,
+                return_offset,
+                return_length,
+                value_ty,
+            )?;
+            some.id()
+        };
+        builder
+            .local_get(return_length)
+            .unop(UnaryOp::I32Eqz)
+            .instr(IfElse {
+                consequent: none_id,
+                alternative: some_id,
+            });
 
         Ok(())
     }
@@ -196,45 +234,38 @@ impl ComplexWord for MapSet {
             .i32_const(id_offset as i32)
             .i32_const(id_length as i32);
 
-        // Create space on the call stack to write the key
         let ty = generator
             .get_expr_type(key)
             .ok_or_else(|| {
                 GeneratorError::TypeError("map-set value expression must be typed".to_owned())
             })?
             .clone();
-        let (key_offset, key_size) = generator.create_call_stack_local(builder, &ty, true, false);
 
         // Push the key to the data stack
         generator.traverse_expr(builder, key)?;
 
-        // Write the key to the memory (it's already on the data stack)
-        generator.write_to_memory(builder, key_offset, 0, &ty)?;
+        // Serialize the key, which the host uses as-is to locate the entry
+        let (key_offset, key_length) = generator.serialize_to_call_stack(builder, &ty)?;
 
-        // Push the key offset and size to the data stack
-        builder.local_get(key_offset).i32_const(key_size);
+        // Push the key offset and length to the data stack
+        builder.local_get(key_offset).local_get(key_length);
 
-        // Create space on the call stack to write the value
         let ty = generator
             .get_expr_type(value)
             .ok_or_else(|| {
                 GeneratorError::TypeError("map-set value expression must be typed".to_owned())
             })?
             .clone();
-        let (val_offset, size) = generator.create_call_stack_local(builder, &ty, true, false);
-
-        let val_size = generator.module.locals.add(ValType::I32);
-        builder.i32_const(size).local_set(val_size);
-        self.charge(generator, builder, val_size)?;
+        self.charge(generator, builder, get_type_size(&ty) as u32)?;
 
         // Push the value to the data stack
         generator.traverse_expr(builder, value)?;
 
-        // Write the value to the memory (it's already on the data stack)
-        generator.write_to_memory(builder, val_offset, 0, &ty)?;
+        // Serialize the value, to be stored by the host
+        let (val_offset, val_length) = generator.serialize_to_call_stack(builder, &ty)?;
 
-        // Push the value offset and size to the data stack
-        builder.local_get(val_offset).local_get(val_size);
+        // Push the value offset and length to the data stack
+        builder.local_get(val_offset).local_get(val_length);
 
         // Call the host interface function, `map_set`
         builder.call(generator.func_by_name("stdlib.map_set"));
@@ -284,45 +315,38 @@ impl ComplexWord for MapInsert {
             .i32_const(id_offset as i32)
             .i32_const(id_length as i32);
 
-        // Create space on the call stack to write the key
         let ty = generator
             .get_expr_type(key)
             .ok_or_else(|| {
                 GeneratorError::TypeError("map-set value expression must be typed".to_owned())
             })?
             .clone();
-        let (key_offset, key_size) = generator.create_call_stack_local(builder, &ty, true, false);
 
         // Push the key to the data stack
         generator.traverse_expr(builder, key)?;
 
-        // Write the key to the memory (it's already on the data stack)
-        generator.write_to_memory(builder, key_offset, 0, &ty)?;
+        // Serialize the key, which the host uses as-is to locate the entry
+        let (key_offset, key_length) = generator.serialize_to_call_stack(builder, &ty)?;
 
-        // Push the key offset and size to the data stack
-        builder.local_get(key_offset).i32_const(key_size);
+        // Push the key offset and length to the data stack
+        builder.local_get(key_offset).local_get(key_length);
 
-        // Create space on the call stack to write the value
         let ty = generator
             .get_expr_type(value)
             .ok_or_else(|| {
                 GeneratorError::TypeError("map-set value expression must be typed".to_owned())
             })?
             .clone();
-        let (val_offset, size) = generator.create_call_stack_local(builder, &ty, true, false);
-
-        let val_size = generator.module.locals.add(ValType::I32);
-        builder.i32_const(size).local_set(val_size);
-        self.charge(generator, builder, val_size)?;
+        self.charge(generator, builder, get_type_size(&ty) as u32)?;
 
         // Push the value to the data stack
         generator.traverse_expr(builder, value)?;
 
-        // Write the value to the memory (it's already on the data stack)
-        generator.write_to_memory(builder, val_offset, 0, &ty)?;
+        // Serialize the value, to be stored by the host
+        let (val_offset, val_length) = generator.serialize_to_call_stack(builder, &ty)?;
 
-        // Push the value offset and size to the data stack
-        builder.local_get(val_offset).local_get(val_size);
+        // Push the value offset and length to the data stack
+        builder.local_get(val_offset).local_get(val_length);
 
         // Call the host interface function, `map_insert`
         builder.call(generator.func_by_name("stdlib.map_insert"));
@@ -371,27 +395,22 @@ impl ComplexWord for MapDelete {
             .i32_const(id_offset as i32)
             .i32_const(id_length as i32);
 
-        // Create space on the call stack to write the key
         let ty = generator
             .get_expr_type(key)
             .ok_or_else(|| {
                 GeneratorError::TypeError("map-set value expression must be typed".to_owned())
             })?
             .clone();
-        let (key_offset, size) = generator.create_call_stack_local(builder, &ty, true, false);
-
-        let key_size = generator.module.locals.add(ValType::I32);
-        builder.i32_const(size).local_set(key_size);
-        self.charge(generator, builder, key_size)?;
+        self.charge(generator, builder, get_type_size(&ty) as u32)?;
 
         // Push the key to the data stack
         generator.traverse_expr(builder, key)?;
 
-        // Write the key to the memory (it's already on the data stack)
-        generator.write_to_memory(builder, key_offset, 0, &ty)?;
+        // Serialize the key, which the host uses as-is to locate the entry
+        let (key_offset, key_length) = generator.serialize_to_call_stack(builder, &ty)?;
 
-        // Push the key offset and size to the data stack
-        builder.local_get(key_offset).local_get(key_size);
+        // Push the key offset and length to the data stack
+        builder.local_get(key_offset).local_get(key_length);
 
         // Call the host interface function, `map_delete`
         builder.call(
@@ -412,10 +431,13 @@ impl ComplexWord for MapDelete {
 mod tests {
     // use clarity::vm::errors::{CheckErrors, Error};
 
+    use clarity::types::StacksEpochId;
     use clarity::vm::errors::{CheckErrors, Error};
     use clarity::vm::Value;
 
-    use crate::tools::{crosscheck, crosscheck_expect_failure, evaluate};
+    use crate::tools::{
+        crosscheck, crosscheck_calls_memory_use, crosscheck_expect_failure, evaluate, TestConfig,
+    };
 
     //
     // Module with tests that should only be executed
@@ -466,6 +488,31 @@ mod tests {
         crosscheck("(define-map approved-contracts principal bool) (map-insert approved-contracts tx-sender true) (map-get? approved-contracts tx-sender)", Ok(Some(Value::some(Value::Bool(true)).unwrap())));
     }
 
+    #[test]
+    fn map_entries_of_in_memory_types() {
+        crosscheck(
+            r#"
+(define-map m {id: uint, name: (string-ascii 10)} (list 3 (buff 2)))
+(let
+  (
+    (writes (list
+      (map-insert m {id: u1, name: "a"} (list 0x01 0x0203))
+      (map-insert m {id: u1, name: "a"} (list))
+      (map-set m {id: u2, name: "bc"} (list))
+      (map-delete m {id: u2, name: "bc"})
+      (map-delete m {id: u2, name: "bc"})))
+  )
+  {
+    writes: writes,
+    entries: (list (map-get? m {id: u1, name: "a"}) (map-get? m {id: u2, name: "bc"}))
+  })
+"#,
+            evaluate(
+                r#"{writes: (list true false true true false), entries: (list (some (list 0x01 0x0203)) none)}"#,
+            ),
+        );
+    }
+
     #[test]
     fn validate_define_map() {
         // Reserved keyword
@@ -602,4 +649,41 @@ mod tests {
         let expected = Err(Error::Unchecked(CheckErrors::IncorrectArgumentCount(2, 3)));
         crosscheck(snippet, expected);
     }
+
+    #[test]
+    fn map_storage_matches_interpreter_across_epochs() {
+        let snippet = "
+            (define-map m {k: uint} {v: (list 3 (optional uint)), s: (string-ascii 8)})
+            (define-public (set-entry)
+                (ok (map-set m {k: u1} {v: (list (some u1) none), s: \"abc\"})))
+            (define-public (insert-entry)
+                (ok (map-insert m {k: u2} {v: (list none), s: \"abcdefgh\"})))
+            (define-public (insert-existing-entry)
+                (ok (map-insert m {k: u1} {v: (list), s: \"\"})))
+            (define-public (get-entries)
+                (ok (list (map-get? m {k: u1}) (map-get? m {k: u2}) (map-get? m {k: u3}))))
+            (define-public (delete-entry)
+                (ok (map-delete m {k: u1})))
+        ";
+        for epoch in [
+            StacksEpochId::Epoch20,
+            StacksEpochId::Epoch2_05,
+            StacksEpochId::Epoch21,
+            StacksEpochId::Epoch22,
+            StacksEpochId::Epoch23,
+            TestConfig::latest_epoch(),
+        ] {
+            crosscheck_calls_memory_use(
+                snippet,
+                &[
+                    "set-entry",
+                    "insert-entry",
+                    "insert-existing-entry",
+                    "get-entries",
+                    "delete-entry",
+                ],
+                epoch,
+            );
+        }
+    }
 }
@@ -1,11 +1,10 @@
-use clarity::vm::types::{ASCIIData, CharType};
 use clarity::vm::{ClarityName, SymbolicExpression};
 
 use super::{ComplexWord, Word};
 use crate::check_args;
 use crate::cost::WordCharge;
 use crate::wasm_generator::{ArgumentsExt, GeneratorError, WasmGenerator};
-use crate::wasm_utils::{check_argument_count, signature_from_string, ArgumentCountCheck};
+use crate::wasm_utils::{check_argument_count, ArgumentCountCheck};
 
 #[derive(Debug)]
 pub struct Print;
@@ -40,23 +39,9 @@ impl ComplexWord for Print {
             .clone();
         let val_locals = generator.save_to_locals(builder, &ty, true);
 
-        let ty_for_serde = generator.type_for_serialization(&ty);
-        let serialized_ty = ty_for_serde.to_string();
-        // Ensure (at compile time) type can be reconstructed
-        signature_from_string(
-            &serialized_ty,
-            generator.contract_analysis.clarity_version,
-            generator.contract_analysis.epoch,
-        )
-        .map_err(|e| {
-            GeneratorError::TypeError(format!("serialized type cannot be deserialized: {e:?}"))
-        })?;
-        let serialized_ty = serialized_ty.bytes().collect();
-
-        let (serialized_ty_offset, serialized_ty_len) =
-            generator.add_clarity_string_literal(&CharType::ASCII(ASCIIData {
-                data: serialized_ty,
-            }))?;
+        // The host reads the value with its type from the type table.
+        let type_id = generator.type_table.id(&ty);
+        let serialized_ty_len = generator.type_for_serialization(&ty).to_string().len() as u32;
 
         self.charge(generator, builder, serialized_ty_len)?;
 
@@ -71,10 +56,8 @@ impl ComplexWord for Print {
         // Then load the offset and length onto the stack
         builder.local_get(value_offset).i32_const(value_length);
 
-        // Push type offset and length onto the stack
-        builder
-            .i32_const(serialized_ty_offset as i32)
-            .i32_const(serialized_ty_len as i32);
+        // Push the type id onto the stack
+        builder.i32_const(type_id as i32);
 
         // Call the host interface function, `print`
         builder.call(generator.func_by_name("stdlib.print"));
@@ -115,9 +115,17 @@ impl ComplexWord for Fold {
         let expr_ty = generator.get_expr_type(expr).cloned().ok_or_else(|| {
             GeneratorError::TypeError("Fold expression should be typed".to_owned())
         })?;
+        // Builtins and inlined functions are emitted in the loop, and don't
+        // need any space for their return value.
+        let simple = words::lookup_simple(func).or(words::lookup_variadic_simple(func));
+        let inlined = simple.is_none() && generator.is_inlinable(func);
         // the `include_repr` argument should be false here, but with our current implementation, we need the full size of the
         // type without (offset, len), which is a behavior we don't have for now. We are allocating 8 bytes too many.
-        let (return_offset, _) = generator.create_call_stack_local(builder, &expr_ty, true, true);
+        let return_offset = (simple.is_none() && !inlined).then(|| {
+            generator
+                .create_call_stack_local(builder, &expr_ty, true, true)
+                .0
+        });
 
         // We need to find the correct types expected by the function `func` and the result type of the fold expression
         // to make sure everything will be coherent in the end.
@@ -252,13 +260,20 @@ impl ComplexWord for Fold {
             loop_.local_get(*result_local);
         }
 
-        if let Some(simple) = words::lookup_simple(func).or(words::lookup_variadic_simple(func)) {
+        if let Some(simple) = simple {
             // Call simple builtin
 
             let arg_a_ty = type_from_sequence_element(&elem_ty);
             let arg_types = &[arg_a_ty, result_clar_ty.clone()];
 
             simple.visit(generator, &mut loop_, arg_types, &result_clar_ty)?;
+        } else if inlined {
+            // Inline the private function
+            generator.inline_call(&mut loop_, func)?;
+            // since the accumulator and the return type of the function could have different types, we need to duck-type.
+            if let Some(tys) = &fold_func_ty {
+                generator.duck_type(&mut loop_, &tys.return_ty, &tys.acc_ty)?;
+            }
         } else {
             // Call user defined function
             generator.visit_call_user_defined(
@@ -266,7 +281,7 @@ impl ComplexWord for Fold {
                 func,
                 &result_clar_ty,
                 fold_func_ty.as_ref().map(|func_ty| &func_ty.acc_ty),
-                Some(return_offset),
+                return_offset,
             )?;
             // since the accumulator and the return type of the function could have different types, we need to duck-type.
             if let Some(tys) = &fold_func_ty {
@@ -861,6 +876,14 @@ impl ComplexWord for Map {
             if !variadic || arg_types.len() == 1 {
                 simple.visit(generator, &mut loop_, &arg_types, return_element_type)?;
             }
+        } else if generator.is_inlinable(fname) {
+            // Inline the private function.
+            generator.inline_call(&mut loop_, fname)?;
+            if let Some(FunctionType::Fixed(FixedFunction { returns, .. })) =
+                generator.get_function_type(fname).cloned()
+            {
+                generator.duck_type(&mut loop_, &returns, return_element_type)?;
+            }
         } else {
             let func_return_ty =
                 if let Some(FunctionType::Fixed(FixedFunction { returns, .. })) =
@@ -1,5 +1,7 @@
 use std::ffi::OsStr;
 
+use predicates::prelude::PredicateBooleanExt;
+
 #[test]
 fn test_clar2wasm_no_args() {
     assert_cmd::Command::cargo_bin("clar2wasm")
@@ -43,3 +45,363 @@ fn test_clar2wasm_with_input() {
 
     temp.close().unwrap();
 }
+
+#[test]
+fn test_clar2wasm_without_bulk_memory() {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let contract = temp.join("concat.clar");
+    std::fs::write(
+        &contract,
+        "(define-read-only (join (a (buff 64)) (b (buff 64))) (concat a b))\n",
+    )
+    .unwrap();
+
+    for (flags, bulk_memory) in [(&[][..], true), (&["--no-bulk-memory"][..], false)] {
+        let outfile = temp.join("concat.wasm");
+        assert_cmd::Command::cargo_bin("clar2wasm")
+            .unwrap()
+            .arg(&contract)
+            .arg("-o")
+            .arg(&outfile)
+            .args(flags)
+            .assert()
+            .success();
+
+        let wat = wasmprinter::print_bytes(std::fs::read(outfile).unwrap()).unwrap();
+        assert_eq!(wat.contains("memory.copy"), bulk_memory);
+        assert_eq!(wat.contains("memory.fill"), bulk_memory);
+    }
+
+    temp.close().unwrap();
+}
+
+#[test]
+fn test_clar2wasm_with_dynamic_memory() {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let contract = temp.join("concat.clar");
+    std::fs::write(
+        &contract,
+        "(define-read-only (join (a (buff 64)) (b (buff 64))) (concat a b))\n",
+    )
+    .unwrap();
+
+    for (flags, dynamic_memory) in [(&[][..], false), (&["--dynamic-memory"][..], true)] {
+        let outfile = temp.join("concat.wasm");
+        assert_cmd::Command::cargo_bin("clar2wasm")
+            .unwrap()
+            .arg(&contract)
+            .arg("-o")
+            .arg(&outfile)
+            .args(flags)
+            .assert()
+            .success();
+
+        let wasm = std::fs::read(outfile).unwrap();
+        wasmparser::validate(&wasm).unwrap();
+        let wat = wasmprinter::print_bytes(wasm).unwrap();
+        assert_eq!(wat.contains("memory.grow"), dynamic_memory);
+        assert_eq!(wat.contains("stdlib.set-stack-pointer"), dynamic_memory);
+    }
+
+    temp.close().unwrap();
+}
+
+#[test]
+fn test_clar2wasm_renders_errors() {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let contract = temp.join("invalid.clar");
+    std::fs::write(&contract, "(define-read-only (foo)\n  (+ u1 1))\n").unwrap();
+
+    assert_cmd::Command::cargo_bin("clar2wasm")
+        .unwrap()
+        .arg(&contract)
+        .assert()
+        .stderr(predicates::str::starts_with("analysis failed\nerror: "))
+        .stderr(predicates::str::contains("2 |   (+ u1 1))\n"))
+        .failure();
+
+    temp.close().unwrap();
+}
+
+#[test]
+fn test_crosscheck_report() {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let contract = temp.join("counter.clar");
+    std::fs::write(
+        &contract,
+        r#"
+        (define-data-var counter uint u0)
+        (define-public (add (n uint))
+            (begin
+                (var-set counter (+ (var-get counter) n))
+                (print (var-get counter))
+                (ok (var-get counter))))
+        "#,
+    )
+    .unwrap();
+
+    let calls = temp.join("calls.json");
+    std::fs::write(
+        &calls,
+        r#"[
+            { "contract": "counter", "function": "add", "args": ["u2"] },
+            {
+                "contract": "counter",
+                "function": "add",
+                "args": ["(+ u1 u2)"],
+                "sender": "ST1PQHQKV0RJXZFY1DGX8MNSNYVE3VGZJSRTPGZGM"
+            }
+        ]"#,
+    )
+    .unwrap();
+
+    assert_cmd::Command::cargo_bin("crosscheck")
+        .unwrap()
+        .arg(&contract)
+        .arg("--calls")
+        .arg(&calls)
+        .arg("--all-epochs")
+        .arg("--report")
+        .arg("-")
+        .assert()
+        .stdout(predicates::str::contains(r#""divergences": 0"#))
+        .stdout(predicates::str::contains(r#""compiled": "(ok u5)""#))
+        .success();
+
+    temp.close().unwrap();
+}
+
+#[test]
+fn test_crosscheck_reports_input_errors() {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let contract = temp.join("echo.clar");
+    std::fs::write(&contract, "(define-public (echo (n uint)) (ok n))").unwrap();
+
+    let calls = temp.join("calls.json");
+    std::fs::write(
+        &calls,
+        r#"[{ "contract": "echo", "function": "echo", "args": ["(+ u1"] }]"#,
+    )
+    .unwrap();
+
+    assert_cmd::Command::cargo_bin("crosscheck")
+        .unwrap()
+        .arg(&contract)
+        .arg("--calls")
+        .arg(&calls)
+        .arg("--report")
+        .arg("-")
+        .assert()
+        .stdout(predicates::str::contains(r#""divergences": 0"#))
+        .stdout(predicates::str::contains(r#""input_errors": 1"#))
+        .stderr(predicates::str::contains("call echo.echo: not run"))
+        .failure();
+
+    temp.close().unwrap();
+}
+
+#[test]
+fn test_replay_fixture() {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    std::fs::write(
+        temp.join("token.clar"),
+        r#"
+        (define-fungible-token gold)
+        (define-public (mint (amount uint))
+            (begin
+                (try! (ft-mint? gold amount tx-sender))
+                (ok amount)))
+        "#,
+    )
+    .unwrap();
+
+    let fixture = |expected_result: &str| {
+        format!(
+            r#"{{
+            "transactions": [
+                {{
+                    "type": "deploy",
+                    "contract_id": "SP000000000000000000002Q6VF78.token",
+                    "source": "token.clar",
+                    "block_height": 5
+                }},
+                {{
+                    "type": "call",
+                    "contract_id": "SP000000000000000000002Q6VF78.token",
+                    "function": "mint",
+                    "args": ["0x0100000000000000000000000000000064"],
+                    "sender": "SM2J6ZY48GV1EZ5V2V5RB9MP66SW86PYKKQVX8X0G",
+                    "block_height": 7,
+                    "expected": {{
+                        "result": "{expected_result}",
+                        "events": [{{
+                            "type": "ft_mint_event",
+                            "txid": "0x00",
+                            "ft_mint_event": {{
+                                "asset_identifier": "SP000000000000000000002Q6VF78.token::gold",
+                                "recipient": "SM2J6ZY48GV1EZ5V2V5RB9MP66SW86PYKKQVX8X0G",
+                                "amount": "100"
+                            }}
+                        }}]
+                    }}
+                }}
+            ]
+        }}"#
+        )
+    };
+
+    let matching = temp.join("matching.json");
+    std::fs::write(&matching, fixture("(ok u100)")).unwrap();
+    assert_cmd::Command::cargo_bin("replay")
+        .unwrap()
+        .arg(&matching)
+        .assert()
+        .stdout(predicates::str::contains(
+            "2 transactions replayed without divergence",
+        ))
+        .success();
+
+    let diverging = temp.join("diverging.json");
+    std::fs::write(&diverging, fixture("(ok u1)")).unwrap();
+    assert_cmd::Command::cargo_bin("replay")
+        .unwrap()
+        .arg(&diverging)
+        .assert()
+        .stdout(predicates::str::contains("#0 deploy"))
+        .stderr(predicates::str::contains(
+            "#1 call SP000000000000000000002Q6VF78.token.mint",
+        ))
+        .stderr(predicates::str::contains(
+            "expected result (ok u1), got (ok u100)",
+        ))
+        .failure();
+
+    temp.close().unwrap();
+}
+
+#[test]
+fn test_clarity_test_runner() {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let contract = temp.join("counter.clar");
+    std::fs::write(
+        &contract,
+        r#"
+        (define-data-var counter uint u0)
+        (define-public (add (n uint))
+            (begin
+                (var-set counter (+ (var-get counter) n))
+                (ok (var-get counter))))
+        "#,
+    )
+    .unwrap();
+
+    let tests = temp.join("counter_test.clar");
+    std::fs::write(
+        &tests,
+        r#"
+        (print "deployed")
+        (define-public (test-add)
+            (begin
+                (print block-height)
+                (asserts! (is-eq (contract-call? .counter add u2) (ok u2)) (err u1))
+                (ok true)))
+        (define-public (test-isolated)
+            (begin
+                (asserts! (is-eq (contract-call? .counter add u1) (ok u1)) (err u2))
+                (ok true)))
+        (define-public (test-failing)
+            (err u3))
+        (define-public (helper)
+            (err u4))
+        "#,
+    )
+    .unwrap();
+
+    assert_cmd::Command::cargo_bin("clarity-test")
+        .unwrap()
+        .arg(&contract)
+        .arg("--test")
+        .arg(&tests)
+        .arg("--block-height")
+        .arg("5")
+        .arg("--balance")
+        .arg("ST1PQHQKV0RJXZFY1DGX8MNSNYVE3VGZJSRTPGZGM=1000")
+        .arg("--coverage")
+        .arg(temp.join("lcov.info"))
+        .assert()
+        .stdout(predicates::str::contains("PASS test-add -> (ok true)"))
+        .stdout(predicates::str::contains("print u5"))
+        .stdout(predicates::str::contains("PASS test-isolated -> (ok true)"))
+        .stdout(predicates::str::contains("FAIL test-failing -> (err u3)"))
+        .stdout(predicates::str::contains("helper").not())
+        .stdout(predicates::str::contains("3 tests, 2 passed, 1 failed"))
+        .failure();
+
+    let lcov = std::fs::read_to_string(temp.join("lcov.info")).unwrap();
+    assert!(
+        lcov.contains(&format!("SF:{}\n", tests.display())),
+        "{lcov}"
+    );
+    // Both `asserts!` passed, but never failed.
+    assert!(lcov.contains("BRF:4\nBRH:2\n"), "{lcov}");
+    // The test contract is deployed once, whatever the number of tests.
+    let test_record = lcov
+        .split("end_of_record")
+        .find(|record| record.contains(&format!("SF:{}\n", tests.display())))
+        .unwrap();
+    assert!(test_record.contains("DA:2,1\n"), "{lcov}");
+
+    temp.close().unwrap();
+}
+
+#[test]
+fn test_clarity_bench() {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let contract = temp.join("counter.clar");
+    std::fs::write(
+        &contract,
+        r#"
+        (define-data-var counter uint u0)
+        (define-public (add (n uint))
+            (begin
+                (var-set counter (+ (var-get counter) n))
+                (ok (var-get counter))))
+        "#,
+    )
+    .unwrap();
+
+    assert_cmd::Command::cargo_bin("clarity-bench")
+        .unwrap()
+        .arg(&contract)
+        .args(["--function", "add", "--arg", "u2"])
+        .args(["--iterations", "5", "--warmup", "1", "--deployments", "2"])
+        .assert()
+        .stdout(predicates::str::contains(
+            "counter.add with 2 deployments and 5 calls",
+        ))
+        .stdout(predicates::str::contains("compile  compiled"))
+        .stdout(predicates::str::contains("deploy   interpreted"))
+        .stdout(predicates::str::contains("call     compiled"))
+        // One warmup call and five measured calls.
+        .stdout(predicates::str::contains("compiled result: (ok u12)"))
+        .stdout(predicates::str::contains("interpreted result: (ok u12)"))
+        .success();
+
+    assert_cmd::Command::cargo_bin("clarity-bench")
+        .unwrap()
+        .arg(&contract)
+        .args(["--function", "missing"])
+        .assert()
+        .stderr(predicates::str::contains("failed to call missing"))
+        .failure();
+
+    temp.close().unwrap();
+}
@@ -0,0 +1,340 @@
+//! Golden-file snapshots of the code generated for contracts.
+//!
+//! The contracts of `tests/contracts/` and the snippets of
+//! `tests/snapshots/words/` (one per module of `src/words/`) are compiled,
+//! printed as WAT without the standard library, and compared to their golden
+//! file in `tests/snapshots/golden/`. A mismatch reports the change in module
+//! size and instruction count, followed by the lines which changed.
+//!
+//! Run with `UPDATE_SNAPSHOTS=1` to accept the changes and rewrite the golden
+//! files, including the missing ones. Without it, a missing golden file is a
+//! failure.
+
+use std::collections::HashSet;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use clar2wasm::compile;
+use clarity::types::StacksEpochId;
+use clarity::vm::costs::LimitedCostTracker;
+use clarity::vm::database::MemoryBackingStore;
+use clarity::vm::types::{QualifiedContractIdentifier, StandardPrincipalData};
+use clarity::vm::ClarityVersion;
+use regex::Regex;
+
+// Pinned, so that the snapshots don't change with the latest epoch.
+const EPOCH: StacksEpochId = StacksEpochId::Epoch25;
+const CLARITY_VERSION: ClarityVersion = ClarityVersion::Clarity2;
+
+/// Maximum number of changed lines reported for a snapshot.
+const MAX_DIFF_LINES: usize = 40;
+
+fn update_mode() -> bool {
+    std::env::var("UPDATE_SNAPSHOTS").is_ok_and(|v| !v.is_empty() && v != "0")
+}
+
+/// Splits a module printed by wasmprinter into its top-level items, dedented.
+fn module_items(wat: &str) -> Vec<String> {
+    let mut items = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+
+    for (i, c) in wat.char_indices() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '"' => in_string = true,
+            '(' => {
+                depth += 1;
+                if depth == 2 {
+                    start = i;
+                }
+            }
+            ')' => {
+                if depth == 2 {
+                    let item: Vec<_> = wat[start..=i]
+                        .lines()
+                        .map(|line| line.strip_prefix("  ").unwrap_or(line))
+                        .collect();
+                    items.push(item.join("\n"));
+                }
+                depth -= 1;
+            }
+            _ => {}
+        }
+    }
+    items
+}
+
+/// Removes the indices, which change whenever the standard library does.
+fn normalize(item: &str) -> String {
+    let indices = Regex::new(r" ?\((;\d+;|type \d+)\)").unwrap();
+    indices.replace_all(item, "").into_owned()
+}
+
+fn func_name(item: &str) -> Option<&str> {
+    item.strip_prefix("(func $")?.split([' ', '\n', ')']).next()
+}
+
+/// The items of the standard library, which are stripped from snapshots.
+struct Stdlib {
+    items: HashSet<String>,
+    funcs: HashSet<String>,
+    size: usize,
+}
+
+impl Stdlib {
+    fn load() -> Self {
+        let standard_lib_wasm = include_bytes!(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/src/standard/standard.wasm"
+        ));
+        // Round-trip through walrus, like the generator does.
+        let wasm = walrus::Module::from_buffer(standard_lib_wasm)
+            .unwrap()
+            .emit_wasm();
+        let items: HashSet<_> = module_items(&wasmprinter::print_bytes(&wasm).unwrap())
+            .iter()
+            .map(|item| normalize(item))
+            .collect();
+        let funcs = items
+            .iter()
+            .filter_map(|item| func_name(item))
+            .map(str::to_owned)
+            .collect();
+        Self {
+            items,
+            funcs,
+            size: wasm.len(),
+        }
+    }
+
+    fn contains(&self, item: &str) -> bool {
+        item.starts_with("(type")
+            // custom sections, like the producers of the module
+            || item.starts_with("(@")
+            || func_name(item).is_some_and(|name| self.funcs.contains(name))
+            || self.items.contains(item)
+    }
+}
+
+/// Whether a line of a function body printed by wasmprinter is an
+/// instruction. The closing parenthesis, the locals and the lines which only
+/// delimit blocks are not.
+fn is_instruction(line: &str) -> bool {
+    let opcode = line.split_whitespace().next().unwrap_or_default();
+    !matches!(
+        opcode,
+        "" | ")" | "(local" | "block" | "loop" | "else" | "end"
+    )
+}
+
+/// The golden file content of a module.
+fn snapshot(wasm: &[u8], stdlib: &Stdlib) -> String {
+    let items: Vec<_> = module_items(&wasmprinter::print_bytes(wasm).unwrap())
+        .iter()
+        .map(|item| normalize(item))
+        .filter(|item| !stdlib.contains(item))
+        .collect();
+    let instructions: usize = items
+        .iter()
+        .filter(|item| item.starts_with("(func"))
+        .flat_map(|item| item.lines().skip(1))
+        .filter(|line| is_instruction(line))
+        .count();
+
+    format!(
+        ";; size: {} bytes\n;; instructions: {instructions}\n{}\n",
+        wasm.len().saturating_sub(stdlib.size),
+        items.join("\n")
+    )
+}
+
+/// Reads a `;; {key}: {value}` header of a snapshot.
+fn header(snapshot: &str, key: &str) -> Option<i64> {
+    snapshot.lines().find_map(|line| {
+        line.strip_prefix(";; ")?
+            .strip_prefix(key)?
+            .strip_prefix(": ")?
+            .split(' ')
+            .next()?
+            .parse()
+            .ok()
+    })
+}
+
+/// Describes the changes between two snapshots.
+fn describe_changes(expected: &str, actual: &str) -> String {
+    let mut description = String::new();
+    for key in ["size", "instructions"] {
+        if let (Some(old), Some(new)) = (header(expected, key), header(actual, key)) {
+            let _ = write!(description, "{key} {old} -> {new} ({:+}), ", new - old);
+        }
+    }
+    description.truncate(description.len().saturating_sub(2));
+
+    // Only the lines between the common prefix and suffix are reported.
+    let expected: Vec<_> = expected.lines().skip(2).collect();
+    let actual: Vec<_> = actual.lines().skip(2).collect();
+    let prefix = expected
+        .iter()
+        .zip(&actual)
+        .take_while(|(a, b)| a == b)
+        .count();
+    let suffix = expected[prefix..]
+        .iter()
+        .rev()
+        .zip(actual[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let removed = expected[prefix..expected.len() - suffix]
+        .iter()
+        .map(|line| format!("- {line}"));
+    let added = actual[prefix..actual.len() - suffix]
+        .iter()
+        .map(|line| format!("+ {line}"));
+    let changes: Vec<_> = removed.chain(added).collect();
+
+    let _ = write!(description, "\n  @@ line {} @@", prefix + 3);
+    for line in changes.iter().take(MAX_DIFF_LINES) {
+        let _ = write!(description, "\n  {line}");
+    }
+    if changes.len() > MAX_DIFF_LINES {
+        let _ = write!(
+            description,
+            "\n  ... {} more lines",
+            changes.len() - MAX_DIFF_LINES
+        );
+    }
+    description
+}
+
+/// Compiles the `.clar` files of `sources` in alphabetical order, so that
+/// contracts can call the ones deployed before them, and checks their
+/// snapshots against the golden files in `golden`.
+fn check_snapshots(sources: &Path, golden: &Path) {
+    let stdlib = Stdlib::load();
+    let mut datastore = MemoryBackingStore::new();
+    let update = update_mode();
+
+    let mut paths: Vec<PathBuf> = fs::read_dir(sources)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "clar"))
+        .collect();
+    paths.sort();
+
+    fs::create_dir_all(golden).unwrap();
+    let mut mismatches = Vec::new();
+    for path in paths {
+        let name = path.file_stem().unwrap().to_str().unwrap();
+        let source = fs::read_to_string(&path).unwrap();
+        let contract_id =
+            QualifiedContractIdentifier::new(StandardPrincipalData::transient(), name.into());
+
+        let actual = match compile(
+            &source,
+            &contract_id,
+            LimitedCostTracker::new_free(),
+            CLARITY_VERSION,
+            EPOCH,
+            &mut datastore.as_analysis_db(),
+            false,
+        ) {
+            Ok(mut result) => {
+                datastore
+                    .as_analysis_db()
+                    .execute(|analysis_db| {
+                        analysis_db.insert_contract(&contract_id, &result.contract_analysis)
+                    })
+                    .expect("Failed to insert contract analysis.");
+                snapshot(&result.module.emit_wasm(), &stdlib)
+            }
+            // Errors are part of the snapshot, a contract which stops compiling
+            // is a change too.
+            Err(error) => {
+                let mut snapshot = format!(";; {} error\n", error.phase());
+                for diagnostic in error.diagnostics() {
+                    let _ = writeln!(snapshot, ";; {diagnostic}");
+                }
+                snapshot
+            }
+        };
+
+        let golden_file = golden.join(format!("{name}.wat"));
+        match fs::read_to_string(&golden_file) {
+            Ok(expected) if expected == actual => {}
+            Ok(expected) if !update => {
+                mismatches.push(format!("{name}: {}", describe_changes(&expected, &actual)));
+            }
+            Err(_) if !update => mismatches.push(format!("{name}: missing golden file")),
+            _ => fs::write(&golden_file, actual).unwrap(),
+        }
+    }
+
+    assert!(
+        mismatches.is_empty(),
+        "{} snapshots changed, run with UPDATE_SNAPSHOTS=1 to accept the changes:\n{}",
+        mismatches.len(),
+        mismatches.join("\n")
+    );
+}
+
+fn snapshots_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/snapshots")
+}
+
+#[test]
+fn contracts_snapshots() {
+    check_snapshots(
+        &Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/contracts"),
+        &snapshots_dir().join("golden/contracts"),
+    );
+}
+
+#[test]
+fn words_snapshots() {
+    check_snapshots(
+        &snapshots_dir().join("words"),
+        &snapshots_dir().join("golden/words"),
+    );
+}
+
+#[test]
+fn module_items_are_split_and_normalized() {
+    let wat = r#"(module
+  (type (;0;) (func (param i32)))
+  (func $f (;0;) (type 0) (param i32)
+    local.get 0
+    drop
+  )
+  (data (;0;) (i32.const 0) "a \") (")
+)"#;
+    let items: Vec<_> = module_items(wat).iter().map(|i| normalize(i)).collect();
+    assert_eq!(
+        items,
+        [
+            "(type (func (param i32)))",
+            "(func $f (param i32)\n  local.get 0\n  drop\n)",
+            r#"(data (i32.const 0) "a \") (")"#,
+        ]
+    );
+    assert_eq!(func_name(&items[1]), Some("f"));
+}
+
+#[test]
+fn block_delimiters_are_not_instructions() {
+    let body =
+        "  block ;; label = @1\n    local.get 0\n    br_if 0 (;@1;)\n  end\n  i32.const 1\n)";
+    assert_eq!(body.lines().filter(|line| is_instruction(line)).count(), 3);
+}
@@ -1,4 +1,6 @@
 #![cfg(test)]
+// These tests drive the standard library directly through wasmtime's API.
+#![cfg(not(feature = "wasmi"))]
 
 mod property_tests;
 mod unit_tests;
@@ -1,5 +1,6 @@
 use clar2wasm::tools::{crosscheck_compare_only, crosscheck_compare_only_with_expected_error};
 use clarity::vm::errors::{Error, RuntimeErrorType};
+use proptest::prelude::any;
 use proptest::proptest;
 
 use crate::{int, uint};
@@ -7,6 +8,7 @@ use crate::{int, uint};
 const ONE_VALUE_OPS: [&str; 2] = ["sqrti", "log2"];
 const TWO_VALUE_OPS: [&str; 2] = ["pow", "mod"];
 const MULTI_VALUE_OPS: [&str; 4] = ["+", "-", "*", "/"];
+const INLINE_OPS: [&str; 6] = ["+", "-", "<", "<=", ">", ">="];
 
 proptest! {
     #![proptest_config(super::runtime_config())]
@@ -100,3 +102,35 @@ proptest! {
         }
     }
 }
+
+proptest! {
+    #![proptest_config(super::runtime_config())]
+
+    #[test]
+    fn crossprop_small_constant_int(v1 in int(), v2 in any::<u64>()) {
+        for op in &INLINE_OPS {
+            crosscheck_compare_only_with_expected_error(
+                &format!("({op} {v1} {v2})"),
+                |e| matches!(e, Error::Runtime(
+                    RuntimeErrorType::ArithmeticOverflow |
+                    RuntimeErrorType::ArithmeticUnderflow, _))
+            )
+        }
+    }
+}
+
+proptest! {
+    #![proptest_config(super::runtime_config())]
+
+    #[test]
+    fn crossprop_small_constant_uint(v1 in uint(), v2 in any::<u64>()) {
+        for op in &INLINE_OPS {
+            crosscheck_compare_only_with_expected_error(
+                &format!("({op} {v1} u{v2})"),
+                |e| matches!(e, Error::Runtime(
+                    RuntimeErrorType::ArithmeticOverflow |
+                    RuntimeErrorType::ArithmeticUnderflow, _))
+            )
+        }
+    }
+}
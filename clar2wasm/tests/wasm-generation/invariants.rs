@@ -0,0 +1,338 @@
+//! Stateful fuzzing of contracts.
+//!
+//! A contract is deployed in a fresh [TestEnvironment], which then receives a
+//! random sequence of calls to its public functions, with random arguments and
+//! senders. After each call, the invariants of the contract, read-only functions
+//! without arguments returning `true` when they hold, are checked. Failing
+//! sequences, and calls failing with an error which isn't a runtime error of
+//! the contract, are shrunk by proptest to a minimal sequence of calls.
+
+use std::cell::RefCell;
+use std::fmt;
+
+use clar2wasm::tools::TestEnvironment;
+use clarity::vm::analysis::ContractAnalysis;
+use clarity::vm::errors::Error;
+use clarity::vm::types::{FunctionType, PrincipalData};
+use clarity::vm::Value;
+use proptest::prelude::*;
+use proptest::test_runner::{TestCaseError, TestError, TestRunner};
+
+use crate::{has_prop_values, PropValue};
+
+/// Maximum number of blocks mined before a call.
+const MAX_BLOCKS_BETWEEN_CALLS: u32 = 3;
+
+/// Balance of each sender at the start of a sequence.
+const SENDER_BALANCE: u128 = 1_000_000_000;
+
+/// A call to a public function of the contract under test.
+#[derive(Debug, Clone)]
+pub struct Call {
+    pub function: String,
+    pub args: Vec<PropValue>,
+    pub sender: PrincipalData,
+    /// Number of blocks mined before the call.
+    pub blocks: u32,
+}
+
+impl fmt::Display for Call {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "({}", self.function)?;
+        for arg in &self.args {
+            write!(f, " {arg}")?;
+        }
+        write!(f, ") from {}", self.sender)?;
+        if self.blocks > 0 {
+            write!(f, " after {} blocks", self.blocks)?;
+        }
+        Ok(())
+    }
+}
+
+/// A sequence of calls breaking an invariant.
+#[derive(Debug)]
+pub struct Failure {
+    pub reason: String,
+    /// The first failing sequence generated.
+    pub original: Vec<Call>,
+    /// The minimal sequence proptest shrunk it to.
+    pub minimal: Vec<Call>,
+}
+
+/// The contract under test, and the contracts it depends on.
+pub struct InvariantFuzzer<'a> {
+    /// Contracts deployed before the target, as `(name, source)`.
+    pub dependencies: Vec<(&'a str, &'a str)>,
+    pub target: (&'a str, &'a str),
+    /// Names of the invariant functions of the target.
+    pub invariants: Vec<&'a str>,
+    pub senders: Vec<PrincipalData>,
+    pub max_calls: usize,
+}
+
+impl<'a> InvariantFuzzer<'a> {
+    pub fn new(name: &'a str, source: &'a str, invariants: &[&'a str]) -> Self {
+        Self {
+            dependencies: vec![],
+            target: (name, source),
+            invariants: invariants.to_vec(),
+            senders: [
+                "S1G2081040G2081040G2081040G208105NK8PE5",
+                "ST1PQHQKV0RJXZFY1DGX8MNSNYVE3VGZJSRTPGZGM",
+                "ST2CY5V39NHDPWSXMW9QDT3HC3GD6Q6XX4CFRK9AG",
+            ]
+            .into_iter()
+            .map(|p| PrincipalData::parse(p).unwrap())
+            .collect(),
+            max_calls: 16,
+        }
+    }
+
+    /// Deploys the contracts in a fresh environment, with funded senders.
+    fn environment(&self) -> TestEnvironment {
+        let mut env = TestEnvironment::default();
+        for sender in &self.senders {
+            env.credit_stx(sender, SENDER_BALANCE).unwrap();
+        }
+        for (name, source) in self.dependencies.iter().chain([&self.target]) {
+            env.init_contract_with_snippet(name, source)
+                .unwrap_or_else(|e| panic!("failed to deploy {name}: {e:?}"));
+        }
+        env
+    }
+
+    /// Generates calls to the public functions found in the analysis of the
+    /// target, skipping those taking arguments for which no values can be
+    /// generated, like traits.
+    fn call(&self, analysis: &ContractAnalysis) -> impl Strategy<Value = Call> {
+        let calls: Vec<_> = analysis
+            .public_function_types
+            .iter()
+            .filter_map(|(name, ty)| {
+                let FunctionType::Fixed(fixed) = ty else {
+                    panic!("unexpected signature for {name}: {ty:?}")
+                };
+                fixed
+                    .args
+                    .iter()
+                    .all(|arg| has_prop_values(&arg.signature))
+                    .then_some((name, fixed))
+            })
+            .map(|(name, fixed)| {
+                let function = name.to_string();
+                let args: Vec<_> = fixed
+                    .args
+                    .iter()
+                    .map(|arg| PropValue::from_type(arg.signature.clone()))
+                    .collect();
+                (Just(function), args).boxed()
+            })
+            .collect();
+        assert!(!calls.is_empty(), "no public function can be called");
+
+        (
+            proptest::strategy::Union::new(calls),
+            proptest::sample::select(self.senders.clone()),
+            0..=MAX_BLOCKS_BETWEEN_CALLS,
+        )
+            .prop_map(|((function, args), sender, blocks)| Call {
+                function,
+                args,
+                sender,
+                blocks,
+            })
+    }
+
+    /// Checks all the invariants of the target.
+    fn check_invariants(&self, env: &mut TestEnvironment) -> Result<(), String> {
+        for invariant in &self.invariants {
+            match env.call_contract_function(self.target.0, invariant, &[], None) {
+                Ok(Value::Bool(true)) => {}
+                result => return Err(format!("invariant `{invariant}` returned {result:?}")),
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs `calls` against a fresh deployment of the target, checking the
+    /// invariants after deployment and after each call.
+    pub fn check_sequence(&self, calls: &[Call]) -> Result<(), TestCaseError> {
+        let mut env = self.environment();
+        self.check_invariants(&mut env)
+            .map_err(|e| TestCaseError::fail(format!("{e} after deployment")))?;
+
+        for (i, call) in calls.iter().enumerate() {
+            if call.blocks > 0 {
+                env.advance_chain_tip(call.blocks);
+            }
+            let args: Vec<Value> = call.args.iter().map(|a| a.inner().clone()).collect();
+            match env.call_contract_function(
+                self.target.0,
+                &call.function,
+                &args,
+                Some(call.sender.clone()),
+            ) {
+                // Calls returning an err response or failing at runtime are
+                // rolled back, they cannot break an invariant.
+                Ok(Value::Response(_)) | Err(Error::Runtime(..) | Error::ShortReturn(_)) => {}
+                // Any other error comes from the compiler or the host.
+                result => {
+                    return Err(TestCaseError::fail(format!(
+                        "call #{i}: {call} returned {result:?}"
+                    )))
+                }
+            }
+            self.check_invariants(&mut env)
+                .map_err(|e| TestCaseError::fail(format!("{e} after call #{i}: {call}")))?;
+        }
+        Ok(())
+    }
+
+    /// Runs sequences of calls against the target, and returns the first
+    /// sequence breaking an invariant with its shrunk version, if any.
+    pub fn run(&self, runner: &mut TestRunner) -> Result<(), Failure> {
+        let analysis = self
+            .environment()
+            .get_contract_analysis(self.target.0)
+            .expect("target analysis should be available");
+        let sequences = prop::collection::vec(self.call(&analysis), 1..=self.max_calls);
+
+        let original = RefCell::new(None);
+        let result = runner.run(&sequences, |calls| {
+            let result = self.check_sequence(&calls);
+            if result.is_err() {
+                original.borrow_mut().get_or_insert(calls);
+            }
+            result
+        });
+
+        match result {
+            Ok(()) => Ok(()),
+            Err(TestError::Fail(reason, minimal)) => Err(Failure {
+                reason: reason.to_string(),
+                original: original.into_inner().unwrap_or_else(|| minimal.clone()),
+                minimal,
+            }),
+            Err(TestError::Abort(reason)) => panic!("fuzzing aborted: {reason}"),
+        }
+    }
+}
+
+/// Panics with the minimal failing sequence if an invariant can be broken.
+pub fn assert_invariants_hold(fuzzer: &InvariantFuzzer) {
+    if let Err(failure) = fuzzer.run(&mut TestRunner::new(crate::runtime_config())) {
+        let calls: Vec<_> = failure.minimal.iter().map(Call::to_string).collect();
+        panic!(
+            "{}\nminimal sequence:\n  {}",
+            failure.reason,
+            calls.join("\n  ")
+        );
+    }
+}
+
+const VAULT: &str = r#"
+    (define-map deposits principal uint)
+    (define-data-var total uint u0)
+
+    (define-public (deposit (amount uint))
+        (let ((sender tx-sender))
+            (try! (stx-transfer? amount sender (as-contract tx-sender)))
+            (map-set deposits sender (+ amount (default-to u0 (map-get? deposits sender))))
+            (ok (var-set total (+ (var-get total) amount)))))
+
+    (define-public (withdraw (amount uint))
+        (let ((sender tx-sender)
+              (balance (default-to u0 (map-get? deposits sender))))
+            (asserts! (<= amount balance) (err u1))
+            (try! (as-contract (stx-transfer? amount tx-sender sender)))
+            (map-set deposits sender (- balance amount))
+            (ok (var-set total (- (var-get total) amount)))))
+
+    (define-read-only (total-is-backed)
+        (is-eq (var-get total) (stx-get-balance (as-contract tx-sender))))
+"#;
+
+#[test]
+fn vault_invariants_hold() {
+    assert_invariants_hold(&InvariantFuzzer::new("vault", VAULT, &["total-is-backed"]));
+}
+
+#[test]
+fn broken_invariant_is_shrunk() {
+    let counter = r#"
+        (define-data-var counter uint u0)
+        (define-public (add (n uint))
+            (ok (var-set counter (+ (var-get counter) n))))
+        (define-public (reset)
+            (ok (var-set counter u0)))
+        (define-read-only (below-limit)
+            (< (var-get counter) u100))
+    "#;
+    let fuzzer = InvariantFuzzer::new("counter", counter, &["below-limit"]);
+
+    let failure = fuzzer
+        .run(&mut TestRunner::new(crate::runtime_config()))
+        .expect_err("the invariant should be broken");
+    assert!(failure.reason.contains("below-limit"), "{failure:?}");
+
+    // The shrunk sequence still breaks the invariant, and is no larger than
+    // the original one: fewer calls, adding less in total.
+    assert!(
+        fuzzer.check_sequence(&failure.minimal).is_err(),
+        "{failure:?}"
+    );
+    let added = |calls: &[Call]| -> u128 {
+        calls
+            .iter()
+            .flat_map(|call| &call.args)
+            .map(|arg| match arg.inner() {
+                Value::UInt(n) => *n,
+                _ => 0,
+            })
+            .sum()
+    };
+    assert!(
+        failure.minimal.len() <= failure.original.len(),
+        "{failure:?}"
+    );
+    assert!(
+        added(&failure.minimal) <= added(&failure.original),
+        "{failure:?}"
+    );
+}
+
+#[test]
+fn invariants_see_the_chain_tip() {
+    let timelock = r#"
+        (define-data-var unlocked bool false)
+        (define-public (unlock)
+            (ok (var-set unlocked (> block-height u2))))
+        (define-read-only (still-locked)
+            (not (var-get unlocked)))
+    "#;
+    let fuzzer = InvariantFuzzer::new("timelock", timelock, &["still-locked"]);
+
+    let calls = fuzzer
+        .run(&mut TestRunner::new(crate::runtime_config()))
+        .expect_err("the invariant should be broken")
+        .minimal;
+
+    // Shrinking stops once no call can be removed or mined earlier.
+    let blocks: u32 = calls.iter().map(|call| call.blocks).sum();
+    assert_eq!(blocks, 3, "{calls:?}");
+}
+
+#[test]
+fn functions_taking_traits_are_not_called() {
+    let contract = r#"
+        (define-trait pingable ((ping () (response bool uint))))
+        (define-public (ping-other (other <pingable>))
+            (contract-call? other ping))
+        (define-public (ping)
+            (ok true))
+        (define-read-only (always)
+            true)
+    "#;
+    assert_invariants_hold(&InvariantFuzzer::new("pinger", contract, &["always"]));
+}
@@ -0,0 +1,47 @@
+use clar2wasm::marshal::{Layout, ValueBuilder};
+use clar2wasm::wasm_utils::{get_type_in_memory_size, get_type_size};
+use clarity::vm::Value;
+use proptest::prelude::*;
+
+use crate::{prop_signature, PropValue};
+
+proptest! {
+    #![proptest_config(super::runtime_config())]
+
+    #[test]
+    fn written_value_is_read_back(
+        (ty, value) in prop_signature()
+            .prop_ind_flat_map2(|ty| PropValue::from_type(ty).prop_map_into::<Value>())
+    ) {
+        let layout = Layout::new(&ty).unwrap();
+        let repr_size = get_type_size(&ty);
+        let mut memory = vec![0; (repr_size + get_type_in_memory_size(&ty, true)) as usize];
+
+        let (written, _) = layout.write(&mut memory, 0, repr_size, &value, true).unwrap();
+        prop_assert_eq!(written, repr_size);
+        prop_assert_eq!(layout.visit(&memory, 0, &mut ValueBuilder).unwrap(), value);
+    }
+
+    #[test]
+    fn written_content_is_read_back(
+        (ty, value) in prop_signature()
+            .prop_ind_flat_map2(|ty| PropValue::from_type(ty).prop_map_into::<Value>())
+    ) {
+        let layout = Layout::new(&ty).unwrap();
+        let repr_size = get_type_size(&ty);
+        let mut memory = vec![0; (repr_size + get_type_in_memory_size(&ty, true)) as usize];
+
+        // Without a representation, the content of an in-memory value is all
+        // there is to read.
+        let length = if layout.is_in_memory() {
+            layout.write(&mut memory, 0, 0, &value, false).unwrap().1
+        } else {
+            layout.write(&mut memory, 0, repr_size, &value, false).unwrap();
+            repr_size
+        };
+        prop_assert_eq!(
+            layout.visit_content(&memory, 0, length, &mut ValueBuilder).unwrap(),
+            value
+        );
+    }
+}
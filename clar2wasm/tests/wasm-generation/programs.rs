@@ -0,0 +1,1023 @@
+//! Grammar-based differential fuzzing of whole Clarity programs.
+//!
+//! The other modules plug generated values into a fixed snippet per word. The
+//! strategies below instead generate well-typed programs, composing nested
+//! expressions, definitions and calls between contracts, so that the
+//! interactions between words are exercised too.
+//!
+//! The expressions cover the arithmetic, bitwise and comparison words, the
+//! sequence words (including `fold`, `map` and `filter`), optionals,
+//! responses, tuples, `let`, `match`, data vars and maps, principals, STX and
+//! token operations, reads of the chain state, and the early returns
+//! (`asserts!`, `try!`, `unwrap!` and `unwrap-err!`) in function bodies.
+//!
+//! Each program is deployed with both the compiler and the interpreter. When
+//! they diverge, proptest shrinks the program and the minimal one is saved in
+//! the `corpus` directory, whose programs are all replayed by
+//! `corpus_programs_do_not_diverge`.
+
+use std::collections::hash_map::DefaultHasher;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+
+use clar2wasm::tools::crosscheck_multi_contract_compare_only;
+use clarity::vm::ContractName;
+use proptest::prelude::*;
+use proptest::strategy::Union;
+use proptest::test_runner::{TestError, TestRunner};
+
+/// Nesting depth of the top-level expression of a contract.
+const MAIN_DEPTH: u32 = 3;
+/// Nesting depth of the bodies of the functions and constants.
+const BODY_DEPTH: u32 = 2;
+/// Marker preceding the name of each contract in a corpus file.
+const CONTRACT_MARKER: &str = ";; contract: ";
+
+/// Helpers always defined, so that `fold`, `map` and `filter` have functions
+/// to work with even when none of the generated ones fits, and the tokens of
+/// the token operations.
+const PRELUDE: &str = "(define-fungible-token ft)
+(define-non-fungible-token nft uint)
+(define-private (add-int (x int) (acc int)) (+ x acc))
+(define-private (add-uint (x uint) (acc uint)) (+ x acc))
+(define-private (double (x int)) (* x 2))
+(define-private (is-positive (x int)) (> x 0))
+(define-private (as-uint (x int)) (if (< x 0) u0 (to-uint x)))";
+
+/// The subset of Clarity types used by the generated programs. Tuples always
+/// have two fields, `a` and `b`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Ty {
+    Int,
+    UInt,
+    Bool,
+    Principal,
+    Buff(u32),
+    Ascii(u32),
+    Optional(Box<Ty>),
+    Response(Box<Ty>, Box<Ty>),
+    List(Box<Ty>, u32),
+    Tuple(Box<Ty>, Box<Ty>),
+}
+
+impl fmt::Display for Ty {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Ty::Int => write!(f, "int"),
+            Ty::UInt => write!(f, "uint"),
+            Ty::Bool => write!(f, "bool"),
+            Ty::Principal => write!(f, "principal"),
+            Ty::Buff(len) => write!(f, "(buff {len})"),
+            Ty::Ascii(len) => write!(f, "(string-ascii {len})"),
+            Ty::Optional(ty) => write!(f, "(optional {ty})"),
+            Ty::Response(ok, err) => write!(f, "(response {ok} {err})"),
+            Ty::List(ty, len) => write!(f, "(list {len} {ty})"),
+            Ty::Tuple(a, b) => write!(f, "(tuple (a {a}) (b {b}))"),
+        }
+    }
+}
+
+fn list_of(ty: &Ty, len: u32) -> Ty {
+    Ty::List(Box::new(ty.clone()), len)
+}
+
+fn optional_of(ty: &Ty) -> Ty {
+    Ty::Optional(Box::new(ty.clone()))
+}
+
+fn leaf_ty() -> impl Strategy<Value = Ty> {
+    prop_oneof![
+        Just(Ty::Int),
+        Just(Ty::UInt),
+        Just(Ty::Bool),
+        Just(Ty::Principal),
+        (1u32..=8).prop_map(Ty::Buff),
+        (1u32..=8).prop_map(Ty::Ascii),
+    ]
+}
+
+fn ty() -> BoxedStrategy<Ty> {
+    leaf_ty()
+        .prop_recursive(2, 8, 2, |inner| {
+            prop_oneof![
+                inner.clone().prop_map(Box::new).prop_map(Ty::Optional),
+                (inner.clone(), inner.clone())
+                    .prop_map(|(ok, err)| Ty::Response(Box::new(ok), Box::new(err))),
+                (inner.clone(), 1u32..=4).prop_map(|(ty, len)| Ty::List(Box::new(ty), len)),
+                (inner.clone(), inner).prop_map(|(a, b)| Ty::Tuple(Box::new(a), Box::new(b))),
+            ]
+        })
+        .boxed()
+}
+
+/// Types of the bindings introduced by `let` and `match`.
+fn binding_ty() -> impl Strategy<Value = Ty> {
+    prop_oneof![
+        3 => leaf_ty(),
+        1 => leaf_ty().prop_map(|ty| list_of(&ty, 4)),
+    ]
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Private,
+    ReadOnly,
+    Public,
+}
+
+impl Kind {
+    fn keyword(&self) -> &'static str {
+        match self {
+            Kind::Private => "define-private",
+            Kind::ReadOnly => "define-read-only",
+            Kind::Public => "define-public",
+        }
+    }
+}
+
+/// A function callable from a scope, either locally or with a `contract-call?`.
+#[derive(Debug, Clone)]
+struct Signature {
+    name: String,
+    args: Vec<Ty>,
+    ret: Ty,
+    contract: Option<String>,
+}
+
+impl Signature {
+    fn local(name: &str, args: Vec<Ty>, ret: Ty) -> Self {
+        Self {
+            name: name.to_string(),
+            args,
+            ret,
+            contract: None,
+        }
+    }
+
+    fn call(&self, args: &[String]) -> String {
+        let mut call = match &self.contract {
+            Some(contract) => format!("(contract-call? .{contract} {}", self.name),
+            None => format!("({}", self.name),
+        };
+        for arg in args {
+            call.push(' ');
+            call.push_str(arg);
+        }
+        call.push(')');
+        call
+    }
+}
+
+fn prelude_signatures() -> Vec<Signature> {
+    vec![
+        Signature::local("add-int", vec![Ty::Int, Ty::Int], Ty::Int),
+        Signature::local("add-uint", vec![Ty::UInt, Ty::UInt], Ty::UInt),
+        Signature::local("double", vec![Ty::Int], Ty::Int),
+        Signature::local("is-positive", vec![Ty::Int], Ty::Bool),
+        Signature::local("as-uint", vec![Ty::Int], Ty::UInt),
+    ]
+}
+
+/// Everything an expression may refer to.
+#[derive(Debug, Clone, Default)]
+struct Scope {
+    /// Constants, function arguments and local bindings.
+    values: Vec<(String, Ty)>,
+    /// Data variables.
+    vars: Vec<(String, Ty)>,
+    /// Maps, with their key and value types.
+    maps: Vec<(String, Ty, Ty)>,
+    functions: Vec<Signature>,
+    /// Whether the expression may modify variables, maps and balances.
+    writable: bool,
+    /// Return type of the enclosing function, for the early returns.
+    returns: Option<Ty>,
+    /// Number of local bindings, used to name new ones uniquely.
+    bindings: u32,
+}
+
+impl Scope {
+    /// Returns the name of a new binding of type `ty`, and the scope in which
+    /// it is visible.
+    fn bind(&self, ty: &Ty) -> (String, Scope) {
+        let name = format!("v{}", self.bindings);
+        let mut scope = self.clone();
+        scope.values.push((name.clone(), ty.clone()));
+        scope.bindings += 1;
+        (name, scope)
+    }
+
+    fn local_functions(&self) -> impl Iterator<Item = &Signature> {
+        self.functions.iter().filter(|f| f.contract.is_none())
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// A literal of type `ty`. Optional and response literals are wrapped in an
+/// `if`, so that both of their inner types are known to the type checker.
+fn literal(ty: &Ty) -> BoxedStrategy<String> {
+    match ty {
+        Ty::Int => prop_oneof![8 => -100i128..100, 1 => any::<i128>()]
+            .prop_map(|i| i.to_string())
+            .boxed(),
+        Ty::UInt => prop_oneof![8 => 0u128..100, 1 => any::<u128>()]
+            .prop_map(|u| format!("u{u}"))
+            .boxed(),
+        Ty::Bool => any::<bool>().prop_map(|b| b.to_string()).boxed(),
+        Ty::Principal => prop::sample::select(vec![
+            "'S1G2081040G2081040G2081040G208105NK8PE5",
+            "'ST1PQHQKV0RJXZFY1DGX8MNSNYVE3VGZJSRTPGZGM",
+            "'S1G2081040G2081040G2081040G208105NK8PE5.main",
+        ])
+        .prop_map(String::from)
+        .boxed(),
+        Ty::Buff(len) => prop::collection::vec(any::<u8>(), 0..=*len as usize)
+            .prop_map(|bytes| format!("0x{}", hex(&bytes)))
+            .boxed(),
+        Ty::Ascii(len) => prop::collection::vec(b'a'..=b'z', 0..=*len as usize)
+            .prop_map(|bytes| format!("\"{}\"", String::from_utf8_lossy(&bytes)))
+            .boxed(),
+        Ty::Optional(inner) => (any::<bool>(), literal(inner))
+            .prop_map(|(is_some, v)| format!("(if {is_some} (some {v}) none)"))
+            .boxed(),
+        Ty::Response(ok, err) => (any::<bool>(), literal(ok), literal(err))
+            .prop_map(|(is_ok, o, e)| format!("(if {is_ok} (ok {o}) (err {e}))"))
+            .boxed(),
+        Ty::List(inner, len) => prop::collection::vec(literal(inner), 1..=*len as usize)
+            .prop_map(|items| format!("(list {})", items.join(" ")))
+            .boxed(),
+        Ty::Tuple(a, b) => (literal(a), literal(b))
+            .prop_map(|(a, b)| format!("(tuple (a {a}) (b {b}))"))
+            .boxed(),
+    }
+}
+
+/// An expression of type `ty`, nested at most `depth` times.
+fn expr(ty: &Ty, scope: &Scope, depth: u32) -> BoxedStrategy<String> {
+    let mut leaves = vec![literal(ty)];
+    for (name, value_ty) in &scope.values {
+        if value_ty == ty {
+            leaves.push(Just(name.clone()).boxed());
+        }
+    }
+    for (name, var_ty) in &scope.vars {
+        if var_ty == ty {
+            leaves.push(Just(format!("(var-get {name})")).boxed());
+        }
+    }
+    let leaf = Union::new(leaves).boxed();
+    if depth == 0 {
+        return leaf;
+    }
+
+    let mut compound = generic_forms(ty, scope, depth - 1);
+    compound.extend(typed_forms(ty, scope, depth - 1));
+    prop_oneof![2 => leaf, 3 => Union::new(compound)].boxed()
+}
+
+/// Forms which can produce a value of any type.
+fn generic_forms(ty: &Ty, scope: &Scope, depth: u32) -> Vec<BoxedStrategy<String>> {
+    let mut forms = vec![];
+
+    forms.push(
+        (
+            expr(&Ty::Bool, scope, depth),
+            expr(ty, scope, depth),
+            expr(ty, scope, depth),
+        )
+            .prop_map(|(c, a, b)| format!("(if {c} {a} {b})"))
+            .boxed(),
+    );
+
+    forms.push(
+        expr(ty, scope, depth)
+            .prop_map(|e| format!("(print {e})"))
+            .boxed(),
+    );
+
+    forms.push(
+        expr(ty, scope, depth)
+            .prop_map(|e| format!("(as-contract {e})"))
+            .boxed(),
+    );
+
+    if let Some(returns) = &scope.returns {
+        let thrown = || expr(returns, scope, depth);
+        forms.push(
+            (expr(&optional_of(ty), scope, depth), thrown())
+                .prop_map(|(opt, thrown)| format!("(unwrap! {opt} {thrown})"))
+                .boxed(),
+        );
+        forms.push(
+            (
+                expr(
+                    &Ty::Response(Box::new(ty.clone()), Box::new(Ty::UInt)),
+                    scope,
+                    depth,
+                ),
+                thrown(),
+            )
+                .prop_map(|(res, thrown)| format!("(unwrap! {res} {thrown})"))
+                .boxed(),
+        );
+        forms.push(
+            (
+                expr(
+                    &Ty::Response(Box::new(Ty::Bool), Box::new(ty.clone())),
+                    scope,
+                    depth,
+                ),
+                thrown(),
+            )
+                .prop_map(|(res, thrown)| format!("(unwrap-err! {res} {thrown})"))
+                .boxed(),
+        );
+        // `try!` returns the `none` or the `err` it meets.
+        let tried = match returns {
+            Ty::Optional(_) => Some(optional_of(ty)),
+            Ty::Response(_, err) => Some(Ty::Response(Box::new(ty.clone()), err.clone())),
+            _ => None,
+        };
+        if let Some(tried) = tried {
+            forms.push(
+                expr(&tried, scope, depth)
+                    .prop_map(|e| format!("(try! {e})"))
+                    .boxed(),
+            );
+        }
+    }
+
+    let (target, outer) = (ty.clone(), scope.clone());
+    forms.push(
+        binding_ty()
+            .prop_flat_map(move |value_ty| {
+                let (name, inner) = outer.bind(&value_ty);
+                (
+                    Just(name),
+                    expr(&value_ty, &outer, depth),
+                    expr(&target, &inner, depth),
+                )
+            })
+            .prop_map(|(name, value, body)| format!("(let (({name} {value})) {body})"))
+            .boxed(),
+    );
+
+    let (target, outer) = (ty.clone(), scope.clone());
+    forms.push(
+        binding_ty()
+            .prop_flat_map(move |some_ty| {
+                let (name, inner) = outer.bind(&some_ty);
+                (
+                    expr(&optional_of(&some_ty), &outer, depth),
+                    Just(name),
+                    expr(&target, &inner, depth),
+                    expr(&target, &outer, depth),
+                )
+            })
+            .prop_map(|(opt, name, some, none)| format!("(match {opt} {name} {some} {none})"))
+            .boxed(),
+    );
+
+    let (target, outer) = (ty.clone(), scope.clone());
+    forms.push(
+        (binding_ty(), binding_ty())
+            .prop_flat_map(move |(ok_ty, err_ty)| {
+                let (ok_name, ok_scope) = outer.bind(&ok_ty);
+                // Each arm only sees its own binding, but both get distinct
+                // names.
+                let mut err_outer = outer.clone();
+                err_outer.bindings = ok_scope.bindings;
+                let (err_name, err_scope) = err_outer.bind(&err_ty);
+                let response = Ty::Response(Box::new(ok_ty), Box::new(err_ty));
+                (
+                    expr(&response, &outer, depth),
+                    Just(ok_name),
+                    expr(&target, &ok_scope, depth),
+                    Just(err_name),
+                    expr(&target, &err_scope, depth),
+                )
+            })
+            .prop_map(|(res, ok_name, ok, err_name, err)| {
+                format!("(match {res} {ok_name} {ok} {err_name} {err})")
+            })
+            .boxed(),
+    );
+
+    forms.push(
+        (expr(ty, scope, depth), expr(&optional_of(ty), scope, depth))
+            .prop_map(|(default, opt)| format!("(default-to {default} {opt})"))
+            .boxed(),
+    );
+
+    forms.push(
+        expr(&optional_of(ty), scope, depth)
+            .prop_map(|opt| format!("(unwrap-panic {opt})"))
+            .boxed(),
+    );
+
+    forms.push(
+        expr(
+            &Ty::Response(Box::new(ty.clone()), Box::new(Ty::Bool)),
+            scope,
+            depth,
+        )
+        .prop_map(|res| format!("(unwrap-panic {res})"))
+        .boxed(),
+    );
+
+    forms.push(
+        expr(
+            &Ty::Response(Box::new(Ty::Bool), Box::new(ty.clone())),
+            scope,
+            depth,
+        )
+        .prop_map(|res| format!("(unwrap-err-panic {res})"))
+        .boxed(),
+    );
+
+    forms.push(
+        (expr(&Ty::Bool, scope, depth), expr(ty, scope, depth))
+            .prop_map(|(effect, e)| format!("(begin {effect} {e})"))
+            .boxed(),
+    );
+
+    forms.push(
+        expr(
+            &Ty::Tuple(Box::new(ty.clone()), Box::new(Ty::Bool)),
+            scope,
+            depth,
+        )
+        .prop_map(|tuple| format!("(get a {tuple})"))
+        .boxed(),
+    );
+
+    forms.push(
+        expr(
+            &Ty::Tuple(Box::new(Ty::Bool), Box::new(ty.clone())),
+            scope,
+            depth,
+        )
+        .prop_map(|tuple| format!("(get b {tuple})"))
+        .boxed(),
+    );
+
+    for function in &scope.functions {
+        if function.ret != *ty {
+            continue;
+        }
+        let args: Vec<_> = function
+            .args
+            .iter()
+            .map(|arg| expr(arg, scope, depth))
+            .collect();
+        let function = function.clone();
+        forms.push(args.prop_map(move |args| function.call(&args)).boxed());
+    }
+
+    for function in scope.local_functions() {
+        if function.args.len() == 2 && function.args[1] == *ty && function.ret == *ty {
+            let name = function.name.clone();
+            forms.push(
+                (
+                    expr(&list_of(&function.args[0], 4), scope, depth),
+                    expr(ty, scope, depth),
+                )
+                    .prop_map(move |(list, init)| format!("(fold {name} {list} {init})"))
+                    .boxed(),
+            );
+        }
+    }
+
+    forms
+}
+
+/// Forms specific to the type `ty`.
+fn typed_forms(ty: &Ty, scope: &Scope, depth: u32) -> Vec<BoxedStrategy<String>> {
+    let sub = |ty: &Ty| expr(ty, scope, depth);
+    let binary = |op: &'static str, ty: &Ty| {
+        (sub(ty), sub(ty))
+            .prop_map(move |(a, b)| format!("({op} {a} {b})"))
+            .boxed()
+    };
+    let unary =
+        |op: &'static str, ty: &Ty| sub(ty).prop_map(move |a| format!("({op} {a})")).boxed();
+    // Appending to or concatenating sequences of at most `len` elements.
+    let bounded = |op: &'static str, seq: &Ty, other: &Ty, len: u32| {
+        (sub(seq), sub(other))
+            .prop_map(move |(a, b)| format!("(unwrap-panic (as-max-len? ({op} {a} {b}) u{len}))"))
+            .boxed()
+    };
+
+    let mut forms = vec![];
+    match ty {
+        Ty::Int | Ty::UInt => {
+            for op in [
+                "+", "-", "*", "/", "mod", "pow", "xor", "bit-and", "bit-or", "bit-xor",
+            ] {
+                forms.push(binary(op, ty));
+            }
+            for op in ["sqrti", "log2", "bit-not"] {
+                forms.push(unary(op, ty));
+            }
+            for op in ["bit-shift-left", "bit-shift-right"] {
+                forms.push(
+                    (sub(ty), sub(&Ty::UInt))
+                        .prop_map(move |(a, b)| format!("({op} {a} {b})"))
+                        .boxed(),
+                );
+            }
+        }
+        _ => {}
+    }
+    match ty {
+        Ty::Int => {
+            forms.push(unary("to-int", &Ty::UInt));
+            forms.push(unary("buff-to-int-be", &Ty::Buff(8)));
+            forms.push(unary("buff-to-int-le", &Ty::Buff(8)));
+        }
+        Ty::UInt => {
+            forms.push(unary("to-uint", &Ty::Int));
+            forms.push(unary("buff-to-uint-be", &Ty::Buff(8)));
+            forms.push(unary("buff-to-uint-le", &Ty::Buff(8)));
+            forms.push(unary("len", &list_of(&Ty::Int, 4)));
+            forms.push(unary("len", &Ty::Buff(8)));
+            forms.push(unary("len", &Ty::Ascii(8)));
+            for keyword in [
+                "stacks-block-height",
+                "burn-block-height",
+                "tenure-height",
+                "chain-id",
+            ] {
+                forms.push(Just(keyword.to_string()).boxed());
+            }
+            forms.push(unary("stx-get-balance", &Ty::Principal));
+            forms.push(
+                sub(&Ty::Principal)
+                    .prop_map(|p| format!("(ft-get-balance ft {p})"))
+                    .boxed(),
+            );
+            forms.push(Just("(ft-get-supply ft)".to_string()).boxed());
+        }
+        Ty::Bool => {
+            forms.push(binary("and", ty));
+            forms.push(binary("or", ty));
+            forms.push(unary("not", ty));
+            for op in ["<", ">", "<=", ">="] {
+                forms.push(binary(op, &Ty::Int));
+                forms.push(binary(op, &Ty::UInt));
+            }
+            let outer = scope.clone();
+            forms.push(
+                binding_ty()
+                    .prop_flat_map(move |ty| (expr(&ty, &outer, depth), expr(&ty, &outer, depth)))
+                    .prop_map(|(a, b)| format!("(is-eq {a} {b})"))
+                    .boxed(),
+            );
+            forms.push(unary("is-some", &optional_of(&Ty::Int)));
+            forms.push(unary("is-none", &optional_of(&Ty::Int)));
+            let response = Ty::Response(Box::new(Ty::Int), Box::new(Ty::UInt));
+            forms.push(unary("is-ok", &response));
+            forms.push(unary("is-err", &response));
+            forms.push(unary("is-standard", &Ty::Principal));
+            for keyword in ["is-in-mainnet", "is-in-regtest"] {
+                forms.push(Just(keyword.to_string()).boxed());
+            }
+            if let Some(returns) = &scope.returns {
+                forms.push(
+                    (sub(&Ty::Bool), sub(returns))
+                        .prop_map(|(c, thrown)| format!("(asserts! {c} {thrown})"))
+                        .boxed(),
+                );
+            }
+            if scope.writable {
+                for (name, var_ty) in &scope.vars {
+                    let name = name.clone();
+                    forms.push(
+                        sub(var_ty)
+                            .prop_map(move |v| format!("(var-set {name} {v})"))
+                            .boxed(),
+                    );
+                }
+                for (name, key_ty, value_ty) in &scope.maps {
+                    for op in ["map-set", "map-insert"] {
+                        let name = name.clone();
+                        forms.push(
+                            (sub(key_ty), sub(value_ty))
+                                .prop_map(move |(k, v)| format!("({op} {name} {k} {v})"))
+                                .boxed(),
+                        );
+                    }
+                    let name = name.clone();
+                    forms.push(
+                        sub(key_ty)
+                            .prop_map(move |k| format!("(map-delete {name} {k})"))
+                            .boxed(),
+                    );
+                }
+            }
+        }
+        Ty::Principal => {
+            for keyword in ["tx-sender", "contract-caller"] {
+                forms.push(Just(keyword.to_string()).boxed());
+            }
+        }
+        Ty::Buff(len) | Ty::Ascii(len) => {
+            forms.push(bounded("concat", ty, ty, *len));
+            forms.push(
+                (sub(ty), sub(ty), sub(&Ty::UInt), sub(&Ty::UInt))
+                    .prop_map(|(default, seq, from, to)| {
+                        format!("(default-to {default} (slice? {seq} {from} {to}))")
+                    })
+                    .boxed(),
+            );
+        }
+        Ty::Optional(inner) => {
+            forms.push(unary("some", inner));
+            match **inner {
+                Ty::Int => forms.push(unary("string-to-int?", &Ty::Ascii(8))),
+                Ty::UInt => {
+                    forms.push(unary("string-to-uint?", &Ty::Ascii(8)));
+                    forms.push(
+                        sub(&Ty::UInt)
+                            .prop_map(|height| format!("(get-stacks-block-info? time {height})"))
+                            .boxed(),
+                    );
+                    forms.push(
+                        (sub(&list_of(&Ty::Int, 4)), sub(&Ty::Int))
+                            .prop_map(|(list, item)| format!("(index-of? {list} {item})"))
+                            .boxed(),
+                    );
+                }
+                Ty::Principal => forms.push(
+                    sub(&Ty::UInt)
+                        .prop_map(|id| format!("(nft-get-owner? nft {id})"))
+                        .boxed(),
+                ),
+                Ty::List(ref item, _) => forms.push(
+                    (sub(inner), sub(&Ty::UInt), sub(item))
+                        .prop_map(|(list, index, item)| {
+                            format!("(replace-at? {list} {index} {item})")
+                        })
+                        .boxed(),
+                ),
+                _ => {}
+            }
+            forms.push(
+                (sub(&list_of(inner, 4)), sub(&Ty::UInt))
+                    .prop_map(|(list, index)| format!("(element-at? {list} {index})"))
+                    .boxed(),
+            );
+            for (name, key_ty, value_ty) in &scope.maps {
+                if *value_ty == **inner {
+                    let name = name.clone();
+                    forms.push(
+                        sub(key_ty)
+                            .prop_map(move |k| format!("(map-get? {name} {k})"))
+                            .boxed(),
+                    );
+                }
+            }
+        }
+        Ty::Response(ok, err) => {
+            forms.push(
+                (sub(&Ty::Bool), sub(ok), sub(err))
+                    .prop_map(|(c, o, e)| format!("(if {c} (ok {o}) (err {e}))"))
+                    .boxed(),
+            );
+            if scope.writable && **ok == Ty::Bool && **err == Ty::UInt {
+                let principal = || sub(&Ty::Principal);
+                for op in ["stx-transfer?", "ft-transfer? ft", "nft-transfer? nft"] {
+                    forms.push(
+                        (sub(&Ty::UInt), principal(), principal())
+                            .prop_map(move |(n, from, to)| format!("({op} {n} {from} {to})"))
+                            .boxed(),
+                    );
+                }
+                for op in [
+                    "stx-burn?",
+                    "ft-mint? ft",
+                    "ft-burn? ft",
+                    "nft-mint? nft",
+                    "nft-burn? nft",
+                ] {
+                    forms.push(
+                        (sub(&Ty::UInt), principal())
+                            .prop_map(move |(n, p)| format!("({op} {n} {p})"))
+                            .boxed(),
+                    );
+                }
+            }
+        }
+        Ty::List(inner, len) => {
+            forms.push(
+                prop::collection::vec(sub(inner), 1..=*len as usize)
+                    .prop_map(|items| format!("(list {})", items.join(" ")))
+                    .boxed(),
+            );
+            forms.push(bounded("append", ty, inner, *len));
+            forms.push(bounded("concat", ty, ty, *len));
+            for function in scope.local_functions() {
+                if function.args.len() == 1 && function.ret == **inner {
+                    let name = function.name.clone();
+                    forms.push(
+                        sub(&list_of(&function.args[0], *len))
+                            .prop_map(move |list| format!("(map {name} {list})"))
+                            .boxed(),
+                    );
+                }
+                if function.args.len() == 1
+                    && function.args[0] == **inner
+                    && function.ret == Ty::Bool
+                {
+                    let name = function.name.clone();
+                    forms.push(
+                        sub(ty)
+                            .prop_map(move |list| format!("(filter {name} {list})"))
+                            .boxed(),
+                    );
+                }
+            }
+        }
+        Ty::Tuple(a, b) => {
+            forms.push(
+                (sub(a), sub(b))
+                    .prop_map(|(a, b)| format!("(tuple (a {a}) (b {b}))"))
+                    .boxed(),
+            );
+            forms.push(
+                (sub(ty), sub(a))
+                    .prop_map(|(tuple, a)| format!("(merge {tuple} (tuple (a {a})))"))
+                    .boxed(),
+            );
+        }
+    }
+    forms
+}
+
+/// The shape of a contract: the types of its definitions.
+#[derive(Debug, Clone)]
+struct Skeleton {
+    constants: Vec<Ty>,
+    vars: Vec<Ty>,
+    maps: Vec<(Ty, Ty)>,
+    functions: Vec<(Kind, Vec<Ty>, Ty)>,
+    main: Ty,
+}
+
+fn function_signature() -> impl Strategy<Value = (Kind, Vec<Ty>, Ty)> {
+    (
+        prop_oneof![
+            Just(Kind::Private),
+            Just(Kind::ReadOnly),
+            Just(Kind::Public)
+        ],
+        prop::collection::vec(ty(), 0..=2),
+        ty(),
+    )
+        .prop_map(|(kind, args, ret)| {
+            // Public functions must return a response.
+            let ret = match (kind, ret) {
+                (Kind::Public, ret @ Ty::Response(..)) => ret,
+                (Kind::Public, ret) => Ty::Response(Box::new(ret), Box::new(Ty::UInt)),
+                (_, ret) => ret,
+            };
+            (kind, args, ret)
+        })
+}
+
+fn skeleton() -> impl Strategy<Value = Skeleton> {
+    (
+        prop::collection::vec(ty(), 0..=2),
+        prop::collection::vec(ty(), 0..=2),
+        prop::collection::vec((leaf_ty(), ty()), 0..=1),
+        prop::collection::vec(function_signature(), 0..=3),
+        ty(),
+    )
+        .prop_map(|(constants, vars, maps, functions, main)| Skeleton {
+            constants,
+            vars,
+            maps,
+            functions,
+            main,
+        })
+}
+
+/// Generates the source of a contract with the shape of `skeleton`. Its
+/// top-level expression may call the public and read-only functions in
+/// `external`.
+fn contract_source(skeleton: &Skeleton, external: &[Signature]) -> BoxedStrategy<String> {
+    let mut definitions = vec![Just(PRELUDE.to_string()).boxed()];
+    let mut scope = Scope {
+        functions: prelude_signatures(),
+        ..Default::default()
+    };
+
+    for (i, ty) in skeleton.constants.iter().enumerate() {
+        let name = format!("c{i}");
+        definitions.push(
+            expr(ty, &Scope::default(), BODY_DEPTH)
+                .prop_map(move |value| format!("(define-constant {name} {value})"))
+                .boxed(),
+        );
+        scope.values.push((format!("c{i}"), ty.clone()));
+    }
+
+    for (i, ty) in skeleton.vars.iter().enumerate() {
+        let name = format!("dv{i}");
+        let var_ty = ty.clone();
+        definitions.push(
+            literal(ty)
+                .prop_map(move |value| format!("(define-data-var {name} {var_ty} {value})"))
+                .boxed(),
+        );
+        scope.vars.push((format!("dv{i}"), ty.clone()));
+    }
+
+    for (i, (key_ty, value_ty)) in skeleton.maps.iter().enumerate() {
+        let name = format!("mp{i}");
+        definitions.push(Just(format!("(define-map {name} {key_ty} {value_ty})")).boxed());
+        scope.maps.push((name, key_ty.clone(), value_ty.clone()));
+    }
+
+    // Functions may only call the non-public functions defined before them,
+    // which rules out recursion.
+    let mut callable = scope.functions.clone();
+    let mut defined = vec![];
+    for (i, (kind, args, ret)) in skeleton.functions.iter().enumerate() {
+        let name = format!("f{i}");
+        let mut body_scope = Scope {
+            functions: callable.clone(),
+            writable: *kind == Kind::Public,
+            returns: Some(ret.clone()),
+            ..scope.clone()
+        };
+        let mut params = String::new();
+        for (j, arg) in args.iter().enumerate() {
+            body_scope.values.push((format!("p{j}"), arg.clone()));
+            params.push_str(&format!(" (p{j} {arg})"));
+        }
+        let header = format!("({} ({name}{params})", kind.keyword());
+        definitions.push(
+            expr(ret, &body_scope, BODY_DEPTH)
+                .prop_map(move |body| format!("{header} {body})"))
+                .boxed(),
+        );
+
+        let signature = Signature::local(&name, args.clone(), ret.clone());
+        if *kind != Kind::Public {
+            callable.push(signature.clone());
+        }
+        defined.push(signature);
+    }
+
+    let main_scope = Scope {
+        functions: scope
+            .functions
+            .iter()
+            .chain(&defined)
+            .chain(external)
+            .cloned()
+            .collect(),
+        writable: true,
+        ..scope
+    };
+    definitions.push(expr(&skeleton.main, &main_scope, MAIN_DEPTH));
+
+    definitions.prop_map(|defs| defs.join("\n")).boxed()
+}
+
+/// The functions of a contract which other contracts can call.
+fn external_signatures(contract: &str, skeleton: &Skeleton) -> Vec<Signature> {
+    skeleton
+        .functions
+        .iter()
+        .enumerate()
+        .filter(|(_, (kind, _, _))| *kind != Kind::Private)
+        .map(|(i, (_, args, ret))| Signature {
+            name: format!("f{i}"),
+            args: args.clone(),
+            ret: ret.clone(),
+            contract: Some(contract.to_string()),
+        })
+        .collect()
+}
+
+/// Contracts deployed in order.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct Program {
+    contracts: Vec<(String, String)>,
+}
+
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (name, source) in &self.contracts {
+            writeln!(f, "{CONTRACT_MARKER}{name}")?;
+            writeln!(f, "{source}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Program {
+    /// Parses a program in the format produced by [Program]'s `Display`.
+    fn parse(text: &str) -> Self {
+        let mut contracts: Vec<(String, String)> = vec![];
+        for line in text.lines() {
+            match line.strip_prefix(CONTRACT_MARKER) {
+                Some(name) => contracts.push((name.trim().to_string(), String::new())),
+                None => {
+                    let (_, source) = contracts
+                        .last_mut()
+                        .expect("corpus programs must start with a contract marker");
+                    source.push_str(line);
+                    source.push('\n');
+                }
+            }
+        }
+        Self { contracts }
+    }
+
+    fn crosscheck(&self) {
+        let contracts: Vec<(ContractName, &str)> = self
+            .contracts
+            .iter()
+            .map(|(name, source)| (name.as_str().into(), source.as_str()))
+            .collect();
+        crosscheck_multi_contract_compare_only(&contracts);
+    }
+}
+
+fn program() -> impl Strategy<Value = Program> {
+    prop_oneof![
+        skeleton().prop_flat_map(|skeleton| {
+            contract_source(&skeleton, &[]).prop_map(|source| Program {
+                contracts: vec![("main".to_string(), source)],
+            })
+        }),
+        (skeleton(), skeleton()).prop_flat_map(|(callee, caller)| {
+            let external = external_signatures("callee", &callee);
+            (
+                contract_source(&callee, &[]),
+                contract_source(&caller, &external),
+            )
+                .prop_map(|(callee, caller)| Program {
+                    contracts: vec![
+                        ("callee".to_string(), callee),
+                        ("caller".to_string(), caller),
+                    ],
+                })
+        }),
+    ]
+}
+
+fn corpus_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/wasm-generation/corpus")
+}
+
+/// Saves `program` in the corpus, so that it is replayed by later runs.
+fn save_to_corpus(program: &Program) -> PathBuf {
+    let mut hasher = DefaultHasher::new();
+    program.hash(&mut hasher);
+    let path = corpus_dir().join(format!("{:016x}.clar", hasher.finish()));
+    std::fs::create_dir_all(corpus_dir()).expect("failed to create the corpus directory");
+    std::fs::write(&path, program.to_string()).expect("failed to save the program");
+    path
+}
+
+#[test]
+fn generated_programs_do_not_diverge() {
+    let mut runner = TestRunner::new(crate::runtime_config());
+    match runner.run(&program(), |program| {
+        program.crosscheck();
+        Ok(())
+    }) {
+        Ok(()) => {}
+        Err(TestError::Fail(reason, program)) => {
+            let path = save_to_corpus(&program);
+            panic!(
+                "{reason}\nminimal diverging program, saved to {}:\n{program}",
+                path.display()
+            );
+        }
+        Err(e) => panic!("{e}"),
+    }
+}
+
+#[test]
+fn corpus_programs_do_not_diverge() {
+    let mut paths: Vec<_> = std::fs::read_dir(corpus_dir())
+        .expect("failed to read the corpus directory")
+        .map(|entry| entry.expect("failed to read corpus entry").path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "clar"))
+        .collect();
+    paths.sort();
+
+    for path in paths {
+        let text = std::fs::read_to_string(&path).expect("failed to read corpus program");
+        Program::parse(&text).crosscheck();
+    }
+}